@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::metadata::MetadataItem;
+use crate::office::{rewrite_package, PartAction};
+
+// Besides whatever the contained files carry, a zip archive records a
+// modification time, optional extra fields (NTFS/Unix timestamps, owner
+// UID/GID) and an optional comment for every entry, plus a comment for the
+// archive as a whole. Rewriting the archive through `rewrite_package` drops
+// all of them: entries get the default 1980-01-01 timestamp and no extras.
+
+/// Cleans a file extracted from an archive. Returns `None` for files it doesn't handle.
+pub type EntryCleaner<'a> = &'a dyn Fn(&Path, &Path) -> Result<Option<Vec<MetadataItem>>>;
+
+pub fn strip_archive_metadata(
+    input_path: &Path,
+    output_path: &Path,
+    clean_entry: Option<EntryCleaner>,
+) -> Result<Vec<MetadataItem>> {
+    let mut removed_metadata = describe_archive(input_path)?;
+    let mut nested_error = None;
+
+    rewrite_package(input_path, output_path, |name, content| {
+        let clean_entry = match clean_entry {
+            Some(clean_entry) if nested_error.is_none() && !name.ends_with('/') => clean_entry,
+            _ => return PartAction::Keep,
+        };
+
+        match clean_nested_file(output_path, name, content, clean_entry) {
+            Ok(Some((cleaned, metadata))) => {
+                removed_metadata.extend(metadata.into_iter().map(|mut item| {
+                    item.tag = format!("{} -> {}", name, item.tag);
+                    item
+                }));
+                PartAction::Replace(cleaned)
+            }
+            Ok(None) => PartAction::Keep,
+            Err(e) => {
+                nested_error = Some(e.context(format!("Failed to clean {} inside archive", name)));
+                PartAction::Keep
+            }
+        }
+    })?;
+
+    if let Some(e) = nested_error {
+        // Don't leave behind an archive that still contains an uncleaned file
+        if input_path != output_path {
+            let _ = fs::remove_file(output_path);
+        }
+        return Err(e);
+    }
+
+    Ok(removed_metadata)
+}
+
+fn describe_archive(input_path: &Path) -> Result<Vec<MetadataItem>> {
+    let input = File::open(input_path)
+        .with_context(|| format!("Failed to open archive: {}", input_path.display()))?;
+    let mut archive = ZipArchive::new(BufReader::new(input))
+        .with_context(|| format!("Failed to read archive: {}", input_path.display()))?;
+
+    let mut metadata = Vec::new();
+
+    let comment = String::from_utf8_lossy(archive.comment()).trim().to_string();
+    if !comment.is_empty() {
+        metadata.push(MetadataItem::new("Archive Comment", comment));
+    }
+
+    let mut timestamps = Vec::new();
+    let mut extra_fields: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut entry_comments = 0;
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+
+        let time = entry.last_modified();
+        let time = (time.year(), time.month(), time.day(), time.hour(), time.minute(), time.second());
+        if time != (1980, 1, 1, 0, 0, 0) {
+            timestamps.push(time);
+        }
+
+        for header_id in extra_field_ids(entry.extra_data()) {
+            *extra_fields.entry(extra_field_name(header_id)).or_insert(0) += 1;
+        }
+
+        if !entry.comment().trim().is_empty() {
+            entry_comments += 1;
+        }
+    }
+
+    if let Some((year, month, day, hour, minute, second)) = timestamps.iter().max() {
+        metadata.push(MetadataItem::new(
+            "Entry Timestamps",
+            format!(
+                "{} (newest {:04}-{:02}-{:02} {:02}:{:02}:{:02})",
+                timestamps.len(), year, month, day, hour, minute, second
+            ),
+        ));
+    }
+    for (name, count) in extra_fields {
+        metadata.push(MetadataItem::new(format!("Extra Fields ({})", name), count.to_string()));
+    }
+    if entry_comments > 0 {
+        metadata.push(MetadataItem::new("Entry Comments", entry_comments.to_string()));
+    }
+
+    Ok(metadata)
+}
+
+fn extra_field_ids(extra: &[u8]) -> Vec<u16> {
+    let mut ids = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= extra.len() {
+        ids.push(u16::from_le_bytes([extra[pos], extra[pos + 1]]));
+        let size = u16::from_le_bytes([extra[pos + 2], extra[pos + 3]]) as usize;
+        pos += 4 + size;
+    }
+    ids
+}
+
+fn extra_field_name(header_id: u16) -> &'static str {
+    match header_id {
+        0x000A => "NTFS timestamps",
+        0x5455 => "extended timestamps",
+        0x5855 | 0x7855 | 0x7875 => "Unix owner",
+        0x0001 => "zip64",
+        _ => "other",
+    }
+}
+
+/// Runs `clean_entry` on a copy of an archive entry, returning the cleaned bytes
+fn clean_nested_file(
+    output_path: &Path,
+    name: &str,
+    content: &[u8],
+    clean_entry: EntryCleaner,
+) -> Result<Option<(Vec<u8>, Vec<MetadataItem>)>> {
+    let extension = match Path::new(name).extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension.to_lowercase(),
+        None => return Ok(None),
+    };
+
+    // Keep the entry's extension so type detection works on the extracted copy
+    let entry_input = output_path.with_extension(format!("entry.{}", extension));
+    let entry_output = output_path.with_extension(format!("entry.clean.{}", extension));
+
+    let result = fs::write(&entry_input, content)
+        .with_context(|| format!("Failed to extract {}", name))
+        .and_then(|_| clean_entry(&entry_input, &entry_output))
+        .and_then(|metadata| match metadata {
+            Some(metadata) => Ok(Some((fs::read(&entry_output)?, metadata))),
+            None => Ok(None),
+        });
+
+    let _ = fs::remove_file(&entry_input);
+    let _ = fs::remove_file(&entry_output);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Category;
+    use std::io::{Read, Write};
+    use tempfile::Builder;
+    use zip::write::FileOptions;
+    use zip::{DateTime, ZipWriter};
+
+    fn write_test_zip(path: &Path) {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        let options = FileOptions::default()
+            .last_modified_time(DateTime::from_date_and_time(2023, 5, 17, 14, 30, 0).unwrap());
+
+        writer.start_file_with_extra_data("notes.txt", options).unwrap();
+        // Info-ZIP Unix UID/GID field
+        writer.write_all(&[0x75, 0x78, 0x0B, 0x00, 1, 4, 0xE8, 0x03, 0, 0, 4, 0xE8, 0x03, 0, 0]).unwrap();
+        writer.end_extra_data().unwrap();
+        writer.write_all(b"hello").unwrap();
+
+        writer.start_file("data.secret", options).unwrap();
+        writer.write_all(b"untouched").unwrap();
+
+        writer.set_comment("Packed by jane@laptop");
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_strip_archive_metadata() {
+        let input = Builder::new().suffix(".zip").tempfile().unwrap();
+        let output = Builder::new().suffix(".zip").tempfile().unwrap();
+        write_test_zip(input.path());
+
+        let removed = strip_archive_metadata(input.path(), output.path(), None).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Archive Comment: Packed by jane@laptop".to_string()));
+        assert!(removed.contains(&"Entry Timestamps: 2 (newest 2023-05-17 14:30:00)".to_string()));
+        assert!(removed.contains(&"Extra Fields (Unix owner): 1".to_string()));
+
+        let mut archive = ZipArchive::new(File::open(output.path()).unwrap()).unwrap();
+        assert!(archive.comment().is_empty());
+        let entry = archive.by_name("notes.txt").unwrap();
+        assert!(entry.extra_data().is_empty());
+        assert_eq!(entry.last_modified().year(), 1980);
+    }
+
+    #[test]
+    fn test_strip_archive_metadata_recurses() {
+        let input = Builder::new().suffix(".zip").tempfile().unwrap();
+        let output = Builder::new().suffix(".zip").tempfile().unwrap();
+        write_test_zip(input.path());
+
+        let clean_txt = |input: &Path, output: &Path| -> Result<Option<Vec<MetadataItem>>> {
+            if input.extension().and_then(|e| e.to_str()) != Some("txt") {
+                return Ok(None);
+            }
+            fs::write(output, b"cleaned")?;
+            Ok(Some(vec![MetadataItem::new("Author", "Jane Doe")]))
+        };
+
+        let removed = strip_archive_metadata(input.path(), output.path(), Some(&clean_txt)).unwrap();
+        let nested = removed.iter().find(|item| item.tag == "notes.txt -> Author").unwrap();
+        assert_eq!(nested.value, "Jane Doe");
+        assert_eq!(nested.category, Category::Author);
+
+        let mut archive = ZipArchive::new(File::open(output.path()).unwrap()).unwrap();
+        let mut content = String::new();
+        archive.by_name("notes.txt").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "cleaned");
+        content.clear();
+        archive.by_name("data.secret").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "untouched");
+    }
+}
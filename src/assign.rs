@@ -0,0 +1,285 @@
+// Values written in place of removed metadata, for files that should look ordinary
+// rather than conspicuously empty. Fields have one name across formats and each
+// format maps them to its own tags.
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::metadata::{Category, MetadataItem};
+
+const SET_WARNING: &str = "Warning: set";
+
+/// A field that can be given a value with `MetaStripper::set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SetField {
+    Author,
+    Title,
+    Copyright,
+    Software,
+    Make,
+    Model,
+    /// Creation and modification time
+    Date,
+}
+
+impl SetField {
+    pub const ALL: &[SetField] = &[
+        SetField::Author,
+        SetField::Title,
+        SetField::Copyright,
+        SetField::Software,
+        SetField::Make,
+        SetField::Model,
+        SetField::Date,
+    ];
+
+    /// The category the tags this field is written to fall in
+    pub fn category(self) -> Category {
+        match self {
+            SetField::Author => Category::Author,
+            SetField::Title => Category::Content,
+            SetField::Copyright => Category::Copyright,
+            SetField::Software => Category::Software,
+            SetField::Make | SetField::Model => Category::Device,
+            SetField::Date => Category::Timestamps,
+        }
+    }
+}
+
+impl fmt::Display for SetField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SetField::Author => "author",
+            SetField::Title => "title",
+            SetField::Copyright => "copyright",
+            SetField::Software => "software",
+            SetField::Make => "make",
+            SetField::Model => "model",
+            SetField::Date => "date",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SetField {
+    type Err = anyhow::Error;
+
+    /// Parses the names printed by `Display`, e.g. "author" or "date"
+    fn from_str(s: &str) -> Result<Self> {
+        SetField::ALL
+            .iter()
+            .copied()
+            .find(|field| field.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = SetField::ALL.iter().map(ToString::to_string).collect();
+                anyhow::anyhow!("Unknown field '{}' (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
+
+/// A value for a field, parsed from "key=value", e.g. "author=A. Nonymous" or
+/// "date=2020-01-01 12:00:00"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub field: SetField,
+    pub value: String,
+}
+
+impl Assignment {
+    /// The value of a `date` assignment, taken as UTC
+    pub fn date(&self) -> Option<NaiveDateTime> {
+        parse_date(&self.value)
+    }
+}
+
+impl FromStr for Assignment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, value) = s
+            .split_once('=')
+            .filter(|(_, value)| !value.trim().is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Expected key=value, got '{}'", s))?;
+        let field = key.parse()?;
+        let value = value.trim().to_string();
+        if field == SetField::Date && parse_date(&value).is_none() {
+            anyhow::bail!("Invalid date '{}' (expected e.g. 2020-01-01 or 2020-01-01T12:00:00)", value);
+        }
+        Ok(Self { field, value })
+    }
+}
+
+/// The span `--anonymize` picks dates from, parsed from "2019-01-01..2023-12-31".
+/// A date without a time as the end includes that whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl DateRange {
+    /// The five years up to now
+    pub fn recent() -> Self {
+        let end = Utc::now().naive_utc();
+        Self { start: end - Duration::days(5 * 365), end }
+    }
+}
+
+impl FromStr for DateRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid date range '{}' (expected e.g. 2019-01-01..2023-12-31)", s);
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let start = parse_date(start.trim()).ok_or_else(invalid)?;
+        let end = match NaiveDate::parse_from_str(end.trim(), "%Y-%m-%d") {
+            Ok(day) => day.and_hms_opt(23, 59, 59).ok_or_else(invalid)?,
+            Err(_) => parse_date(end.trim()).ok_or_else(invalid)?,
+        };
+        if start > end {
+            anyhow::bail!("Invalid date range '{}': it ends before it starts", s);
+        }
+        Ok(Self { start, end })
+    }
+}
+
+// Common phones, with a software version each has shipped with, so the three fit together
+const CAMERAS: &[(&str, &str, &str)] = &[
+    ("Apple", "iPhone 12", "15.7"),
+    ("Apple", "iPhone 13", "16.6"),
+    ("Apple", "iPhone 14 Pro", "17.1.2"),
+    ("samsung", "SM-G991B", "G991BXXU5CVLL"),
+    ("samsung", "SM-A525F", "A525FXXU4BVJ1"),
+    ("Google", "Pixel 7", "HQ1A.230205.002"),
+    ("Xiaomi", "Redmi Note 10 Pro", "V13.0.6.0.SKFMIXM"),
+];
+
+// Software that produces a large share of everyday PDFs
+const PDF_PRODUCERS: &[&str] = &[
+    "Microsoft® Word for Microsoft 365",
+    "LibreOffice 7.5",
+    "Skia/PDF m116",
+    "macOS Version 13.4 (Build 22F66) Quartz PDFContext",
+];
+
+/// Plausible random values for `fields`: one of a few common phones for the make,
+/// model and software of images, a common PDF producer for the software of other
+/// files, and a date within `range`
+pub(crate) fn anonymize(fields: &[SetField], range: &DateRange) -> Vec<Assignment> {
+    let camera = CAMERAS[random_below(CAMERAS.len() as u64) as usize];
+    let has_camera = fields.iter().any(|field| matches!(field, SetField::Make | SetField::Model));
+    let mut set = Vec::new();
+    for &field in fields {
+        let value = match field {
+            SetField::Make => camera.0.to_string(),
+            SetField::Model => camera.1.to_string(),
+            SetField::Software if has_camera => camera.2.to_string(),
+            SetField::Software => PDF_PRODUCERS[random_below(PDF_PRODUCERS.len() as u64) as usize].to_string(),
+            SetField::Date => {
+                let span = (range.end - range.start).num_seconds().max(0) as u64;
+                let date = range.start + Duration::seconds(random_below(span + 1) as i64);
+                date.format("%Y-%m-%dT%H:%M:%S").to_string()
+            }
+            SetField::Author | SetField::Title | SetField::Copyright => continue,
+        };
+        set.push(Assignment { field, value });
+    }
+    set
+}
+
+/// A random number below `bound`. Not suitable for cryptography, which picking
+/// a camera model doesn't need.
+fn random_below(bound: u64) -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    hasher.finish() % bound.max(1)
+}
+
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim_end_matches('Z');
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+}
+
+/// The value `set` gives `field`, formatted for the format by `date` if it's a date
+pub(crate) fn value_of(set: &[Assignment], field: SetField, date: fn(NaiveDateTime) -> String) -> Option<String> {
+    let assignment = set.iter().rev().find(|assignment| assignment.field == field)?;
+    match field {
+        SetField::Date => assignment.date().map(date),
+        _ => Some(assignment.value.clone()),
+    }
+}
+
+/// A warning naming the fields in `set` that `format` files have no tag for
+pub(crate) fn unsupported(set: &[Assignment], supported: &[SetField], format: &str) -> Option<MetadataItem> {
+    let mut fields: Vec<String> = Vec::new();
+    for assignment in set.iter().filter(|assignment| !supported.contains(&assignment.field)) {
+        let name = assignment.field.to_string();
+        if !fields.contains(&name) {
+            fields.push(name);
+        }
+    }
+    (!fields.is_empty()).then(|| {
+        let warning = format!("{} can't be set in {} files", fields.join(", "), format);
+        MetadataItem::with_category(Category::Notice, SET_WARNING, warning)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_assignment() {
+        let assignment: Assignment = "Author= A. Nonymous".parse().unwrap();
+        assert_eq!(assignment, Assignment { field: SetField::Author, value: "A. Nonymous".to_string() });
+
+        let date: Assignment = "date=2020-01-02".parse().unwrap();
+        assert_eq!(date.date().unwrap().to_string(), "2020-01-02 00:00:00");
+        let exif_date = value_of(&[date], SetField::Date, |date| date.format("%Y:%m:%d %H:%M:%S").to_string());
+        assert_eq!(exif_date.as_deref(), Some("2020:01:02 00:00:00"));
+
+        assert!("author".parse::<Assignment>().is_err());
+        assert!("author=".parse::<Assignment>().is_err());
+        assert!("owner=me".parse::<Assignment>().is_err());
+        assert!("date=yesterday".parse::<Assignment>().is_err());
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let range: DateRange = "2019-01-01..2019-01-31".parse().unwrap();
+        assert_eq!(range.end.to_string(), "2019-01-31 23:59:59");
+        assert!("2020-01-01..2019-01-01".parse::<DateRange>().is_err());
+
+        let fields = [SetField::Make, SetField::Model, SetField::Software, SetField::Date];
+        let set = anonymize(&fields, &range);
+        assert_eq!(set.iter().map(|assignment| assignment.field).collect::<Vec<_>>(), fields);
+        let camera = (set[0].value.as_str(), set[1].value.as_str(), set[2].value.as_str());
+        assert!(CAMERAS.contains(&camera));
+        let date = set[3].date().unwrap();
+        assert!(range.start <= date && date <= range.end);
+
+        let set = anonymize(&[SetField::Software], &range);
+        assert!(PDF_PRODUCERS.contains(&set[0].value.as_str()));
+    }
+
+    #[test]
+    fn test_unsupported_fields() {
+        let set: Vec<Assignment> = ["make=Canon", "author=A", "model=X", "make=Nikon"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let warning = unsupported(&set, &[SetField::Author], "PDF").unwrap();
+        assert_eq!(warning.value, "make, model can't be set in PDF files");
+        assert!(unsupported(&set, SetField::ALL, "JPEG").is_none());
+    }
+}
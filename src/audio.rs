@@ -0,0 +1,510 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::MetadataItem;
+use crate::temp;
+use crate::video::{extract_video_metadata, file_arg, FfmpegConfig};
+
+// Native strippers return the cleaned file contents and the metadata they removed
+type TagStripper = fn(&[u8]) -> Result<(Vec<u8>, Vec<MetadataItem>)>;
+
+pub fn strip_audio_metadata(input_path: &Path, output_path: &Path, ffmpeg: &FfmpegConfig) -> Result<Vec<MetadataItem>> {
+    let extension = crate::detect_format(input_path).unwrap_or_default();
+
+    // MP3, FLAC and WAV tags are simple enough to remove natively; Ogg pages and
+    // MP4 atoms need remuxing, which is left to ffmpeg
+    let native: Option<TagStripper> = match extension.as_str() {
+        "mp3" => Some(strip_mp3_tags),
+        "flac" => Some(strip_flac_tags),
+        "wav" => Some(strip_wav_tags),
+        _ => None,
+    };
+
+    match native {
+        Some(strip) => {
+            let data = fs::read(input_path)
+                .with_context(|| format!("Failed to read audio file: {}", input_path.display()))?;
+            let (cleaned, removed_metadata) = strip(&data)
+                .with_context(|| format!("Failed to parse audio file: {}", input_path.display()))?;
+            fs::write(output_path, cleaned)
+                .with_context(|| format!("Failed to save audio file: {}", output_path.display()))?;
+            Ok(removed_metadata)
+        }
+        None => strip_with_ffmpeg(input_path, output_path, &extension, ffmpeg),
+    }
+}
+
+fn strip_with_ffmpeg(
+    input_path: &Path,
+    output_path: &Path,
+    extension: &str,
+    ffmpeg: &FfmpegConfig,
+) -> Result<Vec<MetadataItem>> {
+    ffmpeg.require(&format!("{} files", extension))?;
+
+    let removed_metadata = extract_video_metadata(input_path, ffmpeg).unwrap_or_else(|_| {
+        vec![
+            MetadataItem::new("Tags (if present)", ""),
+            MetadataItem::new("Encoder information (if present)", ""),
+            MetadataItem::new("Cover art (if present)", ""),
+        ]
+    });
+
+    // Keep the original extension so ffmpeg picks the same container
+    let temp_path = temp::sibling(&output_path.with_extension(extension));
+
+    let mut command = ffmpeg.ffmpeg_command();
+    command
+        .arg("-i")
+        .arg(file_arg(input_path))
+        .args([
+            "-map", "0:a",          // Audio only, leaving out cover art
+            "-map_metadata", "-1",  // Remove all metadata
+            "-c", "copy",           // Copy streams without re-encoding
+            "-y",                   // Overwrite output file if it exists
+        ])
+        .args(ffmpeg.output_args())
+        .arg(file_arg(&temp_path));
+    let status = ffmpeg.run(&mut command);
+    if !matches!(&status, Ok(output) if output.status.success()) {
+        // Don't leave a partly written file behind after a failure or a timeout
+        let _ = fs::remove_file(&temp_path);
+    }
+    let status = status
+        .with_context(|| format!("Failed to execute ffmpeg command for: {}", input_path.display()))?;
+
+    if !status.status.success() {
+        let error = String::from_utf8_lossy(&status.stderr);
+        return Err(anyhow::anyhow!("ffmpeg failed: {}", error));
+    }
+
+    fs::rename(&temp_path, output_path)
+        .with_context(|| format!("Failed to move temporary file to: {}", output_path.display()))?;
+
+    Ok(removed_metadata)
+}
+
+fn strip_mp3_tags(data: &[u8]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    let mut metadata = Vec::new();
+    let mut start = 0;
+    let mut end = data.len();
+
+    // ID3v2 tags sit at the start of the file, possibly more than one
+    while data.len() >= start + 10 && &data[start..start + 3] == b"ID3" {
+        let header = &data[start..start + 10];
+        let major_version = header[3];
+        let flags = header[5];
+        let size = syncsafe(&header[6..10]);
+        // Footer present flag adds another 10 bytes
+        let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+        let tag_end = start + 10 + size + footer;
+        if tag_end > data.len() {
+            return Err(anyhow::anyhow!("Truncated ID3v2 tag"));
+        }
+
+        let tag_size = size + 10 + footer;
+        metadata.push(MetadataItem::new(format!("ID3v2.{} Tag", major_version), format!("{} bytes", tag_size)));
+        metadata.extend(describe_id3v2_frames(&data[start + 10..start + 10 + size], major_version, flags));
+        start = tag_end;
+    }
+
+    // ID3v1 (and the extended "TAG+" block in front of it) sit at the end
+    if end >= start + 128 && &data[end - 128..end - 125] == b"TAG" {
+        metadata.extend(describe_id3v1(&data[end - 128..end]));
+        end -= 128;
+        if end >= start + 227 && &data[end - 227..end - 223] == b"TAG+" {
+            metadata.push(MetadataItem::new("ID3v1 Extended Tag", "227 bytes"));
+            end -= 227;
+        }
+    }
+
+    Ok((data[start..end].to_vec(), metadata))
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0usize, |acc, &b| (acc << 7) | (b & 0x7F) as usize)
+}
+
+fn describe_id3v2_frames(tag: &[u8], major_version: u8, flags: u8) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    // Unsynchronised or v2.2 tags aren't worth decoding frame by frame
+    if flags & 0x80 != 0 || major_version < 3 {
+        return metadata;
+    }
+
+    let mut pos = 0;
+    // Skip the extended header if present
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        pos = if major_version == 4 {
+            syncsafe(&tag[0..4])
+        } else {
+            u32::from_be_bytes([tag[0], tag[1], tag[2], tag[3]]) as usize + 4
+        };
+    }
+
+    while pos + 10 <= tag.len() {
+        let id = &tag[pos..pos + 4];
+        // Padding
+        if id[0] == 0 {
+            break;
+        }
+        let size = if major_version == 4 {
+            syncsafe(&tag[pos + 4..pos + 8])
+        } else {
+            u32::from_be_bytes([tag[pos + 4], tag[pos + 5], tag[pos + 6], tag[pos + 7]]) as usize
+        };
+        let frame_end = pos + 10 + size;
+        if frame_end > tag.len() {
+            break;
+        }
+        let frame = &tag[pos + 10..frame_end];
+        let id = String::from_utf8_lossy(id).to_string();
+
+        let description = match id.as_str() {
+            "APIC" => MetadataItem::new("Attached Picture", format!("{} bytes", size)),
+            "GEOB" => MetadataItem::new("Embedded Object", format!("{} bytes", size)),
+            "PRIV" => MetadataItem::new("Private Frame", format!("{} bytes", size)),
+            "COMM" | "USLT" if frame.len() > 4 => {
+                // Encoding, language, then a description and the text
+                let text = decode_id3_text(frame[0], &frame[4..]);
+                let text = text.split('\0').filter(|s| !s.is_empty()).collect::<Vec<_>>().join(" ");
+                MetadataItem::new(id3_frame_name(&id), text)
+            }
+            _ if id.starts_with('T') && !frame.is_empty() => {
+                let text = decode_id3_text(frame[0], &frame[1..]).replace('\0', ", ");
+                MetadataItem::new(id3_frame_name(&id), text.trim_end_matches([',', ' ']))
+            }
+            _ => MetadataItem::new(id3_frame_name(&id), format!("{} bytes", size)),
+        };
+        metadata.push(description);
+
+        pos = frame_end;
+    }
+
+    metadata
+}
+
+fn id3_frame_name(id: &str) -> &str {
+    match id {
+        "TIT2" => "Title",
+        "TPE1" => "Artist",
+        "TPE2" => "Album Artist",
+        "TALB" => "Album",
+        "TYER" => "Year",
+        "TDRC" => "Recording Date",
+        "TDEN" => "Encoding Date",
+        "TCON" => "Genre",
+        "TCOM" => "Composer",
+        "TENC" => "Encoded By",
+        "TSSE" => "Encoder Settings",
+        "TCOP" => "Copyright",
+        "TOWN" => "File Owner",
+        "COMM" => "Comment",
+        "USLT" => "Lyrics",
+        "WXXX" => "URL",
+        _ => id,
+    }
+}
+
+fn decode_id3_text(encoding: u8, bytes: &[u8]) -> String {
+    match encoding {
+        // UTF-16 with BOM, or UTF-16BE without one
+        1 | 2 => {
+            let little_endian = encoding == 1 && bytes.starts_with(&[0xFF, 0xFE]);
+            let bytes = if encoding == 1 && bytes.len() >= 2 { &bytes[2..] } else { bytes };
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| if little_endian {
+                    u16::from_le_bytes([pair[0], pair[1]])
+                } else {
+                    u16::from_be_bytes([pair[0], pair[1]])
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(bytes).to_string(),
+        // ISO-8859-1
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+fn describe_id3v1(tag: &[u8]) -> Vec<MetadataItem> {
+    let field = |range: std::ops::Range<usize>| -> String {
+        tag[range]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| b as char)
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
+
+    let mut metadata = vec![MetadataItem::new("ID3v1 Tag", "128 bytes")];
+    for (name, value) in [
+        ("Title", field(3..33)),
+        ("Artist", field(33..63)),
+        ("Album", field(63..93)),
+        ("Year", field(93..97)),
+        ("Comment", field(97..127)),
+    ] {
+        if !value.is_empty() {
+            metadata.push(MetadataItem::new(format!("ID3v1 {}", name), value));
+        }
+    }
+    metadata
+}
+
+// FLAC metadata block types that hold tags or artwork
+const FLAC_VORBIS_COMMENT: u8 = 4;
+const FLAC_PICTURE: u8 = 6;
+
+fn strip_flac_tags(data: &[u8]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if !data.starts_with(b"fLaC") {
+        return Err(anyhow::anyhow!("Missing FLAC stream marker"));
+    }
+
+    let mut metadata = Vec::new();
+    let mut kept_blocks: Vec<&[u8]> = Vec::new();
+
+    let mut pos = 4;
+    loop {
+        if pos + 4 > data.len() {
+            return Err(anyhow::anyhow!("Truncated FLAC metadata block header"));
+        }
+        let header = data[pos];
+        let is_last = header & 0x80 != 0;
+        let block_type = header & 0x7F;
+        let length = u32::from_be_bytes([0, data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let block_end = pos + 4 + length;
+        if block_end > data.len() {
+            return Err(anyhow::anyhow!("Truncated FLAC metadata block"));
+        }
+
+        match block_type {
+            FLAC_VORBIS_COMMENT => metadata.extend(describe_vorbis_comment(&data[pos + 4..block_end])),
+            FLAC_PICTURE => metadata.push(MetadataItem::new("Embedded Picture", format!("{} bytes", length))),
+            _ => kept_blocks.push(&data[pos..block_end]),
+        }
+
+        pos = block_end;
+        if is_last {
+            break;
+        }
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(b"fLaC");
+    let kept_count = kept_blocks.len();
+    for (index, block) in kept_blocks.into_iter().enumerate() {
+        // Move the last-block flag to whichever block now comes last
+        let mut header = block[0] & 0x7F;
+        if index == kept_count - 1 {
+            header |= 0x80;
+        }
+        output.push(header);
+        output.extend_from_slice(&block[1..]);
+    }
+    output.extend_from_slice(&data[pos..]);
+
+    Ok((output, metadata))
+}
+
+fn describe_vorbis_comment(block: &[u8]) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    let read_u32 = |pos: usize| -> Option<usize> {
+        block.get(pos..pos + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+
+    let vendor_length = match read_u32(0) {
+        Some(length) => length,
+        None => return metadata,
+    };
+    if let Some(vendor) = block.get(4..4 + vendor_length) {
+        metadata.push(MetadataItem::new("Encoder", String::from_utf8_lossy(vendor)));
+    }
+
+    let mut pos = 4 + vendor_length;
+    let count = read_u32(pos).unwrap_or(0);
+    pos += 4;
+    for _ in 0..count {
+        let length = match read_u32(pos) {
+            Some(length) => length,
+            None => break,
+        };
+        let comment = match block.get(pos + 4..pos + 4 + length) {
+            Some(comment) => String::from_utf8_lossy(comment).to_string(),
+            None => break,
+        };
+        if let Some((key, value)) = comment.split_once('=') {
+            metadata.push(MetadataItem::new(format!("Vorbis Comment ({})", key.to_uppercase()), value));
+        }
+        pos += 4 + length;
+    }
+
+    metadata
+}
+
+fn strip_wav_tags(data: &[u8]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(anyhow::anyhow!("Missing RIFF/WAVE header"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    let mut metadata = Vec::new();
+    // The RIFF size is patched once the remaining chunks are known
+    output.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let fourcc = &data[pos..pos + 4];
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        // Chunk payloads are padded to an even length
+        let chunk_end = (pos + 8 + size + (size & 1)).min(data.len());
+        let payload = &data[pos + 8..(pos + 8 + size).min(data.len())];
+
+        match fourcc {
+            b"LIST" if payload.starts_with(b"INFO") => metadata.extend(describe_wav_info(&payload[4..])),
+            b"id3 " | b"ID3 " => metadata.push(MetadataItem::new("ID3 Chunk", format!("{} bytes", size))),
+            b"bext" => metadata.push(MetadataItem::new("Broadcast Extension Chunk", format!("{} bytes", size))),
+            b"iXML" => metadata.push(MetadataItem::new("iXML Chunk", format!("{} bytes", size))),
+            b"_PMX" => metadata.extend(crate::xmp::extract_xmp_fields(&String::from_utf8_lossy(payload))),
+            _ => output.extend_from_slice(&data[pos..chunk_end]),
+        }
+
+        pos = chunk_end;
+    }
+
+    let riff_size = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    Ok((output, metadata))
+}
+
+fn describe_wav_info(list: &[u8]) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    let mut pos = 0;
+    while pos + 8 <= list.len() {
+        let id = String::from_utf8_lossy(&list[pos..pos + 4]).to_string();
+        let size = u32::from_le_bytes([list[pos + 4], list[pos + 5], list[pos + 6], list[pos + 7]]) as usize;
+        let value_end = (pos + 8 + size).min(list.len());
+        let value: String = String::from_utf8_lossy(&list[pos + 8..value_end])
+            .trim_end_matches('\0')
+            .to_string();
+
+        let name = match id.as_str() {
+            "INAM" => "Title",
+            "IART" => "Artist",
+            "IPRD" => "Album",
+            "ICMT" => "Comment",
+            "ICRD" => "Creation Date",
+            "ISFT" => "Software",
+            "IENG" => "Engineer",
+            "ICOP" => "Copyright",
+            "IGNR" => "Genre",
+            other => other,
+        };
+        if !value.is_empty() {
+            metadata.push(MetadataItem::new(name, value));
+        }
+
+        pos += 8 + size + (size & 1);
+    }
+
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id3v2_frame(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn test_strip_mp3_tags() {
+        let audio = [0xFF, 0xFB, 0x90, 0x00, 1, 2, 3, 4];
+
+        let frames = id3v2_frame(b"TIT2", b"\x03Secret Song");
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend_from_slice(&[0, 0, 0, frames.len() as u8]);
+        data.extend(frames);
+        data.extend_from_slice(&audio);
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.extend_from_slice(b"Old Title");
+        id3v1.resize(128, 0);
+        data.extend(id3v1);
+
+        let (cleaned, removed) = strip_mp3_tags(&data).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert_eq!(cleaned, audio);
+        assert!(removed.contains(&"Title: Secret Song".to_string()));
+        assert!(removed.contains(&"ID3v1 Title: Old Title".to_string()));
+    }
+
+    #[test]
+    fn test_strip_flac_tags() {
+        let streaminfo = [0u8; 34];
+        let mut comment = 6u32.to_le_bytes().to_vec();
+        comment.extend_from_slice(b"vendor");
+        comment.extend_from_slice(&1u32.to_le_bytes());
+        comment.extend_from_slice(&(12u32).to_le_bytes());
+        comment.extend_from_slice(b"artist=Jane!");
+
+        let mut data = b"fLaC".to_vec();
+        data.extend_from_slice(&[0x00, 0, 0, 34]);
+        data.extend_from_slice(&streaminfo);
+        data.extend_from_slice(&[0x80 | FLAC_VORBIS_COMMENT, 0, 0, comment.len() as u8]);
+        data.extend(comment);
+        data.extend_from_slice(&[0xFF, 0xF8, 1, 2]);
+
+        let (cleaned, removed) = strip_flac_tags(&data).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Encoder: vendor".to_string()));
+        assert!(removed.contains(&"Vorbis Comment (ARTIST): Jane!".to_string()));
+
+        // STREAMINFO is now the last block and the audio frames follow it
+        let mut expected = b"fLaC".to_vec();
+        expected.extend_from_slice(&[0x80, 0, 0, 34]);
+        expected.extend_from_slice(&streaminfo);
+        expected.extend_from_slice(&[0xFF, 0xF8, 1, 2]);
+        assert_eq!(cleaned, expected);
+    }
+
+    #[test]
+    fn test_strip_wav_tags() {
+        let fmt = [1u8; 16];
+        let mut info = b"INFO".to_vec();
+        info.extend_from_slice(b"IART");
+        info.extend_from_slice(&5u32.to_le_bytes());
+        info.extend_from_slice(b"Jane\0\0");
+
+        let mut body = b"WAVE".to_vec();
+        body.extend_from_slice(b"fmt ");
+        body.extend_from_slice(&16u32.to_le_bytes());
+        body.extend_from_slice(&fmt);
+        body.extend_from_slice(b"LIST");
+        body.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        body.extend(info);
+        body.extend_from_slice(b"data");
+        body.extend_from_slice(&4u32.to_le_bytes());
+        body.extend_from_slice(&[9, 9, 9, 9]);
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend(body);
+
+        let (cleaned, removed) = strip_wav_tags(&data).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert_eq!(removed, vec!["Artist: Jane".to_string()]);
+        assert!(!cleaned.windows(4).any(|w| w == b"LIST"));
+        assert_eq!(u32::from_le_bytes([cleaned[4], cleaned[5], cleaned[6], cleaned[7]]) as usize, cleaned.len() - 8);
+    }
+}
@@ -0,0 +1,213 @@
+// The `strip --report` file: a record of every file processed, what it contained,
+// what was removed and how its size changed, kept as JSON or a standalone HTML page
+// for audit trails.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+use metastripper::{MetadataItem, Report};
+
+use crate::{item_json, FileInfo};
+
+/// One processed file, as it goes into the report
+pub struct AuditEntry<'a> {
+    pub file: &'a FileInfo,
+    pub output_path: &'a Path,
+    /// The metadata found before cleaning
+    pub found: &'a [MetadataItem],
+    pub size_before: Option<u64>,
+    pub result: &'a Result<Report>,
+}
+
+impl AuditEntry<'_> {
+    fn size_after(&self) -> Option<u64> {
+        self.result.as_ref().ok().and_then(|_| fs::metadata(self.output_path).ok()).map(|m| m.len())
+    }
+
+    fn to_json(&self) -> Value {
+        let (status, removed, error) = match self.result {
+            Ok(report) => ("ok", report.metadata.iter().map(item_json).collect(), Value::Null),
+            Err(e) => ("failed", Vec::new(), json!(format!("{:#}", e))),
+        };
+        json!({
+            // JSON can't hold paths that aren't valid UTF-8 as they are
+            "path": self.file.path.to_string_lossy(),
+            "output": self.output_path.to_string_lossy(),
+            "type": self.file.file_type.name(),
+            "status": status,
+            "size_before": self.size_before,
+            "size_after": self.size_after(),
+            "bytes_saved": self.result.as_ref().ok().and_then(|report| report.bytes_saved),
+            "found": self.found.iter().map(item_json).collect::<Vec<_>>(),
+            "removed": removed,
+            "error": error,
+        })
+    }
+}
+
+/// Writes the report as HTML if `path` ends in .html or .htm, and as JSON otherwise
+pub fn write_report(path: &Path, entries: &[AuditEntry]) -> Result<()> {
+    let is_html = matches!(
+        path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref(),
+        Some("html" | "htm")
+    );
+    let files: Vec<Value> = entries.iter().map(AuditEntry::to_json).collect();
+    let cleaned = entries.iter().filter(|entry| entry.result.is_ok());
+    let report = json!({
+        "generated": chrono::Utc::now().to_rfc3339(),
+        "summary": {
+            "files": entries.len(),
+            "failed": entries.iter().filter(|entry| entry.result.is_err()).count(),
+            "items_removed": entries.iter().filter_map(|entry| entry.result.as_ref().ok()).map(|r| r.metadata.len()).sum::<usize>(),
+            "size_before": cleaned.filter_map(|entry| entry.size_before).sum::<u64>(),
+            "bytes_saved": entries.iter().filter_map(|entry| entry.result.as_ref().ok()?.bytes_saved).sum::<i64>(),
+        },
+        "files": files,
+    });
+
+    let contents = if is_html { html_report(&report) } else { serde_json::to_string_pretty(&report)? };
+    fs::write(path, contents).with_context(|| format!("Failed to write report: {}", path.display()))
+}
+
+fn html_report(report: &Value) -> String {
+    let summary = &report["summary"];
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>MetaStripper report</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; width: 100%; }\n\
+         th, td { border: 1px solid #ccc; padding: 0.4em; text-align: left; vertical-align: top; }\n\
+         .failed { background: #fdd; }\n\
+         ul { margin: 0; padding-left: 1.2em; }\n\
+         </style>\n</head>\n<body>\n<h1>MetaStripper report</h1>\n",
+    );
+    html.push_str(&format!(
+        "<p>Generated {}. {} files, {} failed, {} metadata items removed, {} bytes saved.</p>\n",
+        escape(report["generated"].as_str().unwrap_or_default()),
+        summary["files"],
+        summary["failed"],
+        summary["items_removed"],
+        summary["bytes_saved"],
+    ));
+    html.push_str("<table>\n<tr><th>File</th><th>Type</th><th>Size before</th><th>Size after</th><th>Found</th><th>Removed</th></tr>\n");
+
+    for file in report["files"].as_array().into_iter().flatten() {
+        let failed = file["status"] == "failed";
+        let removed = if failed {
+            format!("Failed: {}", escape(file["error"].as_str().unwrap_or_default()))
+        } else {
+            item_list(&file["removed"])
+        };
+        html.push_str(&format!(
+            "<tr{}><td>{}<br><small>{}</small></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            if failed { " class=\"failed\"" } else { "" },
+            escape(file["path"].as_str().unwrap_or_default()),
+            escape(file["output"].as_str().unwrap_or_default()),
+            escape(file["type"].as_str().unwrap_or_default()),
+            file["size_before"],
+            file["size_after"],
+            item_list(&file["found"]),
+            removed,
+        ));
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn item_list(items: &Value) -> String {
+    let items: Vec<String> = items
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|item| {
+            let text = match item["value"].as_str() {
+                Some("") | None => item["tag"].as_str().unwrap_or_default().to_string(),
+                Some(value) => format!("{}: {}", item["tag"].as_str().unwrap_or_default(), value),
+            };
+            format!("<li>{}</li>", escape(&text))
+        })
+        .collect();
+    if items.is_empty() {
+        String::new()
+    } else {
+        format!("<ul>{}</ul>", items.concat())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metastripper::FileType;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_report() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("clean.jpg");
+        fs::write(&output, b"cleaned").unwrap();
+        let path = PathBuf::from("a&b.jpg");
+        let file = FileInfo { path: path.clone(), file_type: FileType::Image, relative: path };
+        let found = vec![MetadataItem::new("Author", "<Jane>")];
+        let cleaned = Ok(Report {
+            path: file.path.clone(),
+            file_type: FileType::Image,
+            metadata: found.clone(),
+            properties: Vec::new(),
+            bytes_saved: Some(13),
+        });
+        let failed = Err(anyhow::anyhow!("Failed to decode image"));
+        let entries = [
+            AuditEntry { file: &file, output_path: &output, found: &found, size_before: Some(20), result: &cleaned },
+            AuditEntry { file: &file, output_path: &output, found: &[], size_before: Some(20), result: &failed },
+        ];
+
+        let json_path = dir.path().join("report.json");
+        write_report(&json_path, &entries).unwrap();
+        let report: Value = serde_json::from_slice(&fs::read(&json_path).unwrap()).unwrap();
+        assert_eq!(report["summary"]["files"], 2);
+        assert_eq!(report["summary"]["failed"], 1);
+        assert_eq!(report["summary"]["items_removed"], 1);
+        assert_eq!(report["summary"]["bytes_saved"], 13);
+        assert_eq!(report["files"][0]["path"], "a&b.jpg");
+        assert_eq!(report["files"][0]["size_after"], 7);
+        assert_eq!(report["files"][0]["removed"][0]["value"], "<Jane>");
+        assert_eq!(report["files"][1]["status"], "failed");
+        assert_eq!(report["files"][1]["size_after"], Value::Null);
+
+        let html_path = dir.path().join("report.HTML");
+        write_report(&html_path, &entries).unwrap();
+        let html = fs::read_to_string(&html_path).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<td>a&amp;b.jpg<br>"));
+        assert!(html.contains("<li>Author: &lt;Jane&gt;</li>"));
+        assert!(html.contains("<tr class=\"failed\">"));
+        assert!(html.contains("Failed: Failed to decode image"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_report_non_unicode_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(OsStr::from_bytes(b"caf\xe9.svg"));
+        let file = FileInfo { path: path.clone(), file_type: FileType::Image, relative: path.clone() };
+        let failed = Err(anyhow::anyhow!("Failed to parse SVG"));
+        let entries = [AuditEntry { file: &file, output_path: &path, found: &[], size_before: None, result: &failed }];
+
+        let json_path = dir.path().join("report.json");
+        write_report(&json_path, &entries).unwrap();
+        let report: Value = serde_json::from_slice(&fs::read(&json_path).unwrap()).unwrap();
+        assert!(report["files"][0]["path"].as_str().unwrap().ends_with("caf\u{fffd}.svg"));
+        let html_path = dir.path().join("report.html");
+        write_report(&html_path, &entries).unwrap();
+        assert!(fs::read_to_string(&html_path).unwrap().contains("caf\u{fffd}.svg"));
+    }
+}
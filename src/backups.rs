@@ -0,0 +1,132 @@
+// Records the backups `strip --backup` makes, with hashes of each backup and of the
+// cleaned file, so `restore` can tell a backup that has been tampered with or a
+// cleaned file edited since from the state it left them in.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::backup_path;
+use crate::cache::{decode_path, key, sha256_file};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    backup_path: String,
+    backup_sha256: String,
+    output_path: String,
+    output_sha256: String,
+}
+
+/// Backups made by earlier runs, keyed by the path of the original
+#[derive(Debug, Default)]
+pub struct BackupLedger {
+    path: PathBuf,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl BackupLedger {
+    /// ~/.local/share/metastripper/backups.json, or the platform's equivalent
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("metastripper").join("backups.json"))
+    }
+
+    /// Reads the ledger at `path`, or starts an empty one if there is none yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid backup ledger: {}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read backup ledger: {}", path.display())),
+        };
+        Ok(Self { path: path.to_path_buf(), entries })
+    }
+
+    /// Records that `original` has just been backed up and cleaned into `output`
+    pub fn record(&mut self, original: &Path, output: &Path) -> Result<()> {
+        let backup = backup_path(original);
+        let hash = |path: &Path| sha256_file(path).with_context(|| format!("Failed to hash {}", path.display()));
+        let entry = Entry {
+            backup_sha256: hash(&backup)?,
+            backup_path: key(&backup),
+            output_sha256: hash(output)?,
+            output_path: key(output),
+        };
+        self.entries.insert(key(original), entry);
+        Ok(())
+    }
+
+    /// Every original with a recorded backup
+    pub fn originals(&self) -> Vec<PathBuf> {
+        self.entries.keys().map(|original| decode_path(original)).collect()
+    }
+
+    /// Checks that the backup of `original` is still the file that was backed up, and,
+    /// if the file was cleaned in place, that it hasn't changed since, so restoring
+    /// doesn't throw away later edits. Returns false for backups the ledger doesn't know.
+    pub fn validate(&self, original: &Path) -> Result<bool> {
+        let Some(entry) = self.entries.get(&key(original)) else { return Ok(false) };
+
+        let backup = decode_path(&entry.backup_path);
+        if sha256_file(&backup).ok().as_ref() != Some(&entry.backup_sha256) {
+            anyhow::bail!("the backup {} no longer matches the original it was made from", backup.display());
+        }
+        if entry.output_path == key(original) && sha256_file(original).ok().as_ref() != Some(&entry.output_sha256) {
+            anyhow::bail!("the file has changed since it was cleaned");
+        }
+        Ok(true)
+    }
+
+    /// Forgets the backup of `original`, once it has been restored
+    pub fn remove(&mut self, original: &Path) {
+        self.entries.remove(&key(original));
+    }
+
+    /// Writes the ledger back to where it was loaded from
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create ledger directory: {}", parent.display()))?;
+        }
+        // Written to a temporary file first, so an interrupted run can't corrupt the ledger
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_vec(&self.entries)?)
+            .and_then(|_| fs::rename(&temp_path, &self.path))
+            .with_context(|| format!("Failed to write backup ledger: {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_validate() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("photo.jpg");
+        fs::write(backup_path(&original), b"original").unwrap();
+        fs::write(&original, b"cleaned").unwrap();
+
+        let ledger_path = dir.path().join("state").join("backups.json");
+        let mut ledger = BackupLedger::load(&ledger_path).unwrap();
+        assert!(!ledger.validate(&original).unwrap());
+        ledger.record(&original, &original).unwrap();
+        ledger.save().unwrap();
+
+        let mut ledger = BackupLedger::load(&ledger_path).unwrap();
+        assert_eq!(ledger.originals(), vec![fs::canonicalize(&original).unwrap()]);
+        assert!(ledger.validate(&original).unwrap());
+
+        fs::write(&original, b"edited").unwrap();
+        assert!(ledger.validate(&original).unwrap_err().to_string().contains("has changed since it was cleaned"));
+        fs::write(&original, b"cleaned").unwrap();
+        fs::write(backup_path(&original), b"tampered").unwrap();
+        assert!(ledger.validate(&original).is_err());
+
+        ledger.remove(&original);
+        assert!(ledger.originals().is_empty());
+    }
+}
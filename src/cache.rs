@@ -0,0 +1,227 @@
+// Remembers which files have already been cleaned, so re-running over a large photo
+// library only processes what is new or has changed since. A file counts as unchanged
+// when its size and modification time match, or failing that, its SHA-256 hash, and it
+// was cleaned with the same options.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    len: u64,
+    /// Nanoseconds since the Unix epoch, where the file system records it
+    modified: Option<u128>,
+    sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    input: Stamp,
+    output_path: String,
+    output: Stamp,
+    /// `MetaStripper::options_hash` of the run that cleaned it
+    #[serde(default)]
+    options: String,
+}
+
+/// Files cleaned by earlier runs, keyed by the input path
+#[derive(Debug, Default)]
+pub struct CleanCache {
+    path: PathBuf,
+    entries: BTreeMap<String, Entry>,
+    /// The options hash of this run
+    options: String,
+}
+
+impl CleanCache {
+    /// ~/.cache/metastripper/cleaned.json, or the platform's equivalent
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("metastripper").join("cleaned.json"))
+    }
+
+    /// Reads the cache at `path`, or starts an empty one if there is none yet, for a run
+    /// cleaning with the options that hash to `options`
+    pub fn load(path: &Path, options: &str) -> Result<Self> {
+        let entries = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Invalid cache file: {}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read cache file: {}", path.display())),
+        };
+        Ok(Self { path: path.to_path_buf(), entries, options: options.to_string() })
+    }
+
+    /// Whether `input` was cleaned into `output` before with this run's options, and
+    /// neither has changed since
+    pub fn is_clean(&self, input: &Path, output: &Path) -> bool {
+        match self.entries.get(&key(input)) {
+            Some(entry) => {
+                entry.options == self.options
+                    && entry.output_path == key(output)
+                    && matches(input, &entry.input)
+                    && matches(output, &entry.output)
+            }
+            None => false,
+        }
+    }
+
+    /// Records that `input` has just been cleaned into `output`
+    pub fn record(&mut self, input: &Path, output: &Path) {
+        let (Some(input_stamp), Some(output_stamp)) = (stamp(input), stamp(output)) else { return };
+        let entry =
+            Entry { input: input_stamp, output_path: key(output), output: output_stamp, options: self.options.clone() };
+        self.entries.insert(key(input), entry);
+    }
+
+    /// Writes the cache back to where it was loaded from
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create cache directory: {}", parent.display()))?;
+        }
+        // Written to a temporary file first, so an interrupted run can't corrupt the cache
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_vec(&self.entries)?)
+            .and_then(|_| fs::rename(&temp_path, &self.path))
+            .with_context(|| format!("Failed to write cache file: {}", self.path.display()))
+    }
+}
+
+/// Paths are stored absolute, so runs from different directories share entries
+pub(crate) fn key(path: &Path) -> String {
+    encode_path(&fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+// Paths are stored as JSON text, which a name that isn't valid Unicode can't be turned
+// into without losing which file it was, so such a path is written as a NUL, which no
+// path can hold, followed by its bytes in hex (on Windows, its UTF-16 units)
+const RAW_PATH: char = '\0';
+
+/// `path` as text that tells it apart from every other path, for `decode_path`
+pub(crate) fn encode_path(path: &Path) -> String {
+    if let Some(text) = path.to_str() {
+        return text.to_string();
+    }
+    let mut encoded = String::from(RAW_PATH);
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        encoded.extend(path.as_os_str().as_bytes().iter().map(|byte| format!("{:02x}", byte)));
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        encoded.extend(path.as_os_str().encode_wide().map(|unit| format!("{:04x}", unit)));
+    }
+    encoded
+}
+
+/// The path `encode_path` gave `text` for
+pub(crate) fn decode_path(text: &str) -> PathBuf {
+    let Some(hex) = text.strip_prefix(RAW_PATH) else { return PathBuf::from(text) };
+    let digits = |width: usize| (0..hex.len() / width).filter_map(move |i| hex.get(i * width..(i + 1) * width));
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let bytes: Vec<u8> = digits(2).filter_map(|byte| u8::from_str_radix(byte, 16).ok()).collect();
+        PathBuf::from(std::ffi::OsStr::from_bytes(&bytes))
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStringExt;
+        let units: Vec<u16> = digits(4).filter_map(|unit| u16::from_str_radix(unit, 16).ok()).collect();
+        PathBuf::from(std::ffi::OsString::from_wide(&units))
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp { len: metadata.len(), modified: modified_nanos(&metadata), sha256: sha256_file(path).ok()? })
+}
+
+/// Hashing is the slow part, so it's skipped when the modification time already matches
+fn matches(path: &Path, stamp: &Stamp) -> bool {
+    let Ok(metadata) = fs::metadata(path) else { return false };
+    if metadata.len() != stamp.len {
+        return false;
+    }
+    if stamp.modified.is_some() && modified_nanos(&metadata) == stamp.modified {
+        return true;
+    }
+    sha256_file(path).map(|hash| hash == stamp.sha256).unwrap_or(false)
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> Option<u128> {
+    let modified = metadata.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::FileTimes;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_is_clean() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("photo.jpg");
+        let output = dir.path().join("clean.jpg");
+        fs::write(&input, b"original").unwrap();
+        fs::write(&output, b"cleaned").unwrap();
+
+        let cache_path = dir.path().join("cache").join("cleaned.json");
+        let mut cache = CleanCache::load(&cache_path, "options").unwrap();
+        assert!(!cache.is_clean(&input, &output));
+        cache.record(&input, &output);
+        cache.save().unwrap();
+
+        let cache = CleanCache::load(&cache_path, "options").unwrap();
+        assert!(cache.is_clean(&input, &output));
+        assert!(!cache.is_clean(&input, &dir.path().join("elsewhere.jpg")));
+        // Cleaning with other options, such as keeping GPS, gives a different file
+        assert!(!CleanCache::load(&cache_path, "other options").unwrap().is_clean(&input, &output));
+
+        // A new modification time alone doesn't count as a change
+        let later = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options().write(true).open(&input).unwrap().set_times(FileTimes::new().set_modified(later)).unwrap();
+        assert!(cache.is_clean(&input, &output));
+
+        fs::write(&input, b"replaced").unwrap();
+        assert!(!cache.is_clean(&input, &output));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_encode_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(encode_path(Path::new("/photos/café.jpg")), "/photos/café.jpg");
+        let first = Path::new(OsStr::from_bytes(b"/photos/caf\xe9.jpg"));
+        let second = Path::new(OsStr::from_bytes(b"/photos/caf\xe8.jpg"));
+        assert_ne!(encode_path(first), encode_path(second));
+        assert_eq!(decode_path(&encode_path(first)), first);
+        assert_eq!(decode_path("/photos/café.jpg"), Path::new("/photos/café.jpg"));
+    }
+
+    #[test]
+    fn test_sha256_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("empty");
+        fs::write(&path, b"").unwrap();
+        assert_eq!(sha256_file(&path).unwrap(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+}
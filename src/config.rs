@@ -0,0 +1,322 @@
+// Defaults read from TOML config files. A user-wide file and a per-project
+// .metastripper.toml are layered, the project one winning, and command-line
+// options win over both.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::assign::{Assignment, DateRange};
+use crate::metadata::{Category, Sensitivity};
+
+// Profiles available without a config file. Config files can override them by name.
+const BUILTIN_PROFILES: &str = r#"
+# Files posted publicly: all metadata goes, and so does anything else PDFs can carry
+[social-media]
+pdf_remove_attachments = true
+pdf_remove_javascript = true
+
+# Records kept for the long term: who made them, when and on what terms stays,
+# while locations, devices and software go
+[archival]
+keep = ["author", "copyright", "timestamps", "content", "icc", "orientation"]
+"#;
+
+/// Name of the per-project config file, looked up in the current directory and its parents
+pub const PROJECT_CONFIG_FILE: &str = ".metastripper.toml";
+
+/// Settings from config files. Unset fields leave the command-line default in place.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where cleaned copies are written
+    pub output_dir: Option<PathBuf>,
+    /// Whether originals are backed up before they are overwritten
+    pub backup: Option<bool>,
+    /// Metadata categories to leave in place
+    #[serde(deserialize_with = "categories")]
+    pub keep: Option<Vec<Category>>,
+    /// Metadata categories to remove, keeping the rest
+    #[serde(deserialize_with = "categories")]
+    pub remove: Option<Vec<Category>>,
+    /// Glob patterns of files to leave out of directory scans
+    pub exclude: Vec<String>,
+    /// ffmpeg binary to use
+    pub ffmpeg_path: Option<PathBuf>,
+    /// ffprobe binary to use
+    pub ffprobe_path: Option<PathBuf>,
+    /// Seconds ffmpeg may spend on one file before it's stopped
+    pub ffmpeg_timeout: Option<u64>,
+    /// Named rule sets, from `[profile.<name>]` tables
+    #[serde(rename = "profile")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named set of rules, picked with `--profile` instead of a list of flags. Unset
+/// fields leave the config file and command-line defaults in place.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Metadata categories to leave in place
+    #[serde(deserialize_with = "categories")]
+    pub keep: Option<Vec<Category>>,
+    /// Metadata categories to remove, keeping the rest
+    #[serde(deserialize_with = "categories")]
+    pub remove: Option<Vec<Category>>,
+    /// Only metadata at least this sensitive is removed
+    #[serde(deserialize_with = "parsed")]
+    pub min_severity: Option<Sensitivity>,
+    /// Values written in place of removed metadata, from a table such as `{ author = "A. Nonymous" }`
+    #[serde(deserialize_with = "assignments")]
+    pub set: Vec<Assignment>,
+    /// Whether device, software and date fields get random values
+    pub anonymize: Option<bool>,
+    /// The span random dates are picked from
+    #[serde(deserialize_with = "parsed")]
+    pub anonymize_dates: Option<DateRange>,
+    /// Whether ICC color profiles are removed
+    pub strip_icc: Option<bool>,
+    /// Whether files embedded in PDFs are removed
+    pub pdf_remove_attachments: Option<bool>,
+    /// Whether JavaScript in PDFs is removed
+    pub pdf_remove_javascript: Option<bool>,
+}
+
+impl Config {
+    /// Loads the user config and the nearest project config above the current directory
+    pub fn load() -> Result<Self> {
+        let mut config = Config::default();
+        if let Some(path) = user_config_path().filter(|path| path.is_file()) {
+            config = config.merge(Config::from_file(&path)?);
+        }
+        let current_dir = std::env::current_dir().context("Failed to get the current directory")?;
+        if let Some(path) = find_project_config(&current_dir) {
+            config = config.merge(Config::from_file(&path)?);
+        }
+        Ok(config)
+    }
+
+    /// Reads one config file. Relative paths in it are taken relative to the file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let mut config: Config = toml::from_str(&text)
+            .with_context(|| format!("Invalid config file: {}", path.display()))?;
+
+        if config.keep.is_some() && config.remove.is_some() {
+            anyhow::bail!("Invalid config file: {}: keep and remove can't both be set", path.display());
+        }
+        if let Some((name, _)) = config.profiles.iter().find(|(_, profile)| profile.keep.is_some() && profile.remove.is_some()) {
+            anyhow::bail!("Invalid config file: {}: keep and remove can't both be set in profile {}", path.display(), name);
+        }
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        if let Some(output_dir) = &mut config.output_dir {
+            *output_dir = base.join(&*output_dir);
+        }
+        // Bare program names such as "ffmpeg" are still looked up on PATH
+        for program in [&mut config.ffmpeg_path, &mut config.ffprobe_path].into_iter().flatten() {
+            if program.components().count() > 1 {
+                *program = base.join(&*program);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Layers `other` on top of this config: its settings win, and exclude patterns add up
+    pub fn merge(mut self, other: Config) -> Self {
+        self.output_dir = other.output_dir.or(self.output_dir);
+        self.backup = other.backup.or(self.backup);
+        // keep and remove are alternatives, so setting either replaces both
+        if other.keep.is_some() || other.remove.is_some() {
+            self.keep = other.keep;
+            self.remove = other.remove;
+        }
+        self.exclude.extend(other.exclude);
+        self.ffmpeg_path = other.ffmpeg_path.or(self.ffmpeg_path);
+        self.ffprobe_path = other.ffprobe_path.or(self.ffprobe_path);
+        self.ffmpeg_timeout = other.ffmpeg_timeout.or(self.ffmpeg_timeout);
+        // A profile is replaced as a whole, so it never mixes rules from two files
+        self.profiles.extend(other.profiles);
+        self
+    }
+
+    /// The profile called `name`, from the config files or built in
+    pub fn profile(&self, name: &str) -> Result<Profile> {
+        if let Some(profile) = self.profiles.get(name) {
+            return Ok(profile.clone());
+        }
+        let builtin: BTreeMap<String, Profile> = toml::from_str(BUILTIN_PROFILES).expect("built-in profiles are valid");
+        if let Some(profile) = builtin.get(name) {
+            return Ok(profile.clone());
+        }
+
+        let mut names: Vec<&String> = builtin.keys().chain(self.profiles.keys()).collect();
+        names.sort();
+        names.dedup();
+        let names: Vec<&str> = names.into_iter().map(String::as_str).collect();
+        anyhow::bail!("Unknown profile '{}' (available: {})", name, names.join(", "))
+    }
+}
+
+/// ~/.config/metastripper/config.toml, or under $XDG_CONFIG_HOME if that is set
+fn user_config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_dir.join("metastripper").join("config.toml"))
+}
+
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+fn categories<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Category>>, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names
+        .iter()
+        .map(|name| name.parse().map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+fn assignments<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Assignment>, D::Error> {
+    let values = BTreeMap::<String, String>::deserialize(deserializer)?;
+    values
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value).parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &path,
+            r#"
+output_dir = "cleaned"
+backup = true
+keep = ["orientation", "icc"]
+exclude = ["node_modules/**"]
+ffmpeg_path = "ffmpeg"
+ffprobe_path = "bin/ffprobe"
+ffmpeg_timeout = 300
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.output_dir, Some(dir.path().join("cleaned")));
+        assert_eq!(config.backup, Some(true));
+        assert_eq!(config.keep, Some(vec![Category::Orientation, Category::ColorProfile]));
+        assert_eq!(config.remove, None);
+        assert_eq!(config.exclude, vec!["node_modules/**"]);
+        assert_eq!(config.ffmpeg_path, Some(PathBuf::from("ffmpeg")));
+        assert_eq!(config.ffprobe_path, Some(dir.path().join("bin/ffprobe")));
+        assert_eq!(config.ffmpeg_timeout, Some(300));
+
+        fs::write(&path, "keep = [\"gps\"]\nremove = [\"author\"]\n").unwrap();
+        assert!(Config::from_file(&path).is_err());
+        fs::write(&path, "keep = [\"location\"]\n").unwrap();
+        assert!(Config::from_file(&path).is_err());
+        fs::write(&path, "output = \"cleaned\"\n").unwrap();
+        assert!(Config::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let user = Config {
+            backup: Some(true),
+            keep: Some(vec![Category::Copyright]),
+            exclude: vec!["*.tmp".to_string()],
+            ffmpeg_path: Some(PathBuf::from("/opt/ffmpeg")),
+            ..Config::default()
+        };
+        let project = Config {
+            backup: Some(false),
+            remove: Some(vec![Category::Gps]),
+            exclude: vec!["build/**".to_string()],
+            ..Config::default()
+        };
+
+        let config = user.merge(project);
+        assert_eq!(config.backup, Some(false));
+        assert_eq!(config.keep, None);
+        assert_eq!(config.remove, Some(vec![Category::Gps]));
+        assert_eq!(config.exclude, vec!["*.tmp", "build/**"]);
+        assert_eq!(config.ffmpeg_path, Some(PathBuf::from("/opt/ffmpeg")));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &path,
+            r#"
+[profile.journalism]
+remove = ["gps", "device", "author"]
+min_severity = "medium"
+set = { author = "Staff" }
+anonymize = true
+anonymize_dates = "2020-01-01..2020-12-31"
+
+[profile.archival]
+keep = ["copyright"]
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        let journalism = config.profile("journalism").unwrap();
+        assert_eq!(journalism.remove, Some(vec![Category::Gps, Category::Device, Category::Author]));
+        assert_eq!(journalism.min_severity, Some(Sensitivity::Medium));
+        assert_eq!(journalism.set, vec!["author=Staff".parse().unwrap()]);
+        assert_eq!(journalism.anonymize, Some(true));
+        assert_eq!(journalism.anonymize_dates, Some("2020-01-01..2020-12-31".parse().unwrap()));
+
+        // Config files override built-in profiles, and the others stay available
+        assert_eq!(config.profile("archival").unwrap().keep, Some(vec![Category::Copyright]));
+        assert_eq!(config.profile("social-media").unwrap().pdf_remove_javascript, Some(true));
+        let error = config.profile("press").unwrap_err().to_string();
+        assert_eq!(error, "Unknown profile 'press' (available: archival, journalism, social-media)");
+
+        fs::write(&path, "[profile.bad]\nset = { owner = \"me\" }\n").unwrap();
+        assert!(Config::from_file(&path).is_err());
+        fs::write(&path, "[profile.bad]\nkeep = [\"gps\"]\nremove = [\"author\"]\n").unwrap();
+        assert!(Config::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let dir = tempdir().unwrap();
+        let nested = dir.path().join("photos").join("2024");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.path().join(PROJECT_CONFIG_FILE), "").unwrap();
+
+        assert_eq!(find_project_config(&nested), Some(dir.path().join(PROJECT_CONFIG_FILE)));
+    }
+}
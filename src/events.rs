@@ -0,0 +1,169 @@
+// The `strip --progress json` events, one JSON object per line, for programs that wrap
+// metastripper and draw their own progress: `started` with the size of the batch, then
+// `file_done` or `file_failed` as each file finishes, in the order they finish, and
+// `finished` with the totals.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use metastripper::Progress;
+
+pub struct Events {
+    out: Mutex<Box<dyn Write + Send>>,
+    started: Instant,
+}
+
+impl Events {
+    /// Sends events to the open file descriptor `fd`, or standard output without one
+    pub fn open(fd: Option<i32>) -> Result<Self> {
+        let out: Box<dyn Write + Send> = match fd {
+            None => Box::new(std::io::stdout()),
+            Some(fd) => Box::new(open_fd(fd)?),
+        };
+        Ok(Self::to(out))
+    }
+
+    fn to(out: Box<dyn Write + Send>) -> Self {
+        Self { out: Mutex::new(out), started: Instant::now() }
+    }
+
+    pub fn started(&self, files: usize, bytes: u64) {
+        self.send(json!({ "event": "started", "total_files": files, "total_bytes": bytes }));
+    }
+
+    pub fn file(&self, progress: &Progress) {
+        let mut event = json!({
+            // JSON can't hold paths that aren't valid UTF-8 as they are
+            "path": progress.path.to_string_lossy(),
+            "completed": progress.completed,
+            "total": progress.total,
+            "bytes": progress.bytes,
+        });
+        match progress.result {
+            Ok(report) => {
+                event["event"] = json!("file_done");
+                event["removed"] = json!(report.metadata.len());
+                event["bytes_saved"] = json!(report.bytes_saved);
+            }
+            Err(e) => {
+                event["event"] = json!("file_failed");
+                event["error"] = json!(format!("{:#}", e));
+            }
+        }
+        self.send(event);
+    }
+
+    pub fn finished(&self, processed: usize, failed: usize, removed: usize, bytes_saved: i64) {
+        self.send(json!({
+            "event": "finished",
+            "files_processed": processed,
+            "files_failed": failed,
+            "metadata_items_removed": removed,
+            "bytes_saved": bytes_saved,
+            "elapsed_seconds": self.started.elapsed().as_secs_f64(),
+        }));
+    }
+
+    /// Writes one event line. A reader that went away doesn't stop the batch, so write
+    /// errors are ignored.
+    fn send(&self, event: Value) {
+        let mut out = self.out.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let _ = writeln!(out, "{}", event).and_then(|_| out.flush());
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<std::fs::File> {
+    use std::os::fd::FromRawFd;
+    // Taking ownership of a descriptor that isn't open would close some other file later
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        anyhow::bail!("--progress-fd {} is not an open file descriptor", fd);
+    }
+    // SAFETY: the descriptor is open, and was handed to this process for the events
+    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> Result<std::fs::File> {
+    anyhow::bail!("--progress-fd is only available on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// Collects what's written, so it can be read back while `Events` still holds it
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let shared = Shared::default();
+        let events = Events::to(Box::new(shared.clone()));
+        events.started(2, 300);
+        let report = Ok(metastripper::Report {
+            path: "photo.jpg".into(),
+            file_type: metastripper::FileType::Image,
+            metadata: vec![metastripper::MetadataItem::new("Author", "Jane")],
+            properties: Vec::new(),
+            bytes_saved: Some(40),
+        });
+        let error = Err(anyhow::anyhow!("Failed to decode image"));
+        let progress = |path: &'static str, completed, result| Progress {
+            path: Path::new(path),
+            completed,
+            total: 2,
+            bytes: 100,
+            failed: completed == 2,
+            result,
+        };
+        events.file(&progress("photo.jpg", 1, &report));
+        events.file(&progress("broken.jpg", 2, &error));
+        events.finished(1, 1, 1, 40);
+
+        let data = shared.0.lock().unwrap().clone();
+        let lines: Vec<Value> =
+            String::from_utf8(data).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], json!({ "event": "started", "total_files": 2, "total_bytes": 300 }));
+        assert_eq!(lines[1]["event"], "file_done");
+        assert_eq!(lines[1]["path"], "photo.jpg");
+        assert_eq!(lines[1]["removed"], 1);
+        assert_eq!(lines[1]["bytes_saved"], 40);
+        assert_eq!(lines[2]["event"], "file_failed");
+        assert_eq!(lines[2]["error"], "Failed to decode image");
+        assert_eq!(lines[3]["event"], "finished");
+        assert_eq!(lines[3]["files_failed"], 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_unicode_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let shared = Shared::default();
+        let events = Events::to(Box::new(shared.clone()));
+        let error = Err(anyhow::anyhow!("Failed to parse SVG"));
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9.svg"));
+        events.file(&Progress { path, completed: 1, total: 1, bytes: 0, failed: true, result: &error });
+        let data = shared.0.lock().unwrap().clone();
+        let event: Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(event["path"], "caf\u{fffd}.svg");
+    }
+}
@@ -0,0 +1,85 @@
+// Include and exclude glob patterns for directory scans. Like .gitignore, a
+// pattern without a slash matches a file or directory name at any depth, while
+// one with a slash matches the path below the scanned directory.
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+// Windows file names are case-insensitive, so `*.jpg` matches IMG_0001.JPG there too
+const CASE_INSENSITIVE: bool = cfg!(windows);
+
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    /// Builds a filter; with no include patterns, every file not excluded is included
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let include = if include.is_empty() { None } else { Some(build_globs(include)?) };
+        Ok(Self { include, exclude: build_globs(exclude)? })
+    }
+
+    /// Whether a directory should be skipped along with everything in it
+    pub fn excludes_dir(&self, path: &Path) -> bool {
+        self.exclude.is_match(path)
+    }
+
+    /// Whether a file should be processed
+    pub fn includes_file(&self, path: &Path) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        };
+        included && !self.exclude.is_match(path)
+    }
+}
+
+fn build_globs(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = match pattern.strip_prefix('/') {
+            Some(pattern) => pattern.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{}", pattern),
+        };
+        let glob = GlobBuilder::new(&anchored)
+            .case_insensitive(CASE_INSENSITIVE)
+            .build()
+            .with_context(|| format!("Invalid glob pattern: {}", pattern))?;
+        builder.add(glob);
+    }
+    builder.build().context("Failed to build glob patterns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(&patterns(&["*.jpg", "*.png"]), &patterns(&["node_modules", "drafts/**"])).unwrap();
+
+        assert!(filter.includes_file(Path::new("a.jpg")));
+        assert!(filter.includes_file(Path::new("photos/2024/a.png")));
+        assert!(!filter.includes_file(Path::new("notes.pdf")));
+        assert!(!filter.includes_file(Path::new("drafts/a.jpg")));
+        assert!(filter.excludes_dir(Path::new("node_modules")));
+        assert!(filter.excludes_dir(Path::new("web/node_modules")));
+        assert!(!filter.excludes_dir(Path::new("photos")));
+
+        let anchored = PathFilter::new(&[], &patterns(&["/build"])).unwrap();
+        assert!(anchored.excludes_dir(Path::new("build")));
+        assert!(!anchored.excludes_dir(Path::new("src/build")));
+        assert!(anchored.includes_file(Path::new("notes.pdf")));
+
+        assert!(PathFilter::new(&patterns(&["[a-"]), &[]).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::image::extract_exif_metadata;
+use crate::metadata::MetadataItem;
+use crate::xmp::extract_xmp_fields;
+
+// HEIF (and its HEIC/AVIF flavours) stores metadata as items in the ISO-BMFF
+// `meta` box. Rather than rewriting the item tables and shifting every offset,
+// the payload bytes of the Exif and XMP items are blanked in place so the
+// coded image data is left untouched. The items themselves stay listed, so a payload
+// that's already all zeros counts as removed, and cleaning the output again or
+// verifying it finds nothing.
+
+pub fn strip_heif_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<MetadataItem>> {
+    let mut removed_metadata = extract_exif_metadata(input_path).unwrap_or_default();
+
+    let mut data = fs::read(input_path)
+        .with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+    let item_metadata = blank_metadata_items(&mut data)
+        .with_context(|| format!("Failed to parse HEIF container: {}", input_path.display()))?;
+    removed_metadata.extend(item_metadata);
+
+    fs::write(output_path, data)
+        .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+
+    Ok(removed_metadata)
+}
+
+#[derive(Debug)]
+pub(crate) struct BoxHeader {
+    pub(crate) box_type: [u8; 4],
+    // Offsets of the payload (after the header) and the end of the box
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+#[derive(Debug)]
+struct ItemInfo {
+    id: u32,
+    item_type: [u8; 4],
+    content_type: String,
+}
+
+#[derive(Debug)]
+struct ItemLocation {
+    id: u32,
+    construction_method: u8,
+    // Absolute (offset, length) pairs, resolved against the file or idat box
+    extents: Vec<(u64, u64)>,
+}
+
+fn blank_metadata_items(data: &mut [u8]) -> Result<Vec<MetadataItem>> {
+    let boxes = read_boxes(data, 0, data.len())?;
+    if !boxes.iter().any(|b| &b.box_type == b"ftyp") {
+        return Err(anyhow::anyhow!("Missing ftyp box"));
+    }
+
+    let meta = match boxes.iter().find(|b| &b.box_type == b"meta") {
+        Some(meta) => meta,
+        None => return Ok(Vec::new()),
+    };
+
+    // meta is a full box: skip version and flags
+    let children = read_boxes(data, meta.start + 4, meta.end)?;
+    let items = match children.iter().find(|b| &b.box_type == b"iinf") {
+        Some(iinf) => parse_iinf(data, iinf)?,
+        None => return Ok(Vec::new()),
+    };
+    let locations = match children.iter().find(|b| &b.box_type == b"iloc") {
+        Some(iloc) => parse_iloc(data, iloc)?,
+        None => return Ok(Vec::new()),
+    };
+    let idat_start = children
+        .iter()
+        .find(|b| &b.box_type == b"idat")
+        .map(|b| b.start as u64);
+
+    let mut metadata = Vec::new();
+
+    for item in &items {
+        let is_exif = &item.item_type == b"Exif";
+        let is_xmp = &item.item_type == b"mime" && item.content_type == "application/rdf+xml";
+        if !is_exif && !is_xmp {
+            continue;
+        }
+
+        let location = match locations.iter().find(|l| l.id == item.id) {
+            Some(location) => location,
+            None => continue,
+        };
+
+        let base = match location.construction_method {
+            0 => 0,
+            1 => match idat_start {
+                Some(start) => start,
+                None => continue,
+            },
+            // Items constructed from other items don't own any bytes
+            _ => continue,
+        };
+
+        let mut payload = Vec::new();
+        for &(offset, length) in &location.extents {
+            let start = base.checked_add(offset);
+            let end = start.and_then(|start| start.checked_add(length)).filter(|&end| end <= data.len() as u64);
+            let (Some(start), Some(end)) = (start, end) else {
+                return Err(anyhow::anyhow!("Item {} extends past the end of the file", item.id));
+            };
+            let (start, end) = (start as usize, end as usize);
+            payload.extend_from_slice(&data[start..end]);
+            data[start..end].fill(0);
+        }
+
+        if payload.iter().all(|&b| b == 0) {
+            continue;
+        }
+        if is_exif {
+            metadata.push(MetadataItem::new("HEIF Exif Item", format!("{} bytes", payload.len())));
+        } else {
+            metadata.extend(extract_xmp_fields(&String::from_utf8_lossy(&payload)));
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// The boxes between `pos` and `end`, which must follow each other without gaps
+pub(crate) fn read_boxes(data: &[u8], mut pos: usize, end: usize) -> Result<Vec<BoxHeader>> {
+    let mut boxes = Vec::new();
+
+    while pos + 8 <= end {
+        let size = read_uint(data, pos, 4)?;
+        let mut box_type = [0u8; 4];
+        box_type.copy_from_slice(&data[pos + 4..pos + 8]);
+
+        let (start, size) = match size {
+            // Box extends to the end of its parent
+            0 => (pos + 8, (end - pos) as u64),
+            // 64-bit size follows the type
+            1 => (pos + 16, read_uint(data, pos + 8, 8)?),
+            size => (pos + 8, size),
+        };
+        // A 64-bit size can be anything, so it mustn't wrap around
+        let box_end = usize::try_from(size).ok().and_then(|size| pos.checked_add(size));
+        let Some(box_end) = box_end.filter(|&box_end| box_end <= end && box_end >= start) else {
+            return Err(anyhow::anyhow!("Invalid box size at offset {}", pos));
+        };
+
+        boxes.push(BoxHeader { box_type, start, end: box_end });
+        pos = box_end;
+    }
+
+    Ok(boxes)
+}
+
+fn parse_iinf(data: &[u8], iinf: &BoxHeader) -> Result<Vec<ItemInfo>> {
+    let version = read_uint(data, iinf.start, 1)? as u8;
+    let entries_start = iinf.start + 4 + if version == 0 { 2 } else { 4 };
+
+    let mut items = Vec::new();
+    for infe in read_boxes(data, entries_start, iinf.end)? {
+        if &infe.box_type != b"infe" {
+            continue;
+        }
+
+        // Only version 2 and later entries carry an item type
+        let version = read_uint(data, infe.start, 1)? as u8;
+        if version < 2 {
+            continue;
+        }
+
+        let mut pos = infe.start + 4;
+        let id_size = if version == 2 { 2 } else { 4 };
+        let id = read_uint(data, pos, id_size)? as u32;
+        // Skip the item ID and protection index
+        pos += id_size + 2;
+
+        let mut item_type = [0u8; 4];
+        item_type.copy_from_slice(data.get(pos..pos + 4).context("Truncated infe box")?);
+        pos += 4;
+
+        let (_name, next) = read_cstring(data, pos, infe.end);
+        let content_type = if &item_type == b"mime" {
+            read_cstring(data, next, infe.end).0
+        } else {
+            String::new()
+        };
+
+        items.push(ItemInfo { id, item_type, content_type });
+    }
+
+    Ok(items)
+}
+
+fn parse_iloc(data: &[u8], iloc: &BoxHeader) -> Result<Vec<ItemLocation>> {
+    let version = read_uint(data, iloc.start, 1)? as u8;
+    let mut pos = iloc.start + 4;
+
+    let sizes = read_uint(data, pos, 2)?;
+    let offset_size = ((sizes >> 12) & 0xF) as usize;
+    let length_size = ((sizes >> 8) & 0xF) as usize;
+    let base_offset_size = ((sizes >> 4) & 0xF) as usize;
+    let index_size = if version == 1 || version == 2 { (sizes & 0xF) as usize } else { 0 };
+    pos += 2;
+
+    let count_size = if version < 2 { 2 } else { 4 };
+    let item_count = read_uint(data, pos, count_size)?;
+    pos += count_size;
+
+    let mut locations = Vec::new();
+    for _ in 0..item_count {
+        let id_size = if version < 2 { 2 } else { 4 };
+        let id = read_uint(data, pos, id_size)? as u32;
+        pos += id_size;
+
+        let mut construction_method = 0;
+        if version == 1 || version == 2 {
+            construction_method = (read_uint(data, pos, 2)? & 0xF) as u8;
+            pos += 2;
+        }
+
+        // Data reference index
+        pos += 2;
+        let base_offset = read_uint(data, pos, base_offset_size)?;
+        pos += base_offset_size;
+
+        let extent_count = read_uint(data, pos, 2)?;
+        pos += 2;
+
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            pos += index_size;
+            let offset = read_uint(data, pos, offset_size)?;
+            pos += offset_size;
+            let length = read_uint(data, pos, length_size)?;
+            pos += length_size;
+            let offset = base_offset.checked_add(offset).context("Invalid iloc extent offset")?;
+            extents.push((offset, length));
+        }
+
+        locations.push(ItemLocation { id, construction_method, extents });
+    }
+
+    Ok(locations)
+}
+
+fn read_uint(data: &[u8], pos: usize, size: usize) -> Result<u64> {
+    let bytes = data.get(pos..pos + size).context("Unexpected end of HEIF data")?;
+    Ok(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+}
+
+fn read_cstring(data: &[u8], pos: usize, end: usize) -> (String, usize) {
+    let slice = data.get(pos..end).unwrap_or(&[]);
+    match slice.iter().position(|&b| b == 0) {
+        Some(nul) => (String::from_utf8_lossy(&slice[..nul]).to_string(), pos + nul + 1),
+        None => (String::from_utf8_lossy(slice).to_string(), end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    fn make_box(box_type: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut b = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(box_type);
+        b.extend_from_slice(payload);
+        b
+    }
+
+    fn make_infe(id: u16, item_type: &[u8], content_type: &str) -> Vec<u8> {
+        let mut payload = vec![2, 0, 0, 0];
+        payload.extend_from_slice(&id.to_be_bytes());
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(item_type);
+        payload.push(0);
+        if !content_type.is_empty() {
+            payload.extend_from_slice(content_type.as_bytes());
+            payload.push(0);
+        }
+        make_box(b"infe", &payload)
+    }
+
+    /// Builds a minimal HEIF-style file with an Exif item and an XMP item stored in mdat
+    fn build_test_heif(brand: &[u8], exif: &[u8], xmp: &[u8]) -> Vec<u8> {
+        let mut ftyp = brand.to_vec();
+        ftyp.extend_from_slice(&[0, 0, 0, 0]);
+        ftyp.extend_from_slice(b"mif1");
+        ftyp.extend_from_slice(brand);
+        let ftyp = make_box(b"ftyp", &ftyp);
+
+        let mut iinf = vec![0, 0, 0, 0, 0, 2];
+        iinf.extend(make_infe(1, b"Exif", ""));
+        iinf.extend(make_infe(2, b"mime", "application/rdf+xml"));
+        let iinf = make_box(b"iinf", &iinf);
+
+        // The iloc box has a fixed size, so the mdat offset can be computed up front
+        let iloc_len = 8 + 4 + 2 + 2 + 2 * (2 + 2 + 2 + 4 + 4);
+        let meta_len = 8 + 4 + iinf.len() + iloc_len;
+        let mdat_payload_start = (ftyp.len() + meta_len + 8) as u32;
+
+        let mut iloc = vec![0, 0, 0, 0, 0x44, 0x00, 0, 2];
+        for (id, offset, length) in [
+            (1u16, mdat_payload_start, exif.len() as u32),
+            (2u16, mdat_payload_start + exif.len() as u32, xmp.len() as u32),
+        ] {
+            iloc.extend_from_slice(&id.to_be_bytes());
+            iloc.extend_from_slice(&[0, 0, 0, 1]);
+            iloc.extend_from_slice(&offset.to_be_bytes());
+            iloc.extend_from_slice(&length.to_be_bytes());
+        }
+        let iloc = make_box(b"iloc", &iloc);
+        assert_eq!(iloc.len(), iloc_len);
+
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend(iinf);
+        meta.extend(iloc);
+        let meta = make_box(b"meta", &meta);
+
+        let mut mdat = exif.to_vec();
+        mdat.extend_from_slice(xmp);
+
+        let mut file = ftyp;
+        file.extend(meta);
+        file.extend(make_box(b"mdat", &mdat));
+        file
+    }
+
+    #[test]
+    fn test_strip_heif_metadata() {
+        let input = Builder::new().suffix(".heic").tempfile().unwrap();
+        let output = Builder::new().suffix(".heic").tempfile().unwrap();
+
+        let exif = b"\0\0\0\0MM\0\x2a\0\0\0\x08\0\0\0\0\0\0";
+        let xmp = br#"<x:xmpmeta xmp:CreatorTool="Test Camera"/>"#;
+        let original = build_test_heif(b"heic", exif, xmp);
+        fs::write(&input, &original).unwrap();
+
+        let removed = strip_heif_metadata(input.path(), output.path()).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&format!("HEIF Exif Item: {} bytes", exif.len())));
+        assert!(removed.contains(&"XMP Creator Tool: Test Camera".to_string()));
+
+        // Same layout, but the item payloads are blanked
+        let cleaned = fs::read(output.path()).unwrap();
+        assert_eq!(cleaned.len(), original.len());
+        let payload_start = original.len() - exif.len() - xmp.len();
+        assert!(cleaned[payload_start..].iter().all(|&b| b == 0));
+        assert_eq!(cleaned[..payload_start], original[..payload_start]);
+
+        // The blanked items are what verifying the cleaned file reads
+        let report = crate::MetaStripper::new().verify_file(output.path()).unwrap();
+        assert!(report.metadata.is_empty(), "{:?}", report.metadata);
+    }
+
+    #[test]
+    fn test_strip_avif_metadata() {
+        let input = Builder::new().suffix(".avif").tempfile().unwrap();
+        let output = Builder::new().suffix(".avif").tempfile().unwrap();
+
+        let exif = b"\0\0\0\0MM\0\x2a\0\0\0\x08\0\0\0\0\0\0";
+        let xmp = br#"<x:xmpmeta><dc:creator>Jane Doe</dc:creator></x:xmpmeta>"#;
+        fs::write(&input, build_test_heif(b"avif", exif, xmp)).unwrap();
+
+        let removed = crate::image::strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"XMP Creator: Jane Doe".to_string()));
+
+        let cleaned = fs::read(output.path()).unwrap();
+        assert!(!cleaned.windows(8).any(|w| w == b"Jane Doe"));
+        let report = crate::MetaStripper::new().verify_file(output.path()).unwrap();
+        assert!(report.metadata.is_empty(), "{:?}", report.metadata);
+    }
+
+    #[test]
+    fn test_strip_heif_metadata_rejects_other_files() {
+        let mut data = b"not an iso-bmff file".to_vec();
+        assert!(blank_metadata_items(&mut data).is_err());
+
+        // A 64-bit box size that would wrap around the end of memory
+        let mut data = make_box(b"ftyp", b"heic");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"meta");
+        data.extend_from_slice(&(u64::MAX - 8).to_be_bytes());
+        assert!(blank_metadata_items(&mut data).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::image::{strip_png_chunks, PNG_SIGNATURE};
+use crate::metadata::{Category, MetadataItem};
+
+// ICO and CUR files are a directory of images, each a bare bitmap or a complete PNG.
+// Bitmaps carry no metadata, but the PNGs keep their text and time chunks, so each is
+// cleaned like a standalone PNG and the directory rewritten with the new sizes.
+
+const HEADER_LEN: usize = 6;
+const ENTRY_LEN: usize = 16;
+
+pub fn strip_ico_metadata(input_path: &Path, output_path: &Path, keep: &[Category]) -> Result<Vec<MetadataItem>> {
+    let data = fs::read(input_path).with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+    let (cleaned, metadata) =
+        strip_icon_images(&data, keep).with_context(|| format!("Failed to parse icon: {}", input_path.display()))?;
+    fs::write(output_path, cleaned).with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    Ok(metadata)
+}
+
+fn strip_icon_images(data: &[u8], keep: &[Category]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    let count = match data.get(..HEADER_LEN) {
+        Some([0, 0, 1 | 2, 0, low, high]) => u16::from_le_bytes([*low, *high]) as usize,
+        _ => return Err(anyhow::anyhow!("Missing icon header")),
+    };
+    let directory = data.get(HEADER_LEN..HEADER_LEN + count * ENTRY_LEN).context("Truncated icon directory")?;
+
+    let mut images = Vec::with_capacity(count);
+    let mut metadata: Vec<MetadataItem> = Vec::new();
+    for entry in directory.chunks_exact(ENTRY_LEN) {
+        let size = u32::from_le_bytes(entry[8..12].try_into()?) as usize;
+        let offset = u32::from_le_bytes(entry[12..16].try_into()?) as usize;
+        let image = data.get(offset..offset.saturating_add(size)).context("Icon image past end of file")?;
+        if image.starts_with(PNG_SIGNATURE) {
+            let (cleaned, items) = strip_png_chunks(image, keep)?;
+            // Icon packs often repeat the same chunks in every size
+            for item in items {
+                if !metadata.contains(&item) {
+                    metadata.push(item);
+                }
+            }
+            images.push(cleaned);
+        } else {
+            images.push(image.to_vec());
+        }
+    }
+
+    // The images follow the directory in its order, with their new sizes and offsets
+    let mut output = data[..HEADER_LEN].to_vec();
+    let mut offset = HEADER_LEN + directory.len();
+    for (entry, image) in directory.chunks_exact(ENTRY_LEN).zip(&images) {
+        output.extend_from_slice(&entry[..8]);
+        output.extend_from_slice(&(image.len() as u32).to_le_bytes());
+        output.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += image.len();
+    }
+    for image in &images {
+        output.extend_from_slice(image);
+    }
+    Ok((output, metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::write_png_chunk;
+    use image::{ImageFormat, RgbaImage};
+    use std::io::Cursor;
+    use tempfile::Builder;
+
+    fn png_with_text(size: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        RgbaImage::new(size, size).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        // A tEXt chunk right after IHDR
+        let mut chunk = Vec::new();
+        write_png_chunk(&mut chunk, b"tEXt", b"Author\0Jane Doe");
+        let ihdr_end = PNG_SIGNATURE.len() + 25;
+        [&png[..ihdr_end], &chunk, &png[ihdr_end..]].concat()
+    }
+
+    #[test]
+    fn test_strip_ico_metadata() {
+        // A bitmap between two PNG sizes
+        let images = [(16, png_with_text(16)), (1, vec![40, 0, 0, 0, 1, 2, 3, 4]), (32, png_with_text(32))];
+        let mut data = vec![0, 0, 1, 0, images.len() as u8, 0];
+        let mut offset = HEADER_LEN + images.len() * ENTRY_LEN;
+        for (size, image) in &images {
+            data.extend_from_slice(&[*size, *size, 0, 0, 1, 0, 32, 0]);
+            data.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += image.len();
+        }
+        for (_, image) in &images {
+            data.extend_from_slice(image);
+        }
+
+        let input = Builder::new().suffix(".ico").tempfile().unwrap();
+        let output = Builder::new().suffix(".ico").tempfile().unwrap();
+        fs::write(&input, &data).unwrap();
+
+        let removed = strip_ico_metadata(input.path(), output.path(), &[]).unwrap();
+        assert_eq!(removed.iter().map(ToString::to_string).collect::<Vec<_>>(), ["PNG Text (Author): Jane Doe"]);
+
+        let cleaned = fs::read(output.path()).unwrap();
+        assert!(!cleaned.windows(8).any(|w| w == b"Jane Doe"));
+        let icon = image::load_from_memory_with_format(&cleaned, ImageFormat::Ico).unwrap();
+        assert_eq!(icon.width(), 32);
+        assert!(strip_icon_images(b"\0\0\x01\0\x05\0", &[]).is_err());
+    }
+}
@@ -1,99 +1,1170 @@
 use anyhow::{Context, Result};
-use image::ImageFormat;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::codecs::bmp::BmpDecoder;
+use image::codecs::gif::GifDecoder;
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat};
 use std::path::Path;
-use std::fs::File;
-use std::io::BufReader;
-use exif::{Reader, Tag, Value, In};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Write};
+use exif::experimental::Writer;
+use exif::{Field, Reader, Tag, Value, In};
 
-pub fn strip_image_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<String>> {
-    // Read the image
-    let img = image::open(input_path)
-        .with_context(|| format!("Failed to open image: {}", input_path.display()))?;
+use crate::assign::{self, Assignment, SetField};
+use crate::heif;
+use crate::ico;
+use crate::iptc::extract_photoshop_fields;
+use crate::jp2;
+use crate::makernote::{self, is_fingerprint};
+use crate::metadata::{Category, MetadataItem};
+use crate::psd;
+use crate::raw;
+use crate::svg;
+use crate::xmp::extract_xmp_fields;
+
+/// Strips image metadata. EXIF fields and PNG text chunks in the `keep` categories
+/// are left in JPEG, PNG and WebP files; other formats lose all metadata except an
+/// ICC profile, which is written back after re-encoding if `ColorProfile` is kept.
+/// With `rotate`, the EXIF orientation is applied to the pixels and the image re-encoded.
+/// The `set` values are written to EXIF in JPEG and PNG files.
+pub fn strip_image_metadata(
+    input_path: &Path,
+    output_path: &Path,
+    keep: &[Category],
+    rotate: bool,
+    set: &[Assignment],
+) -> Result<Vec<MetadataItem>> {
+    let mut removed_metadata = strip_image(input_path, output_path, keep, rotate)?;
+    if !set.is_empty() {
+        removed_metadata.extend(set_image_metadata(output_path, set)?);
+    }
+    Ok(removed_metadata)
+}
+
+fn strip_image(input_path: &Path, output_path: &Path, keep: &[Category], rotate: bool) -> Result<Vec<MetadataItem>> {
+    // WebP, HEIF, SVG, JPEG 2000, JPEG XL, icons and Photoshop documents are cleaned at
+    // the container level and don't need a full decode
+    let extension = crate::detect_format(input_path);
+    match extension.as_deref() {
+        Some("webp") if rotate => {
+            // WebP can't be re-encoded here, so keep the tag rather than lose the rotation
+            let keep = [keep, &[Category::Orientation]].concat();
+            return strip_webp_metadata(input_path, output_path, &keep);
+        }
+        Some("webp") => return strip_webp_metadata(input_path, output_path, keep),
+        Some("heic") | Some("heif") | Some("avif") => return heif::strip_heif_metadata(input_path, output_path),
+        Some("svg") => return svg::strip_svg_metadata(input_path, output_path),
+        Some("jp2") => return jp2::strip_jp2_metadata(input_path, output_path, keep),
+        Some("jxl") => return jp2::strip_jxl_metadata(input_path, output_path, keep),
+        Some("ico" | "cur") => return ico::strip_ico_metadata(input_path, output_path, keep),
+        Some("psd" | "psb") => return psd::strip_psd_metadata(input_path, output_path, keep),
+        _ => {}
+    }
+
+    // Determine the output format based on the input file's detected format
+    let format = match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => ImageFormat::Jpeg,
+        Some("png") => ImageFormat::Png,
+        Some("gif") => ImageFormat::Gif,
+        Some("bmp") => ImageFormat::Bmp,
+        Some("tiff" | "tif") => ImageFormat::Tiff,
+        _ => return Err(anyhow::anyhow!("Unsupported image format")),
+    };
 
     // Extract actual metadata before stripping
     let mut removed_metadata = Vec::new();
     
     // Try to extract EXIF data
-    let exif_metadata = extract_exif_metadata(input_path);
-    
-    // Extract basic image information
-    let basic_metadata = extract_basic_image_metadata(&img);
-    
-    if let Ok(metadata) = exif_metadata {
+    if let Ok(metadata) = extract_exif_metadata(input_path) {
         removed_metadata.extend(metadata);
     }
     
-    // Always include basic metadata
-    removed_metadata.extend(basic_metadata);
+    // Always include basic metadata, read from the header, since only rotated images
+    // and BMPs are decoded
+    removed_metadata.extend(header_metadata(input_path, format));
     
     // If we still don't have any metadata, use the fallback
     if removed_metadata.is_empty() {
-        removed_metadata.push("EXIF metadata (if present)".to_string());
-        removed_metadata.push("GPS data (if present)".to_string());
-        removed_metadata.push("Camera info (if present)".to_string());
+        removed_metadata.push(MetadataItem::new("EXIF metadata (if present)", ""));
+        removed_metadata.push(MetadataItem::new("GPS data (if present)", ""));
+        removed_metadata.push(MetadataItem::new("Camera info (if present)", ""));
     }
 
-    // Determine the output format based on the input file extension
-    let format = match input_path.extension().and_then(|e| e.to_str()) {
-        Some("jpg") | Some("jpeg") => ImageFormat::Jpeg,
-        Some("png") => ImageFormat::Png,
-        Some("gif") => ImageFormat::Gif,
-        Some("bmp") => ImageFormat::Bmp,
-        Some("tiff") => ImageFormat::Tiff,
-        _ => return Err(anyhow::anyhow!("Unsupported image format")),
+    let mut orientation = if rotate { exif_orientation(input_path).filter(|&o| o > 1) } else { None };
+
+    // Re-encoding would keep only the first frame of an animated PNG or the first page
+    // of a TIFF, so keep the tag rather than lose the rotation, as for WebP
+    let keep_with_orientation;
+    let multi_frame = orientation.is_some()
+        && match format {
+            ImageFormat::Png => is_animated_png(input_path),
+            ImageFormat::Tiff => fs::read(input_path).is_ok_and(|data| raw::count_tiff_pages(&data) > 1),
+            _ => false,
+        };
+    let keep = if multi_frame {
+        orientation = None;
+        keep_with_orientation = [keep, &[Category::Orientation]].concat();
+        &keep_with_orientation[..]
+    } else {
+        keep
+    };
+
+    if let Some(orientation) = orientation {
+        // The rotation is now part of the pixels, so no metadata needs to survive
+        let icc_profile = carry_icc_profile(input_path, format, keep, &mut removed_metadata);
+        let img = apply_orientation(decode_image(input_path)?, orientation);
+        save_image(&img, output_path, format, icc_profile.as_deref())?;
+    } else if format == ImageFormat::Png {
+        // Drop ancillary metadata chunks while copying image chunks verbatim
+        let data = fs::read(input_path)
+            .with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+        let (cleaned, chunk_metadata) = strip_png_chunks(&data, keep)
+            .with_context(|| format!("Failed to parse PNG: {}", input_path.display()))?;
+        removed_metadata.retain(|item| !keep.contains(&item.category) || is_fingerprint(item));
+        removed_metadata.extend(chunk_metadata);
+        fs::write(output_path, cleaned)
+            .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    } else if format == ImageFormat::Tiff {
+        // Rewrite the tags of every page in place, which keeps all pages and their compression
+        let mut data = fs::read(input_path)
+            .with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+        let pages = raw::strip_tiff_pages(&mut data, keep)
+            .with_context(|| format!("Failed to parse TIFF: {}", input_path.display()))?;
+        // The XMP packet goes in full, whatever its fields are
+        removed_metadata
+            .retain(|item| !keep.contains(&item.category) || is_fingerprint(item) || item.tag.starts_with("XMP "));
+        merge_page_metadata(&mut removed_metadata, pages);
+        fs::write(output_path, data)
+            .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    } else if format == ImageFormat::Gif {
+        // Drop extension blocks in place, which keeps every animation frame
+        let data = fs::read(input_path)
+            .with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+        let (cleaned, block_metadata) = strip_gif_blocks(&data, keep)
+            .with_context(|| format!("Failed to parse GIF: {}", input_path.display()))?;
+        removed_metadata.extend(block_metadata);
+        fs::write(output_path, cleaned)
+            .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    } else if format == ImageFormat::Jpeg {
+        // Drop metadata segments at the byte level so the compressed pixel data is untouched
+        let data = fs::read(input_path)
+            .with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+        let (cleaned, segment_metadata) = strip_jpeg_segments(&data, keep)
+            .with_context(|| format!("Failed to parse JPEG: {}", input_path.display()))?;
+        removed_metadata.retain(|item| !keep.contains(&item.category) || is_fingerprint(item));
+        removed_metadata.extend(segment_metadata);
+        fs::write(output_path, cleaned)
+            .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    } else {
+        // Save the image without metadata
+        let icc_profile = carry_icc_profile(input_path, format, keep, &mut removed_metadata);
+        save_image(&decode_image(input_path)?, output_path, format, icc_profile.as_deref())?;
+    }
+
+    Ok(removed_metadata)
+}
+
+pub(crate) fn exif_orientation(path: &Path) -> Option<u32> {
+    let file = File::open(path).ok()?;
+    let exif = Reader::new().read_from_container(&mut BufReader::new(file)).ok()?;
+    exif.get_field(Tag::Orientation, In::PRIMARY)?.value.get_uint(0)
+}
+
+/// Transforms the pixels the way a viewer would for the given EXIF orientation
+fn apply_orientation(img: image::DynamicImage, orientation: u32) -> image::DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Reads the ICC profile of an image that is about to be re-encoded. Returns it if
+/// it should be written back, or reports it as removed otherwise.
+fn carry_icc_profile(
+    path: &Path,
+    format: ImageFormat,
+    keep: &[Category],
+    removed_metadata: &mut Vec<MetadataItem>,
+) -> Option<Vec<u8>> {
+    let profile = read_icc_profile(path, format)?;
+    if keep.contains(&Category::ColorProfile) {
+        Some(profile)
+    } else {
+        removed_metadata.push(MetadataItem::new("ICC Profile", format!("{} bytes", profile.len())));
+        None
+    }
+}
+
+fn read_icc_profile(path: &Path, format: ImageFormat) -> Option<Vec<u8>> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    match format {
+        ImageFormat::Jpeg => JpegDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::Png => PngDecoder::new(reader).ok()?.icc_profile(),
+        ImageFormat::Tiff => TiffDecoder::new(reader).ok()?.icc_profile(),
+        _ => None,
+    }
+}
+
+fn save_image(img: &DynamicImage, output_path: &Path, format: ImageFormat, icc_profile: Option<&[u8]>) -> Result<()> {
+    let mut encoded = Cursor::new(Vec::new());
+    img.write_to(&mut encoded, format)
+        .with_context(|| format!("Failed to encode image: {}", output_path.display()))?;
+    let mut data = encoded.into_inner();
+
+    // The encoders can't write ICC profiles, so the profile is spliced into the output
+    if let Some(profile) = icc_profile {
+        data = match format {
+            ImageFormat::Jpeg => embed_jpeg_icc(&data, profile)?,
+            ImageFormat::Png => embed_png_icc(&data, profile)?,
+            ImageFormat::Tiff => embed_tiff_icc(&data, profile)?,
+            _ => data,
+        };
+    }
+
+    fs::write(output_path, data)
+        .with_context(|| format!("Failed to save image: {}", output_path.display()))
+}
+
+// An APP2 segment holds at most this much profile data after its header,
+// sequence number and segment count
+const JPEG_ICC_CHUNK: usize = u16::MAX as usize - 2 - 14;
+
+/// Inserts APP2 ICC segments after the SOI marker and any JFIF APP0 segment
+fn embed_jpeg_icc(data: &[u8], profile: &[u8]) -> Result<Vec<u8>> {
+    let chunks: Vec<&[u8]> = profile.chunks(JPEG_ICC_CHUNK).collect();
+    // Chunks are numbered in a single byte
+    let count = u8::try_from(chunks.len())
+        .map_err(|_| anyhow::anyhow!("ICC profile of {} bytes is too large for a JPEG", profile.len()))?;
+
+    let mut insert_at = 2;
+    if data.get(2..4) == Some(&[0xFF, 0xE0]) && data.len() >= 6 {
+        insert_at = 4 + u16::from_be_bytes([data[4], data[5]]) as usize;
+    }
+
+    let mut output = Vec::with_capacity(data.len() + profile.len() + chunks.len() * 18);
+    output.extend_from_slice(&data[..insert_at]);
+    for (index, chunk) in (1..=count).zip(&chunks) {
+        output.extend_from_slice(&[0xFF, JPEG_APP2]);
+        output.extend_from_slice(&((chunk.len() + ICC_HEADER.len() + 4) as u16).to_be_bytes());
+        output.extend_from_slice(ICC_HEADER);
+        output.extend_from_slice(&[index, count]);
+        output.extend_from_slice(chunk);
+    }
+    output.extend_from_slice(&data[insert_at..]);
+    Ok(output)
+}
+
+/// Inserts an iCCP chunk right after IHDR, which the spec requires to come first
+fn embed_png_icc(data: &[u8], profile: &[u8]) -> Result<Vec<u8>> {
+    // Signature, then the IHDR length, type, 13 bytes of data and CRC
+    let insert_at = PNG_SIGNATURE.len() + 12 + 13;
+    if data.get(PNG_SIGNATURE.len() + 4..PNG_SIGNATURE.len() + 8) != Some(b"IHDR") || data.len() < insert_at {
+        return Err(anyhow::anyhow!("Encoded PNG doesn't start with IHDR"));
+    }
+
+    let mut chunk = b"ICC Profile\0\0".to_vec();
+    let mut encoder = ZlibEncoder::new(chunk, Compression::default());
+    encoder.write_all(profile)?;
+    chunk = encoder.finish()?;
+
+    let mut output = Vec::with_capacity(data.len() + chunk.len() + 12);
+    output.extend_from_slice(&data[..insert_at]);
+    write_png_chunk(&mut output, b"iCCP", &chunk);
+    output.extend_from_slice(&data[insert_at..]);
+    Ok(output)
+}
+
+const TIFF_TAG_ICC_PROFILE: u16 = 34675;
+const TIFF_TAG_XMP: u16 = 700;
+// DNG's serial number of the camera body, which kamadak-exif has no name for
+const TIFF_TAG_CAMERA_SERIAL_NUMBER: u16 = 0xC62F;
+const TIFF_TYPE_UNDEFINED: u16 = 7;
+
+fn embed_tiff_icc(data: &[u8], profile: &[u8]) -> Result<Vec<u8>> {
+    add_tiff_tag(data, TIFF_TAG_ICC_PROFILE, profile)
+}
+
+/// Appends `value` and a copy of the first IFD with an entry for it added (replacing
+/// any existing one), then points the header at the new IFD
+fn add_tiff_tag(data: &[u8], tag: u16, value: &[u8]) -> Result<Vec<u8>> {
+    let big_endian = match data.get(0..4) {
+        Some([b'I', b'I', 42, 0]) => false,
+        Some([b'M', b'M', 0, 42]) => true,
+        _ => return Err(anyhow::anyhow!("Encoded TIFF has no valid header")),
+    };
+    let read_u16 = |pos: usize| -> Result<u16> {
+        let b: [u8; 2] = data.get(pos..pos + 2).context("Unexpected end of TIFF data")?.try_into()?;
+        Ok(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
     };
+    let read_u32 = |pos: usize| -> Result<u32> {
+        let b: [u8; 4] = data.get(pos..pos + 4).context("Unexpected end of TIFF data")?.try_into()?;
+        Ok(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+    };
+    let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+    let u32_bytes = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+    let ifd = read_u32(4)? as usize;
+    let count = read_u16(ifd)? as usize;
+    let next_ifd = read_u32(ifd + 2 + count * 12)?;
+    let mut entries = Vec::with_capacity(count + 1);
+    for index in 0..count {
+        let pos = ifd + 2 + index * 12;
+        if read_u16(pos)? != tag {
+            entries.push((read_u16(pos)?, data[pos..pos + 12].to_vec()));
+        }
+    }
+
+    // Values and IFDs start on word boundaries
+    let mut output = data.to_vec();
+    output.resize(output.len() + output.len() % 2, 0);
+
+    let mut entry = u16_bytes(tag).to_vec();
+    entry.extend_from_slice(&u16_bytes(TIFF_TYPE_UNDEFINED));
+    entry.extend_from_slice(&u32_bytes(value.len() as u32));
+    if value.len() <= 4 {
+        // Small values are stored in the entry itself
+        entry.extend_from_slice(value);
+        entry.resize(12, 0);
+    } else {
+        entry.extend_from_slice(&u32_bytes(output.len() as u32));
+        output.extend_from_slice(value);
+        output.resize(output.len() + output.len() % 2, 0);
+    }
+    entries.push((tag, entry));
+    // Entries must be sorted by tag
+    entries.sort_by_key(|(tag, _)| *tag);
+
+    let new_ifd = output.len() as u32;
+    output.extend_from_slice(&u16_bytes(entries.len() as u16));
+    for (_, entry) in &entries {
+        output.extend_from_slice(entry);
+    }
+    output.extend_from_slice(&u32_bytes(next_ifd));
+    output[4..8].copy_from_slice(&u32_bytes(new_ifd));
+
+    Ok(output)
+}
+
+// JPEG markers that carry metadata rather than image data
+const JPEG_APP1: u8 = 0xE1; // EXIF and XMP
+const JPEG_APP2: u8 = 0xE2; // ICC profiles
+const JPEG_APP12: u8 = 0xEC; // Photoshop Save for Web settings
+const JPEG_APP13: u8 = 0xED; // Photoshop IRB and IPTC
+const JPEG_COM: u8 = 0xFE; // Comments
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const DUCKY_HEADER: &[u8] = b"Ducky";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+// Packets over 64 KB continue in extension segments
+const XMP_EXTENSION_HEADER: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+
+fn strip_jpeg_segments(data: &[u8], keep: &[Category]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if data.len() < 2 || data[0] != 0xFF || data[1] != 0xD8 {
+        return Err(anyhow::anyhow!("Missing JPEG start of image marker"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&data[..2]);
+    let mut metadata = Vec::new();
+    // A large profile is split across several APP2 segments
+    let mut icc_bytes = 0;
+    let mut xmp_extension_bytes = 0;
+
+    let mut pos = 2;
+    while pos < data.len() {
+        if data[pos] != 0xFF {
+            return Err(anyhow::anyhow!("Invalid JPEG marker at offset {}", pos));
+        }
+
+        // Markers may be preceded by any number of 0xFF fill bytes
+        let mut marker_pos = pos + 1;
+        while marker_pos < data.len() && data[marker_pos] == 0xFF {
+            marker_pos += 1;
+        }
+        if marker_pos >= data.len() {
+            return Err(anyhow::anyhow!("Truncated JPEG marker"));
+        }
+        let marker = data[marker_pos];
+
+        // Standalone markers have no length field
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            output.extend_from_slice(&[0xFF, marker]);
+            pos = marker_pos + 1;
+            continue;
+        }
+
+        // End of image: keep the marker and anything trailing it
+        if marker == 0xD9 {
+            output.extend_from_slice(&[0xFF, marker]);
+            output.extend_from_slice(&data[marker_pos + 1..]);
+            break;
+        }
+
+        if marker_pos + 2 >= data.len() {
+            return Err(anyhow::anyhow!("Truncated JPEG segment"));
+        }
+        let length = u16::from_be_bytes([data[marker_pos + 1], data[marker_pos + 2]]) as usize;
+        let segment_end = marker_pos + 1 + length;
+        if length < 2 || segment_end > data.len() {
+            return Err(anyhow::anyhow!("Invalid JPEG segment length at offset {}", pos));
+        }
+
+        // Start of scan: the entropy-coded data follows, copy the rest verbatim
+        if marker == 0xDA {
+            output.push(0xFF);
+            output.extend_from_slice(&data[marker_pos..]);
+            break;
+        }
 
-    // Save the image without metadata
-    img.save_with_format(output_path, format)
+        let payload = &data[marker_pos + 3..segment_end];
+        if marker == JPEG_APP2 && payload.starts_with(ICC_HEADER) && !keep.contains(&Category::ColorProfile) {
+            // Sequence number and segment count follow the header
+            icc_bytes += payload.len().saturating_sub(ICC_HEADER.len() + 2);
+        } else if marker == JPEG_APP12 && payload.starts_with(DUCKY_HEADER) && !keep.contains(&Category::Software) {
+            // Photoshop's Save for Web records its quality setting and any comment here
+            let size = format!("{} bytes", payload.len());
+            metadata.push(MetadataItem::with_category(Category::Software, "Photoshop Save for Web", size));
+        } else if !matches!(marker, JPEG_APP1 | JPEG_APP13 | JPEG_COM) {
+            output.push(0xFF);
+            output.extend_from_slice(&data[marker_pos..segment_end]);
+        } else if marker == JPEG_APP1 {
+            if let Some(packet) = payload.strip_prefix(XMP_HEADER) {
+                metadata.extend(extract_xmp_fields(&String::from_utf8_lossy(packet)));
+            } else if payload.starts_with(XMP_EXTENSION_HEADER) {
+                xmp_extension_bytes += payload.len() - XMP_EXTENSION_HEADER.len();
+            }
+            let kept = payload.strip_prefix(EXIF_HEADER).and_then(|tiff| filter_exif(tiff, keep));
+            if let Some(tiff) = kept.filter(|tiff| tiff.len() + EXIF_HEADER.len() + 2 <= u16::MAX as usize) {
+                output.extend_from_slice(&[0xFF, JPEG_APP1]);
+                output.extend_from_slice(&((tiff.len() + EXIF_HEADER.len() + 2) as u16).to_be_bytes());
+                output.extend_from_slice(EXIF_HEADER);
+                output.extend_from_slice(&tiff);
+            }
+        } else if marker == JPEG_COM {
+            let item = MetadataItem::new("JPEG Comment", String::from_utf8_lossy(payload).trim_end_matches('\0').trim());
+            if keep.contains(&item.category) {
+                output.push(0xFF);
+                output.extend_from_slice(&data[marker_pos..segment_end]);
+            } else {
+                metadata.push(item);
+            }
+        } else if marker == JPEG_APP13 {
+            // Removed in full regardless of `keep`, so every field in it is reported
+            metadata.extend(extract_photoshop_fields(payload));
+        }
+
+        pos = segment_end;
+    }
+
+    if xmp_extension_bytes > 0 {
+        metadata.push(MetadataItem::new("XMP Extension", format!("{} bytes", xmp_extension_bytes)));
+    }
+    if icc_bytes > 0 {
+        metadata.push(MetadataItem::new("ICC Profile", format!("{} bytes", icc_bytes)));
+    }
+
+    Ok((output, metadata))
+}
+
+pub(crate) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// PNG chunks that carry metadata rather than image data
+const PNG_METADATA_CHUNKS: &[&[u8; 4]] = &[b"tEXt", b"iTXt", b"zTXt", b"tIME", b"eXIf", b"iCCP"];
+
+/// Adds what was removed from each page of a TIFF to the EXIF metadata read from the
+/// first, listing a tag again only if a later page gave it a value of its own
+fn merge_page_metadata(metadata: &mut Vec<MetadataItem>, pages: Vec<Vec<MetadataItem>>) {
+    if pages.len() > 1 {
+        metadata.push(MetadataItem::with_category(Category::Technical, "Pages", pages.len().to_string()));
+    }
+    let first_page: Vec<String> = metadata.iter().map(|item| item.tag.clone()).collect();
+    for (index, page) in pages.into_iter().enumerate() {
+        for item in page {
+            let listed = if index == 0 {
+                first_page.contains(&item.tag)
+            } else {
+                metadata.iter().any(|other| other.tag == item.tag && other.value.trim_matches('"') == item.value)
+            };
+            if !listed {
+                metadata.push(item);
+            }
+        }
+    }
+}
+
+/// Whether a PNG has an animation control chunk, which must come before the image data
+fn is_animated_png(path: &Path) -> bool {
+    let Ok(data) = fs::read(path) else { return false };
+    let mut pos = PNG_SIGNATURE.len();
+    while let Some(header) = data.get(pos..pos + 8) {
+        match &header[4..] {
+            b"acTL" => return true,
+            b"IDAT" | b"IEND" => return false,
+            _ => pos += 12 + u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize,
+        }
+    }
+    false
+}
+
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+pub(crate) fn strip_png_chunks(data: &[u8], keep: &[Category]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err(anyhow::anyhow!("Missing PNG signature"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    let mut metadata = Vec::new();
+    output.extend_from_slice(PNG_SIGNATURE);
+
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < data.len() {
+        if pos + 8 > data.len() {
+            return Err(anyhow::anyhow!("Truncated PNG chunk header at offset {}", pos));
+        }
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        // Length, type, data and CRC
+        let chunk_end = pos + 12 + length;
+        if chunk_end > data.len() {
+            return Err(anyhow::anyhow!("Truncated PNG chunk at offset {}", pos));
+        }
+        let chunk_data = &data[pos + 8..pos + 8 + length];
+
+        if PNG_METADATA_CHUNKS.iter().any(|c| c.as_slice() == chunk_type) {
+            let item = describe_png_chunk(chunk_type, chunk_data);
+            if chunk_type == b"iTXt" && chunk_data.starts_with(PNG_XMP_KEYWORD) {
+                // XMP is removed in full, like in every other format
+                let packet = itxt_text(&chunk_data[PNG_XMP_KEYWORD.len()..]);
+                metadata.extend(extract_xmp_fields(&packet));
+            } else if chunk_type == b"eXIf" {
+                if let Some(exif) = filter_exif(chunk_data, keep) {
+                    write_png_chunk(&mut output, b"eXIf", &exif);
+                }
+                // Readable EXIF is already listed field by field
+                if Reader::new().read_raw(chunk_data.to_vec()).is_err() {
+                    metadata.push(item);
+                }
+            } else if keep.contains(&item.category) {
+                output.extend_from_slice(&data[pos..chunk_end]);
+            } else {
+                metadata.push(item);
+            }
+        } else {
+            output.extend_from_slice(&data[pos..chunk_end]);
+        }
+
+        pos = chunk_end;
+        if chunk_type == b"IEND" {
+            break;
+        }
+    }
+
+    Ok((output, metadata))
+}
+
+pub(crate) fn write_png_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+    output.extend_from_slice(&png_crc(chunk_type, data).to_be_bytes());
+}
+
+// CRC-32 over the chunk type and data, as required by the PNG spec
+fn png_crc(chunk_type: &[u8], data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in chunk_type.iter().chain(data) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Rebuilds a TIFF-structured EXIF block with only the main image's fields in the
+/// `keep` categories, plus the JPEG thumbnail if thumbnails are kept. Maker notes,
+/// serial numbers and the owner name go whatever is kept. Returns `None` if nothing
+/// is kept.
+pub(crate) fn filter_exif(tiff: &[u8], keep: &[Category]) -> Option<Vec<u8>> {
+    if keep.is_empty() {
+        return None;
+    }
+
+    let exif = Reader::new().read_raw(tiff.to_vec()).ok()?;
+    let kept: Vec<&Field> = exif
+        .fields()
+        .filter(|field| field.ifd_num == In::PRIMARY && field.tag.number() != TIFF_TAG_XMP)
+        .filter(|field| !is_camera_fingerprint(field.tag))
+        .filter(|field| keep.contains(&Category::from_tag(&field.tag.to_string())))
+        .collect();
+    if kept.is_empty() {
+        return None;
+    }
+
+    let mut writer = Writer::new();
+    for field in kept {
+        writer.push_field(field);
+    }
+    // Anything else in IFD1 is dropped with the rest of the non-primary fields
+    if let Some(thumbnail) = exif_thumbnail(&exif).filter(|_| keep.contains(&Category::Thumbnails)) {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    writer.write(&mut buf, exif.little_endian()).ok()?;
+    Some(buf.into_inner())
+}
+
+fn is_camera_fingerprint(tag: Tag) -> bool {
+    matches!(tag, Tag::MakerNote | Tag::BodySerialNumber | Tag::LensSerialNumber | Tag::CameraOwnerName)
+        || tag.number() == TIFF_TAG_CAMERA_SERIAL_NUMBER
+}
+
+/// Writes the `set` values into the EXIF block of a cleaned JPEG or PNG, adding one
+/// if cleaning left none. Other formats only get a warning.
+fn set_image_metadata(path: &Path, set: &[Assignment]) -> Result<Option<MetadataItem>> {
+    let format = crate::detect_format(path);
+    if !matches!(format.as_deref(), Some("jpg" | "jpeg" | "png")) {
+        let format = format.unwrap_or_default().to_uppercase();
+        return Ok(assign::unsupported(set, &[], &format));
+    }
+
+    let data = fs::read(path).with_context(|| format!("Failed to read image: {}", path.display()))?;
+    let updated = if format.as_deref() == Some("png") { set_png_exif(&data, set)? } else { set_jpeg_exif(&data, set)? };
+    fs::write(path, updated).with_context(|| format!("Failed to save image: {}", path.display()))?;
+    Ok(None)
+}
+
+/// The EXIF tags each field is written to
+fn exif_tags(field: SetField) -> &'static [Tag] {
+    match field {
+        SetField::Author => &[Tag::Artist],
+        SetField::Title => &[Tag::ImageDescription],
+        SetField::Copyright => &[Tag::Copyright],
+        SetField::Software => &[Tag::Software],
+        SetField::Make => &[Tag::Make],
+        SetField::Model => &[Tag::Model],
+        SetField::Date => &[Tag::DateTime, Tag::DateTimeOriginal, Tag::DateTimeDigitized],
+    }
+}
+
+/// Rebuilds a TIFF-structured EXIF block, or starts a new one, with the `set` values
+/// replacing the fields they map to
+fn set_exif(tiff: Option<&[u8]>, set: &[Assignment]) -> Result<Vec<u8>> {
+    let exif = tiff.and_then(|tiff| Reader::new().read_raw(tiff.to_vec()).ok());
+    let tags: Vec<Tag> = set.iter().flat_map(|assignment| exif_tags(assignment.field)).copied().collect();
+
+    let mut fields = Vec::new();
+    for &field in SetField::ALL {
+        let date = |date: chrono::NaiveDateTime| date.format("%Y:%m:%d %H:%M:%S").to_string();
+        if let Some(value) = assign::value_of(set, field, date) {
+            for &tag in exif_tags(field) {
+                fields.push(Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![value.clone().into_bytes()]) });
+            }
+        }
+    }
+
+    let mut writer = Writer::new();
+    if let Some(exif) = &exif {
+        for field in exif.fields().filter(|field| field.ifd_num == In::PRIMARY && !tags.contains(&field.tag)) {
+            writer.push_field(field);
+        }
+        if let Some(thumbnail) = exif_thumbnail(exif) {
+            writer.set_jpeg(thumbnail, In::THUMBNAIL);
+        }
+    }
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    let little_endian = exif.as_ref().map(|exif| exif.little_endian()).unwrap_or(false);
+    writer.write(&mut buf, little_endian).context("Failed to write EXIF")?;
+    Ok(buf.into_inner())
+}
+
+/// Replaces the EXIF segment of a JPEG, or adds one right after the start of image marker
+fn set_jpeg_exif(data: &[u8], set: &[Assignment]) -> Result<Vec<u8>> {
+    let mut exif_segment = None;
+    let mut pos = 2;
+    // Metadata segments come before the image data, so stop at the first other segment
+    while let Some(&[0xFF, marker, high, low]) = data.get(pos..pos + 4) {
+        let end = pos + 2 + u16::from_be_bytes([high, low]) as usize;
+        if !(0xE0..=0xEF).contains(&marker) && marker != JPEG_COM {
+            break;
+        }
+        if marker == JPEG_APP1 && data.get(pos + 4..end).is_some_and(|payload| payload.starts_with(EXIF_HEADER)) {
+            exif_segment = Some(pos..end);
+            break;
+        }
+        pos = end;
+    }
+
+    let existing = exif_segment.clone().map(|segment| &data[segment.start + 4 + EXIF_HEADER.len()..segment.end]);
+    let tiff = set_exif(existing, set)?;
+    let length = tiff.len() + EXIF_HEADER.len() + 2;
+    if length > u16::MAX as usize {
+        anyhow::bail!("EXIF block too large for a JPEG segment");
+    }
+
+    let (before, after) = match exif_segment {
+        Some(segment) => (&data[..segment.start], &data[segment.end..]),
+        None => (&data[..2], &data[2..]),
+    };
+    let mut output = Vec::with_capacity(data.len() + length + 2);
+    output.extend_from_slice(before);
+    output.extend_from_slice(&[0xFF, JPEG_APP1]);
+    output.extend_from_slice(&(length as u16).to_be_bytes());
+    output.extend_from_slice(EXIF_HEADER);
+    output.extend_from_slice(&tiff);
+    output.extend_from_slice(after);
+    Ok(output)
+}
+
+/// Replaces the eXIf chunk of a PNG, or adds one before the image data
+fn set_png_exif(data: &[u8], set: &[Assignment]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(PNG_SIGNATURE);
+    let mut written = false;
+    let mut pos = PNG_SIGNATURE.len();
+    while let Some(header) = data.get(pos..pos + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_end = pos + 12 + length;
+        let chunk = data.get(pos..chunk_end).ok_or_else(|| anyhow::anyhow!("Truncated PNG chunk at offset {}", pos))?;
+        match &header[4..] {
+            b"eXIf" => {
+                write_png_chunk(&mut output, b"eXIf", &set_exif(Some(&chunk[8..8 + length]), set)?);
+                written = true;
+            }
+            // eXIf must come before the first IDAT, and before fdAT frames in animations
+            b"IDAT" | b"fdAT" | b"IEND" if !written => {
+                write_png_chunk(&mut output, b"eXIf", &set_exif(None, set)?);
+                written = true;
+                output.extend_from_slice(chunk);
+            }
+            _ => output.extend_from_slice(chunk),
+        }
+        pos = chunk_end;
+    }
+    Ok(output)
+}
+
+/// The JPEG thumbnail stored in IFD1, if any
+fn exif_thumbnail(exif: &exif::Exif) -> Option<&[u8]> {
+    let offset = exif.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+    let length = exif.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+    exif.buf().get(offset..offset.checked_add(length)?)
+}
+
+/// Size of the IFD1 thumbnail, whether it's stored as a JPEG or as uncompressed strips
+fn exif_thumbnail_size(exif: &exif::Exif) -> Option<usize> {
+    if let Some(field) = exif.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL) {
+        return field.value.get_uint(0).map(|length| length as usize);
+    }
+    let strips = exif.get_field(Tag::StripByteCounts, In::THUMBNAIL)?;
+    Some(strips.value.iter_uint()?.map(|count| count as usize).sum())
+}
+
+/// The text of an iTXt chunk, given the data after its keyword
+fn itxt_text(rest: &[u8]) -> String {
+    // Compression flag, compression method, language tag, translated keyword, text
+    rest.get(2..)
+        .and_then(|r| r.splitn(3, |&b| b == 0).nth(2))
+        .map(|t| String::from_utf8_lossy(t).to_string())
+        .unwrap_or_default()
+}
+
+fn describe_png_chunk(chunk_type: &[u8], data: &[u8]) -> MetadataItem {
+    // Text chunks start with a NUL-terminated Latin-1 keyword
+    let (keyword, rest) = match data.iter().position(|&b| b == 0) {
+        Some(nul) => (&data[..nul], &data[nul + 1..]),
+        None => (data, &[][..]),
+    };
+    let keyword: String = keyword.iter().map(|&b| b as char).collect();
+
+    match chunk_type {
+        b"tEXt" => {
+            let text: String = rest.iter().map(|&b| b as char).collect();
+            MetadataItem::new(format!("PNG Text ({})", keyword), text)
+        }
+        b"iTXt" => {
+            let compressed = rest.first().copied().unwrap_or(0) != 0;
+            let text = itxt_text(rest);
+            if compressed {
+                MetadataItem::new(
+                    format!("PNG International Text ({})", keyword),
+                    format!("compressed, {} bytes", rest.len()),
+                )
+            } else {
+                MetadataItem::new(format!("PNG International Text ({})", keyword), text)
+            }
+        }
+        b"zTXt" => MetadataItem::new(format!("PNG Compressed Text ({})", keyword), format!("{} bytes", rest.len())),
+        b"tIME" if data.len() >= 7 => MetadataItem::new(
+            "PNG Modification Time",
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                u16::from_be_bytes([data[0], data[1]]),
+                data[2], data[3], data[4], data[5], data[6]
+            ),
+        ),
+        b"eXIf" => MetadataItem::new("PNG EXIF Chunk", format!("{} bytes", data.len())),
+        // Profile name, compression method, compressed profile
+        b"iCCP" => MetadataItem::new(format!("ICC Profile ({})", keyword), format!("compressed, {} bytes", rest.len().saturating_sub(1))),
+        _ => MetadataItem::new(
+            format!("PNG {} Chunk", String::from_utf8_lossy(chunk_type)),
+            format!("{} bytes", data.len()),
+        ),
+    }
+}
+
+// GIF blocks and extension labels
+const GIF_EXTENSION: u8 = 0x21;
+const GIF_IMAGE: u8 = 0x2C;
+const GIF_TRAILER: u8 = 0x3B;
+const GIF_COMMENT: u8 = 0xFE;
+const GIF_APPLICATION: u8 = 0xFF;
+
+/// Removes comment and application extensions, keeping image data, graphic control
+/// (frame delay) and looping extensions. ICC profile extensions stay if kept.
+fn strip_gif_blocks(data: &[u8], keep: &[Category]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if !data.starts_with(b"GIF87a") && !data.starts_with(b"GIF89a") {
+        return Err(anyhow::anyhow!("Missing GIF signature"));
+    }
+    if data.len() < 13 {
+        return Err(anyhow::anyhow!("Truncated GIF header"));
+    }
+
+    // Header, logical screen descriptor and optional global color table
+    let mut pos = 13 + gif_color_table_size(data[10]);
+    let mut output = data.get(..pos).context("Truncated GIF color table")?.to_vec();
+    let mut metadata = Vec::new();
+
+    loop {
+        match data.get(pos) {
+            Some(&GIF_EXTENSION) => {
+                let label = *data.get(pos + 1).context("Truncated GIF extension")?;
+                let block_end = gif_sub_blocks_end(data, pos + 2)?;
+                let removed = match label {
+                    GIF_COMMENT => {
+                        let comment = gif_sub_blocks(&data[pos + 2..block_end]);
+                        let item = MetadataItem::new("GIF Comment", String::from_utf8_lossy(&comment).trim());
+                        Some(vec![item]).filter(|items| !keep.contains(&items[0].category))
+                    }
+                    GIF_APPLICATION => describe_gif_application(&data[pos + 2..block_end], keep),
+                    // Graphic control (frame delays) and plain text extensions
+                    _ => None,
+                };
+
+                match removed {
+                    Some(items) => metadata.extend(items),
+                    None => output.extend_from_slice(&data[pos..block_end]),
+                }
+                pos = block_end;
+            }
+            Some(&GIF_IMAGE) => {
+                // Image descriptor, optional local color table, LZW code size, image data
+                let descriptor = data.get(pos..pos + 10).context("Truncated GIF image descriptor")?;
+                let data_start = pos + 10 + gif_color_table_size(descriptor[9]) + 1;
+                let block_end = gif_sub_blocks_end(data, data_start)?;
+                output.extend_from_slice(&data[pos..block_end]);
+                pos = block_end;
+            }
+            Some(&GIF_TRAILER) | None => {
+                output.push(GIF_TRAILER);
+                break;
+            }
+            Some(byte) => return Err(anyhow::anyhow!("Unknown GIF block 0x{:02X} at offset {}", byte, pos)),
+        }
+    }
+
+    Ok((output, metadata))
+}
+
+/// Lists what an application extension holds, or returns `None` if it should be kept
+fn describe_gif_application(blocks: &[u8], keep: &[Category]) -> Option<Vec<MetadataItem>> {
+    let payload = gif_sub_blocks(blocks);
+    // The first sub-block holds an 8 byte identifier and 3 byte authentication code
+    let (identifier, rest) = payload.split_at(payload.len().min(11));
+
+    match identifier {
+        // Animation loop count
+        b"NETSCAPE2.0" | b"ANIMEXTS1.0" => None,
+        b"ICCRGBG1012" if keep.contains(&Category::ColorProfile) => None,
+        b"ICCRGBG1012" => Some(vec![MetadataItem::new("ICC Profile", format!("{} bytes", rest.len()))]),
+        // XMP is stored raw, with the sub-block lengths falling inside the packet
+        b"XMP DataXMP" => Some(extract_xmp_fields(&String::from_utf8_lossy(blocks.get(12..).unwrap_or_default()))),
+        _ => Some(vec![MetadataItem::new(
+            format!("GIF Application Extension ({})", String::from_utf8_lossy(identifier)),
+            format!("{} bytes", rest.len()),
+        )]),
+    }
+}
+
+/// Size of the color table announced by a packed flags byte
+fn gif_color_table_size(flags: u8) -> usize {
+    if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+/// Position just past the sub-block chain starting at `pos`
+fn gif_sub_blocks_end(data: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let size = *data.get(pos).context("Truncated GIF data")? as usize;
+        pos += 1 + size;
+        if size == 0 {
+            return Ok(pos);
+        }
+        if pos > data.len() {
+            return Err(anyhow::anyhow!("Truncated GIF data"));
+        }
+    }
+}
+
+/// Concatenated contents of a sub-block chain
+fn gif_sub_blocks(mut data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    while let Some((&size, rest)) = data.split_first() {
+        let size = (size as usize).min(rest.len());
+        payload.extend_from_slice(&rest[..size]);
+        data = &rest[size..];
+    }
+    payload
+}
+
+fn strip_webp_metadata(input_path: &Path, output_path: &Path, keep: &[Category]) -> Result<Vec<MetadataItem>> {
+    let mut removed_metadata = extract_exif_metadata(input_path).unwrap_or_default();
+    removed_metadata.retain(|item| !keep.contains(&item.category) || is_fingerprint(item));
+
+    // Not every WebP variant can be decoded, so basic info is best-effort
+    if let Ok(img) = image::open(input_path) {
+        removed_metadata.extend(extract_basic_image_metadata(&img));
+    }
+
+    let data = fs::read(input_path)
+        .with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+    let (cleaned, chunk_metadata) = strip_webp_chunks(&data, keep)
+        .with_context(|| format!("Failed to parse WebP: {}", input_path.display()))?;
+    removed_metadata.extend(chunk_metadata);
+
+    fs::write(output_path, cleaned)
         .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
 
     Ok(removed_metadata)
 }
 
-fn extract_basic_image_metadata(img: &image::DynamicImage) -> Vec<String> {
+// VP8X feature flags announcing the optional metadata chunks
+const WEBP_FLAG_ICC: u8 = 0x20;
+const WEBP_FLAG_EXIF: u8 = 0x08;
+const WEBP_FLAG_XMP: u8 = 0x04;
+
+fn strip_webp_chunks(data: &[u8], keep: &[Category]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(anyhow::anyhow!("Missing RIFF/WEBP header"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    let mut metadata = Vec::new();
+    // The RIFF size is patched once the remaining chunks are known
+    output.extend_from_slice(b"RIFF\0\0\0\0WEBP");
+    // The VP8X flags are patched too, once it's known which metadata chunks remain
+    let mut vp8x_flags = None;
+    let mut kept_flags = 0;
+
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let fourcc = &data[pos..pos + 4];
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        // Chunk payloads are padded to an even length
+        let chunk_end = pos + 8 + size + (size & 1);
+        if pos + 8 + size > data.len() {
+            return Err(anyhow::anyhow!("Truncated WebP chunk at offset {}", pos));
+        }
+        let payload = &data[pos + 8..pos + 8 + size];
+
+        match fourcc {
+            b"EXIF" => {
+                metadata.push(MetadataItem::new("WebP EXIF Chunk", format!("{} bytes", size)));
+                // Some writers prefix the TIFF data with the JPEG-style EXIF header
+                let tiff = payload.strip_prefix(EXIF_HEADER).unwrap_or(payload);
+                if let Some(exif) = filter_exif(tiff, keep) {
+                    write_riff_chunk(&mut output, b"EXIF", &exif);
+                    kept_flags |= WEBP_FLAG_EXIF;
+                }
+            }
+            b"XMP " => metadata.extend(extract_xmp_fields(&String::from_utf8_lossy(payload))),
+            b"ICCP" if keep.contains(&Category::ColorProfile) => {
+                output.extend_from_slice(&data[pos..chunk_end.min(data.len())]);
+                kept_flags |= WEBP_FLAG_ICC;
+            }
+            b"ICCP" => metadata.push(MetadataItem::new("ICC Profile", format!("{} bytes", size))),
+            _ => {
+                if fourcc == b"VP8X" && size > 0 {
+                    vp8x_flags = Some(output.len() + 8);
+                }
+                output.extend_from_slice(&data[pos..chunk_end.min(data.len())]);
+            }
+        }
+
+        pos = chunk_end;
+    }
+
+    if let Some(flags) = vp8x_flags {
+        // Clear the flags for the chunks we dropped
+        output[flags] &= !(WEBP_FLAG_ICC | WEBP_FLAG_EXIF | WEBP_FLAG_XMP) | kept_flags;
+    }
+
+    let riff_size = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    Ok((output, metadata))
+}
+
+fn write_riff_chunk(output: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(fourcc);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+fn decode_image(path: &Path) -> Result<DynamicImage> {
+    image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(image::ImageError::IoError)
+        .and_then(|reader| reader.decode())
+        .with_context(|| format!("Failed to open image: {}", path.display()))
+}
+
+/// The dimensions and color type of an image, read from its header without decoding
+/// the pixels. JPEGs are read from their SOF segment, so files the decoder can't
+/// handle, such as arithmetic-coded ones, still get them.
+fn header_metadata(path: &Path, format: ImageFormat) -> Vec<MetadataItem> {
+    let describe = |dimensions: (u32, u32), color: String| {
+        vec![
+            MetadataItem::new("Image Dimensions", format!("{}x{}", dimensions.0, dimensions.1)),
+            MetadataItem::new("Color Type", color),
+        ]
+    };
+    if format == ImageFormat::Jpeg {
+        let Ok(data) = fs::read(path) else { return Vec::new() };
+        return jpeg_frame(&data).map(|(dimensions, color)| describe(dimensions, color)).unwrap_or_default();
+    }
+    let Ok(file) = File::open(path) else { return Vec::new() };
+    let reader = BufReader::new(file);
+    let header = match format {
+        ImageFormat::Png => PngDecoder::new(reader).map(|d| (d.dimensions(), d.color_type())),
+        ImageFormat::Gif => GifDecoder::new(reader).map(|d| (d.dimensions(), d.color_type())),
+        ImageFormat::Tiff => TiffDecoder::new(reader).map(|d| (d.dimensions(), d.color_type())),
+        ImageFormat::Bmp => BmpDecoder::new(reader).map(|d| (d.dimensions(), d.color_type())),
+        _ => return Vec::new(),
+    };
+    header.map(|(dimensions, color)| describe(dimensions, format!("{:?}", color))).unwrap_or_default()
+}
+
+/// The dimensions and color type in a JPEG's start of frame segment, named as the
+/// decoder names color types
+fn jpeg_frame(data: &[u8]) -> Option<((u32, u32), String)> {
+    let mut pos = 2;
+    while let [0xFF, marker, rest @ ..] = data.get(pos..)? {
+        match marker {
+            // Fill bytes, and standalone markers without a length
+            0xFF => pos += 1,
+            0x01 | 0xD0..=0xD7 => pos += 2,
+            // Start of scan or end of image before any frame
+            0xD9 | 0xDA => return None,
+            // Huffman and arithmetic coding tables share the range of frame markers
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let [_, _, precision, h1, h0, w1, w0, components, ..] = *rest else { return None };
+                let width = u16::from_be_bytes([w1, w0]) as u32;
+                let height = u16::from_be_bytes([h1, h0]) as u32;
+                let color = match components {
+                    1 => "L",
+                    3 => "Rgb",
+                    4 => "Cmyk",
+                    _ => return Some(((width, height), format!("{} components", components))),
+                };
+                return Some(((width, height), format!("{}{}", color, if precision > 8 { 16 } else { 8 })));
+            }
+            _ => {
+                let length = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+                pos += 2 + length;
+            }
+        }
+    }
+    None
+}
+
+fn extract_basic_image_metadata(img: &image::DynamicImage) -> Vec<MetadataItem> {
     let mut metadata = Vec::new();
     
     // Get image dimensions
     let width = img.width();
     let height = img.height();
-    metadata.push(format!("Image Dimensions: {}x{}", width, height));
+    metadata.push(MetadataItem::new("Image Dimensions", format!("{}x{}", width, height)));
     
     // Get color type
     let color_type = format!("{:?}", img.color());
-    metadata.push(format!("Color Type: {}", color_type));
+    metadata.push(MetadataItem::new("Color Type", color_type));
     
     // Format-specific metadata could be added here
     
     metadata
 }
 
-fn extract_exif_metadata(path: &Path) -> Result<Vec<String>> {
+pub fn extract_exif_metadata(path: &Path) -> Result<Vec<MetadataItem>> {
     let file = File::open(path)?;
     let mut reader = BufReader::new(&file);
     let exif = Reader::new().read_from_container(&mut reader)?;
-    
+    let document = matches!(crate::detect_format(path).as_deref(), Some("tiff" | "tif"));
+    Ok(describe_exif(&exif, document))
+}
+
+/// Lists the fields of an EXIF block given as bare TIFF data, as stored in the Exif
+/// boxes of JPEG 2000 and JPEG XL files
+pub(crate) fn extract_exif_fields(tiff: &[u8]) -> Vec<MetadataItem> {
+    Reader::new().read_raw(tiff.to_vec()).map(|exif| describe_exif(&exif, false)).unwrap_or_default()
+}
+
+/// Lists what `exif` holds. For a TIFF `document`, whose own pages are what's read, the
+/// second page isn't a thumbnail, and tags that describe how the pages are stored
+/// aren't EXIF metadata.
+fn describe_exif(exif: &exif::Exif, document: bool) -> Vec<MetadataItem> {
     let mut metadata = Vec::new();
     
     // Camera information
-    if let Some(make) = get_exif_string(&exif, Tag::Make, In::PRIMARY) {
-        metadata.push(format!("Camera Make: {}", make));
+    if let Some(make) = get_exif_string(exif, Tag::Make, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Camera Make", make));
     }
     
-    if let Some(model) = get_exif_string(&exif, Tag::Model, In::PRIMARY) {
-        metadata.push(format!("Camera Model: {}", model));
+    if let Some(model) = get_exif_string(exif, Tag::Model, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Camera Model", model));
     }
     
-    if let Some(software) = get_exif_string(&exif, Tag::Software, In::PRIMARY) {
-        metadata.push(format!("Software: {}", software));
+    if let Some(software) = get_exif_string(exif, Tag::Software, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Software", software));
     }
     
     // Date information
-    if let Some(date) = get_exif_string(&exif, Tag::DateTime, In::PRIMARY) {
-        metadata.push(format!("Date/Time: {}", date));
+    if let Some(date) = get_exif_string(exif, Tag::DateTime, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Date/Time", date));
     }
     
-    if let Some(date) = get_exif_string(&exif, Tag::DateTimeOriginal, In::PRIMARY) {
-        metadata.push(format!("Original Date/Time: {}", date));
+    if let Some(date) = get_exif_string(exif, Tag::DateTimeOriginal, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Original Date/Time", date));
     }
     
     // GPS information
@@ -101,106 +1172,178 @@ fn extract_exif_metadata(path: &Path) -> Result<Vec<String>> {
                   exif.get_field(Tag::GPSLongitude, In::PRIMARY).is_some();
     
     if has_gps {
-        // Format GPS coordinates if available
-        let mut gps_info = String::from("GPS Location: ");
-        let mut has_coords = false;
-        
-        if let (Some(lat), Some(lat_ref)) = (
-            exif.get_field(Tag::GPSLatitude, In::PRIMARY),
-            exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY)
-        ) {
-            if let (Value::Rational(lat_vals), Value::Ascii(lat_ref_vals)) = (&lat.value, &lat_ref.value) {
-                if lat_vals.len() >= 3 && !lat_ref_vals.is_empty() {
-                    let lat_deg = lat_vals[0].to_f64();
-                    let lat_min = lat_vals[1].to_f64();
-                    let lat_sec = lat_vals[2].to_f64();
-                    let lat_ref = std::str::from_utf8(&lat_ref_vals[0]).unwrap_or("N");
-                    
-                    gps_info.push_str(&format!("{:.6}° {:.6}' {:.6}\" {} ", 
-                        lat_deg, lat_min, lat_sec, lat_ref));
-                    has_coords = true;
-                }
-            }
-        }
-        
-        if let (Some(lon), Some(lon_ref)) = (
-            exif.get_field(Tag::GPSLongitude, In::PRIMARY),
-            exif.get_field(Tag::GPSLongitudeRef, In::PRIMARY)
+        match (
+            gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef),
+            gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef),
         ) {
-            if let (Value::Rational(lon_vals), Value::Ascii(lon_ref_vals)) = (&lon.value, &lon_ref.value) {
-                if lon_vals.len() >= 3 && !lon_ref_vals.is_empty() {
-                    let lon_deg = lon_vals[0].to_f64();
-                    let lon_min = lon_vals[1].to_f64();
-                    let lon_sec = lon_vals[2].to_f64();
-                    let lon_ref = std::str::from_utf8(&lon_ref_vals[0]).unwrap_or("E");
-                    
-                    gps_info.push_str(&format!("{:.6}° {:.6}' {:.6}\" {}", 
-                        lon_deg, lon_min, lon_sec, lon_ref));
-                    has_coords = true;
-                }
+            (Some(lat), Some(lon)) => {
+                metadata.push(MetadataItem::new("GPS Location", format!("{:.6}, {:.6}", lat, lon)));
             }
+            _ => metadata.push(MetadataItem::new("GPS Data", "Present but could not be parsed")),
         }
-        
-        if has_coords {
-            metadata.push(gps_info);
-        } else {
-            metadata.push("GPS Data: Present but could not be parsed".to_string());
-        }
+    }
+
+    if let Some(altitude) = gps_altitude(exif) {
+        metadata.push(MetadataItem::new("GPS Altitude", format!("{:.1} m", altitude)));
+    }
+
+    if let Some(timestamp) = gps_timestamp(exif) {
+        metadata.push(MetadataItem::new("GPS Timestamp", timestamp));
     }
     
     // Other important EXIF tags
-    if let Some(exposure) = get_exif_string(&exif, Tag::ExposureTime, In::PRIMARY) {
-        metadata.push(format!("Exposure Time: {}", exposure));
+    if let Some(exposure) = get_exif_string(exif, Tag::ExposureTime, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Exposure Time", exposure));
     }
     
-    if let Some(aperture) = get_exif_string(&exif, Tag::FNumber, In::PRIMARY) {
-        metadata.push(format!("Aperture: {}", aperture));
+    if let Some(aperture) = get_exif_string(exif, Tag::FNumber, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Aperture", aperture));
     }
     
-    if let Some(iso) = get_exif_string(&exif, Tag::ISOSpeed, In::PRIMARY) {
-        metadata.push(format!("ISO: {}", iso));
+    if let Some(iso) = get_exif_string(exif, Tag::ISOSpeed, In::PRIMARY) {
+        metadata.push(MetadataItem::new("ISO", iso));
     }
 
     // Check some additional tags
-    if let Some(orientation) = get_exif_string(&exif, Tag::Orientation, In::PRIMARY) {
-        metadata.push(format!("Orientation: {}", orientation));
+    if let Some(orientation) = get_exif_string(exif, Tag::Orientation, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Orientation", orientation));
     }
     
-    if let Some(xres) = get_exif_string(&exif, Tag::XResolution, In::PRIMARY) {
-        metadata.push(format!("X Resolution: {}", xres));
+    if let Some(xres) = get_exif_string(exif, Tag::XResolution, In::PRIMARY) {
+        metadata.push(MetadataItem::new("X Resolution", xres));
     }
     
-    if let Some(yres) = get_exif_string(&exif, Tag::YResolution, In::PRIMARY) {
-        metadata.push(format!("Y Resolution: {}", yres));
+    if let Some(yres) = get_exif_string(exif, Tag::YResolution, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Y Resolution", yres));
     }
     
-    if let Some(resolution_unit) = get_exif_string(&exif, Tag::ResolutionUnit, In::PRIMARY) {
-        metadata.push(format!("Resolution Unit: {}", resolution_unit));
+    if let Some(resolution_unit) = get_exif_string(exif, Tag::ResolutionUnit, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Resolution Unit", resolution_unit));
     }
     
-    if let Some(copyright) = get_exif_string(&exif, Tag::Copyright, In::PRIMARY) {
-        metadata.push(format!("Copyright: {}", copyright));
+    if let Some(copyright) = get_exif_string(exif, Tag::Copyright, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Copyright", copyright));
     }
     
-    if let Some(artist) = get_exif_string(&exif, Tag::Artist, In::PRIMARY) {
-        metadata.push(format!("Artist: {}", artist));
+    if let Some(artist) = get_exif_string(exif, Tag::Artist, In::PRIMARY) {
+        metadata.push(MetadataItem::new("Artist", artist));
     }
     
-    if metadata.is_empty() {
-        metadata.push("EXIF metadata was present but no readable values were found".to_string());
+    // Serial numbers and the owner name tie a photo to one camera, however ordinary
+    // the make and model
+    for (tag, name) in [
+        (Tag::BodySerialNumber, "Body Serial Number"),
+        (Tag::LensSerialNumber, "Lens Serial Number"),
+        (Tag::CameraOwnerName, "Camera Owner Name"),
+    ] {
+        if let Some(value) = get_exif_string(exif, tag, In::PRIMARY) {
+            metadata.push(MetadataItem::new(name, value));
+        }
+    }
+    let camera_serial = exif.fields().find(|f| f.ifd_num == In::PRIMARY && f.tag.number() == TIFF_TAG_CAMERA_SERIAL_NUMBER);
+    if let Some(field) = camera_serial {
+        metadata.push(MetadataItem::new("Camera Serial Number", field.display_value().to_string()));
+    }
+
+    // Maker notes are removed whole, with whatever serial numbers can be read out of them
+    if let Some(Value::Undefined(note, offset)) = exif.get_field(Tag::MakerNote, In::PRIMARY).map(|f| &f.value) {
+        metadata.push(MetadataItem::new("Maker Notes", format!("{} bytes", note.len())));
+        let make = match exif.get_field(Tag::Make, In::PRIMARY).map(|f| &f.value) {
+            Some(Value::Ascii(values)) => values.first().map(|make| String::from_utf8_lossy(make).into_owned()),
+            _ => None,
+        };
+        let note = *offset as usize..*offset as usize + note.len();
+        let big_endian = !exif.little_endian();
+        metadata.extend(makernote::identifying_fields(exif.buf(), big_endian, &make.unwrap_or_default(), note));
+    }
+
+    // TIFF files can carry an XMP packet as a tag of their own
+    if let Some(field) = exif.fields().find(|f| f.ifd_num == In::PRIMARY && f.tag.number() == TIFF_TAG_XMP) {
+        if let Value::Byte(packet) | Value::Undefined(packet, _) = &field.value {
+            metadata.extend(extract_xmp_fields(&String::from_utf8_lossy(packet)));
+        }
+    }
+
+    // The thumbnail may still show what was cropped out of or edited in the main image
+    if let Some(size) = exif_thumbnail_size(exif).filter(|_| !document) {
+        metadata.push(MetadataItem::new(format!("Embedded thumbnail ({} KB)", size.div_ceil(1024)), ""));
+    }
+
+    let has_exif = !document || exif.fields().any(|field| field.tag.context() != exif::Context::Tiff);
+    if metadata.is_empty() && has_exif {
+        metadata.push(MetadataItem::new("EXIF metadata was present but no readable values were found", ""));
     }
     
-    Ok(metadata)
+    metadata
 }
 
 fn get_exif_string(exif: &exif::Exif, tag: Tag, ifd: In) -> Option<String> {
     exif.get_field(tag, ifd).map(|field| field.display_value().to_string())
 }
 
+/// Reads a degrees/minutes/seconds GPS tag as signed decimal degrees, negative
+/// south of the equator and west of Greenwich
+fn gps_coordinate(exif: &exif::Exif, tag: Tag, ref_tag: Tag) -> Option<f64> {
+    let Value::Rational(dms) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    if dms.len() < 3 {
+        return None;
+    }
+    let hemisphere = match exif.get_field(ref_tag, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Ascii(refs)) => refs.first().and_then(|r| r.first()).copied().unwrap_or(b'N'),
+        _ => b'N',
+    };
+    Some(dms_to_decimal(dms[0].to_f64(), dms[1].to_f64(), dms[2].to_f64(), hemisphere))
+}
+
+fn dms_to_decimal(degrees: f64, minutes: f64, seconds: f64, hemisphere: u8) -> f64 {
+    let decimal = degrees + minutes / 60.0 + seconds / 3600.0;
+    if matches!(hemisphere.to_ascii_uppercase(), b'S' | b'W') {
+        -decimal
+    } else {
+        decimal
+    }
+}
+
+/// Altitude in metres, negative below sea level
+fn gps_altitude(exif: &exif::Exif) -> Option<f64> {
+    let Value::Rational(altitude) = &exif.get_field(Tag::GPSAltitude, In::PRIMARY)?.value else {
+        return None;
+    };
+    let altitude = altitude.first()?.to_f64();
+    let below_sea_level = exif
+        .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        == Some(1);
+    Some(if below_sea_level { -altitude } else { altitude })
+}
+
+/// The satellite time of the fix, which is in UTC unlike the camera's own clock
+fn gps_timestamp(exif: &exif::Exif) -> Option<String> {
+    let time = match &exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)?.value {
+        Value::Rational(hms) if hms.len() >= 3 => format!(
+            "{:02}:{:02}:{:02}",
+            hms[0].to_f64() as u32,
+            hms[1].to_f64() as u32,
+            hms[2].to_f64() as u32
+        ),
+        _ => return None,
+    };
+    let date = match exif.get_field(Tag::GPSDateStamp, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Ascii(dates)) => dates.first().map(|date| String::from_utf8_lossy(date).trim().to_string()),
+        _ => None,
+    };
+    Some(match date {
+        Some(date) if !date.is_empty() => format!("{} {} UTC", date, time),
+        _ => format!("{} UTC", time),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::NamedTempFile;
+    use tempfile::{Builder, NamedTempFile};
 
     #[test]
     fn test_strip_image_metadata() {
@@ -210,11 +1353,711 @@ mod tests {
 
         // Create a simple test image
         let img = image::RgbImage::new(100, 100);
-        img.save(&input).unwrap();
+        img.save_with_format(&input, ImageFormat::Png).unwrap();
 
         // Test stripping metadata
-        let result = strip_image_metadata(input.path(), output.path());
+        let result = strip_image_metadata(input.path(), output.path(), &[], false, &[]);
         assert!(result.is_ok());
         assert!(!result.unwrap().is_empty());
     }
+
+    #[test]
+    fn test_strip_jpeg_lossless() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128]));
+        img.save(&input).unwrap();
+        let original = fs::read(&input).unwrap();
+
+        // Inject an EXIF-like APP1 segment and a comment right after SOI
+        let mut tagged = original[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, JPEG_APP1, 0x00, 0x08]);
+        tagged.extend_from_slice(b"Exif\0\0");
+        tagged.extend_from_slice(&[0xFF, JPEG_COM, 0x00, 0x07]);
+        tagged.extend_from_slice(b"hello");
+        tagged.extend_from_slice(&original[2..]);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("JPEG Comment", "hello")));
+
+        // The metadata segments are gone and everything else is byte-identical
+        assert_eq!(fs::read(output.path()).unwrap(), original);
+
+        // Comments are content, so they can be kept
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Content], false, &[]).unwrap();
+        assert!(!removed.iter().any(|item| item.tag == "JPEG Comment"));
+        assert!(fs::read(output.path()).unwrap().windows(5).any(|w| w == b"hello"));
+    }
+
+    #[test]
+    fn test_strip_jpeg_without_decoding() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        // An arithmetic-coded CMYK frame, which the decoder doesn't support
+        let mut frame = vec![0xFF, 0xD8];
+        frame.extend_from_slice(&[0xFF, 0xC9, 0x00, 0x14, 0x08, 0x00, 0x20, 0x00, 0x30, 0x04]);
+        for id in 1..=4 {
+            frame.extend_from_slice(&[id, 0x11, 0x00]);
+        }
+        frame.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, 0x12, 0x34]);
+        frame.extend_from_slice(&[0xFF, 0xD9]);
+        let mut tagged = frame[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, JPEG_COM, 0x00, 0x07]);
+        tagged.extend_from_slice(b"hello");
+        tagged.extend_from_slice(&frame[2..]);
+        fs::write(&input, &tagged).unwrap();
+        assert!(image::open(input.path()).is_err());
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("JPEG Comment", "hello")));
+        assert!(removed.contains(&MetadataItem::new("Image Dimensions", "48x32")));
+        assert!(removed.contains(&MetadataItem::new("Color Type", "Cmyk8")));
+        assert_eq!(fs::read(output.path()).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_strip_jpeg_removes_iptc() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128]));
+        img.save(&input).unwrap();
+        let original = fs::read(&input).unwrap();
+
+        // Photoshop resource block holding an IPTC By-line and City
+        let iim = b"\x1c\x02\x50\x00\x08Jane Doe\x1c\x02\x5a\x00\x06Berlin";
+        let mut payload = crate::iptc::PHOTOSHOP_HEADER.to_vec();
+        payload.extend_from_slice(b"8BIM\x04\x04\0\0");
+        payload.extend_from_slice(&(iim.len() as u32).to_be_bytes());
+        payload.extend_from_slice(iim);
+
+        let mut tagged = original[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, JPEG_APP13]);
+        tagged.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        tagged.extend_from_slice(&payload);
+        tagged.extend_from_slice(&original[2..]);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Author], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::with_category(Category::Author, "IPTC By-line", "Jane Doe")));
+        assert!(removed.contains(&MetadataItem::with_category(Category::Gps, "IPTC City", "Berlin")));
+        assert_eq!(fs::read(output.path()).unwrap(), original);
+    }
+
+    /// Writes a JPEG whose EXIF block holds `fields` and optionally a thumbnail
+    fn write_jpeg_with_exif(path: &Path, width: u32, height: u32, fields: &[Field], thumbnail: Option<&[u8]>) {
+        let img = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128]));
+        img.save_with_format(path, ImageFormat::Jpeg).unwrap();
+        let original = fs::read(path).unwrap();
+
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        if let Some(thumbnail) = thumbnail {
+            writer.set_jpeg(thumbnail, In::THUMBNAIL);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut tagged = original[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, JPEG_APP1]);
+        tagged.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        tagged.extend_from_slice(EXIF_HEADER);
+        tagged.extend_from_slice(&tiff);
+        tagged.extend_from_slice(&original[2..]);
+        fs::write(path, &tagged).unwrap();
+    }
+
+    #[test]
+    fn test_strip_jpeg_keeps_categories() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        let orientation = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) };
+        let artist = Field { tag: Tag::Artist, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Jane Doe".to_vec()]) };
+        write_jpeg_with_exif(input.path(), 16, 16, &[orientation, artist], None);
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Orientation], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("Artist", "\"Jane Doe\"")));
+        assert!(!removed.iter().any(|item| item.category == Category::Orientation));
+
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(File::open(output.path()).unwrap()))
+            .unwrap();
+        assert_eq!(exif.get_field(Tag::Orientation, In::PRIMARY).unwrap().value.get_uint(0), Some(6));
+        assert!(exif.get_field(Tag::Artist, In::PRIMARY).is_none());
+        assert_eq!(image::open(output.path()).unwrap().width(), 16);
+    }
+
+    #[test]
+    fn test_strip_jpeg_removes_camera_fingerprint() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        // Canon maker notes with the body serial number inline, in the big-endian order
+        // the test EXIF block is written in
+        let mut note = 1u16.to_be_bytes().to_vec();
+        note.extend_from_slice(&[0x00, 0x0C, 0x00, 0x04, 0, 0, 0, 1]);
+        note.extend_from_slice(&987_654u32.to_be_bytes());
+        note.extend_from_slice(&[0; 4]);
+        let ascii = |tag, value: &str| Field { tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![value.as_bytes().to_vec()]) };
+        let fields = [
+            ascii(Tag::Make, "Canon"),
+            ascii(Tag::BodySerialNumber, "123456"),
+            ascii(Tag::CameraOwnerName, "Jane Doe"),
+            Field { tag: Tag::MakerNote, ifd_num: In::PRIMARY, value: Value::Undefined(note, 0) },
+        ];
+        write_jpeg_with_exif(input.path(), 16, 16, &fields, None);
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Device], false, &[]).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Body Serial Number: \"123456\"".to_string()));
+        assert!(removed.contains(&"Camera Owner Name: \"Jane Doe\"".to_string()));
+        assert!(removed.contains(&"Maker Notes: 18 bytes".to_string()));
+        assert!(removed.contains(&"Body Serial Number (Maker Notes): 987654".to_string()));
+        assert!(!removed.iter().any(|item| item.starts_with("Camera Make")));
+
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(File::open(output.path()).unwrap()))
+            .unwrap();
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+        for tag in [Tag::BodySerialNumber, Tag::CameraOwnerName, Tag::MakerNote] {
+            assert!(exif.get_field(tag, In::PRIMARY).is_none());
+        }
+    }
+
+    #[test]
+    fn test_set_writes_exif() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        let orientation = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) };
+        let artist = Field { tag: Tag::Artist, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Jane Doe".to_vec()]) };
+        write_jpeg_with_exif(input.path(), 16, 16, &[orientation, artist], None);
+
+        let set: Vec<Assignment> = ["author=A. Nonymous", "date=2020-01-02"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Orientation], false, &set).unwrap();
+        assert!(removed.contains(&MetadataItem::new("Artist", "\"Jane Doe\"")));
+
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(File::open(output.path()).unwrap()))
+            .unwrap();
+        let text = |tag| get_exif_string(&exif, tag, In::PRIMARY);
+        assert_eq!(text(Tag::Artist).as_deref(), Some("\"A. Nonymous\""));
+        assert_eq!(text(Tag::DateTimeOriginal).as_deref(), Some("2020-01-02 00:00:00"));
+        assert_eq!(exif.get_field(Tag::Orientation, In::PRIMARY).unwrap().value.get_uint(0), Some(6));
+        assert_eq!(image::open(output.path()).unwrap().width(), 16);
+
+        // Images cleaned without any EXIF left get a new block
+        let png = Builder::new().suffix(".png").tempfile().unwrap();
+        image::RgbImage::new(4, 4).save_with_format(png.path(), ImageFormat::Png).unwrap();
+        let png_output = Builder::new().suffix(".png").tempfile().unwrap();
+        strip_image_metadata(png.path(), png_output.path(), &[], false, &set).unwrap();
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(File::open(png_output.path()).unwrap()))
+            .unwrap();
+        assert_eq!(get_exif_string(&exif, Tag::Artist, In::PRIMARY).as_deref(), Some("\"A. Nonymous\""));
+        assert_eq!(image::open(png_output.path()).unwrap().width(), 4);
+    }
+
+    #[test]
+    fn test_gps_decoded_to_decimal_degrees() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let rational = |values: &[(u32, u32)]| {
+            Value::Rational(values.iter().map(|&(num, denom)| exif::Rational { num, denom }).collect())
+        };
+        let ascii = |text: &[u8]| Value::Ascii(vec![text.to_vec()]);
+        let fields = [
+            (Tag::GPSLatitudeRef, ascii(b"N")),
+            (Tag::GPSLatitude, rational(&[(51, 1), (30, 1), (0, 1)])),
+            (Tag::GPSLongitudeRef, ascii(b"W")),
+            (Tag::GPSLongitude, rational(&[(0, 1), (7, 1), (3000, 100)])),
+            (Tag::GPSAltitudeRef, Value::Byte(vec![0])),
+            (Tag::GPSAltitude, rational(&[(355, 10)])),
+            (Tag::GPSTimeStamp, rational(&[(14, 1), (5, 1), (9, 1)])),
+            (Tag::GPSDateStamp, ascii(b"2024:05:01")),
+        ]
+        .map(|(tag, value)| Field { tag, ifd_num: In::PRIMARY, value });
+        write_jpeg_with_exif(input.path(), 16, 16, &fields, None);
+
+        let metadata = extract_exif_metadata(input.path()).unwrap();
+        assert!(metadata.contains(&MetadataItem::new("GPS Location", "51.500000, -0.125000")));
+        assert!(metadata.contains(&MetadataItem::new("GPS Altitude", "35.5 m")));
+        let timestamp = MetadataItem::new("GPS Timestamp", "2024:05:01 14:05:09 UTC");
+        assert!(metadata.contains(&timestamp));
+        assert_eq!(dms_to_decimal(33.0, 52.0, 4.8, b'S'), -(33.0 + 52.0 / 60.0 + 4.8 / 3600.0));
+    }
+
+    #[test]
+    fn test_strip_jpeg_removes_thumbnail() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        let artist = Field { tag: Tag::Artist, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Jane Doe".to_vec()]) };
+        let thumbnail = vec![0xAB; 2000];
+        write_jpeg_with_exif(input.path(), 16, 16, &[artist], Some(&thumbnail));
+        let read_exif = |path: &Path| Reader::new().read_from_container(&mut BufReader::new(File::open(path).unwrap())).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Author], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("Embedded thumbnail (2 KB)", "")));
+        assert_eq!(removed.iter().find(|item| item.tag.starts_with("Embedded")).unwrap().category, Category::Thumbnails);
+        let exif = read_exif(output.path());
+        assert!(exif.get_field(Tag::Artist, In::PRIMARY).is_some());
+        assert!(exif_thumbnail(&exif).is_none());
+        assert!(!fs::read(output.path()).unwrap().windows(16).any(|w| w == [0xAB; 16]));
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Author, Category::Thumbnails], false, &[]).unwrap();
+        assert!(!removed.iter().any(|item| item.category == Category::Thumbnails));
+        assert_eq!(exif_thumbnail(&read_exif(output.path())), Some(&thumbnail[..]));
+    }
+
+    #[test]
+    fn test_strip_jpeg_rotates_pixels() {
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+
+        let orientation = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) };
+        write_jpeg_with_exif(input.path(), 16, 8, &[orientation], None);
+
+        strip_image_metadata(input.path(), output.path(), &[], true, &[]).unwrap();
+
+        let rotated = image::open(output.path()).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (8, 16));
+        assert_eq!(exif_orientation(output.path()), None);
+    }
+
+    fn png_chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(chunk_type);
+        chunk.extend_from_slice(data);
+        // The stripper doesn't validate CRCs, so a placeholder is fine
+        chunk.extend_from_slice(&[0, 0, 0, 0]);
+        chunk
+    }
+
+    #[test]
+    fn test_strip_png_chunks() {
+        let input = Builder::new().suffix(".png").tempfile().unwrap();
+        let output = Builder::new().suffix(".png").tempfile().unwrap();
+
+        let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128]));
+        img.save(&input).unwrap();
+        let original = fs::read(&input).unwrap();
+
+        // Insert text and time chunks right after IHDR (signature + 25 byte IHDR chunk)
+        let ihdr_end = PNG_SIGNATURE.len() + 25;
+        let mut tagged = original[..ihdr_end].to_vec();
+        tagged.extend(png_chunk(b"tEXt", b"Author\0Jane Doe"));
+        tagged.extend(png_chunk(b"tIME", &[0x07, 0xE8, 1, 2, 3, 4, 5]));
+        tagged.extend_from_slice(&original[ihdr_end..]);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"PNG Text (Author): Jane Doe".to_string()));
+        assert!(removed.contains(&"PNG Modification Time: 2024-01-02 03:04:05".to_string()));
+
+        // Image chunks are copied verbatim, so the output matches the untagged original
+        assert_eq!(fs::read(output.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn test_strip_png_keeps_categories() {
+        let input = Builder::new().suffix(".png").tempfile().unwrap();
+        let output = Builder::new().suffix(".png").tempfile().unwrap();
+
+        let img = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128]));
+        img.save(&input).unwrap();
+        let original = fs::read(&input).unwrap();
+
+        let ihdr_end = PNG_SIGNATURE.len() + 25;
+        let copyright = png_chunk(b"tEXt", b"Copyright\0(c) Jane Doe");
+        let mut tagged = original[..ihdr_end].to_vec();
+        tagged.extend_from_slice(&copyright);
+        tagged.extend(png_chunk(b"tEXt", b"Author\0Jane Doe"));
+        tagged.extend_from_slice(&original[ihdr_end..]);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[Category::Copyright], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("PNG Text (Author)", "Jane Doe")));
+        assert!(!removed.iter().any(|item| item.category == Category::Copyright));
+
+        let mut expected = original[..ihdr_end].to_vec();
+        expected.extend_from_slice(&copyright);
+        expected.extend_from_slice(&original[ihdr_end..]);
+        assert_eq!(fs::read(output.path()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_png_crc() {
+        // CRC of an empty IEND chunk, as found at the end of every PNG
+        assert_eq!(png_crc(b"IEND", &[]), 0xAE42_6082);
+    }
+
+    fn riff_chunk(fourcc: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    #[test]
+    fn test_strip_webp_chunks() {
+        let input = Builder::new().suffix(".webp").tempfile().unwrap();
+        let output = Builder::new().suffix(".webp").tempfile().unwrap();
+
+        let img = image::RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8 * 16, y as u8 * 16, 128, 255]));
+        img.save(&input).unwrap();
+        let simple = fs::read(&input).unwrap();
+
+        // Convert to the extended format with EXIF and XMP chunks around the image data
+        let mut vp8x = vec![WEBP_FLAG_EXIF | WEBP_FLAG_XMP, 0, 0, 0];
+        vp8x.extend_from_slice(&[15, 0, 0, 15, 0, 0]);
+        let mut body = b"WEBP".to_vec();
+        body.extend(riff_chunk(b"VP8X", &vp8x));
+        body.extend_from_slice(&simple[12..]);
+        body.extend(riff_chunk(b"EXIF", b"MM\0\x2a\0\0\0\x08\0\0"));
+        body.extend(riff_chunk(b"XMP ", br#"<x:xmpmeta xmp:CreatorTool="Test Editor"/>"#));
+        let mut tagged = b"RIFF".to_vec();
+        tagged.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        tagged.extend(body);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"WebP EXIF Chunk: 10 bytes".to_string()));
+        assert!(removed.contains(&"XMP Creator Tool: Test Editor".to_string()));
+
+        let cleaned = fs::read(output.path()).unwrap();
+        assert!(!cleaned.windows(4).any(|w| w == b"EXIF" || w == b"XMP "));
+        assert_eq!(cleaned[20] & (WEBP_FLAG_EXIF | WEBP_FLAG_XMP), 0);
+        assert_eq!(u32::from_le_bytes([cleaned[4], cleaned[5], cleaned[6], cleaned[7]]) as usize, cleaned.len() - 8);
+        assert_eq!(image::open(output.path()).unwrap().width(), 16);
+    }
+
+    #[test]
+    fn test_icc_profile_survives_reencoding() {
+        let profile: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128])));
+
+        for (suffix, format) in [(".jpg", ImageFormat::Jpeg), (".png", ImageFormat::Png), (".tiff", ImageFormat::Tiff)] {
+            let input = Builder::new().suffix(suffix).tempfile().unwrap();
+            let output = Builder::new().suffix(suffix).tempfile().unwrap();
+            save_image(&img, input.path(), format, Some(&profile)).unwrap();
+            assert_eq!(read_icc_profile(input.path(), format).as_ref(), Some(&profile), "{:?}", format);
+
+            let removed = strip_image_metadata(input.path(), output.path(), &[Category::ColorProfile], false, &[]).unwrap();
+            assert!(!removed.iter().any(|item| item.category == Category::ColorProfile));
+            assert_eq!(read_icc_profile(output.path(), format).as_ref(), Some(&profile), "{:?}", format);
+            assert_eq!(image::open(output.path()).unwrap().width(), 16);
+
+            let removed = strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+            assert!(removed.iter().any(|item| item.category == Category::ColorProfile), "{:?}", format);
+            assert_eq!(read_icc_profile(output.path(), format), None, "{:?}", format);
+        }
+
+        // JPEG numbers the chunks of a profile in one byte
+        assert!(embed_jpeg_icc(&[0xFF, 0xD8], &vec![0; JPEG_ICC_CHUNK * 255]).is_ok());
+        assert!(embed_jpeg_icc(&[0xFF, 0xD8], &vec![0; JPEG_ICC_CHUNK * 255 + 1]).is_err());
+    }
+
+    const TEST_XMP: &[u8] = br#"<x:xmpmeta><rdf:Description xmp:CreatorTool="Test Editor" xmpMM:DocumentID="uuid:42"/></x:xmpmeta>"#;
+
+    #[test]
+    fn test_strip_xmp_packets() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 128])));
+        let keep = [Category::Software, Category::Other];
+
+        // JPEG: APP1 segment with the XMP namespace header
+        let input = Builder::new().suffix(".jpg").tempfile().unwrap();
+        let output = Builder::new().suffix(".jpg").tempfile().unwrap();
+        img.save(&input).unwrap();
+        let original = fs::read(&input).unwrap();
+        let mut tagged = original[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, JPEG_APP1]);
+        tagged.extend_from_slice(&((XMP_HEADER.len() + TEST_XMP.len() + 2) as u16).to_be_bytes());
+        tagged.extend_from_slice(XMP_HEADER);
+        tagged.extend_from_slice(TEST_XMP);
+        tagged.extend_from_slice(&original[2..]);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &keep, false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("XMP Creator Tool", "Test Editor")));
+        assert_eq!(fs::read(output.path()).unwrap(), original);
+
+        // PNG: iTXt chunk with the XMP keyword
+        let input = Builder::new().suffix(".png").tempfile().unwrap();
+        let output = Builder::new().suffix(".png").tempfile().unwrap();
+        img.save(&input).unwrap();
+        let original = fs::read(&input).unwrap();
+        let ihdr_end = PNG_SIGNATURE.len() + 25;
+        let mut itxt = PNG_XMP_KEYWORD.to_vec();
+        itxt.extend_from_slice(&[0, 0, 0, 0]);
+        itxt.extend_from_slice(TEST_XMP);
+        let mut tagged = original[..ihdr_end].to_vec();
+        tagged.extend(png_chunk(b"iTXt", &itxt));
+        tagged.extend_from_slice(&original[ihdr_end..]);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &keep, false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("XMP Document ID", "uuid:42")));
+        assert_eq!(fs::read(output.path()).unwrap(), original);
+
+        // TIFF: tag 700 in the first IFD
+        let input = Builder::new().suffix(".tiff").tempfile().unwrap();
+        let output = Builder::new().suffix(".tiff").tempfile().unwrap();
+        img.save(&input).unwrap();
+        fs::write(&input, add_tiff_tag(&fs::read(&input).unwrap(), TIFF_TAG_XMP, TEST_XMP).unwrap()).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &keep, false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("XMP Creator Tool", "Test Editor")));
+        assert!(!fs::read(output.path()).unwrap().windows(TEST_XMP.len()).any(|w| w == TEST_XMP));
+    }
+
+    // Little-endian TIFF with a 1x1 grayscale page for each pixel value, each page
+    // naming its artist and the first one also rotated
+    fn build_test_tiff(pixels: &[u8]) -> Vec<u8> {
+        let entry = |tag: u16, field_type: u16, count: u32, value: u32| {
+            let mut bytes = tag.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&field_type.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes
+        };
+
+        let mut data = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        for (index, &pixel) in pixels.iter().enumerate() {
+            // 10 entries -> 126 bytes, then the artist and the pixel
+            let offset = data.len() as u32;
+            let next = if index + 1 < pixels.len() { offset + 138 } else { 0 };
+            data.extend_from_slice(&10u16.to_le_bytes());
+            for (tag, value) in [(256, 1), (257, 1), (258, 8), (259, 1), (262, 1)] {
+                data.extend(entry(tag, 3, 1, value));
+            }
+            data.extend(entry(273, 4, 1, offset + 136));
+            data.extend(entry(274, 3, 1, if index == 0 { 6 } else { 1 }));
+            data.extend(entry(278, 3, 1, 1));
+            data.extend(entry(279, 4, 1, 1));
+            data.extend(entry(315, 2, 9, offset + 126));
+            data.extend_from_slice(&next.to_le_bytes());
+            data.extend_from_slice(b"Jane Doe\0\0");
+            data.extend_from_slice(&[pixel, 0]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_strip_tiff_keeps_pages() {
+        let input = Builder::new().suffix(".tiff").tempfile().unwrap();
+        let output = Builder::new().suffix(".tiff").tempfile().unwrap();
+        let original = build_test_tiff(&[10, 20, 30]);
+        fs::write(&input, &original).unwrap();
+
+        // Rotating can't flatten the pages, so the orientation stays as a tag
+        let removed = strip_image_metadata(input.path(), output.path(), &[], true, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::with_category(Category::Technical, "Pages", "3")));
+        assert_eq!(removed.iter().filter(|item| item.tag == "Artist").count(), 1);
+
+        let cleaned = fs::read(output.path()).unwrap();
+        assert_eq!(cleaned.len(), original.len());
+        assert_eq!(raw::count_tiff_pages(&cleaned), 3);
+        assert!(!cleaned.windows(8).any(|w| w == b"Jane Doe"));
+        for (page, pixel) in [10, 20, 30].into_iter().enumerate() {
+            assert_eq!(cleaned[8 + page * 138 + 136], pixel);
+        }
+        assert_eq!(exif_orientation(output.path()), Some(6));
+        assert_eq!(image::open(output.path()).unwrap().to_luma8().into_raw(), vec![10]);
+    }
+
+    #[test]
+    fn test_strip_tiff_verifies_clean() {
+        let input = Builder::new().suffix(".tiff").tempfile().unwrap();
+        let output = Builder::new().suffix(".tiff").tempfile().unwrap();
+        fs::write(&input, build_test_tiff(&[10, 20, 30])).unwrap();
+
+        let stripper = crate::MetaStripper::new();
+        stripper.strip_file_to(input.path(), output.path()).unwrap();
+        assert_eq!(raw::count_tiff_pages(&fs::read(output.path()).unwrap()), 3);
+        let report = stripper.verify_file(output.path()).unwrap();
+        assert!(report.metadata.is_empty(), "{:?}", report.metadata);
+        // The original still has its metadata reported
+        assert!(!stripper.verify_file(input.path()).unwrap().metadata.is_empty());
+        // A single page, as other programs write it
+        image::GrayImage::from_pixel(4, 4, image::Luma([90])).save(input.path()).unwrap();
+        fs::write(&input, add_tiff_tag(&fs::read(&input).unwrap(), TIFF_TAG_XMP, TEST_XMP).unwrap()).unwrap();
+        stripper.strip_file_to(input.path(), output.path()).unwrap();
+        let report = stripper.verify_file(output.path()).unwrap();
+        assert!(report.metadata.is_empty(), "{:?}", report.metadata);
+    }
+
+    #[test]
+    fn test_strip_gif_keeps_animation() {
+        use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+        use image::{AnimationDecoder, Delay, Frame};
+
+        let input = Builder::new().suffix(".gif").tempfile().unwrap();
+        let output = Builder::new().suffix(".gif").tempfile().unwrap();
+
+        let mut original = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut original);
+            encoder.set_repeat(Repeat::Infinite).unwrap();
+            let frames = (0..3u8).map(|i| {
+                let buffer = image::RgbaImage::from_pixel(8, 8, image::Rgba([i * 80, 0, 0, 255]));
+                Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        // Comment and application extensions just before the trailer
+        let mut tagged = original[..original.len() - 1].to_vec();
+        tagged.extend_from_slice(b"\x21\xFE\x0CMade by Jane\x00");
+        tagged.extend_from_slice(b"\x21\xFF\x0BMYAPP   1.0\x03abc\x00");
+        tagged.push(GIF_TRAILER);
+        fs::write(&input, &tagged).unwrap();
+
+        let removed = strip_image_metadata(input.path(), output.path(), &[], false, &[]).unwrap();
+        assert!(removed.contains(&MetadataItem::new("GIF Comment", "Made by Jane")));
+        assert!(removed.contains(&MetadataItem::new("GIF Application Extension (MYAPP   1.0)", "3 bytes")));
+
+        // Frames, delays and the loop count are untouched
+        let cleaned = fs::read(output.path()).unwrap();
+        assert_eq!(cleaned, original);
+        let frames = GifDecoder::new(Cursor::new(cleaned)).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].delay(), Delay::from_numer_denom_ms(100, 1));
+    }
+
+    fn orientation_exif(orientation: u16) -> Vec<u8> {
+        let field = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![orientation]) };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        tiff.into_inner()
+    }
+
+    /// Two-frame APNG, rotated by its EXIF orientation
+    fn build_test_apng() -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(8, 4, image::Rgb([200, 0, 0]));
+        let mut still = Vec::new();
+        DynamicImage::ImageRgb8(img).write_to(&mut Cursor::new(&mut still), ImageFormat::Png).unwrap();
+
+        // Gather the IDAT data of the still image to reuse for both frames
+        let mut idat = Vec::new();
+        let mut pos = PNG_SIGNATURE.len() + 25;
+        while pos + 8 <= still.len() {
+            let length = u32::from_be_bytes(still[pos..pos + 4].try_into().unwrap()) as usize;
+            if &still[pos + 4..pos + 8] == b"IDAT" {
+                idat.extend_from_slice(&still[pos + 8..pos + 8 + length]);
+            }
+            pos += 12 + length;
+        }
+
+        let frame_control = |sequence: u32| {
+            let mut fctl = sequence.to_be_bytes().to_vec();
+            for value in [8u32, 4, 0, 0] {
+                fctl.extend_from_slice(&value.to_be_bytes());
+            }
+            fctl.extend_from_slice(&[0, 1, 0, 10, 0, 0]);
+            fctl
+        };
+
+        let mut apng = still[..PNG_SIGNATURE.len() + 25].to_vec();
+        write_png_chunk(&mut apng, b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]);
+        write_png_chunk(&mut apng, b"eXIf", &orientation_exif(6));
+        write_png_chunk(&mut apng, b"fcTL", &frame_control(0));
+        write_png_chunk(&mut apng, b"IDAT", &idat);
+        write_png_chunk(&mut apng, b"fcTL", &frame_control(1));
+        let mut fdat = 2u32.to_be_bytes().to_vec();
+        fdat.extend_from_slice(&idat);
+        write_png_chunk(&mut apng, b"fdAT", &fdat);
+        write_png_chunk(&mut apng, b"IEND", &[]);
+        apng
+    }
+
+    /// Two-frame animated WebP, rotated by its EXIF orientation
+    fn build_test_animated_webp() -> Vec<u8> {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(8, 4, image::Rgba([0, 200, 0, 255])));
+        let mut still = Vec::new();
+        img.write_to(&mut Cursor::new(&mut still), ImageFormat::WebP).unwrap();
+
+        // Canvas flags (animation and EXIF) and size, minus one
+        let vp8x = [0x02 | WEBP_FLAG_EXIF, 0, 0, 0, 7, 0, 0, 3, 0, 0];
+        let mut body = b"WEBP".to_vec();
+        body.extend(riff_chunk(b"VP8X", &vp8x));
+        // Background color and loop count
+        body.extend(riff_chunk(b"ANIM", &[0, 0, 0, 0, 0, 0]));
+        for _ in 0..2 {
+            // Offset, size minus one and duration as 24-bit values, then flags, then the image
+            let mut frame = vec![0, 0, 0, 0, 0, 0, 7, 0, 0, 3, 0, 0, 100, 0, 0, 0];
+            frame.extend_from_slice(&still[12..]);
+            body.extend(riff_chunk(b"ANMF", &frame));
+        }
+        body.extend(riff_chunk(b"EXIF", &orientation_exif(6)));
+
+        let mut webp = b"RIFF".to_vec();
+        webp.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        webp.extend(body);
+        webp
+    }
+
+    #[test]
+    fn test_animated_images_keep_all_frames() {
+        use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+        use image::codecs::webp::WebPDecoder;
+        use image::{AnimationDecoder, Delay, Frame};
+
+        let mut gif = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            encoder.set_repeat(Repeat::Infinite).unwrap();
+            let frames = (0..2u8).map(|i| {
+                let buffer = image::RgbaImage::from_pixel(8, 4, image::Rgba([0, 0, i * 200, 255]));
+                Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        let cases = [(".png", build_test_apng()), (".gif", gif), (".webp", build_test_animated_webp())];
+        for (suffix, data) in cases {
+            let input = Builder::new().suffix(suffix).tempfile().unwrap();
+            let output = Builder::new().suffix(suffix).tempfile().unwrap();
+            fs::write(&input, &data).unwrap();
+
+            // Asking for rotation must not flatten the animation
+            strip_image_metadata(input.path(), output.path(), &[], true, &[]).unwrap();
+
+            let cleaned = Cursor::new(fs::read(output.path()).unwrap());
+            let frames = match suffix {
+                ".png" => PngDecoder::new(cleaned).unwrap().apng().into_frames().collect_frames(),
+                ".gif" => GifDecoder::new(cleaned).unwrap().into_frames().collect_frames(),
+                _ => WebPDecoder::new(cleaned).unwrap().into_frames().collect_frames(),
+            };
+            let frames = frames.unwrap();
+            assert_eq!(frames.len(), 2, "{}", suffix);
+            assert_eq!(frames[0].buffer().dimensions(), (8, 4), "{}", suffix);
+
+            // The rotation survives as the orientation tag instead
+            if suffix != ".gif" {
+                assert_eq!(exif_orientation(output.path()), Some(6), "{}", suffix);
+            }
+        }
+    }
+
+    #[test]
+    fn test_strip_jpeg_segments_rejects_non_jpeg() {
+        assert!(strip_jpeg_segments(b"not a jpeg", &[]).is_err());
+    }
 } 
\ No newline at end of file
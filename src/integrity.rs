@@ -0,0 +1,245 @@
+// Safeguards against a stripper that damages the content it was only meant to clean:
+// before a cleaned file takes its place, it is compared with the original, decoded
+// where the format allows, and refused if it shrank too much or no longer shows,
+// plays or pages the same.
+
+use image::{DynamicImage, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::{Category, MetadataItem};
+use crate::video::{self, FfmpegConfig};
+use crate::FileType;
+
+const INTEGRITY_WARNING: &str = "Warning: integrity";
+const IDENTICAL_WARNING: &str = "Warning: content changed";
+
+/// Checks each cleaned file has to pass before it replaces or joins the originals
+#[derive(Debug, Clone, Default)]
+pub struct Safeguards {
+    /// Refuse outputs more than this many percent smaller than the original. Metadata
+    /// is rarely most of a file, so a big drop usually means lost content.
+    pub max_shrink: Option<f64>,
+    /// Decode the output and compare it with the original: the dimensions and a
+    /// checksum of the pixels of images, the duration of videos and audio, and the
+    /// page count of PDFs
+    pub check_content: bool,
+    /// Keep outputs that fail a check, with a warning notice in the report, instead of
+    /// refusing them
+    pub warn_only: bool,
+    /// Report whether the pixels of images, or the packets of video and audio streams,
+    /// are unchanged, for formats that are cleaned without re-encoding
+    pub report_identical: bool,
+}
+
+impl Safeguards {
+    fn is_enabled(&self) -> bool {
+        self.max_shrink.is_some() || self.check_content
+    }
+}
+
+/// What a file decodes to, as far as it can be compared
+#[derive(Debug, Clone, PartialEq)]
+enum Content {
+    Image { width: u32, height: u32, pixels: Vec<u8> },
+    Duration(f64),
+    Pages(usize),
+}
+
+/// Problems found comparing the cleaned `output` with the original at `input`, e.g.
+/// "the image is 0x0 instead of 640x480". With `rotated`, images may have had their
+/// orientation applied, so their pixels and the order of their dimensions may differ.
+pub(crate) fn check(
+    safeguards: &Safeguards,
+    file_type: FileType,
+    input: &Path,
+    output: &Path,
+    rotated: bool,
+    ffmpeg: &FfmpegConfig,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !safeguards.is_enabled() {
+        return problems;
+    }
+
+    if let (Some(limit), Ok(before), Ok(after)) = (safeguards.max_shrink, fs::metadata(input), fs::metadata(output)) {
+        let (before, after) = (before.len(), after.len());
+        let shrink = if before > 0 { before.saturating_sub(after) as f64 * 100.0 / before as f64 } else { 0.0 };
+        if shrink > limit {
+            problems.push(format!("it shrank by {:.0}%, more than the {}% allowed", shrink, limit));
+        }
+    }
+
+    // Originals that can't be decoded here give nothing to compare against
+    if safeguards.check_content {
+        if let Some(before) = content(file_type, input, ffmpeg) {
+            match content(file_type, output, ffmpeg) {
+                Some(after) => problems.extend(compare(&before, &after, rotated)),
+                None => problems.push("it no longer decodes".to_string()),
+            }
+        }
+    }
+    problems
+}
+
+/// Whether the cleaned `output` holds exactly the content of the original: "Pixel
+/// Identical" for images, "Streams Identical" for video and audio, plus a warning when
+/// it doesn't. Nothing is reported for formats that can't be compared this way.
+pub(crate) fn identical(
+    file_type: FileType,
+    input: &Path,
+    output: &Path,
+    rotated: bool,
+    ffmpeg: &FfmpegConfig,
+) -> Vec<MetadataItem> {
+    let (tag, same) = match file_type {
+        FileType::Image => match (content(file_type, input, ffmpeg), content(file_type, output, ffmpeg)) {
+            (Some(Content::Image { pixels, .. }), Some(Content::Image { pixels: new_pixels, .. })) => {
+                ("Pixel Identical", pixels == new_pixels)
+            }
+            _ => return Vec::new(),
+        },
+        FileType::Video | FileType::Audio => {
+            match (video::stream_hashes(input, ffmpeg), video::stream_hashes(output, ffmpeg)) {
+                (Some(before), Some(after)) => ("Streams Identical", kept_unchanged(&before, &after)),
+                _ => return Vec::new(),
+            }
+        }
+        _ => return Vec::new(),
+    };
+
+    let mut items = vec![MetadataItem::with_category(Category::Notice, tag, if same { "yes" } else { "no" })];
+    // Applying the orientation turns the pixels on purpose
+    if !same && !rotated {
+        let warning = match file_type {
+            FileType::Image => "its pixels differ from the original's, so it was re-encoded",
+            _ => "its audio or video differs from the original's, so it was re-encoded",
+        };
+        items.push(MetadataItem::with_category(Category::Notice, IDENTICAL_WARNING, warning));
+    }
+    items
+}
+
+/// Whether each stream hash in `after` is one of `before`'s, in the same order, so
+/// streams dropped on purpose, such as with `--video-drop-streams`, aren't a difference
+fn kept_unchanged(before: &[String], after: &[String]) -> bool {
+    let mut before = before.iter();
+    after.iter().all(|stream| before.any(|original| original == stream))
+}
+
+/// A warning notice listing `problems`, for outputs kept despite them
+pub(crate) fn warning(problems: &[String]) -> MetadataItem {
+    let warning = format!("cleaned file kept although {}", problems.join(" and "));
+    MetadataItem::with_category(Category::Notice, INTEGRITY_WARNING, warning)
+}
+
+fn content(file_type: FileType, path: &Path, ffmpeg: &FfmpegConfig) -> Option<Content> {
+    match file_type {
+        FileType::Image => {
+            let format = crate::detect_format(path).and_then(ImageFormat::from_extension)?;
+            // The formats cleaned without touching the pixels, or re-encoded losslessly
+            use ImageFormat::*;
+            if !matches!(format, Jpeg | Png | Gif | Bmp | Tiff | WebP) {
+                return None;
+            }
+            let img = image::io::Reader::open(path).ok()?.with_guessed_format().ok()?.decode().ok()?;
+            Some(image_content(&img))
+        }
+        FileType::Video | FileType::Audio => video::probe_duration(path, ffmpeg).map(Content::Duration),
+        FileType::Pdf => lopdf::Document::load(path).ok().map(|document| Content::Pages(document.get_pages().len())),
+        _ => None,
+    }
+}
+
+fn image_content(img: &DynamicImage) -> Content {
+    let pixels = Sha256::digest(img.to_rgba8().as_raw()).to_vec();
+    Content::Image { width: img.width(), height: img.height(), pixels }
+}
+
+fn compare(before: &Content, after: &Content, rotated: bool) -> Option<String> {
+    match (before, after) {
+        (Content::Image { width, height, pixels }, Content::Image { width: new_width, height: new_height, pixels: new_pixels }) => {
+            let turned = rotated && (width, height) == (new_height, new_width);
+            if (width, height) != (new_width, new_height) && !turned {
+                Some(format!("the image is {}x{} instead of {}x{}", new_width, new_height, width, height))
+            } else if pixels != new_pixels && !rotated {
+                Some("its pixels changed".to_string())
+            } else {
+                None
+            }
+        }
+        // Remuxing can move the end by a frame or so
+        (Content::Duration(before), Content::Duration(after)) => ((before - after).abs() > (before * 0.01).max(0.1))
+            .then(|| format!("it lasts {:.1}s instead of {:.1}s", after, before)),
+        (Content::Pages(before), Content::Pages(after)) => {
+            (before != after).then(|| format!("it has {} pages instead of {}", after, before))
+        }
+        _ => Some("it no longer decodes as the same kind of file".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.png");
+        let output = dir.path().join("output.png");
+        let ffmpeg = FfmpegConfig::default();
+        image::RgbImage::from_fn(8, 4, |x, y| image::Rgb([x as u8 * 30, y as u8 * 60, 0])).save(&input).unwrap();
+        fs::copy(&input, &output).unwrap();
+
+        let safeguards = Safeguards { max_shrink: Some(50.0), check_content: true, ..Safeguards::default() };
+        assert!(check(&safeguards, FileType::Image, &input, &output, false, &ffmpeg).is_empty());
+
+        image::RgbImage::new(8, 4).save(&output).unwrap();
+        assert_eq!(check(&safeguards, FileType::Image, &input, &output, false, &ffmpeg), vec!["its pixels changed"]);
+
+        image::RgbImage::from_fn(4, 8, |x, y| image::Rgb([y as u8 * 30, x as u8 * 60, 0])).save(&output).unwrap();
+        assert!(check(&safeguards, FileType::Image, &input, &output, true, &ffmpeg).is_empty());
+        assert_eq!(
+            check(&safeguards, FileType::Image, &input, &output, false, &ffmpeg),
+            vec!["the image is 4x8 instead of 8x4"]
+        );
+
+        fs::write(&output, &fs::read(&input).unwrap()[..20]).unwrap();
+        let problems = check(&safeguards, FileType::Image, &input, &output, false, &ffmpeg);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("it shrank by"));
+        assert_eq!(problems[1], "it no longer decodes");
+        assert!(check(&Safeguards::default(), FileType::Image, &input, &output, false, &ffmpeg).is_empty());
+    }
+
+    #[test]
+    fn test_identical() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.png");
+        let output = dir.path().join("output.png");
+        let ffmpeg = FfmpegConfig::default();
+        image::RgbImage::from_fn(8, 4, |x, y| image::Rgb([x as u8 * 30, y as u8 * 60, 0])).save(&input).unwrap();
+        // Different bytes, same pixels
+        let rgba = image::RgbaImage::from_fn(8, 4, |x, y| image::Rgba([x as u8 * 30, y as u8 * 60, 0, 255]));
+        rgba.save(&output).unwrap();
+        let text = |items: Vec<MetadataItem>| items.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(text(identical(FileType::Image, &input, &output, false, &ffmpeg)), ["Pixel Identical: yes"]);
+
+        image::RgbImage::new(8, 4).save(&output).unwrap();
+        let items = identical(FileType::Image, &input, &output, false, &ffmpeg);
+        assert_eq!(text(items.clone())[0], "Pixel Identical: no");
+        assert_eq!(items[1].tag, IDENTICAL_WARNING);
+        assert_eq!(identical(FileType::Image, &input, &output, true, &ffmpeg).len(), 1);
+        assert!(identical(FileType::Pdf, &input, &output, false, &ffmpeg).is_empty());
+
+        let streams = |list: &[&str]| list.iter().map(|stream| stream.to_string()).collect::<Vec<_>>();
+        let before = streams(&["v,SHA256=aa", "a,SHA256=bb", "a,SHA256=cc"]);
+        assert!(kept_unchanged(&before, &before));
+        // Dropping the first audio stream leaves the rest as they were
+        assert!(kept_unchanged(&before, &streams(&["v,SHA256=aa", "a,SHA256=cc"])));
+        assert!(!kept_unchanged(&before, &streams(&["v,SHA256=aa", "a,SHA256=dd"])));
+        assert!(!kept_unchanged(&before, &streams(&["a,SHA256=cc", "v,SHA256=aa"])));
+    }
+}
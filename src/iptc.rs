@@ -0,0 +1,209 @@
+// IPTC IIM records, as written by newsroom and Photoshop workflows. In JPEGs
+// they live in an APP13 segment, wrapped in a Photoshop image resource block
+// alongside other resources such as thumbnails and print settings.
+
+use crate::metadata::{Category, MetadataItem};
+
+pub const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+
+pub(crate) const RESOURCE_IPTC: u16 = 0x0404;
+pub(crate) const RESOURCE_THUMBNAIL: u16 = 0x040C;
+pub(crate) const RESOURCE_THUMBNAIL_OLD: u16 = 0x0409;
+pub(crate) const RESOURCE_VERSION_INFO: u16 = 0x0421;
+
+// Datasets of the IIM application record (record 2)
+const IPTC_DATASETS: &[(u8, &str, Category)] = &[
+    (5, "Object Name", Category::Content),
+    (15, "Category", Category::Content),
+    (25, "Keywords", Category::Content),
+    (40, "Special Instructions", Category::Content),
+    (55, "Date Created", Category::Timestamps),
+    (60, "Time Created", Category::Timestamps),
+    (62, "Digital Creation Date", Category::Timestamps),
+    (63, "Digital Creation Time", Category::Timestamps),
+    (65, "Originating Program", Category::Software),
+    (80, "By-line", Category::Author),
+    (85, "By-line Title", Category::Author),
+    (90, "City", Category::Gps),
+    (92, "Sub-location", Category::Gps),
+    (95, "Province/State", Category::Gps),
+    (100, "Country Code", Category::Gps),
+    (101, "Country", Category::Gps),
+    (103, "Original Transmission Reference", Category::Other),
+    (105, "Headline", Category::Content),
+    (110, "Credit", Category::Copyright),
+    (115, "Source", Category::Copyright),
+    (116, "Copyright Notice", Category::Copyright),
+    (118, "Contact", Category::Author),
+    (120, "Caption/Abstract", Category::Content),
+    (122, "Writer/Editor", Category::Author),
+];
+
+/// Lists the contents of a JPEG APP13 payload: each IPTC field, the application that
+/// wrote the resources, plus a summary of the other Photoshop resources
+pub fn extract_photoshop_fields(payload: &[u8]) -> Vec<MetadataItem> {
+    let Some(mut data) = payload.strip_prefix(PHOTOSHOP_HEADER) else {
+        return vec![MetadataItem::new("APP13 Segment", format!("{} bytes", payload.len()))];
+    };
+
+    let mut metadata = Vec::new();
+    let mut other_resources = 0;
+
+    while data.len() >= 12 && data.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([data[4], data[5]]);
+        // Pascal string name, padded so length byte plus name is even
+        let name_len = data[6] as usize;
+        let size_pos = 6 + ((name_len + 2) & !1);
+        let Some(size) = data.get(size_pos..size_pos + 4) else { break };
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        let start = size_pos + 4;
+        let Some(resource) = data.get(start..start.saturating_add(size)) else { break };
+
+        match id {
+            RESOURCE_IPTC => metadata.extend(extract_iptc_fields(resource)),
+            RESOURCE_THUMBNAIL | RESOURCE_THUMBNAIL_OLD => metadata.push(MetadataItem::new(
+                format!("Photoshop thumbnail ({} KB)", size.div_ceil(1024)),
+                "",
+            )),
+            RESOURCE_VERSION_INFO => match version_info_writer(resource) {
+                Some(writer) => metadata.push(MetadataItem::with_category(Category::Software, "Photoshop Writer", writer)),
+                None => other_resources += 1,
+            },
+            _ => other_resources += 1,
+        }
+
+        // Resource data is padded to an even length
+        data = data.get(start + size + (size & 1)..).unwrap_or(&[]);
+    }
+
+    // Only Photoshop and the applications imitating it write image resources at all
+    if other_resources > 0 {
+        let resources = other_resources.to_string();
+        metadata.push(MetadataItem::with_category(Category::Software, "Photoshop Image Resources", resources));
+    }
+    metadata
+}
+
+/// The name of the application in a version info resource, e.g. "Adobe Photoshop". It
+/// follows a version number and a flag, as a count of characters and UTF-16 text.
+fn version_info_writer(resource: &[u8]) -> Option<String> {
+    let length = u32::from_be_bytes(resource.get(5..9)?.try_into().ok()?) as usize;
+    let text = resource.get(9..9 + length.checked_mul(2)?)?;
+    let units: Vec<u16> = text.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+    let writer = String::from_utf16_lossy(&units).trim_end_matches('\0').trim().to_string();
+    (!writer.is_empty()).then_some(writer)
+}
+
+/// Lists the application record fields of an IIM stream as "IPTC <Field>" items.
+/// Repeated fields such as keywords are joined with commas.
+pub fn extract_iptc_fields(data: &[u8]) -> Vec<MetadataItem> {
+    let mut fields: Vec<(usize, Vec<String>)> = Vec::new();
+    let mut pos = 0;
+
+    // Each dataset is a 0x1C marker, record and dataset numbers and a length
+    while pos + 5 <= data.len() && data[pos] == 0x1C {
+        let (record, dataset) = (data[pos + 1], data[pos + 2]);
+        let mut length = u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        pos += 5;
+
+        // Extended datasets give the size of the length field instead
+        if length & 0x8000 != 0 {
+            let size = length & 0x7FFF;
+            let Some(bytes) = data.get(pos..pos + size).filter(|_| size <= 4) else { break };
+            length = bytes.iter().fold(0, |acc, &b| (acc << 8) | b as usize);
+            pos += size;
+        }
+
+        let Some(value) = data.get(pos..pos.saturating_add(length)) else { break };
+        pos += length;
+
+        if record != 2 {
+            continue;
+        }
+        if let Some(index) = IPTC_DATASETS.iter().position(|(number, _, _)| *number == dataset) {
+            let value = String::from_utf8_lossy(value).trim().to_string();
+            match fields.iter_mut().find(|(i, _)| *i == index) {
+                Some((_, values)) => values.push(value),
+                None => fields.push((index, vec![value])),
+            }
+        }
+    }
+
+    let mut metadata: Vec<MetadataItem> = fields
+        .into_iter()
+        .map(|(index, values)| {
+            let (_, name, category) = IPTC_DATASETS[index];
+            MetadataItem::with_category(category, format!("IPTC {}", name), values.join(", "))
+        })
+        .collect();
+
+    if metadata.is_empty() {
+        metadata.push(MetadataItem::new("IPTC Data", format!("{} bytes", data.len())));
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset(record: u8, number: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x1C, record, number];
+        bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    fn resource(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"8BIM".to_vec();
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_extract_iptc_fields() {
+        let mut iim = dataset(1, 90, b"\x1b%G");
+        iim.extend(dataset(2, 0, &[0, 4]));
+        iim.extend(dataset(2, 80, b"Jane Doe"));
+        iim.extend(dataset(2, 25, b"protest"));
+        iim.extend(dataset(2, 90, b"Berlin"));
+        iim.extend(dataset(2, 25, b"city hall"));
+
+        let fields = extract_iptc_fields(&iim);
+        let text: Vec<String> = fields.iter().map(ToString::to_string).collect();
+        assert_eq!(text, vec!["IPTC By-line: Jane Doe", "IPTC Keywords: protest, city hall", "IPTC City: Berlin"]);
+        assert_eq!(fields[0].category, Category::Author);
+        assert_eq!(fields[2].category, Category::Gps);
+    }
+
+    #[test]
+    fn test_extract_photoshop_fields() {
+        let mut payload = PHOTOSHOP_HEADER.to_vec();
+        payload.extend(resource(0x03ED, &[0; 16]));
+        payload.extend(resource(RESOURCE_IPTC, &dataset(2, 120, b"Crowd outside")));
+        payload.extend(resource(RESOURCE_THUMBNAIL, &[0; 3000]));
+        let mut version_info = vec![0, 0, 0, 1, 1, 0, 0, 0, 9];
+        version_info.extend("Photoshop".encode_utf16().flat_map(u16::to_be_bytes));
+        payload.extend(resource(RESOURCE_VERSION_INFO, &version_info));
+
+        let fields = extract_photoshop_fields(&payload);
+        let text: Vec<String> = fields.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            text,
+            vec![
+                "IPTC Caption/Abstract: Crowd outside",
+                "Photoshop thumbnail (3 KB)",
+                "Photoshop Writer: Photoshop",
+                "Photoshop Image Resources: 1",
+            ]
+        );
+        assert_eq!(fields[3].category, Category::Software);
+        assert_eq!(extract_photoshop_fields(b"other").len(), 1);
+    }
+}
@@ -0,0 +1,226 @@
+// A log of the files a batch plans to clean and the ones it has finished, appended to
+// as the run goes, so a run that is interrupted can be resumed where it stopped. It
+// also records the name of every temporary file before the run writes it, so the ones
+// an interrupted run left can be removed without touching anybody else's.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use log::warn;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::cache::{decode_path, encode_path, key};
+use crate::temp;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Record {
+    Started { pid: u32 },
+    Planned { input: String, output: String },
+    Temp { path: String },
+    Done { input: String },
+}
+
+/// The journal of a batch, shared by the threads cleaning it
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    done: HashSet<String>,
+}
+
+impl Journal {
+    /// Starts a new journal at `path`, replacing any earlier one
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create journal directory: {}", parent.display()))?;
+        }
+        let file = File::create(path).with_context(|| format!("Failed to create journal: {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf(), file: Arc::new(Mutex::new(file)), done: HashSet::new() })
+    }
+
+    /// Reopens the journal of an interrupted run to carry on from where it stopped, and
+    /// removes the temporary files that run left behind. Returns the journal and the
+    /// number of files removed.
+    pub fn resume(path: &Path) -> Result<(Self, usize)> {
+        let data = fs::read_to_string(path).with_context(|| format!("Failed to read journal: {}", path.display()))?;
+        // A run stopped halfway through writing a record leaves it without its newline
+        let complete = &data[..data.rfind('\n').map_or(0, |end| end + 1)];
+        let mut records = Vec::new();
+        for (number, line) in complete.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let record = serde_json::from_str::<Record>(line)
+                .with_context(|| format!("Invalid journal: {} (line {})", path.display(), number + 1))?;
+            records.push(record);
+        }
+
+        // Most were renamed into place or removed once finished, and are already gone
+        let removed = records
+            .iter()
+            .filter(|record| matches!(record, Record::Temp { path } if fs::remove_file(decode_path(path)).is_ok()))
+            .count();
+
+        let done = records
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Done { input } => Some(input),
+                _ => None,
+            })
+            .collect();
+        let file = File::options()
+            .append(true)
+            .open(path)
+            .and_then(|file| file.set_len(complete.len() as u64).map(|_| file))
+            .with_context(|| format!("Failed to open journal: {}", path.display()))?;
+        Ok((Self { path: path.to_path_buf(), file: Arc::new(Mutex::new(file)), done }, removed))
+    }
+
+    /// Whether an earlier run in this journal finished cleaning `input`
+    pub fn is_done(&self, input: &Path) -> bool {
+        self.done.contains(&key(input))
+    }
+
+    /// Records that this process is about to clean each input into its output, and from
+    /// then on every temporary file it writes, until the journal is dropped
+    pub fn plan(&self, jobs: &[(PathBuf, PathBuf)]) -> Result<()> {
+        let mut records = vec![Record::Started { pid: std::process::id() }];
+        records.extend(jobs.iter().map(|(input, output)| Record::Planned {
+            input: key(input),
+            output: encode_path(&absolute(output)),
+        }));
+        self.append(&records)?;
+
+        let (path, file) = (self.path.clone(), Arc::clone(&self.file));
+        temp::observe(Some(Box::new(move |temp_path| {
+            let record = Record::Temp { path: encode_path(&absolute(temp_path)) };
+            if let Err(e) = append(&path, &file, &[record]) {
+                warn!("{:#}; --resume can't remove {}", e, temp_path.display());
+            }
+        })));
+        Ok(())
+    }
+
+    /// Records that `input` has been cleaned and its output is in place
+    pub fn complete(&self, input: &Path) -> Result<()> {
+        self.append(&[Record::Done { input: key(input) }])
+    }
+
+    /// Deletes the journal once every file in it has been cleaned
+    pub fn finish(&self) -> Result<()> {
+        fs::remove_file(&self.path).with_context(|| format!("Failed to remove journal: {}", self.path.display()))
+    }
+
+    fn append(&self, records: &[Record]) -> Result<()> {
+        append(&self.path, &self.file, records)
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        temp::observe(None);
+    }
+}
+
+// Each record goes out in a single write, so one cut short by an interruption can only
+// ever be the last line
+fn append(path: &Path, file: &Mutex<File>, records: &[Record]) -> Result<()> {
+    let mut data = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut data, record)?;
+        data.push(b'\n');
+    }
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    file.write_all(&data)
+        .and_then(|_| file.flush())
+        .with_context(|| format!("Failed to write journal: {}", path.display()))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resume() {
+        let dir = tempdir().unwrap();
+        let journal_path = dir.path().join("journal.jsonl");
+        let first = dir.path().join("first.jpg");
+        let second = dir.path().join("second.jpg");
+        fs::write(&first, b"first").unwrap();
+        fs::write(&second, b"second").unwrap();
+
+        // Written as by another process, so resuming doesn't touch this one's temporary files
+        let journal = Journal::create(&journal_path).unwrap();
+        let planned = |path: &Path| Record::Planned { input: key(path), output: key(path) };
+        let temp_path = dir.path().join(".metastripper-4000000-7-0123abcd-second.jpg");
+        let finished_temp = dir.path().join(".metastripper-4000000-6-0123abcd-first.jpg");
+        let temp = |path: &Path| Record::Temp { path: key(path) };
+        journal
+            .append(&[
+                Record::Started { pid: 4_000_000 },
+                planned(&first),
+                planned(&second),
+                temp(&finished_temp),
+                temp(&temp_path),
+            ])
+            .unwrap();
+        journal.complete(&first).unwrap();
+        drop(journal);
+
+        // An interrupted run leaves a temporary file and a torn last line, next to the
+        // temporary file of another run that is still going
+        fs::write(&temp_path, b"half").unwrap();
+        let other_run = dir.path().join(".metastripper-4000000-7-4567cdef-second.jpg");
+        fs::write(&other_run, b"someone else's").unwrap();
+        let mut file = File::options().append(true).open(&journal_path).unwrap();
+        file.write_all(br#"{"event":"done","inp"#).unwrap();
+
+        let (journal, removed) = Journal::resume(&journal_path).unwrap();
+        assert_eq!(removed, 1);
+        assert!(!temp_path.exists());
+        assert!(other_run.exists());
+        assert!(journal.is_done(&first));
+        assert!(!journal.is_done(&second));
+
+        journal.complete(&second).unwrap();
+        drop(journal);
+        let (journal, _) = Journal::resume(&journal_path).unwrap();
+        assert!(journal.is_done(&second));
+        journal.finish().unwrap();
+        assert!(!journal_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resume_non_unicode_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempdir().unwrap();
+        let journal_path = dir.path().join("journal.jsonl");
+        // Names that only differ where they aren't valid UTF-8
+        let first = dir.path().join(OsStr::from_bytes(b"caf\xe9.jpg"));
+        let second = dir.path().join(OsStr::from_bytes(b"caf\xe8.jpg"));
+        fs::write(&first, b"first").unwrap();
+        fs::write(&second, b"second").unwrap();
+        let temp_path = dir.path().join(OsStr::from_bytes(b".metastripper-4000000-1-0123abcd-caf\xe8.jpg"));
+
+        let journal = Journal::create(&journal_path).unwrap();
+        journal.append(&[Record::Temp { path: encode_path(&temp_path) }]).unwrap();
+        journal.complete(&first).unwrap();
+        drop(journal);
+        fs::write(&temp_path, b"half").unwrap();
+
+        let (journal, removed) = Journal::resume(&journal_path).unwrap();
+        assert_eq!(removed, 1);
+        assert!(!temp_path.exists());
+        assert!(journal.is_done(&first));
+        assert!(!journal.is_done(&second));
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::heif::read_boxes;
+use crate::image::{extract_exif_fields, filter_exif};
+use crate::makernote::is_fingerprint;
+use crate::metadata::{Category, MetadataItem};
+use crate::xmp::extract_xmp_fields;
+
+// JPEG 2000 (JP2) and JPEG XL files are sequences of boxes like HEIF, but neither
+// keeps a table of absolute offsets, so metadata boxes can be dropped outright and
+// the rest copied byte for byte. The coded image is never decoded or re-encoded.
+
+const JP2_SIGNATURE: &[u8] = b"\x00\x00\x00\x0CjP  \r\n\x87\n";
+const JXL_SIGNATURE: &[u8] = b"\x00\x00\x00\x0CJXL \r\n\x87\n";
+// A bare JPEG XL codestream, which has no boxes to hold metadata in
+const JXL_CODESTREAM: &[u8] = b"\xFF\x0A";
+
+const XMP_UUID: &[u8] = b"\xBE\x7A\xCF\xCB\x97\xA9\x42\xE8\x9C\x71\x99\x94\x91\xE3\xAF\xAC";
+const EXIF_UUID: &[u8] = b"JpgTiffExif->JP2";
+const GEOJP2_UUID: &[u8] = b"\xB1\x4B\xF8\xBD\x08\x3D\x4B\x43\xA5\xAE\x8C\xD7\xD5\xA6\xCE\x03";
+
+// Codestream markers: start of codestream, comment and start of the first tile-part
+const J2K_SOC: u8 = 0x4F;
+const J2K_COM: u8 = 0x64;
+const J2K_SOT: u8 = 0x90;
+
+/// What becomes of a box, or of an image resource in a Photoshop document
+pub(crate) enum Action {
+    Copy,
+    Drop(Vec<MetadataItem>),
+    /// Written again with a new payload
+    Replace(Vec<u8>, Vec<MetadataItem>),
+}
+
+pub fn strip_jp2_metadata(input_path: &Path, output_path: &Path, keep: &[Category]) -> Result<Vec<MetadataItem>> {
+    strip_boxes(input_path, output_path, JP2_SIGNATURE, keep, jp2_box)
+}
+
+pub fn strip_jxl_metadata(input_path: &Path, output_path: &Path, keep: &[Category]) -> Result<Vec<MetadataItem>> {
+    if fs::read(input_path).is_ok_and(|data| data.starts_with(JXL_CODESTREAM)) {
+        fs::copy(input_path, output_path)
+            .with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+        return Ok(Vec::new());
+    }
+    strip_boxes(input_path, output_path, JXL_SIGNATURE, keep, jxl_box)
+}
+
+fn strip_boxes(
+    input_path: &Path,
+    output_path: &Path,
+    signature: &[u8],
+    keep: &[Category],
+    action: fn(&[u8; 4], &[u8], &[Category]) -> Action,
+) -> Result<Vec<MetadataItem>> {
+    let data = fs::read(input_path).with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+    let (cleaned, metadata) = rewrite_boxes(&data, signature, keep, action)
+        .with_context(|| format!("Failed to parse image container: {}", input_path.display()))?;
+    fs::write(output_path, cleaned).with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    Ok(metadata)
+}
+
+fn rewrite_boxes(
+    data: &[u8],
+    signature: &[u8],
+    keep: &[Category],
+    action: fn(&[u8; 4], &[u8], &[Category]) -> Action,
+) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    if !data.starts_with(signature) {
+        return Err(anyhow::anyhow!("Missing signature box"));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    let mut metadata = Vec::new();
+    let mut pos = 0;
+    for header in read_boxes(data, 0, data.len())? {
+        match action(&header.box_type, &data[header.start..header.end], keep) {
+            Action::Copy => output.extend_from_slice(&data[pos..header.end]),
+            Action::Drop(items) => metadata.extend(items),
+            Action::Replace(payload, items) => {
+                output.extend(make_box(&header.box_type, &payload));
+                metadata.extend(items);
+            }
+        }
+        pos = header.end;
+    }
+    output.extend_from_slice(&data[pos..]);
+    Ok((output, metadata))
+}
+
+fn jp2_box(box_type: &[u8; 4], payload: &[u8], keep: &[Category]) -> Action {
+    match box_type {
+        b"xml " => xml_box(payload, keep),
+        b"uuid" if payload.starts_with(XMP_UUID) => {
+            Action::Drop(extract_xmp_fields(&String::from_utf8_lossy(&payload[16..])))
+        }
+        b"uuid" if payload.starts_with(EXIF_UUID) => {
+            let tiff = &payload[16..];
+            let tiff = tiff.strip_prefix(b"Exif\0\0").unwrap_or(tiff);
+            exif_box(tiff, EXIF_UUID, keep)
+        }
+        b"uuid" if payload.starts_with(GEOJP2_UUID) => {
+            kept_or_dropped(MetadataItem::with_category(Category::Gps, "GeoJP2 Georeferencing", size(payload)), keep)
+        }
+        b"uuid" => {
+            let id: String = payload.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+            kept_or_dropped(MetadataItem::new(format!("UUID Box {}", id), size(payload)), keep)
+        }
+        // Lists of URLs where the vendors of uuid boxes describe them
+        b"uinf" => kept_or_dropped(MetadataItem::new("UUID Info Box", size(payload)), keep),
+        b"jp2i" => kept_or_dropped(MetadataItem::new("Intellectual Property Rights", size(payload)), keep),
+        b"jp2c" => match strip_codestream_comments(payload, keep) {
+            Some((codestream, items)) => Action::Replace(codestream, items),
+            None => Action::Copy,
+        },
+        _ => Action::Copy,
+    }
+}
+
+fn jxl_box(box_type: &[u8; 4], payload: &[u8], keep: &[Category]) -> Action {
+    match box_type {
+        // The TIFF header follows a 4-byte offset to it
+        b"Exif" => {
+            let offset = payload.get(..4).map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+            exif_box(payload.get(4 + offset..).unwrap_or_default(), &[0; 4], keep)
+        }
+        b"xml " => xml_box(payload, keep),
+        // JUMBF, which carries C2PA content credentials: edit history, signer and all
+        b"jumb" => {
+            let c2pa = payload.windows(4).any(|w| w == b"c2pa");
+            let name = if c2pa { "Content Credentials (C2PA)" } else { "JUMBF Box" };
+            kept_or_dropped(MetadataItem::new(name, size(payload)), keep)
+        }
+        // Brotli-compressed boxes name the type they hold first
+        b"brob" if matches!(payload.get(..4), Some(b"Exif" | b"xml " | b"jumb")) => {
+            let inner = String::from_utf8_lossy(&payload[..4]).trim().to_string();
+            Action::Drop(vec![MetadataItem::new(format!("Compressed {} Box", inner), size(payload))])
+        }
+        // Holds what's needed to rebuild the original JPEG, including its comments and
+        // application segments, so it can't outlive the metadata
+        b"jbrd" => kept_or_dropped(MetadataItem::new("JPEG Reconstruction Data", size(payload)), keep),
+        _ => Action::Copy,
+    }
+}
+
+/// An Exif box with only the fields in the `keep` categories, starting with `prefix`
+fn exif_box(tiff: &[u8], prefix: &[u8], keep: &[Category]) -> Action {
+    let mut items = extract_exif_fields(tiff);
+    if items.is_empty() {
+        items.push(MetadataItem::new("Exif Box", size(tiff)));
+    }
+    items.retain(|item| !keep.contains(&item.category) || is_fingerprint(item));
+    match filter_exif(tiff, keep) {
+        Some(kept) => Action::Replace([prefix, &kept].concat(), items),
+        None => Action::Drop(items),
+    }
+}
+
+/// XMP packets go in full, as in other formats. Other XML is GML georeferencing or
+/// a vendor's own.
+fn xml_box(payload: &[u8], keep: &[Category]) -> Action {
+    let text = String::from_utf8_lossy(payload);
+    if text.contains("xmpmeta") || text.contains("rdf:RDF") {
+        Action::Drop(extract_xmp_fields(&text))
+    } else if text.contains("gml:") {
+        kept_or_dropped(MetadataItem::with_category(Category::Gps, "GML Georeferencing", size(payload)), keep)
+    } else {
+        kept_or_dropped(MetadataItem::new("XML Box", size(payload)), keep)
+    }
+}
+
+fn size(payload: &[u8]) -> String {
+    format!("{} bytes", payload.len())
+}
+
+pub(crate) fn kept_or_dropped(item: MetadataItem, keep: &[Category]) -> Action {
+    if keep.contains(&item.category) {
+        Action::Copy
+    } else {
+        Action::Drop(vec![item])
+    }
+}
+
+/// Removes the comment segments from the main header of a JPEG 2000 codestream, where
+/// encoders put their name and version. Returns `None` if there are none to remove.
+fn strip_codestream_comments(codestream: &[u8], keep: &[Category]) -> Option<(Vec<u8>, Vec<MetadataItem>)> {
+    if codestream.get(..2) != Some(&[0xFF, J2K_SOC]) {
+        return None;
+    }
+
+    let mut output = codestream[..2].to_vec();
+    let mut metadata = Vec::new();
+    let mut pos = 2;
+    // Every marker in the main header has a length, up to the first tile-part
+    while let Some(&[0xFF, marker, high, low]) = codestream.get(pos..pos + 4) {
+        let end = pos + 2 + u16::from_be_bytes([high, low]) as usize;
+        if marker == J2K_SOT || end > codestream.len() {
+            break;
+        }
+        if marker == J2K_COM {
+            // Latin-1 text follows a 2-byte registration value of 1, anything else is binary
+            let text = match codestream.get(pos + 4..end) {
+                Some([0, 1, text @ ..]) => text.iter().map(|&b| b as char).collect::<String>().trim().to_string(),
+                _ => format!("{} bytes", end - pos),
+            };
+            let item = MetadataItem::new("Codestream Comment", text);
+            if !keep.contains(&item.category) {
+                metadata.push(item);
+                pos = end;
+                continue;
+            }
+        }
+        output.extend_from_slice(&codestream[pos..end]);
+        pos = end;
+    }
+    output.extend_from_slice(&codestream[pos..]);
+    (!metadata.is_empty()).then_some((output, metadata))
+}
+
+fn make_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + 16);
+    match u32::try_from(payload.len() + 8) {
+        Ok(size) => {
+            data.extend_from_slice(&size.to_be_bytes());
+            data.extend_from_slice(box_type);
+        }
+        // Too large for a 32-bit size, so a 64-bit one follows the type
+        Err(_) => {
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(box_type);
+            data.extend_from_slice(&(payload.len() as u64 + 16).to_be_bytes());
+        }
+    }
+    data.extend_from_slice(payload);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+    use std::io::Cursor;
+    use tempfile::Builder;
+
+    fn test_exif() -> Vec<u8> {
+        let mut writer = Writer::new();
+        let artist = Field { tag: Tag::Artist, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Jane Doe".to_vec()]) };
+        let orientation = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) };
+        writer.push_field(&artist);
+        writer.push_field(&orientation);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        tiff.into_inner()
+    }
+
+    #[test]
+    fn test_strip_jp2_metadata() {
+        // A codestream with an encoder comment in its main header
+        let mut codestream = vec![0xFF, J2K_SOC, 0xFF, 0x51, 0, 4, 9, 9];
+        codestream.extend_from_slice(&[0xFF, J2K_COM, 0, 15, 0, 1]);
+        codestream.extend_from_slice(b"Kakadu-v7.1");
+        codestream.extend_from_slice(&[0xFF, J2K_SOT, 0, 2, 0xFF, 0xD9]);
+
+        let mut data = JP2_SIGNATURE.to_vec();
+        data.extend(make_box(b"ftyp", b"jp2 \0\0\0\0jp2 "));
+        data.extend(make_box(b"jp2h", &make_box(b"ihdr", &[0; 14])));
+        data.extend(make_box(b"uuid", &[XMP_UUID, br#"<x:xmpmeta xmp:CreatorTool="Photoshop"/>"#].concat()));
+        data.extend(make_box(b"uuid", &[GEOJP2_UUID, &[0; 32]].concat()));
+        data.extend(make_box(b"jp2c", &codestream));
+
+        let input = Builder::new().suffix(".jp2").tempfile().unwrap();
+        let output = Builder::new().suffix(".jp2").tempfile().unwrap();
+        fs::write(&input, &data).unwrap();
+
+        let removed = strip_jp2_metadata(input.path(), output.path(), &[Category::Gps]).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert_eq!(removed, ["XMP Creator Tool: Photoshop", "Codestream Comment: Kakadu-v7.1"]);
+
+        let cleaned = fs::read(output.path()).unwrap();
+        assert!(cleaned.starts_with(JP2_SIGNATURE));
+        assert!(cleaned.windows(16).any(|w| w == GEOJP2_UUID));
+        assert!(!cleaned.windows(6).any(|w| w == b"Kakadu"));
+        assert!(cleaned.ends_with(&[0xFF, 0x51, 0, 4, 9, 9, 0xFF, J2K_SOT, 0, 2, 0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn test_strip_jxl_metadata() {
+        let mut data = JXL_SIGNATURE.to_vec();
+        data.extend(make_box(b"ftyp", b"jxl \0\0\0\0jxl "));
+        data.extend(make_box(b"Exif", &[&[0; 4], &test_exif()[..]].concat()));
+        data.extend(make_box(b"brob", b"xml compressed"));
+        data.extend(make_box(b"jxlc", &[0xFF, 0x0A, 1, 2, 3]));
+
+        let input = Builder::new().suffix(".jxl").tempfile().unwrap();
+        let output = Builder::new().suffix(".jxl").tempfile().unwrap();
+        fs::write(&input, &data).unwrap();
+
+        let removed = strip_jxl_metadata(input.path(), output.path(), &[Category::Orientation]).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert_eq!(removed, ["Artist: \"Jane Doe\"", "Compressed xml Box: 14 bytes"]);
+
+        // The orientation stays in a rebuilt Exif box
+        let cleaned = fs::read(output.path()).unwrap();
+        let boxes = read_boxes(&cleaned, 0, cleaned.len()).unwrap();
+        let types: Vec<&[u8]> = boxes.iter().map(|b| &b.box_type[..]).collect();
+        assert_eq!(types, [&b"JXL "[..], b"ftyp", b"Exif", b"jxlc"]);
+        let exif = extract_exif_fields(&cleaned[boxes[2].start + 4..boxes[2].end]);
+        assert_eq!(exif.iter().map(|item| item.category).collect::<Vec<_>>(), [Category::Orientation]);
+        assert!(cleaned.ends_with(&[0xFF, 0x0A, 1, 2, 3]));
+
+        // A bare codestream has nowhere to keep metadata
+        fs::write(&input, [0xFF, 0x0A, 1, 2, 3]).unwrap();
+        assert!(strip_jxl_metadata(input.path(), output.path(), &[]).unwrap().is_empty());
+        assert_eq!(fs::read(output.path()).unwrap(), [0xFF, 0x0A, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,1463 @@
+//! Removes privacy-sensitive metadata from images, documents, audio, video and
+//! archives.
+//!
+//! ```no_run
+//! use metastripper::MetaStripper;
+//! use std::path::Path;
+//!
+//! let stripper = MetaStripper::new().output_dir("cleaned");
+//! let report = stripper.strip_file(Path::new("photo.jpg"))?;
+//! for item in &report.metadata {
+//!     println!("removed {}", item);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs::{self, FileTimes};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+mod archive;
+mod assign;
+mod audio;
+mod backups;
+mod cache;
+mod config;
+mod filter;
+mod heif;
+mod ico;
+mod image;
+mod integrity;
+mod iptc;
+mod jp2;
+mod journal;
+mod location;
+mod makernote;
+mod matroska;
+mod metadata;
+mod mp4;
+mod paths;
+mod office;
+mod ole;
+mod pdf;
+mod psd;
+mod raw;
+mod rename;
+mod schedule;
+mod shred;
+mod sniff;
+mod svg;
+mod temp;
+mod video;
+mod watch;
+mod xattrs;
+mod xmp;
+
+use archive::{strip_archive_metadata, EntryCleaner};
+use audio::strip_audio_metadata;
+use image::strip_image_metadata;
+use office::strip_office_metadata;
+use pdf::{inspect_pdf_metadata, strip_pdf_metadata};
+use schedule::Throttle;
+
+pub use assign::{Assignment, DateRange, SetField};
+pub use backups::BackupLedger;
+pub use cache::CleanCache;
+pub use config::{Config, Profile};
+pub use filter::PathFilter;
+pub use integrity::Safeguards;
+pub use journal::Journal;
+pub use metadata::{privacy_score, Category, MetadataItem, Sensitivity};
+pub use paths::resolve_input;
+pub use pdf::PdfOptions;
+pub use rename::NameTemplate;
+pub use schedule::IoPriority;
+pub use video::{FfmpegConfig, StreamKind, VideoOptions};
+pub use watch::{ChangeTracker, FolderEvents};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Image,
+    Raw,
+    Video,
+    Pdf,
+    Audio,
+    Office,
+    Archive,
+    Location,
+    Unknown,
+}
+
+/// What happens to the EXIF orientation of images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrientationMode {
+    /// Leave the orientation tag in place so photos still display upright
+    #[default]
+    Keep,
+    /// Rotate the pixels to match the tag and re-encode, then drop the tag
+    Rotate,
+    /// Remove the tag along with everything else
+    Strip,
+}
+
+impl std::str::FromStr for OrientationMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" => Ok(OrientationMode::Keep),
+            "rotate" => Ok(OrientationMode::Rotate),
+            "strip" => Ok(OrientationMode::Strip),
+            _ => Err(anyhow::anyhow!("Unknown orientation mode '{}' (expected keep, rotate or strip)", s)),
+        }
+    }
+}
+
+/// What happens to the file system timestamps of cleaned files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampMode {
+    /// Leave them as writing the cleaned file set them, i.e. the time of cleaning
+    #[default]
+    Current,
+    /// Give the cleaned file the original's modification and access times
+    Preserve,
+    /// Set both to 1980-01-01, like zip entries, so they reveal nothing
+    Reset,
+}
+
+// Files above this size are scheduled like videos, one at a time
+const LARGE_FILE_BYTES: u64 = 256 * 1024 * 1024;
+
+// 1980-01-01 00:00:00 UTC, the earliest time zip archives can store
+const RESET_TIMESTAMP_SECS: u64 = 315_532_800;
+
+/// Metadata found in (or removed from) a single file
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub path: PathBuf,
+    pub file_type: FileType,
+    pub metadata: Vec<MetadataItem>,
+    /// Dimensions, codecs and notices that describe the file. These are listed
+    /// alongside the metadata but never removed, so they aren't counted with it.
+    pub properties: Vec<MetadataItem>,
+    /// How much smaller the cleaned file is. `None` for inspect and verify reports.
+    pub bytes_saved: Option<i64>,
+}
+
+impl Report {
+    fn new(path: &Path, file_type: FileType, items: Vec<MetadataItem>, bytes_saved: Option<i64>) -> Self {
+        let (properties, metadata) = items.into_iter().partition(MetadataItem::is_descriptive);
+        Self { path: path.to_path_buf(), file_type, metadata, properties, bytes_saved }
+    }
+}
+
+/// Passed to the progress callback after each file in a batch finishes
+#[derive(Debug)]
+pub struct Progress<'a> {
+    pub path: &'a Path,
+    pub completed: usize,
+    pub total: usize,
+    /// Size of the file before it was cleaned
+    pub bytes: u64,
+    /// Whether cleaning the file failed
+    pub failed: bool,
+    /// The file's report, or why cleaning it failed
+    pub result: &'a Result<Report>,
+}
+
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// The error `strip_files` gives files it didn't start on because an earlier one
+/// failed and `fail_fast` is set
+#[derive(Debug, thiserror::Error)]
+#[error("Skipped after an earlier file failed")]
+pub struct Skipped;
+
+/// Strips, inspects and verifies files. Cleaned files replace the originals
+/// unless an output directory is set.
+#[derive(Default)]
+pub struct MetaStripper {
+    output_dir: Option<PathBuf>,
+    backup: bool,
+    keep_maker_notes: bool,
+    recurse_archives: bool,
+    keep: Vec<Category>,
+    min_severity: Option<Sensitivity>,
+    orientation: OrientationMode,
+    strip_icc: bool,
+    ffmpeg: FfmpegConfig,
+    video: VideoOptions,
+    pdf: PdfOptions,
+    safeguards: Safeguards,
+    set: Vec<Assignment>,
+    anonymize: Option<DateRange>,
+    timestamps: TimestampMode,
+    shred: bool,
+    preserve_owner: bool,
+    chmod: Option<u32>,
+    deterministic: bool,
+    follow_symlinks: bool,
+    fail_fast: bool,
+    jobs: Option<usize>,
+    nice: Option<i32>,
+    io_priority: Option<IoPriority>,
+    throttle: Option<Throttle>,
+    temp_dir: Option<PathBuf>,
+    progress: Option<ProgressCallback>,
+}
+
+impl MetaStripper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes cleaned files into `dir` under their original names
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    /// Copies each original to `<name>.bak` before cleaning it
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Keeps maker notes in RAW camera files (needed by some RAW converters)
+    pub fn keep_maker_notes(mut self, keep: bool) -> Self {
+        self.keep_maker_notes = keep;
+        self
+    }
+
+    /// Leaves metadata in these categories in place where the format allows it
+    /// (EXIF and text chunks in JPEG, PNG and WebP images, PDF Info entries, video tags)
+    pub fn keep(mut self, categories: impl IntoIterator<Item = Category>) -> Self {
+        self.keep = categories.into_iter().collect();
+        self
+    }
+
+    /// The inverse of `keep`: removes only metadata in these categories and leaves
+    /// everything else in place where the format allows it
+    pub fn remove_only(mut self, categories: impl IntoIterator<Item = Category>) -> Self {
+        let remove: Vec<Category> = categories.into_iter().collect();
+        self.keep = Category::ALL.iter().copied().filter(|c| !remove.contains(c)).collect();
+        if remove.contains(&Category::Orientation) {
+            self.orientation = OrientationMode::Strip;
+        }
+        if remove.contains(&Category::ColorProfile) {
+            self.strip_icc = true;
+        }
+        self
+    }
+
+    /// Keeps exactly these categories and removes everything else, whatever `keep`,
+    /// `min_severity`, `strip_icc` and a kept orientation asked for, e.g. to apply
+    /// choices made file by file
+    pub fn only_keep(mut self, categories: impl IntoIterator<Item = Category>) -> Self {
+        self.keep = categories.into_iter().collect();
+        self.min_severity = None;
+        self.strip_icc = !self.keep.contains(&Category::ColorProfile);
+        if self.keep.contains(&Category::Orientation) {
+            self.orientation = OrientationMode::Keep;
+        } else if self.orientation == OrientationMode::Keep {
+            self.orientation = OrientationMode::Strip;
+        }
+        self
+    }
+
+    /// Whether cleaning removes metadata in `category` where the format allows it
+    pub fn removes(&self, category: Category) -> bool {
+        !self.kept_categories().contains(&category)
+    }
+
+    /// Only removes categories that are at least this sensitive, e.g. `High` to
+    /// strip locations and authors but leave timestamps and camera settings alone
+    pub fn min_severity(mut self, severity: Sensitivity) -> Self {
+        self.min_severity = Some(severity);
+        self
+    }
+
+    /// Whether image orientation is kept (the default), baked into the pixels or removed
+    pub fn orientation(mut self, mode: OrientationMode) -> Self {
+        self.orientation = mode;
+        self
+    }
+
+    /// Removes embedded ICC color profiles, which are kept by default so colors
+    /// don't shift in wide-gamut images
+    pub fn strip_icc(mut self, strip: bool) -> Self {
+        self.strip_icc = strip;
+        self
+    }
+
+    /// Which ffmpeg and ffprobe to run for video and Ogg/M4A audio
+    pub fn ffmpeg(mut self, config: FfmpegConfig) -> Self {
+        self.ffmpeg = config;
+        self
+    }
+
+    /// Which kinds of streams videos keep, and whether they keep their chapters
+    pub fn video(mut self, options: VideoOptions) -> Self {
+        self.video = options;
+        self
+    }
+
+    /// Whether PDFs also lose their attachments and scripts
+    pub fn pdf(mut self, options: PdfOptions) -> Self {
+        self.pdf = options;
+        self
+    }
+
+    /// Checks each cleaned file against its original before writing it, e.g. that it
+    /// didn't shrink by more than half or that its pixels are unchanged. Files that
+    /// fail are left as they were and reported as failed, unless `warn_only` is set.
+    pub fn safeguards(mut self, safeguards: Safeguards) -> Self {
+        self.safeguards = safeguards;
+        self
+    }
+
+    /// Writes these values into cleaned files in place of the removed ones, e.g. a pen
+    /// name as the author. Fields a format has no tag for are skipped with a warning
+    /// notice in the report.
+    pub fn set(mut self, assignments: impl IntoIterator<Item = Assignment>) -> Self {
+        self.set = assignments.into_iter().collect();
+        self
+    }
+
+    /// Fills device, software and date fields with plausible random values instead of
+    /// leaving them empty, which can stand out as much as the originals: the make,
+    /// model and software of a common phone in JPEG and PNG images, a common PDF
+    /// producer in PDFs, and a date within `range` in both and in videos. Values given
+    /// with `set` and fields in kept categories are left alone.
+    pub fn anonymize(mut self, range: DateRange) -> Self {
+        self.anonymize = Some(range);
+        self
+    }
+
+    /// Whether cleaned files keep the original's modification time, get a fixed
+    /// one, or (the default) the time they were cleaned
+    pub fn timestamps(mut self, mode: TimestampMode) -> Self {
+        self.timestamps = mode;
+        self
+    }
+
+    /// Overwrites each original with zeros once it has been cleaned, then deletes it
+    /// unless the cleaned file took its place. Has no effect on SSDs and copy-on-write
+    /// file systems that write the zeros elsewhere.
+    pub fn shred(mut self, shred: bool) -> Self {
+        self.shred = shred;
+        self
+    }
+
+    /// Gives cleaned copies written elsewhere the original's owner and group as well as
+    /// its permissions, where the system allows it (on Unix, when running as root).
+    /// Files cleaned in place always keep them.
+    pub fn preserve_owner(mut self, preserve: bool) -> Self {
+        self.preserve_owner = preserve;
+        self
+    }
+
+    /// Sets the permissions of every cleaned file to `mode`, such as 0o644, instead of
+    /// copying the original's
+    pub fn chmod(mut self, mode: u32) -> Self {
+        self.chmod = Some(mode);
+        self
+    }
+
+    /// Makes the same input always give byte-identical output: ffmpeg leaves out its
+    /// version and the current time, identifiers PDF/A files need are derived from
+    /// their content, and cleaned files get the fixed timestamps of `TimestampMode::Reset`.
+    /// Values picked by `anonymize` are still random.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        if deterministic {
+            self.timestamps = TimestampMode::Reset;
+        }
+        self
+    }
+
+    /// Cleans the file a symbolic link points to when the cleaned file would be written
+    /// over the link. Without it such links are refused, since renaming the cleaned file
+    /// into place would turn the link into a regular file and leave its target as it was.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Also cleans supported files stored inside zip archives
+    pub fn recurse_archives(mut self, recurse: bool) -> Self {
+        self.recurse_archives = recurse;
+        self
+    }
+
+    /// Makes `strip_files` stop starting new files once one has failed. Files already
+    /// being cleaned in parallel still finish; the rest fail with `Skipped`.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// How many files `strip_files` cleans at once. By default there is one thread per
+    /// CPU core.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Runs the threads of `strip_files`, and the ffmpeg processes they start, at
+    /// niceness `nice` (up to 19, the lowest priority) on Unix
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice.min(19));
+        self
+    }
+
+    /// Lowers the disk priority of the threads of `strip_files`, and the ffmpeg
+    /// processes they start, on Linux
+    pub fn io_priority(mut self, priority: IoPriority) -> Self {
+        self.io_priority = Some(priority);
+        self
+    }
+
+    /// Starts the files of `strip_files` no faster than an average of `bytes_per_second`,
+    /// going by their sizes, so a large batch leaves the disks room for other programs
+    pub fn throttle(mut self, bytes_per_second: u64) -> Self {
+        self.throttle = Some(Throttle::new(bytes_per_second));
+        self
+    }
+
+    /// Writes cleaned files and scratch copies in `dir` rather than next to each
+    /// destination and in the system's temporary directory. Cleaned files are copied
+    /// into place when `dir` is on another file system.
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Called after each file processed by `strip_files`
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// A hash of the options that decide what a cleaned file holds, such as the kept
+    /// categories and video streams, for telling files cleaned one way from another
+    pub fn options_hash(&self) -> String {
+        let mut keep = self.keep.clone();
+        keep.sort();
+        keep.dedup();
+        let mut streams: Vec<String> = self.video.streams.iter().map(|kind| format!("{:?}", kind)).collect();
+        streams.sort();
+        streams.dedup();
+        // The PDF password only decides how the output is encrypted, and isn't worth storing
+        let pdf = &self.pdf;
+        let options = format!(
+            "{} {:?} {:?} {} {} {:?} {} {:?} {} {} {} {} {:?} {:?} {}",
+            env!("CARGO_PKG_VERSION"),
+            keep,
+            self.min_severity,
+            self.keep_maker_notes,
+            self.recurse_archives,
+            self.orientation,
+            self.strip_icc,
+            streams,
+            self.video.keep_chapters,
+            pdf.remove_attachments,
+            pdf.remove_javascript,
+            pdf.flatten,
+            self.set,
+            self.anonymize,
+            self.deterministic,
+        );
+        format!("{:x}", Sha256::digest(options))
+    }
+
+    /// Where the cleaned copy of `input_path` is written
+    pub fn output_path(&self, input_path: &Path) -> PathBuf {
+        match (&self.output_dir, input_path.file_name()) {
+            (Some(dir), Some(file_name)) => dir.join(file_name),
+            _ => input_path.to_path_buf(),
+        }
+    }
+
+    pub fn strip_file(&self, path: &Path) -> Result<Report> {
+        self.strip_file_to(path, &self.output_path(path))
+    }
+
+    /// Strips a file into `output_path`, creating its parent directories if needed
+    pub fn strip_file_to(&self, input_path: &Path, output_path: &Path) -> Result<Report> {
+        let file_type = determine_file_type(input_path);
+
+        let target;
+        let output_path = if fs::symlink_metadata(output_path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            if !self.follow_symlinks {
+                anyhow::bail!(
+                    "{} is a symbolic link; use --follow-symlinks to clean the file it points to",
+                    output_path.display()
+                );
+            }
+            target = fs::canonicalize(output_path)
+                .map(|path| paths::simplify(&path).into_owned())
+                .with_context(|| format!("Failed to follow symbolic link {}", output_path.display()))?;
+            &target
+        } else {
+            output_path
+        };
+
+        if let Some(parent) = output_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create output directory: {}", parent.display()))?;
+        }
+
+        if self.backup {
+            fs::copy(input_path, backup_path(input_path))
+                .with_context(|| format!("Failed to create backup for {}", input_path.display()))?;
+        }
+
+        // Measure before cleaning, since the output may replace the input
+        let original_size = file_size(input_path)?;
+        let original_times = match self.timestamps {
+            TimestampMode::Current => None,
+            TimestampMode::Preserve => Some(file_times(input_path)?),
+            TimestampMode::Reset => {
+                let reset = SystemTime::UNIX_EPOCH + Duration::from_secs(RESET_TIMESTAMP_SECS);
+                Some(FileTimes::new().set_accessed(reset).set_modified(reset))
+            }
+        };
+
+        // Held open across the rename, so the original's blocks can still be overwritten
+        // after the cleaned file has taken its name
+        let in_place = is_same_file(input_path, output_path);
+
+        // Extended attributes belong to the file system entry, so they're listed from the
+        // original, and only go when the cleaned file replaces it. An original left in
+        // place keeps its own.
+        let xattr_metadata = if in_place { xattrs::list_xattrs(input_path) } else { Vec::new() };
+        let original = if self.shred {
+            let file = fs::File::options()
+                .write(true)
+                .open(input_path)
+                .with_context(|| format!("Failed to open {} for shredding", input_path.display()))?;
+            Some(file)
+        } else {
+            None
+        };
+
+        // Clean into a temporary file and rename it into place, so a crash or failure
+        // midway never leaves a truncated file behind
+        let temp_path = match &self.temp_dir {
+            Some(dir) => temp::scratch(Some(dir), output_path.file_name().unwrap_or_default())
+                .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?,
+            None => temp::sibling(output_path),
+        };
+        // The ffmpeg timeout covers everything run on this file, checks included
+        let ffmpeg = self.ffmpeg.for_file();
+        let result = self
+            .strip_metadata(file_type, input_path, &temp_path, &self.kept_categories(), true, &ffmpeg)
+            .and_then(|mut metadata| {
+                let rotated = self.orientation == OrientationMode::Rotate
+                    && crate::image::exif_orientation(input_path).is_some_and(|orientation| orientation > 1);
+                let problems = integrity::check(&self.safeguards, file_type, input_path, &temp_path, rotated, &ffmpeg);
+                if !problems.is_empty() {
+                    if !self.safeguards.warn_only {
+                        anyhow::bail!("Refused to write the cleaned file, as {}; the original is unchanged", problems.join(" and "));
+                    }
+                    metadata.push(integrity::warning(&problems));
+                }
+                if self.safeguards.report_identical {
+                    metadata.extend(integrity::identical(file_type, input_path, &temp_path, rotated, &ffmpeg));
+                }
+                copy_permissions(input_path, &temp_path, in_place || self.preserve_owner)?;
+                if let Some(mode) = self.chmod {
+                    set_mode(&temp_path, mode)?;
+                }
+                temp::persist(&temp_path, output_path)
+                    .with_context(|| format!("Failed to replace {}", output_path.display()))?;
+                // Copies made by some strippers and the OS can carry attributes over
+                xattrs::strip_xattrs(output_path);
+                if let Some(original) = &original {
+                    shred::overwrite(original)
+                        .with_context(|| format!("Failed to shred {}", input_path.display()))?;
+                    if !in_place {
+                        fs::remove_file(input_path)
+                            .with_context(|| format!("Failed to remove {}", input_path.display()))?;
+                    }
+                }
+                Ok(metadata)
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        let mut metadata = result?;
+        metadata.extend(xattr_metadata);
+        let bytes_saved = original_size as i64 - file_size(output_path)? as i64;
+
+        if let Some(times) = original_times {
+            fs::File::options()
+                .write(true)
+                .open(output_path)
+                .and_then(|file| file.set_times(times))
+                .with_context(|| format!("Failed to set timestamps of {}", output_path.display()))?;
+        }
+
+        let report = Report::new(input_path, file_type, metadata, Some(bytes_saved));
+        info!(
+            "cleaned path={:?} type={} removed={} bytes_saved={}",
+            input_path,
+            file_type.name(),
+            report.metadata.len(),
+            bytes_saved
+        );
+
+        Ok(report)
+    }
+
+    /// Strips files in parallel, returning results in the same order as `paths`
+    pub fn strip_files(&self, paths: &[PathBuf]) -> Vec<Result<Report>> {
+        let jobs: Vec<(PathBuf, PathBuf)> = paths.iter().map(|path| (path.clone(), self.output_path(path))).collect();
+        self.strip_files_to(&jobs)
+    }
+
+    /// Like `strip_files`, but writes each input to the output path paired with it
+    pub fn strip_files_to(&self, jobs: &[(PathBuf, PathBuf)]) -> Vec<Result<Report>> {
+        let (nice, io_priority) = (self.nice, self.io_priority);
+        if self.jobs.is_none() && nice.is_none() && io_priority.is_none() {
+            return self.run_jobs(jobs);
+        }
+        // Zero threads means one per CPU core. Priorities are set on workers of a pool of
+        // our own, so the caller's threads keep theirs.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.jobs.unwrap_or(0))
+            .start_handler(move |_| schedule::lower_priority(nice, io_priority))
+            .build();
+        match pool {
+            Ok(pool) => pool.install(|| self.run_jobs(jobs)),
+            Err(e) => jobs.iter().map(|_| Err(anyhow::anyhow!("Failed to start worker threads: {}", e))).collect(),
+        }
+    }
+
+    /// Runs a batch on the current thread pool. Heavy files go through one at a time on
+    /// a single thread while the others share the rest, so a long video neither holds
+    /// up the small files queued behind it nor runs alongside other ffmpeg processes.
+    fn run_jobs(&self, jobs: &[(PathBuf, PathBuf)]) -> Vec<Result<Report>> {
+        let completed = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+
+        let run_job = |index: usize| {
+            let (path, output_path) = &jobs[index];
+            // Measured first, since the original may be replaced or shredded
+            let bytes = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+            let result = if self.fail_fast && failed.load(Ordering::Relaxed) {
+                Err(Skipped.into())
+            } else {
+                if let Some(throttle) = &self.throttle {
+                    throttle.wait(bytes);
+                }
+                self.strip_file_to(path, output_path)
+            };
+            match &result {
+                Err(e) if e.is::<Skipped>() => debug!("skipped path={:?}", path),
+                Err(e) => warn!("failed path={:?} error={:?}", path, format!("{:#}", e)),
+                Ok(_) => {}
+            }
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            if let Some(progress) = &self.progress {
+                progress(&Progress {
+                    path,
+                    completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+                    total: jobs.len(),
+                    bytes,
+                    failed: result.is_err(),
+                    result: &result,
+                });
+            }
+            (index, result)
+        };
+
+        let (heavy, light): (Vec<usize>, Vec<usize>) = (0..jobs.len()).partition(|&index| is_heavy(&jobs[index].0));
+        let (heavy_results, light_results) = rayon::join(
+            || heavy.iter().map(|&index| run_job(index)).collect::<Vec<_>>(),
+            || light.par_iter().map(|&index| run_job(index)).collect::<Vec<_>>(),
+        );
+
+        // Put the results back in the order of `jobs`
+        let mut results: Vec<Option<Result<Report>>> = jobs.iter().map(|_| None).collect();
+        for (index, result) in heavy_results.into_iter().chain(light_results) {
+            results[index] = Some(result);
+        }
+        results.into_iter().map(|result| result.expect("every job has a result")).collect()
+    }
+
+    /// Cleans a file read from `input` and writes the result to `output`, for use as a
+    /// filter in pipelines. The format is judged by content, with `format` (an extension
+    /// such as "jpg") as the fallback. The data passes through the temp directory, since
+    /// the format strippers work on files. The report's path is `-`.
+    pub fn strip_stream(&self, input: impl Read, mut output: impl Write, format: Option<&str>) -> Result<Report> {
+        with_stream_file(input, format, self.temp_dir.as_deref(), |input_path| {
+            let output_path = self.scratch_path(input_path.file_name().unwrap_or_default())?;
+            let result = self.strip_file_to(input_path, &output_path).and_then(|report| {
+                let mut cleaned = fs::File::open(&output_path).context("Failed to read the cleaned file")?;
+                io::copy(&mut cleaned, &mut output).context("Failed to write the output")?;
+                output.flush().context("Failed to write the output")?;
+                Ok(report)
+            });
+            let _ = fs::remove_file(&output_path);
+            result
+        })
+    }
+
+    /// Like `strip_stream`, but only lists the metadata in the file read from `input`
+    pub fn inspect_stream(&self, input: impl Read, format: Option<&str>) -> Result<Report> {
+        with_stream_file(input, format, self.temp_dir.as_deref(), |input_path| self.inspect_file(input_path))
+    }
+
+    /// Lists the metadata in a file without modifying it
+    pub fn inspect_file(&self, path: &Path) -> Result<Report> {
+        let file_type = determine_file_type(path);
+        let ffmpeg = self.ffmpeg.for_file();
+
+        let mut metadata = match file_type {
+            FileType::Video if !is_matroska(path) => video::extract_video_metadata(path, &ffmpeg)?,
+            FileType::Pdf => inspect_pdf_metadata(path, &self.pdf)?,
+            FileType::Unknown => Vec::new(),
+            file_type => {
+                // The remaining formats report what they remove while cleaning, so clean a
+                // throwaway copy in the temp directory and discard the result
+                let temp_path = self.scratch_path(path.file_name().unwrap_or_default())?;
+
+                // Nothing is kept, so everything the file contains gets listed
+                let result = self.strip_metadata(file_type, path, &temp_path, &[], false, &ffmpeg);
+                let _ = fs::remove_file(&temp_path);
+                result?
+            }
+        };
+
+        metadata.extend(xattrs::list_xattrs(path));
+
+        Ok(Report::new(path, file_type, metadata, None))
+    }
+
+    /// Like `inspect_file`, but only reports metadata that `strip_file` would remove
+    /// and not write back with `set`
+    pub fn verify_file(&self, path: &Path) -> Result<Report> {
+        let mut report = self.inspect_file(path)?;
+        let mut keep = self.kept_categories();
+        keep.extend(self.set.iter().map(|assignment| assignment.field.category()));
+        if self.anonymize.is_some() {
+            keep.extend(anonymized_fields(report.file_type, path).iter().map(|field| field.category()));
+        }
+        report.metadata.retain(|item| {
+            (!keep.contains(&item.category) || makernote::is_fingerprint(item))
+                && self.pdf.removes(item)
+                && self.video.removes(item)
+        });
+        Ok(report)
+    }
+
+    /// A new file in the temp directory whose name ends in `file_name`
+    fn scratch_path(&self, file_name: impl AsRef<OsStr>) -> Result<PathBuf> {
+        temp::scratch(self.temp_dir.as_deref(), file_name).context("Failed to create a temporary file")
+    }
+
+    fn kept_categories(&self) -> Vec<Category> {
+        let mut keep = self.keep.clone();
+        if self.orientation == OrientationMode::Keep && !keep.contains(&Category::Orientation) {
+            keep.push(Category::Orientation);
+        }
+        if !self.strip_icc && !keep.contains(&Category::ColorProfile) {
+            keep.push(Category::ColorProfile);
+        }
+        if let Some(severity) = self.min_severity {
+            for &category in Category::ALL {
+                if category.default_sensitivity() < severity && !keep.contains(&category) {
+                    keep.push(category);
+                }
+            }
+        }
+        keep
+    }
+
+    /// The values written into a cleaned file: the ones given with `set`, plus random
+    /// ones for the other fields the format can store if anonymizing
+    fn assignments(&self, file_type: FileType, path: &Path, keep: &[Category]) -> Vec<Assignment> {
+        let mut set = Vec::new();
+        if let Some(range) = &self.anonymize {
+            let fields: Vec<SetField> = anonymized_fields(file_type, path)
+                .iter()
+                .copied()
+                .filter(|field| !keep.contains(&field.category()))
+                .filter(|field| !self.set.iter().any(|assignment| assignment.field == *field))
+                .collect();
+            set = assign::anonymize(&fields, range);
+        }
+        set.extend(self.set.iter().cloned());
+        set
+    }
+
+    /// The ffmpeg settings `ffmpeg` for writing files, made bit-exact for deterministic output
+    fn writing_ffmpeg<'a>(&self, ffmpeg: &'a FfmpegConfig) -> Cow<'a, FfmpegConfig> {
+        if !self.deterministic {
+            return Cow::Borrowed(ffmpeg);
+        }
+        let mut ffmpeg = ffmpeg.clone();
+        ffmpeg.bitexact = true;
+        Cow::Owned(ffmpeg)
+    }
+
+    /// The PDF options for writing files, with content-derived IDs for deterministic output
+    fn writing_pdf(&self) -> Cow<'_, PdfOptions> {
+        if !self.deterministic {
+            return Cow::Borrowed(&self.pdf);
+        }
+        Cow::Owned(PdfOptions { deterministic: true, ..self.pdf.clone() })
+    }
+
+    fn strip_metadata(
+        &self,
+        file_type: FileType,
+        input_path: &Path,
+        output_path: &Path,
+        keep: &[Category],
+        write_values: bool,
+        ffmpeg: &FfmpegConfig,
+    ) -> Result<Vec<MetadataItem>> {
+        let set = if write_values { self.assignments(file_type, input_path, keep) } else { Vec::new() };
+        let set = &set[..];
+        // Only images, PDFs and ffmpeg videos have values written back
+        let unsupported = match file_type {
+            FileType::Image | FileType::Pdf | FileType::Archive => None,
+            FileType::Video if !is_matroska(input_path) => None,
+            file_type => assign::unsupported(set, &[], file_type.name()),
+        };
+        let metadata = match file_type {
+            FileType::Image => {
+                let rotate = self.orientation == OrientationMode::Rotate;
+                strip_image_metadata(input_path, output_path, keep, rotate, set)
+            }
+            FileType::Raw => raw::strip_raw_metadata(input_path, output_path, self.keep_maker_notes),
+            // Matroska is edited natively, which doesn't need ffmpeg and never remuxes, but
+            // can't drop a track without rewriting every cluster
+            FileType::Video => {
+                let native = if is_matroska(input_path) {
+                    matroska::strip_matroska_metadata(input_path, output_path, keep, &self.video)?
+                } else {
+                    None
+                };
+                match native {
+                    Some(metadata) => Ok(metadata),
+                    None => {
+                        let ffmpeg = self.writing_ffmpeg(ffmpeg);
+                        video::strip_video_metadata(input_path, output_path, keep, set, &self.video, &ffmpeg)
+                    }
+                }
+            }
+            FileType::Pdf => strip_pdf_metadata(input_path, output_path, keep, set, &self.writing_pdf()),
+            FileType::Audio => strip_audio_metadata(input_path, output_path, &self.writing_ffmpeg(ffmpeg)),
+            FileType::Office => strip_office_metadata(input_path, output_path),
+            FileType::Location => location::strip_location_metadata(input_path, output_path),
+            FileType::Archive => {
+                let clean_entry = |input: &Path, output: &Path| match determine_file_type(input) {
+                    FileType::Unknown => Ok(None),
+                    file_type => self.strip_metadata(file_type, input, output, keep, write_values, ffmpeg).map(Some),
+                };
+                let clean_entry: Option<EntryCleaner> = if self.recurse_archives { Some(&clean_entry) } else { None };
+                strip_archive_metadata(input_path, output_path, clean_entry)
+            }
+            FileType::Unknown => Err(anyhow::anyhow!("Unsupported file type: {}", input_path.display())),
+        };
+        let mut metadata = metadata?;
+        if keeps_nothing(file_type, input_path) {
+            // The cleaned copy is only a temporary file here, so refusing leaves the original as it was
+            // Entries of an archive are listed as "entry -> tag", and were checked when they were cleaned
+            let mut lost: Vec<String> = Vec::new();
+            let kept = |item: &&MetadataItem| self.keep.contains(&item.category) && keep.contains(&item.category);
+            for item in metadata.iter().filter(kept) {
+                let entry = file_type == FileType::Archive && item.tag.contains(" -> ");
+                if !entry && !matches!(item.category, Category::Technical | Category::Notice) {
+                    let name = item.category.to_string();
+                    if !lost.contains(&name) {
+                        lost.push(name);
+                    }
+                }
+            }
+            if !lost.is_empty() {
+                anyhow::bail!(
+                    "{} metadata can't be kept in {} files; the original is unchanged",
+                    lost.join(", "),
+                    detect_format(input_path).unwrap_or_else(|| file_type.name().to_string())
+                );
+            }
+        }
+        metadata.extend(unsupported);
+        Ok(metadata)
+    }
+}
+
+/// Whether cleaning a file of this format removes all its metadata, whatever is kept
+fn keeps_nothing(file_type: FileType, path: &Path) -> bool {
+    match file_type {
+        FileType::Image => matches!(detect_format(path).as_deref(), Some("heic" | "heif" | "avif" | "svg")),
+        FileType::Pdf | FileType::Video | FileType::Unknown => false,
+        FileType::Raw | FileType::Audio | FileType::Office | FileType::Archive | FileType::Location => true,
+    }
+}
+
+impl FileType {
+    /// Lower-case identifier used in machine-readable output
+    pub fn name(self) -> &'static str {
+        match self {
+            FileType::Image => "image",
+            FileType::Raw => "raw",
+            FileType::Video => "video",
+            FileType::Pdf => "pdf",
+            FileType::Audio => "audio",
+            FileType::Office => "office",
+            FileType::Archive => "archive",
+            FileType::Location => "location",
+            FileType::Unknown => "unknown",
+        }
+    }
+}
+
+pub fn determine_file_type(path: &Path) -> FileType {
+    match detect_format(path).as_deref() {
+        Some(
+            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "tif" | "webp" | "heic" | "heif" | "avif" | "svg" | "jp2"
+            | "jxl" | "ico" | "cur" | "psd" | "psb",
+        ) => FileType::Image,
+        Some("cr2" | "nef" | "arw" | "dng") => FileType::Raw,
+        Some("mp4" | "mov" | "avi" | "mkv" | "webm") => FileType::Video,
+        Some("pdf") => FileType::Pdf,
+        Some("mp3" | "flac" | "ogg" | "m4a" | "wav") => FileType::Audio,
+        Some("docx" | "xlsx" | "pptx" | "doc" | "xls" | "ppt" | "odt" | "ods" | "odp") => FileType::Office,
+        Some("zip") => FileType::Archive,
+        Some("gpx" | "kml") => FileType::Location,
+        _ => FileType::Unknown,
+    }
+}
+
+/// The format of a file as a lower-case extension. It's judged by the file's magic
+/// bytes where they are recognized, and by the file name otherwise. The name wins
+/// when it is a more specific variant of the same container, such as a DOCX zip.
+pub fn detect_format(path: &Path) -> Option<String> {
+    let extension = file_extension(path);
+    match (sniff::sniff_path(path), extension) {
+        (Some(sniffed), Some(extension)) if sniff::same_family(&extension, sniffed) => Some(extension),
+        (Some(sniffed), _) => Some(sniffed.to_string()),
+        (None, extension) => extension,
+    }
+}
+
+/// The format a file's content shows it to be, if that contradicts its extension.
+/// Files without an extension aren't reported.
+pub fn format_mismatch(path: &Path) -> Option<&'static str> {
+    let extension = file_extension(path)?;
+    sniff::sniff_path(path).filter(|sniffed| !sniff::same_family(&extension, sniffed))
+}
+
+fn file_extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|e| e.to_str()).map(str::to_lowercase)
+}
+
+/// Videos are remuxed by ffmpeg, which uses several cores of its own, and huge files
+/// take long enough that they're best kept out of the way of the rest
+fn is_heavy(path: &Path) -> bool {
+    determine_file_type(path) == FileType::Video
+        || fs::metadata(path).map(|metadata| metadata.len() > LARGE_FILE_BYTES).unwrap_or(false)
+}
+
+fn is_matroska(path: &Path) -> bool {
+    matches!(detect_format(path).as_deref(), Some("mkv" | "webm"))
+}
+
+/// Copies `input` into a scratch file named after the `format` hint, and runs `process`
+/// on it. The scratch file is removed afterwards, and the report's path set to `-`.
+fn with_stream_file(
+    mut input: impl Read,
+    format: Option<&str>,
+    temp_dir: Option<&Path>,
+    process: impl FnOnce(&Path) -> Result<Report>,
+) -> Result<Report> {
+    let path = temp::scratch(temp_dir, format!("stdin.{}", format.unwrap_or("bin").trim_start_matches('.')))
+        .context("Failed to create a temporary file")?;
+
+    let result = (|| -> Result<Report> {
+        let mut file = fs::File::create(&path).context("Failed to open the temporary file")?;
+        io::copy(&mut input, &mut file).context("Failed to read the input")?;
+        drop(file);
+
+        if determine_file_type(&path) == FileType::Unknown {
+            anyhow::bail!("Unrecognized input format; give its file extension as a hint");
+        }
+        process(&path)
+    })();
+
+    let _ = fs::remove_file(&path);
+    result.map(|report| Report { path: PathBuf::from("-"), ..report })
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Gives a cleaned file the permissions and, with `owner` and where allowed, the owner
+/// of the original
+fn copy_permissions(original: &Path, replacement: &Path, owner: bool) -> Result<()> {
+    let metadata = fs::metadata(original)
+        .with_context(|| format!("Failed to read permissions of {}", original.display()))?;
+
+    // Only root can give files away, so a failure here just leaves the current user as owner.
+    // Ownership goes first, since changing it can clear setuid bits.
+    #[cfg(unix)]
+    if owner {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::chown(replacement, Some(metadata.uid()), Some(metadata.gid()));
+    }
+    #[cfg(not(unix))]
+    let _ = owner;
+
+    fs::set_permissions(replacement, metadata.permissions())
+        .with_context(|| format!("Failed to set permissions of {}", replacement.display()))
+}
+
+/// Sets Unix permission bits such as 0o644. Elsewhere only read-only can be set, for a
+/// mode without any write bits.
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    #[cfg(unix)]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt;
+        fs::Permissions::from_mode(mode)
+    };
+    #[cfg(not(unix))]
+    let permissions = {
+        let mut permissions = fs::metadata(path)?.permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        permissions
+    };
+    fs::set_permissions(path, permissions).with_context(|| format!("Failed to set permissions of {}", path.display()))
+}
+
+fn file_times(path: &Path) -> Result<FileTimes> {
+    let metadata = fs::metadata(path)
+        .with_context(|| format!("Failed to read timestamps of {}", path.display()))?;
+    let mut times = FileTimes::new();
+    // Not every file system records access times
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    Ok(times)
+}
+
+fn file_size(path: &Path) -> Result<u64> {
+    Ok(fs::metadata(path)
+        .with_context(|| format!("Failed to read file size: {}", path.display()))?
+        .len())
+}
+
+/// The fields `MetaStripper::anonymize` fills in, the ones each format can store
+fn anonymized_fields(file_type: FileType, path: &Path) -> &'static [SetField] {
+    match file_type {
+        FileType::Image if matches!(detect_format(path).as_deref(), Some("jpg" | "jpeg" | "png")) => {
+            &[SetField::Make, SetField::Model, SetField::Software, SetField::Date]
+        }
+        FileType::Pdf => &[SetField::Software, SetField::Date],
+        FileType::Video if !is_matroska(path) => &[SetField::Date],
+        _ => &[],
+    }
+}
+
+/// Where `strip_file` keeps the original when backups are enabled
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    const TEST_SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg"><!-- made by Jane --><rect/></svg>"#;
+
+    #[test]
+    fn test_strip_inspect_and_verify() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("drawing.svg");
+        fs::write(&input, TEST_SVG).unwrap();
+
+        let stripper = MetaStripper::new();
+        let inspected = stripper.inspect_file(&input).unwrap();
+        assert_eq!(inspected.file_type, FileType::Image);
+        assert_eq!(inspected.metadata, vec![MetadataItem::new("Comment", "made by Jane")]);
+        assert_eq!(fs::read_to_string(&input).unwrap(), TEST_SVG);
+
+        let stripped = stripper.backup(true).strip_file(&input).unwrap();
+        assert_eq!(stripped.metadata, inspected.metadata);
+        assert_eq!(inspected.bytes_saved, None);
+        assert_eq!(stripped.bytes_saved, Some((TEST_SVG.len() - fs::read(&input).unwrap().len()) as i64));
+        assert!(stripped.bytes_saved.unwrap() > 0);
+        assert!(backup_path(&input).exists());
+        assert!(MetaStripper::new().verify_file(&input).unwrap().metadata.is_empty());
+    }
+
+    #[test]
+    fn test_options_hash() {
+        let stripper = MetaStripper::new();
+        assert_eq!(stripper.options_hash(), MetaStripper::new().backup(true).options_hash());
+        assert_eq!(
+            MetaStripper::new().keep([Category::Gps, Category::Device]).options_hash(),
+            MetaStripper::new().keep([Category::Device, Category::Gps]).options_hash()
+        );
+        assert_ne!(stripper.options_hash(), MetaStripper::new().keep([Category::Gps]).options_hash());
+        assert_ne!(stripper.options_hash(), MetaStripper::new().strip_icc(true).options_hash());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_and_long_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempdir().unwrap();
+        let input = dir.path().join(OsStr::from_bytes(b"caf\xe9 \xff.svg"));
+        fs::write(&input, TEST_SVG).unwrap();
+        let stripper = MetaStripper::new().backup(true);
+        assert_eq!(stripper.strip_file(&input).unwrap().metadata.len(), 1);
+        assert_eq!(backup_path(&input).as_os_str().as_bytes(), [input.as_os_str().as_bytes(), b".bak"].concat());
+        assert_eq!(fs::read(backup_path(&input)).unwrap(), TEST_SVG.as_bytes());
+
+        // Deeply nested, and a file name near the usual 255-byte limit
+        let mut nested = dir.path().to_path_buf();
+        for _ in 0..20 {
+            nested.push("a".repeat(40));
+        }
+        fs::create_dir_all(&nested).unwrap();
+        let input = nested.join(format!("{}.svg", "b".repeat(240)));
+        fs::write(&input, TEST_SVG).unwrap();
+        assert_eq!(MetaStripper::new().strip_file(&input).unwrap().metadata.len(), 1);
+        assert_eq!(fs::read_dir(&nested).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_anonymize_fills_in_random_values() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        ::image::RgbImage::new(8, 4).save(&input).unwrap();
+
+        let range: DateRange = "2019-01-01..2019-12-31".parse().unwrap();
+        let stripper = MetaStripper::new()
+            .set(["model=Pixel 7".parse().unwrap()])
+            .anonymize(range);
+        stripper.strip_file(&input).unwrap();
+
+        let inspected = MetaStripper::new().inspect_file(&input).unwrap();
+        let value = |tag: &str| inspected.metadata.iter().find(|item| item.tag == tag).map(|item| item.value.clone());
+        assert_eq!(value("Camera Model").as_deref(), Some("\"Pixel 7\""));
+        assert!(value("Camera Make").is_some());
+        assert!(value("Original Date/Time").unwrap().starts_with("2019-"));
+        // The values are meant to stay, so verifying with the same options passes
+        assert!(stripper.verify_file(&input).unwrap().metadata.is_empty());
+    }
+
+    #[test]
+    fn test_technical_properties_not_counted_as_removed() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("photo.bmp");
+        ::image::RgbImage::new(8, 4).save(&input).unwrap();
+
+        let report = MetaStripper::new().strip_file(&input).unwrap();
+        assert!(report.properties.contains(&MetadataItem::new("Image Dimensions", "8x4")));
+        assert!(report.metadata.iter().all(|item| !item.is_descriptive()));
+    }
+
+    #[test]
+    fn test_strip_stream() {
+        let mut output = Vec::new();
+        let report = MetaStripper::new().strip_stream(TEST_SVG.as_bytes(), &mut output, None).unwrap();
+        assert_eq!(report.path, PathBuf::from("-"));
+        assert_eq!(report.metadata, vec![MetadataItem::new("Comment", "made by Jane")]);
+        assert!(!String::from_utf8(output).unwrap().contains("Jane"));
+
+        let result = MetaStripper::new().strip_stream(&b"plain text"[..], &mut Vec::new(), None);
+        assert!(result.is_err());
+
+        let report = MetaStripper::new().inspect_stream(TEST_SVG.as_bytes(), Some("svg")).unwrap();
+        assert_eq!(report.metadata, vec![MetadataItem::new("Comment", "made by Jane")]);
+    }
+
+    #[test]
+    fn test_remove_only_keeps_other_categories() {
+        let stripper = MetaStripper::new().remove_only([Category::Gps, Category::Timestamps]);
+        assert!(stripper.keep.contains(&Category::Orientation));
+        assert!(stripper.keep.contains(&Category::Author));
+        assert!(!stripper.keep.contains(&Category::Gps));
+        assert!(!stripper.keep.contains(&Category::Timestamps));
+        assert!(stripper.kept_categories().contains(&Category::ColorProfile));
+
+        let stripper = MetaStripper::new().remove_only([Category::ColorProfile]);
+        assert!(!stripper.kept_categories().contains(&Category::ColorProfile));
+    }
+
+    #[test]
+    fn test_only_keep() {
+        let stripper = MetaStripper::new().min_severity(Sensitivity::High).keep([Category::Author]);
+        assert!(!stripper.removes(Category::Author));
+        assert!(!stripper.removes(Category::Timestamps));
+        assert!(!stripper.removes(Category::Orientation));
+        assert!(stripper.removes(Category::Gps));
+
+        let stripper = stripper.only_keep([Category::Timestamps]);
+        assert!(!stripper.removes(Category::Timestamps));
+        for category in [Category::Author, Category::Orientation, Category::ColorProfile, Category::Gps] {
+            assert!(stripper.removes(category));
+        }
+    }
+
+    #[test]
+    fn test_keep_refused_where_unsupported() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("drawing.svg");
+        fs::write(&input, TEST_SVG).unwrap();
+
+        // SVG comments can't be kept, so the file is refused rather than losing them
+        let error = MetaStripper::new().keep([Category::Content]).strip_file(&input).unwrap_err();
+        assert!(error.to_string().contains("content metadata can't be kept in svg files"), "{}", error);
+        assert_eq!(fs::read_to_string(&input).unwrap(), TEST_SVG);
+        let error = MetaStripper::new().remove_only([Category::Gps]).strip_file(&input).unwrap_err();
+        assert!(error.to_string().contains("content metadata"), "{}", error);
+
+        // Nothing the file contains is kept, so it's cleaned as usual
+        MetaStripper::new().keep([Category::Gps]).strip_file(&input).unwrap();
+        assert_ne!(fs::read_to_string(&input).unwrap(), TEST_SVG);
+    }
+
+    #[test]
+    fn test_min_severity_keeps_lower_tiers() {
+        let stripper = MetaStripper::new().min_severity(Sensitivity::High).strip_icc(true);
+        let kept = stripper.kept_categories();
+        assert!(kept.contains(&Category::Timestamps));
+        assert!(kept.contains(&Category::ColorProfile));
+        assert!(!kept.contains(&Category::Gps));
+        assert!(!kept.contains(&Category::Author));
+    }
+
+    #[test]
+    fn test_file_type_detected_by_content() {
+        let dir = tempdir().unwrap();
+
+        // A JPEG with the wrong extension is still cleaned as a JPEG
+        let misnamed = dir.path().join("photo.PNG");
+        ::image::RgbImage::new(8, 8).save_with_format(&misnamed, ::image::ImageFormat::Jpeg).unwrap();
+        assert_eq!(detect_format(&misnamed).as_deref(), Some("jpg"));
+        assert_eq!(format_mismatch(&misnamed), Some("jpg"));
+        MetaStripper::new().strip_file(&misnamed).unwrap();
+        assert!(fs::read(&misnamed).unwrap().starts_with(&[0xFF, 0xD8]));
+
+        let extensionless = dir.path().join("drawing");
+        fs::write(&extensionless, TEST_SVG).unwrap();
+        assert_eq!(determine_file_type(&extensionless), FileType::Image);
+        assert_eq!(format_mismatch(&extensionless), None);
+
+        // The extension is more specific than a zip signature
+        let document = dir.path().join("report.docx");
+        fs::write(&document, b"PK\x03\x04").unwrap();
+        assert_eq!(determine_file_type(&document), FileType::Office);
+        assert_eq!(format_mismatch(&document), None);
+
+        // Unrecognized content falls back to the extension
+        let unknown = dir.path().join("notes.pdf");
+        fs::write(&unknown, b"not really a pdf").unwrap();
+        assert_eq!(determine_file_type(&unknown), FileType::Pdf);
+    }
+
+    #[test]
+    fn test_overwrite_is_atomic() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("drawing.svg");
+        fs::write(&input, TEST_SVG).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&input, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        MetaStripper::new().strip_file(&input).unwrap();
+        assert!(!fs::read_to_string(&input).unwrap().contains("Jane"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&input).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+
+        // Copies elsewhere get the original's permissions, unless a mode is given
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let copy = dir.path().join("out/drawing.svg");
+            MetaStripper::new().strip_file_to(&input, &copy).unwrap();
+            assert_eq!(fs::metadata(&copy).unwrap().permissions().mode() & 0o777, 0o640);
+            MetaStripper::new().preserve_owner(true).chmod(0o600).strip_file_to(&input, &copy).unwrap();
+            assert_eq!(fs::metadata(&copy).unwrap().permissions().mode() & 0o777, 0o600);
+            fs::remove_dir_all(dir.path().join("out")).unwrap();
+        }
+
+        // A file that fails to clean is left untouched, without temporary files
+        let broken = dir.path().join("broken.pdf");
+        fs::write(&broken, b"%PDF-1.4 truncated").unwrap();
+        assert!(MetaStripper::new().strip_file(&broken).is_err());
+        assert_eq!(fs::read(&broken).unwrap(), b"%PDF-1.4 truncated");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_shred() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("drawing.svg");
+        fs::write(&input, TEST_SVG).unwrap();
+
+        let output = dir.path().join("cleaned.svg");
+        MetaStripper::new().shred(true).strip_file_to(&input, &output).unwrap();
+        assert!(!input.exists());
+        assert!(!fs::read_to_string(&output).unwrap().contains("Jane"));
+
+        // In place, the cleaned file survives the original being overwritten
+        MetaStripper::new().shred(true).strip_file(&output).unwrap();
+        assert!(fs::read_to_string(&output).unwrap().starts_with("<svg"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("drawing.svg");
+        fs::write(&target, TEST_SVG).unwrap();
+        let link = dir.path().join("link.svg");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert!(MetaStripper::new().strip_file(&link).is_err());
+        assert!(fs::read_to_string(&target).unwrap().contains("Jane"));
+
+        MetaStripper::new().follow_symlinks(true).strip_file(&link).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(!fs::read_to_string(&target).unwrap().contains("Jane"));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("drawing.svg");
+        fs::write(&input, TEST_SVG).unwrap();
+        let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_684_333_800);
+        fs::File::options().write(true).open(&input).unwrap().set_times(FileTimes::new().set_modified(taken)).unwrap();
+
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+
+        let preserved = dir.path().join("preserved.svg");
+        MetaStripper::new().timestamps(TimestampMode::Preserve).strip_file_to(&input, &preserved).unwrap();
+        assert_eq!(modified(&preserved), taken);
+
+        let reset = dir.path().join("reset.svg");
+        MetaStripper::new().timestamps(TimestampMode::Reset).strip_file_to(&input, &reset).unwrap();
+        assert_eq!(modified(&reset), SystemTime::UNIX_EPOCH + Duration::from_secs(RESET_TIMESTAMP_SECS));
+
+        let current = dir.path().join("current.svg");
+        MetaStripper::new().strip_file_to(&input, &current).unwrap();
+        assert!(modified(&current) > taken);
+    }
+
+    #[test]
+    fn test_strip_files_reports_progress() {
+        let dir = tempdir().unwrap();
+        let output_dir = dir.path().join("out");
+        fs::create_dir(&output_dir).unwrap();
+
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = dir.path().join(format!("{}.svg", i));
+                fs::write(&path, TEST_SVG).unwrap();
+                path
+            })
+            .collect();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_in_callback = Arc::clone(&seen);
+        let stripper = MetaStripper::new()
+            .output_dir(&output_dir)
+            .on_progress(move |progress| {
+                assert_eq!(progress.bytes, TEST_SVG.len() as u64);
+                assert!(!progress.failed);
+                assert_eq!(progress.result.as_ref().unwrap().path, progress.path);
+                seen_in_callback.lock().unwrap().push((progress.completed, progress.total));
+            });
+
+        let results = stripper.strip_files(&paths);
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(output_dir.join("1.svg").exists());
+
+        // Paired output paths get their directories created
+        let nested = output_dir.join("sub").join("dir").join("0.svg");
+        let results = stripper.strip_files_to(&[(paths[0].clone(), nested.clone())]);
+        assert!(results[0].is_ok());
+        assert!(nested.exists());
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen, vec![(1, 1), (1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn test_jobs_keep_result_order() {
+        let dir = tempdir().unwrap();
+        let drawing = dir.path().join("drawing.svg");
+        fs::write(&drawing, TEST_SVG).unwrap();
+        // Named as a video, so it's scheduled on the heavy side
+        let video = dir.path().join("clip.mp4");
+        fs::write(&video, b"not a video").unwrap();
+
+        let jobs = vec![
+            (video.clone(), dir.path().join("out.mp4")),
+            (drawing.clone(), dir.path().join("out.svg")),
+        ];
+        assert!(is_heavy(&video));
+        assert!(!is_heavy(&drawing));
+
+        let results = MetaStripper::new().jobs(2).strip_files_to(&jobs);
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap().path, drawing);
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let dir = tempdir().unwrap();
+        let broken = dir.path().join("broken.pdf");
+        fs::write(&broken, b"%PDF-1.4 truncated").unwrap();
+        let drawing = dir.path().join("drawing.svg");
+        fs::write(&drawing, TEST_SVG).unwrap();
+        let paths = vec![broken, drawing.clone()];
+
+        // A single thread takes the files in order
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let results = pool.install(|| MetaStripper::new().fail_fast(true).strip_files(&paths));
+        assert!(!results[0].as_ref().unwrap_err().is::<Skipped>());
+        assert!(results[1].as_ref().unwrap_err().is::<Skipped>());
+        assert_eq!(fs::read_to_string(&drawing).unwrap(), TEST_SVG);
+
+        let results = pool.install(|| MetaStripper::new().strip_files(&paths));
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::{Category, MetadataItem};
+use crate::xmp::find_element_value;
+
+// GPX and KML files are location data by definition, so the coordinates and
+// their timestamps are left alone. What can go is everything describing who
+// made the file and with which device: the GPX metadata block, <src> device
+// names, the creator attribute, KML atom author/link elements and comments.
+
+// Elements removed together with everything inside them
+const REMOVED_ELEMENTS: &[(&[u8], &str)] = &[
+    (b"metadata", "GPX Metadata"),
+    (b"src", "Device"),
+    (b"atom:author", "KML Author"),
+    (b"atom:link", "KML Link"),
+];
+
+// Fields worth naming from a removed block
+const BLOCK_FIELDS: &[(&str, &str)] = &[
+    ("name", "Name"),
+    ("desc", "Description"),
+    ("author", "Author"),
+    ("atom:name", "Name"),
+    ("email", "Email"),
+    ("time", "Time"),
+    ("keywords", "Keywords"),
+];
+
+// GPX point elements
+const POINT_ELEMENTS: &[&[u8]] = &[b"wpt", b"trkpt", b"rtept", b"gx:coord"];
+
+// Prefix of the report line flagging a file as inherently location data
+const LOCATION_WARNING: &str = "Warning: location data";
+
+pub fn strip_location_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<MetadataItem>> {
+    let data = fs::read(input_path)
+        .with_context(|| format!("Failed to read location file: {}", input_path.display()))?;
+    let text = String::from_utf8_lossy(&data);
+
+    let (cleaned, removed_metadata) = clean_location_file(&text)
+        .with_context(|| format!("Failed to parse location file: {}", input_path.display()))?;
+
+    fs::write(output_path, cleaned)
+        .with_context(|| format!("Failed to save location file: {}", output_path.display()))?;
+
+    Ok(removed_metadata)
+}
+
+fn clean_location_file(text: &str) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    let mut reader = Reader::from_str(text);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    let mut metadata = Vec::new();
+    let mut points = 0usize;
+    let mut in_coordinates = false;
+
+    // Depth inside an element we're dropping, plus its label and where its source text began
+    let mut skip_depth = 0usize;
+    let mut skip_label = "";
+    let mut skip_start = 0usize;
+
+    loop {
+        let position = reader.buffer_position();
+        let event = reader.read_event()?;
+
+        if skip_depth > 0 {
+            match event {
+                Event::Start(_) => skip_depth += 1,
+                Event::End(_) => {
+                    skip_depth -= 1;
+                    if skip_depth == 0 {
+                        let block = &text[skip_start..reader.buffer_position()];
+                        metadata.extend(describe_block(skip_label, block));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(e) => {
+                if let Some(label) = removed_label(&e) {
+                    skip_depth = 1;
+                    skip_label = label;
+                    skip_start = position;
+                    continue;
+                }
+                if POINT_ELEMENTS.contains(&e.name().as_ref()) {
+                    points += 1;
+                }
+                in_coordinates = e.name().as_ref() == b"coordinates";
+                writer.write_event(Event::Start(clean_creator(&e, &mut metadata)?))?;
+            }
+            Event::Empty(e) => {
+                if let Some(label) = removed_label(&e) {
+                    metadata.push(MetadataItem::new(label, "present"));
+                    continue;
+                }
+                if POINT_ELEMENTS.contains(&e.name().as_ref()) {
+                    points += 1;
+                }
+                writer.write_event(Event::Empty(clean_creator(&e, &mut metadata)?))?;
+            }
+            Event::Text(e) => {
+                // KML packs "lon,lat[,alt]" tuples separated by whitespace
+                if in_coordinates {
+                    points += e.unescape()?.split_whitespace().count();
+                }
+                writer.write_event(Event::Text(e))?;
+            }
+            Event::End(e) => {
+                in_coordinates = false;
+                writer.write_event(Event::End(e))?;
+            }
+            Event::Comment(comment) => {
+                let comment = String::from_utf8_lossy(&comment).trim().to_string();
+                if !comment.is_empty() {
+                    metadata.push(MetadataItem::new("Comment", comment));
+                }
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+
+    let warning = format!("{} points left in place", points);
+    metadata.insert(0, MetadataItem::with_category(Category::Notice, LOCATION_WARNING, warning));
+    Ok((writer.into_inner(), metadata))
+}
+
+fn removed_label(element: &BytesStart) -> Option<&'static str> {
+    REMOVED_ELEMENTS
+        .iter()
+        .find(|(name, _)| *name == element.name().as_ref())
+        .map(|(_, label)| *label)
+}
+
+/// Blanks the creator attribute on the GPX root, which names the app or device that recorded the track
+fn clean_creator(element: &BytesStart, metadata: &mut Vec<MetadataItem>) -> Result<BytesStart<'static>> {
+    let mut cleaned = element.to_owned();
+    if element.name().as_ref() != b"gpx" {
+        return Ok(cleaned);
+    }
+
+    cleaned.clear_attributes();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.key.as_ref() == b"creator" {
+            let creator = attribute.unescape_value()?;
+            if !creator.trim().is_empty() {
+                metadata.push(MetadataItem::new("Creator", creator.trim()));
+            }
+            // The attribute is required by the GPX schema, so keep it empty
+            cleaned.push_attribute(("creator", ""));
+            continue;
+        }
+        cleaned.push_attribute(attribute);
+    }
+
+    Ok(cleaned)
+}
+
+fn describe_block(label: &str, block: &str) -> Vec<MetadataItem> {
+    let fields: Vec<MetadataItem> = BLOCK_FIELDS
+        .iter()
+        .filter_map(|(tag, name)| {
+            find_element_value(block, tag)
+                .filter(|value| !value.is_empty())
+                .map(|value| MetadataItem::new(format!("{} {}", label, name), value))
+        })
+        .collect();
+
+    if !fields.is_empty() {
+        return fields;
+    }
+
+    // Blocks like <src> hold a single value
+    let value: String = block
+        .split('<')
+        .filter_map(|part| part.split_once('>').map(|(_, text)| text.trim()))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if value.is_empty() {
+        vec![MetadataItem::new(label, "present")]
+    } else {
+        vec![MetadataItem::new(label, value)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_gpx() {
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- exported by phone -->
+<gpx version="1.1" creator="Garmin Connect" xmlns="http://www.topografix.com/GPX/1/1">
+  <metadata><name>Morning Run</name><author><name>Jane Doe</name></author><time>2024-03-01T07:00:00Z</time></metadata>
+  <trk>
+    <src>Forerunner 255</src>
+    <trkseg>
+      <trkpt lat="52.1" lon="4.3"><time>2024-03-01T07:00:00Z</time></trkpt>
+      <trkpt lat="52.2" lon="4.4"/>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+        let (cleaned, removed) = clean_location_file(gpx).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        let cleaned = String::from_utf8(cleaned).unwrap();
+
+        assert_eq!(removed[0], "Warning: location data: 2 points left in place");
+        assert!(removed.contains(&"Creator: Garmin Connect".to_string()));
+        assert!(removed.contains(&"GPX Metadata Author: Jane Doe".to_string()));
+        assert!(removed.contains(&"Device: Forerunner 255".to_string()));
+        assert!(removed.contains(&"Comment: exported by phone".to_string()));
+
+        assert!(cleaned.contains(r#"creator="""#));
+        assert!(!cleaned.contains("Jane Doe"));
+        assert!(!cleaned.contains("Forerunner"));
+        assert!(cleaned.contains(r#"<trkpt lat="52.1" lon="4.3"><time>2024-03-01T07:00:00Z</time></trkpt>"#));
+    }
+
+    #[test]
+    fn test_clean_kml() {
+        let kml = r#"<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:atom="http://www.w3.org/2005/Atom">
+  <Document>
+    <atom:author><atom:name>Jane Doe</atom:name></atom:author>
+    <Placemark><LineString><coordinates>4.3,52.1,0 4.4,52.2,0
+      4.5,52.3,0</coordinates></LineString></Placemark>
+  </Document>
+</kml>"#;
+
+        let (cleaned, removed) = clean_location_file(kml).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        let cleaned = String::from_utf8(cleaned).unwrap();
+
+        assert_eq!(removed[0], "Warning: location data: 3 points left in place");
+        assert!(removed.contains(&"KML Author Name: Jane Doe".to_string()));
+        assert!(!cleaned.contains("atom:author"));
+        assert!(cleaned.contains("4.5,52.3,0</coordinates>"));
+    }
+}
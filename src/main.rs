@@ -1,129 +1,2298 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use indicatif::{ProgressBar, ProgressStyle};
-use log::{info, warn};
+use log::LevelFilter;
+use metastripper::{
+    backup_path, determine_file_type, format_mismatch, privacy_score, resolve_input, Assignment, BackupLedger, Category, ChangeTracker, CleanCache, Config, DateRange, FfmpegConfig, FileType, FolderEvents, IoPriority, Journal, MetaStripper, MetadataItem, NameTemplate, OrientationMode, PathFilter, PdfOptions, Profile, Report, Safeguards, Sensitivity, Skipped, StreamKind, TimestampMode, VideoOptions,
+};
+use audit::AuditEntry;
+use events::Events;
+use manifest::{Hashed, ManifestEntry};
 use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::process::ExitCode;
+use std::time::Duration;
 use walkdir::WalkDir;
 
-mod image;
-mod pdf;
+mod audit;
+mod events;
+mod manifest;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "tui")]
+mod tui;
 
-// Import the module but not directly the function to avoid linker errors
-mod video;
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Enable verbose logging, the same as --log-level info
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Log messages at LEVEL and above: error, warn, info, debug or trace
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
+
+    /// Append log messages to FILE instead of standard error (at info level unless --log-level is given)
+    #[arg(long, global = true, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Suppress all output except errors
+    #[arg(short = 'q', long, global = true)]
+    quiet: bool,
+
+    /// Ignore ~/.config/metastripper/config.toml and .metastripper.toml files
+    #[arg(long, global = true)]
+    no_config: bool,
+}
+
+// Parsed once per run, so the size of the strip options doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Remove metadata from files
+    Strip(StripArgs),
+    /// List the metadata found in files without modifying them
+    Inspect(InspectArgs),
+    /// Check that files no longer contain removable metadata
+    Verify(InspectArgs),
+    /// Put original files back from their .bak backups
+    Restore(RestoreArgs),
+    /// Keep cleaning files as they are added to or changed in directories
+    Watch(WatchArgs),
+    /// Serve strip, inspect and verify requests from other programs over a Unix socket
+    Daemon(DaemonArgs),
+    /// Run an HTTP service that cleans and inspects uploaded files
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+    /// Browse files and their metadata in a terminal UI, pick what to remove, then clean them
+    #[cfg(feature = "tui")]
+    Browse(BrowseArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+    /// Print the man page, or write one per command into a directory
+    Manpage(ManpageArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// Input files or directories to process
+    inputs: Vec<PathBuf>,
+
+    /// Also process the files and directories listed in this file, one per line, or '-' for standard input
+    #[arg(long, value_name = "FILE")]
+    files_from: Option<PathBuf>,
+
+    /// Entries in --files-from are separated by NUL characters, as printed by find -print0
+    #[arg(short = '0', long = "null", requires = "files_from")]
+    null_separated: bool,
+
+    /// Recursively process subdirectories
+    #[arg(short = 'r', long)]
+    recursive: bool,
+
+    /// Follow symbolic links to files and directories, and clean the files they point to instead of refusing links
+    #[arg(short = 'L', long)]
+    follow_symlinks: bool,
+
+    /// Process only image files
+    #[arg(long)]
+    only_images: bool,
+
+    /// Process only video files
+    #[arg(long)]
+    only_videos: bool,
+
+    /// Process only PDF files
+    #[arg(long)]
+    only_pdfs: bool,
+
+    /// Process only audio files
+    #[arg(long)]
+    only_audio: bool,
+
+    /// Process only office documents
+    #[arg(long)]
+    only_office: bool,
+
+    /// Process only files matching this glob pattern, e.g. '*.jpg' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files and directories matching this glob pattern, e.g. 'node_modules' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct FormatArgs {
+    /// Apply a named set of rules from the config file, or a built-in one: social-media, archival
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Also clean supported files stored inside zip archives
+    #[arg(long)]
+    recurse_archives: bool,
+
+    /// Keep maker notes in RAW camera files (needed by some RAW converters)
+    #[arg(long)]
+    keep_maker_notes: bool,
+
+    /// Metadata categories to leave in place, e.g. orientation,icc,copyright
+    #[arg(long, value_delimiter = ',', value_name = "CATEGORIES")]
+    keep: Vec<Category>,
+
+    /// Remove only these metadata categories and keep the rest, e.g. gps,timestamps
+    #[arg(long, value_delimiter = ',', value_name = "CATEGORIES", conflicts_with = "keep")]
+    remove: Vec<Category>,
+
+    /// Only report and remove metadata at least this sensitive: info, low, medium or high
+    #[arg(long, value_name = "SEVERITY")]
+    min_severity: Option<Sensitivity>,
+
+    /// Write a value in place of the removed metadata, e.g. author='A. Nonymous' or date=2020-01-01 (repeatable).
+    /// Fields: author, title, copyright, software, make, model, date
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<Assignment>,
+
+    /// Replace device, software and date fields with plausible random values instead of leaving them empty
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Pick --anonymize dates from this range (default: the last five years)
+    #[arg(long, value_name = "FROM..TO", requires = "anonymize")]
+    anonymize_dates: Option<DateRange>,
+
+    /// Image orientation: keep the tag, rotate the pixels to match it, or strip it
+    #[arg(long, value_name = "keep|rotate|strip", default_value = "keep")]
+    orientation: OrientationMode,
+
+    /// Remove embedded ICC color profiles (kept by default to avoid color shifts)
+    #[arg(long)]
+    strip_icc: bool,
+
+    /// Remove files embedded in PDFs (attachments, associated files)
+    #[arg(long)]
+    pdf_remove_attachments: bool,
+
+    /// Remove JavaScript from PDFs (document scripts and actions on opening, links and form fields)
+    #[arg(long)]
+    pdf_remove_javascript: bool,
+
+    /// Also drop objects left behind in PDFs by earlier revisions, such as replaced pages and metadata
+    #[arg(long)]
+    pdf_flatten: bool,
+
+    /// Password for encrypted PDFs, which are encrypted again with it once cleaned
+    #[arg(long, value_name = "PASSWORD", env = "METASTRIPPER_PDF_PASSWORD", hide_env_values = true)]
+    pdf_password: Option<String>,
+
+    /// Drop subtitle tracks from videos (kept by default)
+    #[arg(long)]
+    video_drop_subs: bool,
+
+    /// Keep chapters and their titles in videos (dropped by default)
+    #[arg(long)]
+    video_keep_chapters: bool,
+
+    /// Keep data streams in videos, such as camera telemetry (dropped by default)
+    #[arg(long)]
+    video_keep_data: bool,
+
+    /// Kinds of video streams to keep on top of the default video, audio, subtitle, timecode and attachment, e.g. data
+    #[arg(long, value_delimiter = ',', value_name = "KINDS")]
+    video_keep_streams: Vec<StreamKind>,
+
+    /// Kinds of video streams to drop, e.g. timecode,attachment
+    #[arg(long, value_delimiter = ',', value_name = "KINDS")]
+    video_drop_streams: Vec<StreamKind>,
+
+    /// ffmpeg binary to use for video and Ogg/M4A audio (default: ffmpeg on PATH)
+    #[arg(long, value_name = "PATH", env = "METASTRIPPER_FFMPEG")]
+    ffmpeg_path: Option<PathBuf>,
+
+    /// ffprobe binary to use (default: the one next to --ffmpeg-path, or ffprobe on PATH)
+    #[arg(long, value_name = "PATH", env = "METASTRIPPER_FFPROBE")]
+    ffprobe_path: Option<PathBuf>,
+
+    /// Extra argument passed to ffmpeg before the input, e.g. --ffmpeg-arg=-hwaccel --ffmpeg-arg=auto (repeatable)
+    #[arg(long = "ffmpeg-arg", value_name = "ARG", allow_hyphen_values = true)]
+    ffmpeg_args: Vec<String>,
+
+    /// Directory for files being written (default: next to each cleaned file, and the system temp directory)
+    #[arg(long, value_name = "DIR")]
+    temp_dir: Option<PathBuf>,
+
+    /// Stop ffmpeg and fail the file if its ffmpeg and ffprobe runs take longer than this many seconds in total
+    #[arg(long, value_name = "SECONDS", env = "METASTRIPPER_FFMPEG_TIMEOUT")]
+    ffmpeg_timeout: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// A single JSON array with one object per file
+    Json,
+    /// One JSON object per line, per file
+    Ndjson,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ProgressFormat {
+    /// A progress bar on the terminal
+    Bar,
+    /// started, file_done, file_failed and finished events as JSON, one per line
+    Json,
+}
+
+/// What to do when a cleaned file's destination is already taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ConflictPolicy {
+    /// Replace the existing file
+    Overwrite,
+    /// Leave the existing file alone and don't clean the input
+    Skip,
+    /// Add a number to the name, e.g. photo-1.jpg
+    Rename,
+    /// Stop before cleaning anything
+    Error,
+}
+
+#[derive(clap::Args, Debug)]
+struct StripArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    format: FormatArgs,
+
+    /// Output format for results
+    #[arg(long = "format", value_enum, default_value = "text")]
+    output_format: OutputFormat,
+
+    /// Clean files in place, replacing the originals
+    #[arg(short = 'w', long)]
+    overwrite: bool,
+
+    /// Write cleaned copies to this directory, leaving the originals alone
+    #[arg(short = 'o', long, conflicts_with = "overwrite")]
+    output_dir: Option<PathBuf>,
+
+    /// Name cleaned copies from a template, e.g. '{stem}_clean.{ext}'. Also takes {name}, {hash}, {n} and {date}.
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "overwrite")]
+    rename: Option<NameTemplate>,
+
+    /// When a cleaned file's name is already taken in the output directory: overwrite, skip, rename or error
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "overwrite")]
+    on_conflict: ConflictPolicy,
+
+    /// Show the metadata in each file before and after cleaning, side by side
+    #[arg(short = 'm', long)]
+    show_metadata: bool,
+
+    /// Preview the metadata each file would lose and where it would be written, without making changes
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Create backup of original files (.bak extension)
+    #[arg(short = 'b', long)]
+    backup: bool,
+
+    /// Don't create backups, even if a config file asks for them
+    #[arg(long, conflicts_with = "backup")]
+    no_backup: bool,
+
+    /// Give copies written to --output-dir the original's owner and group too (needs root on Unix)
+    #[arg(long)]
+    preserve_owner: bool,
+
+    /// Set the permissions of cleaned files to MODE, in octal such as 644, instead of copying the original's
+    #[arg(long, value_name = "MODE", value_parser = parse_mode)]
+    chmod: Option<u32>,
+
+    /// Overwrite originals with zeros after cleaning, and delete them when writing to --output-dir
+    #[arg(long, conflicts_with = "backup")]
+    shred: bool,
+
+    /// Write byte-identical output for identical input, with fixed timestamps, e.g. for content-addressed stores
+    #[arg(long, conflicts_with_all = ["preserve_mtime", "anonymize"])]
+    deterministic: bool,
+
+    /// Give cleaned files the original modification and access times
+    #[arg(long)]
+    preserve_mtime: bool,
+
+    /// Set the modification and access times of cleaned files to 1980-01-01
+    #[arg(long, conflicts_with = "preserve_mtime")]
+    touch: bool,
+
+    /// Re-scan each cleaned file and fail if any removable metadata is left
+    #[arg(long)]
+    verify: bool,
+
+    /// Refuse to write cleaned files more than PERCENT smaller than the original
+    #[arg(long, value_name = "PERCENT")]
+    max_shrink: Option<f64>,
+
+    /// Decode each cleaned file and refuse it if its dimensions, pixels, duration or page count changed
+    #[arg(long)]
+    check_integrity: bool,
+
+    /// Report whether each cleaned image has the same pixels, or video and audio the same streams, as the original
+    #[arg(long)]
+    check_identical: bool,
+
+    /// Only warn when a file fails --max-shrink or --check-integrity, and write it anyway
+    #[arg(long)]
+    integrity_warn_only: bool,
+
+    /// Skip files larger than SIZE, e.g. 500M or 2G
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Stop at the first file that fails instead of processing the rest
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Move files that fail to clean into DIR, keeping their paths below the scanned directory, and log why in DIR/failures.log
+    #[arg(long, value_name = "DIR")]
+    failed_dir: Option<PathBuf>,
+
+    /// Number of files to clean at once (default: one per CPU core). Videos are cleaned one at a time.
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Run the workers, and the ffmpeg processes they start, at this niceness: 0 to 19, 19 being the lowest priority (Unix only)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(0..=19))]
+    nice: Option<i32>,
+
+    /// Disk priority of the workers and the ffmpeg processes they start, as with ionice (Linux only)
+    #[arg(long, value_name = "low|idle")]
+    io_priority: Option<IoPriority>,
+
+    /// Start files no faster than an average of this many megabytes of input per second
+    #[arg(long = "throttle-mb-s", value_name = "MB", value_parser = parse_rate)]
+    throttle: Option<u64>,
+
+    /// Read a single file from standard input and write the cleaned file to standard output
+    #[arg(long, conflicts_with_all = ["inputs", "files_from", "output_dir", "overwrite", "backup", "shred", "dry_run", "verify", "failed_dir", "hash_manifest"])]
+    stdin: bool,
+
+    /// Format of the file read with --stdin, as an extension such as jpg, for content that isn't recognized
+    #[arg(long = "type", value_name = "EXTENSION", requires = "stdin")]
+    stdin_type: Option<String>,
+
+    /// Skip files that are unchanged since an earlier --cache run cleaned them
+    #[arg(long)]
+    cache: bool,
+
+    /// Cache file to use instead of ~/.cache/metastripper/cleaned.json (implies --cache)
+    #[arg(long, value_name = "PATH")]
+    cache_file: Option<PathBuf>,
+
+    /// Clean files even if the cache says they are unchanged, and update the cache
+    #[arg(long)]
+    force: bool,
+
+    /// Log planned and finished files to FILE as the run goes, so an interrupted run can be resumed
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    journal: Option<PathBuf>,
+
+    /// Carry on from the run --journal logged: skip the files it finished and remove its temporary files
+    #[arg(long, requires = "journal")]
+    resume: bool,
+
+    /// Write the SHA-256 hash of every input before cleaning and of its cleaned output, with times, to a CSV file
+    #[arg(long, value_name = "FILE")]
+    hash_manifest: Option<PathBuf>,
+
+    /// Write a report of every file, the metadata found and removed, and sizes, as JSON or (for .html files) HTML
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Don't show a progress bar, e.g. on terminals that can't redraw it
+    #[arg(long, conflicts_with = "progress")]
+    no_progress: bool,
+
+    /// Show progress as a bar, or as JSON events, one per line, for programs that draw their own
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "bar")]
+    progress: ProgressFormat,
+
+    /// Write --progress json events to this open file descriptor instead of standard output (Unix only)
+    #[arg(long, value_name = "FD")]
+    progress_fd: Option<i32>,
+
+    /// Show statistics summary
+    #[arg(short = 's', long)]
+    stats: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct InspectArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    format: FormatArgs,
+
+    /// Output format for results
+    #[arg(long = "format", value_enum, default_value = "text")]
+    output_format: OutputFormat,
+
+    /// Stop at the first file that fails (or, when verifying, still has metadata)
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Print an OpenStreetMap link for each GPS position found
+    #[arg(long)]
+    maps_url: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct RestoreArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Restore every file with a backup recorded by strip --backup
+    #[arg(long, conflicts_with_all = ["inputs", "files_from"])]
+    all: bool,
+
+    /// Restore files even if their backup or the cleaned file changed since cleaning
+    #[arg(long)]
+    force: bool,
+
+    /// Leave the .bak files in place after restoring
+    #[arg(short = 'k', long)]
+    keep_backups: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct WatchArgs {
+    /// Directories to watch, with the same filter options as the other commands
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    format: FormatArgs,
+
+    /// Write cleaned files here, mirroring the watched directories, instead of cleaning in place
+    #[arg(short = 'o', long)]
+    output_dir: Option<PathBuf>,
+
+    /// Delete each original once its cleaned copy is in --output-dir
+    #[arg(long = "move", requires = "output_dir")]
+    move_originals: bool,
+
+    /// Also clean the files already there when watching starts
+    #[arg(long)]
+    process_existing: bool,
+
+    /// Seconds a new or changed file has to stay the same before it's cleaned
+    #[arg(long, value_name = "SECONDS", default_value = "2")]
+    interval: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct DaemonArgs {
+    #[command(flatten)]
+    format: FormatArgs,
+
+    /// Socket to listen on (default: metastripper.sock in $XDG_RUNTIME_DIR or the temp directory),
+    /// or on Windows the named pipe (default: \\.\pipe\metastripper)
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+}
+
+#[cfg(feature = "server")]
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    #[command(flatten)]
+    format: FormatArgs,
+
+    /// Address and port to listen on
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Largest upload accepted, in megabytes
+    #[arg(long, value_name = "MB", default_value = "100")]
+    max_upload: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct CompletionsArgs {
+    /// Shell to generate completions for
+    #[arg(value_enum)]
+    shell: clap_complete::Shell,
+}
+
+#[cfg(feature = "tui")]
+#[derive(clap::Args, Debug)]
+struct BrowseArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    format: FormatArgs,
+
+    /// Clean files in place, replacing the originals
+    #[arg(short = 'w', long)]
+    overwrite: bool,
+
+    /// Write cleaned copies to this directory, leaving the originals alone
+    #[arg(short = 'o', long, conflicts_with = "overwrite")]
+    output_dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ManpageArgs {
+    /// Write metastripper.1 and a page per command (metastripper-strip.1, ...) here
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+}
+
+/// One line of the daemon protocol. `id` is echoed back so clients can match
+/// responses to requests.
+#[cfg(any(unix, windows))]
+#[derive(Debug, serde::Deserialize)]
+struct DaemonRequest {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    command: DaemonCommand,
+}
+
+#[cfg(any(unix, windows))]
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum DaemonCommand {
+    /// Cleans `path` in place, or into `output`
+    Strip { path: PathBuf, output: Option<PathBuf> },
+    Inspect { path: PathBuf },
+    Verify { path: PathBuf },
+    Ping,
+}
+
+/// How a run ended. Scripts can tell these apart by the exit code; other errors exit
+/// with 1 and invalid arguments with 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Success,
+    SomeFailed,
+    AllFailed,
+    NoFiles,
+}
+
+impl Outcome {
+    fn from_counts(failed: usize, total: usize) -> Self {
+        match failed {
+            0 => Outcome::Success,
+            failed if failed >= total => Outcome::AllFailed,
+            _ => Outcome::SomeFailed,
+        }
+    }
+
+    fn exit_code(self) -> ExitCode {
+        match self {
+            Outcome::Success => ExitCode::SUCCESS,
+            Outcome::SomeFailed => ExitCode::from(3),
+            Outcome::AllFailed => ExitCode::from(4),
+            Outcome::NoFiles => ExitCode::from(5),
+        }
+    }
+}
+
+/// Nothing on the command line matched a file that can be processed
+#[derive(Debug, thiserror::Error)]
+#[error("No valid files found to process")]
+struct NoFilesFound;
+
+/// Whether `a` and `b` name the same file, where `b` may not exist yet
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            fs::canonicalize(parent).ok().map(|parent| parent.join(name))
+        }
+        _ => None,
+    };
+    a == b || matches!((canonical(a), canonical(b)), (Some(a), Some(b)) if a == b)
+}
+
+/// The device and inode number that identify the file at `path`, and how many hard
+/// links it has, where the platform tells
+fn hard_link_id(path: &Path) -> Option<((u64, u64), u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = fs::metadata(path).ok()?;
+        Some(((metadata.dev(), metadata.ino()), metadata.nlink()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Replaces `path` with a hard link to `target`, through a temporary name so `path`
+/// always exists
+fn relink(target: &Path, path: &Path) -> std::io::Result<()> {
+    let mut temp_name = OsString::from(format!(".metastripper-{}-link-", std::process::id()));
+    temp_name.push(path.file_name().unwrap_or_default());
+    let temp_path = path.with_file_name(temp_name);
+    fs::hard_link(target, &temp_path)?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
+#[derive(Debug)]
+struct FileInfo {
+    path: PathBuf,
+    file_type: FileType,
+    /// Path below the scanned directory, or the file name for files named directly
+    relative: PathBuf,
+}
+
+#[derive(Debug, Default)]
+struct ProcessingStats {
+    files_processed: usize,
+    files_failed: usize,
+    metadata_items_removed: usize,
+    /// Size of the cleaned files before cleaning, and how much smaller they got
+    bytes_before: u64,
+    bytes_saved: i64,
+    by_type: std::collections::HashMap<String, usize>,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(outcome) => outcome.exit_code(),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            if e.is::<NoFilesFound>() {
+                Outcome::NoFiles.exit_code()
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+fn run() -> Result<Outcome> {
+    let mut cli = Cli::parse();
+
+    // Built-in profiles work without config files
+    let config = if cli.no_config { Config::default() } else { Config::load()? };
+    apply_config(&mut cli.command, config)?;
+
+    init_logging(&cli)?;
+
+    match cli.command {
+        Command::Strip(ref args) => run_strip(args, &cli),
+        Command::Inspect(ref args) => run_inspect(args, &cli),
+        Command::Verify(ref args) => run_verify(args, &cli),
+        Command::Restore(ref args) => run_restore(args, &cli),
+        Command::Watch(ref args) => run_watch(args, &cli),
+        Command::Daemon(ref args) => run_daemon(args, &cli),
+        #[cfg(feature = "server")]
+        Command::Serve(ref args) => run_serve(args, &cli),
+        #[cfg(feature = "tui")]
+        Command::Browse(ref args) => run_browse(args, &cli),
+        Command::Completions(ref args) => {
+            clap_complete::generate(args.shell, &mut Cli::command(), "metastripper", &mut std::io::stdout());
+            Ok(Outcome::Success)
+        }
+        Command::Manpage(ref args) => run_manpage(args),
+    }
+}
+
+/// Sends this crate's log messages, one line each with the time, level and module, to
+/// standard error or the log file. RUST_LOG adds to or overrides the level per module.
+fn init_logging(cli: &Cli) -> Result<()> {
+    let level = match cli.log_level {
+        Some(level) => level,
+        None if cli.verbose || cli.log_file.is_some() => LevelFilter::Info,
+        None => return Ok(()),
+    };
+    // --quiet keeps the terminal clear, but a log file was asked for explicitly
+    if cli.quiet && cli.log_file.is_none() {
+        return Ok(());
+    }
+
+    let mut builder = env_logger::Builder::new();
+    builder.filter_module("metastripper", level);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    builder.format(|buf, record| {
+        let time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        writeln!(buf, "{} {:<5} {} {}", time, record.level(), record.target(), record.args())
+    });
+    if let Some(path) = &cli.log_file {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file: {}", path.display()))?;
+        builder.target(env_logger::Target::Pipe(Box::new(file))).write_style(env_logger::WriteStyle::Never);
+    }
+    builder.init();
+    Ok(())
+}
+
+fn run_strip(args: &StripArgs, cli: &Cli) -> Result<Outcome> {
+    if args.stdin {
+        return run_strip_stdin(args, cli);
+    }
+
+    // Don't allow contradictory options
+    if cli.quiet && (cli.verbose || args.show_metadata) {
+        eprintln!("Warning: --quiet mode enabled, --verbose and --show-metadata will be ignored");
+    }
+    // Profiles and config files can ask for random values, which clap can't see
+    if args.deterministic && args.format.anonymize {
+        anyhow::bail!("--deterministic can't be combined with --anonymize, whose values are random");
+    }
+
+    // Validate output directory if specified
+    if let Some(ref output_dir) = args.output_dir {
+        if !output_dir.exists() && !args.dry_run {
+            std::fs::create_dir_all(output_dir)
+                .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+        }
+    }
+
+    let mut files = collect_files(&args.input, cli.quiet)?;
+
+    // Mirror scanned directories under the output directory, so files with the same
+    // name in different subdirectories don't overwrite each other
+    let mut output_paths = HashMap::new();
+    for (counter, file) in files.iter().enumerate() {
+        let mut path = match (args.overwrite, &args.output_dir) {
+            (false, Some(output_dir)) => output_dir.join(&file.relative),
+            _ => file.path.clone(),
+        };
+        if let Some(template) = &args.rename {
+            path.set_file_name(template.render(&file.path, counter + 1)?);
+        }
+        output_paths.insert(file.path.clone(), path);
+    }
+    let output_path = |file: &FileInfo| output_paths[&file.path].clone();
+
+    // Originals are only ever replaced with --overwrite, including when --output-dir or
+    // --rename happen to point a cleaned copy back at its original
+    if !args.overwrite {
+        let in_place: Vec<&FileInfo> = files.iter().filter(|file| is_same_path(&file.path, &output_path(file))).collect();
+        if let Some(first) = in_place.first() {
+            if args.output_dir.is_none() && args.rename.is_none() {
+                anyhow::bail!(
+                    "Nowhere to write cleaned files: use --overwrite (-w) to clean them in place, or --output-dir (-o) or --rename to write cleaned copies"
+                );
+            }
+            anyhow::bail!(
+                "{} would be written over its original{}; use --overwrite (-w) to clean files in place",
+                first.path.display(),
+                if in_place.len() > 1 { format!(" (and {} more files)", in_place.len() - 1) } else { String::new() }
+            );
+        }
+    }
+
+    if let Some(limit) = args.max_file_size {
+        files.retain(|file| match fs::metadata(&file.path) {
+            Ok(metadata) if metadata.len() > limit => {
+                if !cli.quiet {
+                    eprintln!(
+                        "Skipping {}: its size of {} is over --max-file-size",
+                        file.path.display(),
+                        indicatif::HumanBytes(metadata.len())
+                    );
+                }
+                false
+            }
+            _ => true,
+        });
+        if files.is_empty() {
+            return Ok(Outcome::Success);
+        }
+    }
+
+    // Cleaning in place renames the cleaned file over one name of the original, which
+    // would leave its other hard links pointing at the uncleaned original. Each file is
+    // cleaned once instead, and its other names are linked to the result.
+    let mut links = Vec::new();
+    if args.overwrite {
+        let mut first_names: HashMap<(u64, u64), (PathBuf, u64, u64)> = HashMap::new();
+        files.retain(|file| {
+            let Some((id, link_count)) = hard_link_id(&file.path) else { return true };
+            match first_names.get_mut(&id) {
+                Some((first, _, names)) => {
+                    if !is_same_path(&file.path, first) {
+                        links.push((file.path.clone(), first.clone()));
+                        *names += 1;
+                    }
+                    false
+                }
+                None => {
+                    first_names.insert(id, (file.path.clone(), link_count, 1));
+                    true
+                }
+            }
+        });
+        if !cli.quiet {
+            for (first, link_count, names) in first_names.values().filter(|(_, link_count, names)| link_count > names) {
+                eprintln!(
+                    "Warning: {} has {} hard links elsewhere, which will still point to the uncleaned original",
+                    first.display(),
+                    link_count - names
+                );
+            }
+        }
+    }
+
+    let timestamps = match (args.preserve_mtime, args.touch) {
+        (true, _) => TimestampMode::Preserve,
+        (_, true) => TimestampMode::Reset,
+        _ => TimestampMode::Current,
+    };
+    let mut stripper = build_stripper(&args.format)
+        .backup(args.backup)
+        .timestamps(timestamps)
+        .shred(args.shred)
+        .preserve_owner(args.preserve_owner)
+        .deterministic(args.deterministic)
+        .follow_symlinks(args.input.follow_symlinks)
+        .safeguards(Safeguards {
+            max_shrink: args.max_shrink,
+            check_content: args.check_integrity,
+            warn_only: args.integrity_warn_only,
+            report_identical: args.check_identical,
+        })
+        .fail_fast(args.fail_fast);
+    if let Some(jobs) = args.jobs {
+        stripper = stripper.jobs(jobs);
+    }
+    if let Some(nice) = args.nice {
+        stripper = stripper.nice(nice);
+    }
+    if let Some(priority) = args.io_priority {
+        stripper = stripper.io_priority(priority);
+    }
+    if let Some(bytes_per_second) = args.throttle {
+        stripper = stripper.throttle(bytes_per_second);
+    }
+    if let Some(mode) = args.chmod {
+        stripper = stripper.chmod(mode);
+    }
+
+    let mut cache = match (&args.cache_file, args.cache) {
+        (Some(path), _) => Some(CleanCache::load(path, &stripper.options_hash())?),
+        (None, true) => {
+            let path = CleanCache::default_path().context("Failed to find a cache directory; use --cache-file")?;
+            Some(CleanCache::load(&path, &stripper.options_hash())?)
+        }
+        (None, false) => None,
+    };
+    if let (Some(cache), false) = (&cache, args.force) {
+        let found = files.len();
+        files.retain(|file| !cache.is_clean(&file.path, &output_path(file)));
+        if files.len() < found && !cli.quiet {
+            eprintln!("Skipping {} files unchanged since they were cleaned (--force cleans them again)", found - files.len());
+        }
+        if files.is_empty() {
+            return Ok(Outcome::Success);
+        }
+    }
+
+    let journal = match (&args.journal, args.resume) {
+        (Some(path), true) => {
+            let (journal, removed) = Journal::resume(path)?;
+            if removed > 0 && !cli.quiet {
+                eprintln!("Removed {} temporary files left by the interrupted run", removed);
+            }
+            let found = files.len();
+            files.retain(|file| !journal.is_done(&file.path));
+            if files.len() < found && !cli.quiet {
+                eprintln!("Skipping {} files the interrupted run already cleaned", found - files.len());
+            }
+            Some(journal)
+        }
+        (Some(path), false) => Some(Journal::create(path)?),
+        (None, _) => None,
+    };
+
+    let (outputs, refused) = resolve_conflicts(&mut files, output_path, args.on_conflict, args.dry_run, cli.quiet)?;
+    if files.is_empty() {
+        if let Some(journal) = &journal {
+            journal.finish()?;
+        }
+        return Ok(Outcome::Success);
+    }
+
+    let json_output = args.output_format != OutputFormat::Text;
+
+    if args.shred && !args.dry_run && !cli.quiet {
+        eprintln!(
+            "Warning: --shred can't reach old copies kept by SSDs, journaling or copy-on-write file systems, or snapshots"
+        );
+    }
+
+    if args.dry_run {
+        // Read-only: what verify would find in each file is what cleaning would remove
+        let stripper = build_stripper(&args.format);
+        let results: Vec<Result<Report>> = files
+            .par_iter()
+            .zip(&outputs)
+            .map(|(file, output)| {
+                if refused.contains(&file.path) {
+                    Err(conflict_error(output))
+                } else {
+                    stripper.verify_file(&file.path)
+                }
+            })
+            .collect();
+        let failed = results.iter().filter(|result| result.is_err()).count();
+        if json_output {
+            let entries = files
+                .iter()
+                .zip(&results)
+                .zip(&outputs)
+                .map(|((file, result), output)| {
+                    let mut entry = file_json(file, result, "dry_run");
+                    entry["output"] = json!(output.to_string_lossy());
+                    entry
+                })
+                .collect();
+            print_json(args.output_format, entries)?;
+            return Ok(Outcome::from_counts(failed, files.len()));
+        }
+        if !cli.quiet {
+            println!("DRY RUN - No files will be modified");
+            println!("\nFiles that would be processed:");
+            for ((file, result), output) in files.iter().zip(&results).zip(&outputs) {
+                let kind = file_type_to_string(&file.file_type);
+                if *output == file.path {
+                    println!("\n  {} ({}), in place", file.path.display(), kind);
+                } else {
+                    println!("\n  {} ({}) -> {}", file.path.display(), kind, output.display());
+                }
+                match result {
+                    Ok(report) if report.metadata.is_empty() => println!("    no metadata to remove"),
+                    Ok(report) => {
+                        for item in &report.metadata {
+                            println!("    - [{}] {}", item.sensitivity, item);
+                        }
+                    }
+                    Err(e) => println!("    would fail: {:#}", e),
+                }
+            }
+            let removed: usize = results.iter().flatten().map(|report| report.metadata.len()).sum();
+            println!("\nTotal: {} files, {} metadata items would be removed", files.len(), removed);
+        }
+        return Ok(Outcome::from_counts(failed, files.len()));
+    }
+
+    let parallel = args.jobs.unwrap_or_else(rayon::current_num_threads);
+    check_free_space(&files, &outputs, parallel, args.backup)?;
+
+    let total_bytes: u64 = files.iter().filter_map(|file| fs::metadata(&file.path).ok()).map(|m| m.len()).sum();
+    let events = match args.progress {
+        ProgressFormat::Json => {
+            // Anything else on standard output would end up in the middle of the events
+            let conflict = if json_output {
+                Some("--format")
+            } else if args.stats {
+                Some("--stats")
+            } else if args.show_metadata {
+                Some("--show-metadata")
+            } else {
+                None
+            };
+            if let (Some(option), None) = (conflict, args.progress_fd) {
+                anyhow::bail!(
+                    "--progress json and {} both write to standard output; send progress elsewhere with --progress-fd",
+                    option
+                );
+            }
+            let events = Events::open(args.progress_fd)?;
+            events.started(files.len(), total_bytes);
+            Some(std::sync::Arc::new(events))
+        }
+        ProgressFormat::Bar => None,
+    };
+    let events_on_stdout = events.is_some() && args.progress_fd.is_none();
+
+    // Create progress bar unless in quiet mode or writing JSON. It advances by bytes, so
+    // the rate and time left aren't thrown off by a mix of small and large files.
+    let pb = if !cli.quiet && !json_output && !args.no_progress && events.is_none() {
+        let pb = ProgressBar::new(total_bytes);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, {eta} left) {msg}")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        Some(pb)
+    } else {
+        None
+    };
+
+    let mut stats = ProcessingStats::default();
+
+    let jobs: Vec<(PathBuf, PathBuf)> = files.iter().map(|file| file.path.clone()).zip(outputs).collect();
+
+    // Files are marked done as each one finishes, so an interruption loses none of them
+    let journal = journal.map(std::sync::Arc::new);
+    if let Some(journal) = &journal {
+        journal.plan(&jobs)?;
+    }
+    if pb.is_some() || journal.is_some() || events.is_some() {
+        let (pb, journal, events) = (pb.clone(), journal.clone(), events.clone());
+        let failures = std::sync::atomic::AtomicUsize::new(0);
+        stripper = stripper.on_progress(move |progress| {
+            if let (Some(journal), false) = (&journal, progress.failed) {
+                if let Err(e) = journal.complete(progress.path) {
+                    eprintln!("Warning: {:#}", e);
+                }
+            }
+            if let Some(events) = &events {
+                events.file(progress);
+            }
+            let Some(pb) = &pb else { return };
+            let failed = failures.fetch_add(progress.failed as usize, std::sync::atomic::Ordering::Relaxed)
+                + progress.failed as usize;
+            let name = progress.path.file_name().unwrap_or_default().to_string_lossy();
+            if failed > 0 {
+                pb.set_message(format!("{}/{} files, {} failed: {}", progress.completed, progress.total, failed, name));
+            } else {
+                pb.set_message(format!("{}/{} files: {}", progress.completed, progress.total, name));
+            }
+            pb.inc(progress.bytes);
+        });
+    }
+
+    // The report and the before/after view list everything each file contained, which
+    // has to be read before cleaning
+    let show_diff = args.show_metadata && !cli.quiet && !json_output;
+    let before: Vec<(Option<u64>, Vec<MetadataItem>)> = if args.report.is_some() || show_diff {
+        let inspector = build_stripper(&args.format);
+        files
+            .par_iter()
+            .map(|file| {
+                let size = fs::metadata(&file.path).ok().map(|m| m.len());
+                (size, inspector.inspect_file(&file.path).map(|report| report.metadata).unwrap_or_default())
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Cleaning in place replaces the inputs, so they're hashed first
+    let input_hashes: Vec<Option<Hashed>> = match &args.hash_manifest {
+        Some(_) => files.par_iter().map(|file| Hashed::of(&file.path)).collect(),
+        None => Vec::new(),
+    };
+
+    // Process files in parallel
+    let results: Vec<_> = files.iter().zip(stripper.strip_files_to(&jobs)).collect();
+
+    if let Some(manifest_path) = &args.hash_manifest {
+        let entries: Vec<ManifestEntry> = results
+            .iter()
+            .zip(&jobs)
+            .zip(&input_hashes)
+            .map(|(((_, result), (input, output)), input_hash)| ManifestEntry {
+                input,
+                output,
+                input_hash: input_hash.as_ref(),
+                result,
+            })
+            .collect();
+        manifest::write_manifest(manifest_path, &entries)?;
+    }
+
+    if let Some(report_path) = &args.report {
+        let entries: Vec<AuditEntry> = results
+            .iter()
+            .zip(&jobs)
+            .zip(&before)
+            .map(|(((file, result), (_, output_path)), (size_before, found))| AuditEntry {
+                file,
+                output_path,
+                found,
+                size_before: *size_before,
+                result,
+            })
+            .collect();
+        audit::write_report(report_path, &entries)?;
+    }
+
+    for (name, first) in &links {
+        let cleaned = results.iter().any(|(file, result)| &file.path == first && result.is_ok());
+        if cleaned {
+            if let Err(e) = relink(first, name) {
+                eprintln!("Warning: Failed to link {} to its cleaned copy {}: {}", name.display(), first.display(), e);
+            }
+        }
+    }
+
+    if args.backup {
+        let ledger_path = BackupLedger::default_path().context("Failed to find a data directory for the backup ledger")?;
+        let mut ledger = BackupLedger::load(&ledger_path)?;
+        for ((_, result), (input, output)) in results.iter().zip(&jobs) {
+            if result.is_ok() {
+                if let Err(e) = ledger.record(input, output) {
+                    eprintln!("Warning: {:#}; restore can't check this backup", e);
+                }
+            }
+        }
+        ledger.save()?;
+    }
+
+    if let Some(cache) = &mut cache {
+        for ((_, result), (input, output)) in results.iter().zip(&jobs) {
+            if result.is_ok() {
+                cache.record(input, output);
+            }
+        }
+        cache.save()?;
+    }
+
+    if let Some(pb) = pb {
+        pb.finish_with_message("Processing complete");
+    }
+
+    // Check the cleaned files the same way `verify` would, with the same kept categories.
+    // A cleaned file that can't be read back isn't known to be clean, so it counts as dirty.
+    let leftovers: Vec<Option<Result<Report>>> = results
+        .iter()
+        .zip(&jobs)
+        .map(|((_, result), (_, output_path))| match result {
+            Ok(_) if args.verify => match stripper.verify_file(output_path) {
+                Ok(report) if report.metadata.is_empty() => None,
+                leftover => Some(leftover),
+            },
+            _ => None,
+        })
+        .collect();
+
+    // Collect statistics
+    for ((file, result), (_, output_path)) in results.iter().zip(&jobs) {
+        match file.file_type {
+            FileType::Image => *stats.by_type.entry("Images".to_string()).or_insert(0) += 1,
+            FileType::Raw => *stats.by_type.entry("RAW Images".to_string()).or_insert(0) += 1,
+            FileType::Video => *stats.by_type.entry("Videos".to_string()).or_insert(0) += 1,
+            FileType::Pdf => *stats.by_type.entry("PDFs".to_string()).or_insert(0) += 1,
+            FileType::Audio => *stats.by_type.entry("Audio".to_string()).or_insert(0) += 1,
+            FileType::Office => *stats.by_type.entry("Office Documents".to_string()).or_insert(0) += 1,
+            FileType::Archive => *stats.by_type.entry("Archives".to_string()).or_insert(0) += 1,
+            FileType::Location => *stats.by_type.entry("Location Files".to_string()).or_insert(0) += 1,
+            FileType::Unknown => *stats.by_type.entry("Unknown".to_string()).or_insert(0) += 1,
+        }
+
+        match result {
+            Ok(report) => {
+                stats.files_processed += 1;
+                stats.metadata_items_removed += report.metadata.len();
+                // The original may be gone, but the cleaned file's size plus the savings is its size
+                let saved = report.bytes_saved.unwrap_or(0);
+                let size_after = fs::metadata(output_path).map(|metadata| metadata.len()).unwrap_or(0);
+                stats.bytes_saved += saved;
+                stats.bytes_before += (size_after as i64 + saved).max(0) as u64;
+            }
+            // Files skipped after --fail-fast stopped the batch didn't fail themselves
+            Err(e) if e.is::<Skipped>() => {}
+            Err(_) => {
+                stats.files_failed += 1;
+            }
+        }
+    }
+
+    if let Some(events) = &events {
+        events.finished(stats.files_processed, stats.files_failed, stats.metadata_items_removed, stats.bytes_saved);
+    }
+
+    let failures: Vec<(&FileInfo, &anyhow::Error)> = results
+        .iter()
+        .filter_map(|(file, result)| result.as_ref().err().map(|e| (*file, e)))
+        .filter(|(_, e)| !e.is::<Skipped>())
+        .collect();
+    if let Some(failed_dir) = &args.failed_dir {
+        if !failures.is_empty() {
+            let moved = quarantine(failed_dir, &failures)?;
+            if !cli.quiet {
+                eprintln!("Moved {} failed files to {}", moved, failed_dir.display());
+            }
+        }
+    }
+
+    // Kept while files are left to retry, so --resume only goes over those
+    if let Some(journal) = &journal {
+        let unfinished = results.iter().filter(|(_, result)| result.is_err()).count();
+        if unfinished == 0 {
+            journal.finish()?;
+        } else if !cli.quiet {
+            eprintln!("{} files weren't cleaned; --resume with the same journal retries just those", unfinished);
+        }
+    }
+
+    if json_output {
+        let entries = results
+            .iter()
+            .zip(&leftovers)
+            .map(|((file, result), leftover)| file_json(file, result, if leftover.is_some() { "dirty" } else { "ok" }))
+            .collect();
+        print_json(args.output_format, entries)?;
+    }
+
+    // Location files are cleaned but still reveal where someone was, so say so even without --show-metadata
+    if !cli.quiet {
+        for (file, result) in &results {
+            if file.file_type == FileType::Location && result.is_ok() {
+                eprintln!("Warning: {} is location data; its coordinates were not removed", file.path.display());
+            } else if let Ok(report) = result {
+                // e.g. a PDF/A file whose kept metadata can't be carried over
+                for item in report.properties.iter().filter(|item| item.category == Category::Notice && item.tag.starts_with("Warning")) {
+                    eprintln!("Warning: {}: {}", file.path.display(), item.value);
+                }
+            }
+        }
+    }
+
+    // Display results after the progress bar is done, comparing what each file contained
+    // with what a fresh look at the cleaned file finds
+    if show_diff {
+        println!("\nMetadata before and after cleaning:");
+        for (((file, result), (_, output_path)), (_, found)) in results.iter().zip(&jobs).zip(&before) {
+            match result {
+                Ok(_) => {
+                    let after = stripper.inspect_file(output_path).map(|report| report.metadata).unwrap_or_default();
+                    if !found.is_empty() || !after.is_empty() {
+                        println!("\n{}:", file.path.display());
+                        print_diff(found, &after);
+                    }
+                }
+                Err(e) => {
+                    println!("\n{}: Failed - {:#}", file.path.display(), e);
+                }
+            }
+        }
+    }
+
+    // Display statistics if requested
+    if args.stats && !cli.quiet && !json_output {
+        println!("\nProcessing Statistics:");
+        println!("  Files processed successfully: {}", stats.files_processed);
+        println!("  Files failed: {}", stats.files_failed);
+        println!("  Total metadata items removed: {}", stats.metadata_items_removed);
+        if stats.bytes_before > 0 {
+            println!(
+                "  Space saved: {} of {} ({:.1}%)",
+                signed_bytes(stats.bytes_saved),
+                indicatif::HumanBytes(stats.bytes_before),
+                stats.bytes_saved as f64 * 100.0 / stats.bytes_before as f64
+            );
+        }
+        let mut savings: Vec<(&Path, i64)> = results
+            .iter()
+            .filter_map(|(file, result)| Some((file.path.as_path(), result.as_ref().ok()?.bytes_saved?)))
+            .filter(|(_, saved)| *saved != 0)
+            .collect();
+        if !savings.is_empty() {
+            savings.sort_by_key(|(_, saved)| std::cmp::Reverse(*saved));
+            println!("\n  Largest Savings:");
+            for (path, saved) in savings.iter().take(LARGEST_SAVINGS) {
+                println!("    {}: {}", path.display(), signed_bytes(*saved));
+            }
+            if savings.len() > LARGEST_SAVINGS {
+                println!("    ... and {} more files", savings.len() - LARGEST_SAVINGS);
+            }
+        }
+        println!("\n  By File Type:");
+        for (file_type, count) in stats.by_type {
+            println!("    {}: {}", file_type, count);
+        }
+    }
+
+    // Listed last, with the whole error chain, so the reason isn't lost above other output
+    if !failures.is_empty() && !cli.quiet {
+        eprintln!("\n{} of {} files failed:", failures.len(), files.len());
+        for (file, e) in &failures {
+            eprintln!("  {}: {:#}", file.path.display(), e);
+        }
+    }
+
+    let skipped_files = results
+        .iter()
+        .filter(|(_, result)| matches!(result, Err(e) if e.is::<Skipped>()))
+        .count();
+    if skipped_files > 0 && !cli.quiet {
+        eprintln!("Stopped after the first failure; {} files were skipped", skipped_files);
+    }
+
+    let dirty_files = leftovers.iter().flatten().count();
+    if dirty_files > 0 {
+        for ((file, _), leftover) in results.iter().zip(&leftovers) {
+            match leftover {
+                Some(Ok(report)) if !json_output => {
+                    let mut listing = format!("{}: metadata remaining after cleaning\n", file.path.display());
+                    for item in &report.metadata {
+                        listing.push_str(&format!("  - {}\n", item));
+                    }
+                    // Progress events on standard output keep it to themselves
+                    if events_on_stdout {
+                        eprint!("{}", listing);
+                    } else {
+                        print!("{}", listing);
+                    }
+                }
+                Some(Err(e)) => eprintln!("{}: Failed to verify - {:#}", file.path.display(), e),
+                _ => {}
+            }
+        }
+        eprintln!(
+            "Error: {} of {} cleaned files still contain metadata or couldn't be verified",
+            dirty_files,
+            files.len()
+        );
+    }
+
+    Ok(Outcome::from_counts(stats.files_failed + dirty_files, files.len()))
+}
+
+/// Picks each file's destination, applying `policy` where it already exists or an
+/// earlier file in the same run writes there. Skipped files are removed from `files`.
+/// In a dry run, the inputs `ConflictPolicy::Error` would stop at are returned rather
+/// than failing, so they can be reported along with the rest.
+fn resolve_conflicts(
+    files: &mut Vec<FileInfo>,
+    output_path: impl Fn(&FileInfo) -> PathBuf,
+    policy: ConflictPolicy,
+    dry_run: bool,
+    quiet: bool,
+) -> Result<(Vec<PathBuf>, HashSet<PathBuf>)> {
+    let mut claimed = HashSet::new();
+    let mut kept = Vec::new();
+    let mut outputs = Vec::new();
+    let mut refused = HashSet::new();
+
+    for file in files.drain(..) {
+        let mut target = output_path(&file);
+        // Cleaning in place replaces the input itself, which is never a conflict
+        let taken = claimed.contains(&target);
+        if target != file.path && (taken || target.exists()) {
+            match policy {
+                ConflictPolicy::Overwrite => {
+                    if taken && !quiet {
+                        eprintln!(
+                            "Warning: {} replaces the cleaned copy of an earlier file at {}",
+                            file.path.display(),
+                            target.display()
+                        );
+                    }
+                }
+                ConflictPolicy::Skip => {
+                    if !quiet {
+                        eprintln!("Skipping {}: {} already exists", file.path.display(), target.display());
+                    }
+                    continue;
+                }
+                ConflictPolicy::Rename => {
+                    target = numbered_path(&target, |path| claimed.contains(path) || path.exists());
+                }
+                ConflictPolicy::Error if dry_run => {
+                    refused.insert(file.path.clone());
+                }
+                ConflictPolicy::Error => return Err(conflict_error(&target)),
+            }
+        }
+        claimed.insert(target.clone());
+        kept.push(file);
+        outputs.push(target);
+    }
+
+    *files = kept;
+    Ok((outputs, refused))
+}
+
+fn conflict_error(target: &Path) -> anyhow::Error {
+    anyhow::anyhow!("Output file {} already exists (use --on-conflict to overwrite, skip or rename)", target.display())
+}
+
+/// Fails before anything is written if a file system the batch writes to is short of
+/// space. Copies need room for every cleaned file, and backups for every original.
+/// Files cleaned in place only need room for the ones being cleaned at once, since
+/// each original is freed when its cleaned file is renamed over it.
+fn check_free_space(files: &[FileInfo], outputs: &[PathBuf], parallel: usize, backup: bool) -> Result<()> {
+    // Keyed by device, so directories on the same file system share its free space
+    let mut needed: HashMap<u64, (PathBuf, u64, Vec<u64>)> = HashMap::new();
+    for (file, output) in files.iter().zip(outputs) {
+        let Ok(size) = fs::metadata(&file.path).map(|metadata| metadata.len()) else { continue };
+        let Some(dir) = output.ancestors().skip(1).find(|dir| dir.as_os_str().is_empty() || dir.exists()) else { continue };
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let Some((device, _)) = hard_link_id(dir).map(|(id, _)| id) else { continue };
+        let (_, total, in_place) = needed.entry(device).or_insert_with(|| (dir.to_path_buf(), 0, Vec::new()));
+        if is_same_path(&file.path, output) {
+            in_place.push(size);
+            if backup {
+                *total += size;
+            }
+        } else {
+            *total += size;
+        }
+    }
+
+    for (dir, mut total, mut in_place) in needed.into_values() {
+        in_place.sort_unstable_by(|a, b| b.cmp(a));
+        total += in_place.iter().take(parallel).sum::<u64>();
+        match available_space(&dir) {
+            Some(available) if available < total => anyhow::bail!(
+                "Not enough free space in {}: cleaning these files needs about {} but only {} is free",
+                dir.display(),
+                indicatif::HumanBytes(total),
+                indicatif::HumanBytes(available)
+            ),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Free space for unprivileged users on the file system holding `path`
+fn available_space(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `path` is NUL-terminated and `stat` is a valid buffer for the result
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Parses a file mode in octal, such as 644 or 0o600
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let digits = s.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("Invalid mode '{}' (expected octal permissions such as 644)", s))
+}
+
+/// Parses a size such as 500M, 2G or 1048576, in bytes or binary multiples of them
+fn parse_size(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches("ib").trim_end_matches('b');
+    let (number, multiplier) = match digits.char_indices().last() {
+        Some((index, 'k')) => (&digits[..index], 1u64 << 10),
+        Some((index, 'm')) => (&digits[..index], 1 << 20),
+        Some((index, 'g')) => (&digits[..index], 1 << 30),
+        Some((index, 't')) => (&digits[..index], 1 << 40),
+        _ => (digits, 1),
+    };
+    let number = number.trim().parse::<f64>().ok().filter(|number| *number >= 0.0 && number.is_finite());
+    let number = number.ok_or_else(|| format!("Invalid size '{}' (expected e.g. 500M or 2G)", s))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses a --throttle-mb-s rate, in megabytes per second, to bytes per second
+fn parse_rate(s: &str) -> Result<u64, String> {
+    let rate = s.trim().parse::<f64>().ok().filter(|rate| *rate > 0.0 && rate.is_finite());
+    let rate = rate.ok_or_else(|| format!("Invalid rate '{}' (expected megabytes per second, e.g. 50 or 2.5)", s))?;
+    Ok(((rate * (1u64 << 20) as f64) as u64).max(1))
+}
+
+// How many files --stats lists by the space cleaning them saved
+const LARGEST_SAVINGS: usize = 10;
+
+/// A size in bytes for people to read, with a minus sign for a file that grew
+fn signed_bytes(bytes: i64) -> String {
+    let size = indicatif::HumanBytes(bytes.unsigned_abs());
+    if bytes < 0 {
+        format!("-{}", size)
+    } else {
+        size.to_string()
+    }
+}
+
+/// The first of `photo-1.jpg`, `photo-2.jpg`, ... next to `path` that isn't taken
+fn numbered_path(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let numbered = |n: usize| {
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("-{}", n));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        path.with_file_name(name)
+    };
+    (1..).map(numbered).find(|candidate| !taken(candidate)).expect("ran out of numbers")
+}
+
+/// Moves each failed file into `dir`, below the same path as in the scanned directory,
+/// and appends why it failed to `dir/failures.log`. Returns how many were moved.
+fn quarantine(dir: &Path, failures: &[(&FileInfo, &anyhow::Error)]) -> Result<usize> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let mut log = String::new();
+    let mut moved = 0;
+    for (file, error) in failures {
+        // Files from a list can have absolute paths, which would land outside `dir`
+        let relative = if file.relative.is_absolute() {
+            PathBuf::from(file.relative.file_name().unwrap_or_default())
+        } else {
+            file.relative.clone()
+        };
+        let mut target = dir.join(&relative);
+        if target.exists() {
+            target = numbered_path(&target, Path::exists);
+        }
+        let result = fs::create_dir_all(target.parent().unwrap_or(dir)).and_then(|_| move_file(&file.path, &target));
+        match result {
+            Ok(()) => {
+                moved += 1;
+                log.push_str(&format!("{} -> {}: {:#}\n", file.path.display(), target.display(), error));
+            }
+            Err(e) => eprintln!("Warning: Failed to move {} to {}: {}", file.path.display(), target.display(), e),
+        }
+    }
+
+    let log_path = dir.join("failures.log");
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, log.as_bytes()))
+        .with_context(|| format!("Failed to write {}", log_path.display()))?;
+    Ok(moved)
+}
+
+/// Renames `from` to `to`, or copies it across file systems
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Cleans standard input into standard output, so anything else goes to stderr
+fn run_strip_stdin(args: &StripArgs, cli: &Cli) -> Result<Outcome> {
+    if args.output_format != OutputFormat::Text {
+        anyhow::bail!("--stdin writes the cleaned file to standard output and can't be combined with --format");
+    }
+
+    let stripper = build_stripper(&args.format);
+    let report = stripper.strip_stream(std::io::stdin().lock(), std::io::stdout().lock(), args.stdin_type.as_deref())?;
+
+    if args.show_metadata && !cli.quiet {
+        eprintln!("Removed metadata report:");
+        for item in &report.metadata {
+            eprintln!("  - {}", item);
+        }
+    }
+
+    Ok(Outcome::Success)
+}
+
+fn run_inspect(args: &InspectArgs, cli: &Cli) -> Result<Outcome> {
+    let files = collect_files(&args.input, cli.quiet)?;
+    let stripper = build_stripper(&args.format);
+    let json_output = args.output_format != OutputFormat::Text;
+    let min_severity = args.format.min_severity.unwrap_or(Sensitivity::Info);
+    let mut failed_files = 0;
+    let mut scores = Vec::new();
+    let mut entries = Vec::new();
+
+    for file in &files {
+        let result = stripper.inspect_file(&file.path).map(|mut report| {
+            report.metadata.retain(|item| item.sensitivity >= min_severity);
+            report
+        });
+        let failed = result.is_err();
+        if failed {
+            failed_files += 1;
+        }
+        let score = result.as_ref().ok().map(|report| privacy_score(&report.metadata));
+        scores.extend(score);
+
+        if json_output {
+            let mut entry = file_json(file, &result, "ok");
+            entry["privacy_score"] = json!(score);
+            entries.push(entry);
+        } else {
+            match result {
+                Ok(report) => {
+                    if report.metadata.is_empty() {
+                        println!("\n{}: no metadata found", file.path.display());
+                    } else {
+                        println!(
+                            "\n{} ({}, privacy score {}/100):",
+                            file.path.display(),
+                            file_type_to_string(&file.file_type),
+                            score.unwrap_or_default()
+                        );
+                        for item in &report.metadata {
+                            println!("  - [{}] {}", item.sensitivity, item);
+                            if let Some(url) = item.maps_url().filter(|_| args.maps_url) {
+                                println!("    {}", url);
+                            }
+                        }
+                    }
+                    if !report.properties.is_empty() {
+                        println!("  Technical properties (not removed):");
+                        for item in &report.properties {
+                            println!("  - {}", item);
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("\n{}: Failed - {}", file.path.display(), e);
+                }
+            }
+        }
+
+        if failed && args.fail_fast {
+            break;
+        }
+    }
+
+    if json_output {
+        print_json(args.output_format, entries)?;
+    } else if scores.len() > 1 {
+        // The mean rather than the minimum, so one leaky file among many clean ones
+        // doesn't hide how the rest are doing
+        let overall = scores.iter().sum::<u32>() as f64 / scores.len() as f64;
+        println!("\nOverall privacy score: {:.0}/100 across {} files", overall, scores.len());
+    }
+
+    Ok(Outcome::from_counts(failed_files, files.len()))
+}
+
+fn run_verify(args: &InspectArgs, cli: &Cli) -> Result<Outcome> {
+    let files = collect_files(&args.input, cli.quiet)?;
+    let stripper = build_stripper(&args.format);
+    let json_output = args.output_format != OutputFormat::Text;
+    let mut dirty_files = 0;
+    let mut entries = Vec::new();
+
+    for file in &files {
+        let result = stripper.verify_file(&file.path);
+        let clean = matches!(&result, Ok(report) if report.metadata.is_empty());
+        if !clean {
+            dirty_files += 1;
+        }
+
+        if json_output {
+            entries.push(file_json(file, &result, if clean { "clean" } else { "dirty" }));
+        } else {
+            match result {
+                Err(e) => println!("{}: Failed - {}", file.path.display(), e),
+                Ok(_) if clean => {
+                    if !cli.quiet {
+                        println!("{}: clean", file.path.display());
+                    }
+                }
+                Ok(report) => {
+                    println!("{}: metadata remaining", file.path.display());
+                    for item in report.metadata {
+                        println!("  - {}", item);
+                        if let Some(url) = item.maps_url().filter(|_| args.maps_url) {
+                            println!("    {}", url);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !clean && args.fail_fast {
+            break;
+        }
+    }
+
+    if json_output {
+        print_json(args.output_format, entries)?;
+    }
+
+    if dirty_files > 0 {
+        eprintln!("Error: {} of {} files still contain metadata", dirty_files, files.len());
+    }
+
+    Ok(Outcome::from_counts(dirty_files, files.len()))
+}
+
+fn run_restore(args: &RestoreArgs, cli: &Cli) -> Result<Outcome> {
+    let ledger_path = BackupLedger::default_path().context("Failed to find a data directory for the backup ledger")?;
+    let mut ledger = BackupLedger::load(&ledger_path)?;
+    let paths: Vec<PathBuf> = if args.all {
+        ledger.originals()
+    } else {
+        collect_files(&args.input, cli.quiet)?.into_iter().map(|file| file.path).collect()
+    };
+    let mut restored = 0;
+    let mut refused = 0;
+
+    for path in &paths {
+        let backup = backup_path(path);
+        if !backup.is_file() {
+            continue;
+        }
+
+        // Backups made before the ledger existed can't be checked
+        match ledger.validate(path) {
+            Ok(_) => {}
+            Err(e) if args.force => eprintln!("Warning: {}: {:#}; restoring it anyway", path.display(), e),
+            Err(e) => {
+                eprintln!("Skipping {}: {:#} (--force restores it anyway)", path.display(), e);
+                refused += 1;
+                continue;
+            }
+        }
+
+        fs::copy(&backup, path)
+            .with_context(|| format!("Failed to restore {} from {}", path.display(), backup.display()))?;
+        if !args.keep_backups {
+            fs::remove_file(&backup)
+                .with_context(|| format!("Failed to remove backup: {}", backup.display()))?;
+            ledger.remove(path);
+        }
+
+        restored += 1;
+        if !cli.quiet {
+            println!("Restored {}", path.display());
+        }
+    }
+    ledger.save()?;
+
+    if !cli.quiet {
+        println!("\nRestored {} of {} files", restored, paths.len());
+    }
+
+    Ok(Outcome::from_counts(refused, restored + refused))
+}
+
+fn run_watch(args: &WatchArgs, cli: &Cli) -> Result<Outcome> {
+    if args.input.files_from.is_some() {
+        anyhow::bail!("watch only takes directories, not --files-from");
+    }
+    if args.input.inputs.is_empty() {
+        anyhow::bail!("No directories given to watch");
+    }
+    if let Some(dir) = args.input.inputs.iter().find(|input| !input.is_dir()) {
+        anyhow::bail!("Not a directory: {}", dir.display());
+    }
+    if let Some(output_dir) = &args.output_dir {
+        fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
+    }
+
+    let filter = input_filter(&args.input)?;
+    let stripper = build_stripper(&args.format);
+    let mut tracker = ChangeTracker::new();
+    // Started before the first scan, so nothing that changes after it is missed
+    let interval = Duration::from_secs(args.interval.max(1));
+    let mut events = FolderEvents::new(&args.input.inputs, args.input.recursive, interval);
+
+    // Cleaned copies must not be picked up again when the output directory is inside
+    // a watched one, and neither must half-written temporary files
+    let output_dir = args.output_dir.as_ref().and_then(|dir| fs::canonicalize(dir).ok());
+    let scan = || -> Vec<FileInfo> {
+        scan_inputs(&args.input.inputs, &args.input, &filter)
+            .into_iter()
+            .filter(|file| !file.path.file_name().unwrap_or_default().to_string_lossy().starts_with(".metastripper-"))
+            .filter(|file| match (&output_dir, fs::canonicalize(&file.path)) {
+                (Some(output_dir), Ok(path)) => !path.starts_with(output_dir),
+                _ => true,
+            })
+            .collect()
+    };
+
+    if !args.process_existing {
+        let existing: Vec<PathBuf> = scan().into_iter().map(|file| file.path).collect();
+        tracker.baseline(&existing);
+    }
+
+    if !cli.quiet {
+        let dirs: Vec<String> = args.input.inputs.iter().map(|dir| dir.display().to_string()).collect();
+        println!("Watching {} (press Ctrl+C to stop)", dirs.join(", "));
+    }
+
+    loop {
+        let files = scan();
+        let paths: Vec<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+
+        for path in tracker.scan(&paths) {
+            let Some(file) = files.iter().find(|file| file.path == path) else { continue };
+            let output_path = match &args.output_dir {
+                Some(output_dir) => output_dir.join(&file.relative),
+                None => file.path.clone(),
+            };
+
+            let result = stripper.strip_file_to(&file.path, &output_path).and_then(|report| {
+                if args.move_originals {
+                    fs::remove_file(&file.path)
+                        .with_context(|| format!("Failed to remove {}", file.path.display()))?;
+                }
+                Ok(report)
+            });
+            match result {
+                Ok(report) => {
+                    if !cli.quiet {
+                        println!("Cleaned {} ({} metadata items removed)", file.path.display(), report.metadata.len());
+                    }
+                }
+                Err(e) => eprintln!("{}: Failed - {:#}", file.path.display(), e),
+            }
+            tracker.handled(&file.path);
+        }
+
+        // Files still being written get another look after a whole interval, whatever
+        // else happens meanwhile; otherwise nothing needs a scan until something changes
+        if tracker.pending() {
+            std::thread::sleep(interval);
+            events.clear();
+        } else {
+            events.wait();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn run_daemon(args: &DaemonArgs, cli: &Cli) -> Result<Outcome> {
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+
+    let socket = args.socket.clone().unwrap_or_else(|| {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("metastripper.sock")
+    });
+
+    clear_stale_socket(&socket)?;
+    // Requests name arbitrary files, so only the owner may send them. The socket is
+    // created that way rather than changed after binding, which would leave a moment
+    // where anyone could connect.
+    // SAFETY: umask only swaps the process's file creation mask, before any threads start
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&socket);
+    unsafe { libc::umask(umask) };
+    let listener = listener.with_context(|| format!("Failed to listen on {}", socket.display()))?;
+
+    if !cli.quiet {
+        println!("Listening on {}", socket.display());
+    }
+
+    let stripper = Arc::new(build_stripper(&args.format));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let Ok(reader) = stream.try_clone() else { continue };
+        let stripper = Arc::clone(&stripper);
+        std::thread::spawn(move || serve_connection(&stripper, reader, stream));
+    }
+
+    Ok(Outcome::Success)
+}
+
+/// A socket left behind by a daemon that didn't shut down cleanly blocks binding. Only
+/// a socket nothing answers on is removed; anything else there is left alone.
+#[cfg(unix)]
+fn clear_stale_socket(socket: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    if let Ok(metadata) = fs::symlink_metadata(socket) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{} already exists and isn't a socket", socket.display());
+        }
+        if UnixStream::connect(socket).is_ok() {
+            anyhow::bail!("Another daemon is already listening on {}", socket.display());
+        }
+        fs::remove_file(socket)
+            .with_context(|| format!("Failed to remove stale socket: {}", socket.display()))?;
+    }
+    Ok(())
+}
+
+/// Serves the daemon on a named pipe, the Windows counterpart of a Unix socket
+#[cfg(windows)]
+fn run_daemon(args: &DaemonArgs, cli: &Cli) -> Result<Outcome> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::sync::Arc;
+
+    let pipe = args.socket.clone().unwrap_or_else(|| PathBuf::from(r"\\.\pipe\metastripper"));
+    let name: Vec<u16> = pipe.as_os_str().encode_wide().chain(Some(0)).collect();
+
+    if !cli.quiet {
+        println!("Listening on {}", pipe.display());
+    }
+
+    let stripper = Arc::new(build_stripper(&args.format));
+    for first in std::iter::once(true).chain(std::iter::repeat(false)) {
+        let instance = named_pipe::create(&name, first)
+            .with_context(|| format!("Failed to listen on {}", pipe.display()))?;
+        if let Err(e) = named_pipe::connect(&instance) {
+            eprintln!("Failed to accept a connection: {}", e);
+            continue;
+        }
+        let Ok(reader) = instance.try_clone() else { continue };
+        let stripper = Arc::clone(&stripper);
+        std::thread::spawn(move || serve_connection(&stripper, reader, instance));
+    }
+
+    Ok(Outcome::Success)
+}
+
+#[cfg(windows)]
+mod named_pipe {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+
+    const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+    // Byte stream, blocking, and local clients only
+    const PIPE_MODE: u32 = 0x8;
+    const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+    const BUFFER_SIZE: u32 = 64 * 1024;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+
+    extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer_size: u32,
+            in_buffer_size: u32,
+            default_timeout: u32,
+            security_attributes: *mut c_void,
+        ) -> *mut c_void;
+        fn ConnectNamedPipe(pipe: *mut c_void, overlapped: *mut c_void) -> i32;
+    }
+
+    /// A new instance of the pipe `name`, a NUL-terminated wide string. The default
+    /// security lets other users read from the pipe but not write requests to it. The
+    /// first instance fails if another process already holds the name, so nothing
+    /// else can be listening in its place.
+    pub(super) fn create(name: &[u16], first: bool) -> io::Result<File> {
+        let open_mode = PIPE_ACCESS_DUPLEX | if first { FILE_FLAG_FIRST_PIPE_INSTANCE } else { 0 };
+        // SAFETY: the name is NUL-terminated, and null security attributes mean the defaults
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_MODE,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        // INVALID_HANDLE_VALUE
+        if handle as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle was just created, and the file owns it from here on
+        Ok(unsafe { File::from_raw_handle(handle) })
+    }
+
+    /// Waits for a client to connect to `pipe`
+    pub(super) fn connect(pipe: &File) -> io::Result<()> {
+        // SAFETY: the handle is a pipe, and without an overlapped structure the call blocks
+        if unsafe { ConnectNamedPipe(pipe.as_raw_handle(), std::ptr::null_mut()) } == 0 {
+            let error = io::Error::last_os_error();
+            // A client that connected before the call is just as good
+            if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED) {
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn run_daemon(_args: &DaemonArgs, _cli: &Cli) -> Result<Outcome> {
+    anyhow::bail!("daemon mode needs Unix domain sockets or named pipes, which aren't available on this platform")
+}
+
+/// Answers each JSON request line on a connection with a JSON response line
+#[cfg(any(unix, windows))]
+fn serve_connection(stripper: &MetaStripper, reader: impl std::io::Read, mut writer: impl Write) {
+    use std::io::{BufRead, BufReader};
+
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        if writeln!(writer, "{}", handle_daemon_request(stripper, &line)).is_err() {
+            break;
+        }
+    }
+}
 
-use image::strip_image_metadata;
-use pdf::strip_pdf_metadata;
+/// Responses to file commands have the same fields as `--format json` entries
+#[cfg(any(unix, windows))]
+fn handle_daemon_request(stripper: &MetaStripper, line: &str) -> Value {
+    let request: DaemonRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return json!({ "id": null, "status": "failed", "error": format!("Invalid request: {}", e) }),
+    };
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Input files or directories to process
-    #[arg(required = true)]
-    inputs: Vec<PathBuf>,
+    let file_info = |path: &PathBuf| FileInfo {
+        path: path.clone(),
+        file_type: determine_file_type(path),
+        relative: path.clone(),
+    };
+    let mut response = match request.command {
+        DaemonCommand::Ping => json!({ "status": "ok" }),
+        DaemonCommand::Strip { path, output } => {
+            let output = output.unwrap_or_else(|| path.clone());
+            file_json(&file_info(&path), &stripper.strip_file_to(&path, &output), "ok")
+        }
+        DaemonCommand::Inspect { path } => file_json(&file_info(&path), &stripper.inspect_file(&path), "ok"),
+        DaemonCommand::Verify { path } => {
+            let result = stripper.verify_file(&path);
+            let clean = matches!(&result, Ok(report) if report.metadata.is_empty());
+            file_json(&file_info(&path), &result, if clean { "clean" } else { "dirty" })
+        }
+    };
+    response["id"] = request.id;
+    response
+}
 
-    /// Overwrite original files instead of creating new ones
-    #[arg(short = 'w', long)]
-    overwrite: bool,
+#[cfg(feature = "server")]
+fn run_serve(args: &ServeArgs, cli: &Cli) -> Result<Outcome> {
+    if !cli.quiet {
+        println!("Listening on http://{}", args.listen);
+    }
+    server::serve(build_stripper(&args.format), &args.listen, args.max_upload.saturating_mul(1024 * 1024))?;
+    Ok(Outcome::Success)
+}
 
-    /// Output directory for cleaned files (ignored if --overwrite is set)
-    #[arg(short = 'o', long)]
-    output_dir: Option<PathBuf>,
+#[cfg(feature = "tui")]
+fn run_browse(args: &BrowseArgs, cli: &Cli) -> Result<Outcome> {
+    if args.output_dir.is_none() && !args.overwrite {
+        anyhow::bail!("Choose where cleaned files go: --overwrite to replace the originals, or --output-dir");
+    }
+    let files = collect_files(&args.input, cli.quiet)?;
+    let stripper = build_stripper(&args.format);
 
-    /// Enable verbose logging
-    #[arg(short, long)]
-    verbose: bool,
-    
-    /// Show detailed report of metadata removed from each file
-    #[arg(short = 'm', long)]
-    show_metadata: bool,
-    
-    /// Recursively process subdirectories
-    #[arg(short = 'r', long)]
-    recursive: bool,
-    
-    /// Preview what would be processed without making changes
-    #[arg(long)]
-    dry_run: bool,
-    
-    /// Create backup of original files (.bak extension)
-    #[arg(short = 'b', long)]
-    backup: bool,
-    
-    /// Process only image files
-    #[arg(long)]
-    only_images: bool,
-    
-    /// Process only video files
-    #[arg(long)]
-    only_videos: bool,
-    
-    /// Process only PDF files
-    #[arg(long)]
-    only_pdfs: bool,
-    
-    /// Show statistics summary
-    #[arg(short = 's', long)]
-    stats: bool,
-    
-    /// Suppress all output except errors
-    #[arg(short = 'q', long)]
-    quiet: bool,
-}
+    if !cli.quiet {
+        eprintln!("Reading metadata from {} files...", files.len());
+    }
+    let listings: Vec<tui::Listing> = files
+        .par_iter()
+        .map(|file| tui::Listing {
+            path: file.path.clone(),
+            items: stripper.inspect_file(&file.path).map(|report| report.metadata).map_err(|e| format!("{:#}", e)),
+        })
+        .collect();
+    let Some(selections) = tui::browse(listings, |category| stripper.removes(category))? else {
+        if !cli.quiet {
+            println!("Nothing was cleaned");
+        }
+        return Ok(Outcome::Success);
+    };
+    if selections.is_empty() {
+        return Ok(Outcome::NoFiles);
+    }
 
-#[derive(Debug)]
-struct FileInfo {
-    path: PathBuf,
-    file_type: FileType,
+    // Files with the same choices are cleaned together, as one batch each
+    let mut batches: std::collections::BTreeMap<Vec<Category>, Vec<(PathBuf, PathBuf)>> = Default::default();
+    for selection in selections {
+        let file = files.iter().find(|file| file.path == selection.path).expect("selected files were listed");
+        let output = match &args.output_dir {
+            Some(dir) if !args.overwrite => dir.join(&file.relative),
+            _ => file.path.clone(),
+        };
+        batches.entry(selection.keep).or_default().push((selection.path, output));
+    }
+    if let Some(dir) = args.output_dir.as_ref().filter(|_| !args.overwrite) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create output directory: {}", dir.display()))?;
+    }
+
+    let (mut cleaned, mut failed) = (0, 0);
+    for (keep, jobs) in &batches {
+        let stripper = build_stripper(&args.format).only_keep(keep.iter().copied());
+        for ((input, _), result) in jobs.iter().zip(stripper.strip_files_to(jobs)) {
+            match result {
+                Ok(report) => {
+                    cleaned += 1;
+                    if !cli.quiet {
+                        println!("{}: cleaned, {} items removed", input.display(), report.metadata.len());
+                    }
+                }
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("{}: Failed - {:#}", input.display(), e);
+                }
+            }
+        }
+    }
+    if !cli.quiet {
+        println!("Cleaned {} files, {} failed", cleaned, failed);
+    }
+    Ok(Outcome::from_counts(failed, cleaned + failed))
 }
 
-#[derive(Debug, PartialEq)]
-enum FileType {
-    Image,
-    Video,
-    PDF,
-    Unknown,
+fn run_manpage(args: &ManpageArgs) -> Result<Outcome> {
+    match &args.output_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+            clap_mangen::generate_to(Cli::command(), dir)
+                .with_context(|| format!("Failed to write man pages to {}", dir.display()))?;
+        }
+        None => clap_mangen::Man::new(Cli::command())
+            .render(&mut std::io::stdout())
+            .context("Failed to write the man page")?,
+    }
+    Ok(Outcome::Success)
 }
 
-#[derive(Debug, Default)]
-struct ProcessingStats {
-    files_processed: usize,
-    files_skipped: usize,
-    files_failed: usize,
-    metadata_items_removed: usize,
-    by_type: std::collections::HashMap<String, usize>,
+/// Fills in the options not given on the command line from the chosen profile,
+/// then from the config files
+fn apply_config(command: &mut Command, config: Config) -> Result<()> {
+    let (input, format) = match command {
+        Command::Strip(args) => {
+            // --overwrite on the command line wins over an output directory from a config file
+            if args.output_dir.is_none() && !args.overwrite {
+                args.output_dir = config.output_dir.clone();
+            }
+            // A backup would keep the very bytes --shred is asked to destroy
+            if !args.backup && !args.no_backup && !args.shred {
+                args.backup = config.backup.unwrap_or(false);
+            }
+            (Some(&mut args.input), Some(&mut args.format))
+        }
+        Command::Inspect(args) | Command::Verify(args) => (Some(&mut args.input), Some(&mut args.format)),
+        Command::Restore(args) => (Some(&mut args.input), None),
+        Command::Watch(args) => {
+            if args.output_dir.is_none() {
+                args.output_dir = config.output_dir.clone();
+            }
+            (Some(&mut args.input), Some(&mut args.format))
+        }
+        Command::Daemon(args) => (None, Some(&mut args.format)),
+        #[cfg(feature = "server")]
+        Command::Serve(args) => (None, Some(&mut args.format)),
+        #[cfg(feature = "tui")]
+        Command::Browse(args) => {
+            if args.output_dir.is_none() && !args.overwrite {
+                args.output_dir = config.output_dir.clone();
+            }
+            (Some(&mut args.input), Some(&mut args.format))
+        }
+        Command::Completions(_) | Command::Manpage(_) => return Ok(()),
+    };
+
+    if let Some(input) = input {
+        input.exclude.extend(config.exclude.iter().cloned());
+    }
+
+    if let Some(format) = format {
+        let profile = match &format.profile {
+            Some(name) => config.profile(name)?,
+            None => Profile::default(),
+        };
+
+        // A keep or remove list on the command line replaces the profile's, which
+        // replaces the configured one
+        if format.keep.is_empty() && format.remove.is_empty() {
+            let (keep, remove) = match (profile.keep, profile.remove) {
+                (None, None) => (config.keep, config.remove),
+                rules => rules,
+            };
+            format.keep = keep.unwrap_or_default();
+            format.remove = remove.unwrap_or_default();
+        }
+        format.min_severity = format.min_severity.or(profile.min_severity);
+        // Values given on the command line come last, so they win
+        format.set.splice(0..0, profile.set);
+        format.anonymize |= profile.anonymize.unwrap_or(false);
+        format.anonymize_dates = format.anonymize_dates.or(profile.anonymize_dates);
+        format.strip_icc |= profile.strip_icc.unwrap_or(false);
+        format.pdf_remove_attachments |= profile.pdf_remove_attachments.unwrap_or(false);
+        format.pdf_remove_javascript |= profile.pdf_remove_javascript.unwrap_or(false);
+        format.ffmpeg_path = format.ffmpeg_path.take().or(config.ffmpeg_path);
+        format.ffprobe_path = format.ffprobe_path.take().or(config.ffprobe_path);
+        format.ffmpeg_timeout = format.ffmpeg_timeout.or(config.ffmpeg_timeout);
+    }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-    
-    // Initialize logging
-    if args.verbose && !args.quiet {
-        env_logger::init();
+fn collect_files(args: &InputArgs, quiet: bool) -> Result<Vec<FileInfo>> {
+    if args.inputs.is_empty() && args.files_from.is_none() {
+        anyhow::bail!("No input files or directories given");
     }
 
-    // Don't allow contradictory options
-    if args.quiet && (args.verbose || args.show_metadata) {
-        eprintln!("Warning: --quiet mode enabled, --verbose and --show-metadata will be ignored");
+    let filter = input_filter(args)?;
+
+    let mut inputs = args.inputs.clone();
+    if let Some(list) = &args.files_from {
+        inputs.extend(read_file_list(list, args.null_separated)?);
+    }
+    let inputs: Vec<PathBuf> = inputs.iter().map(|input| resolve_input(input)).collect();
+
+    let files = scan_inputs(&inputs, args, &filter);
+
+    if files.is_empty() {
+        return Err(NoFilesFound.into());
     }
-    
+
+    // Files are handled as what their content shows them to be, whatever their name
+    if !quiet {
+        for file in &files {
+            if let Some(format) = format_mismatch(&file.path) {
+                eprintln!("Warning: {} looks like a {} file; treating it as one", file.path.display(), format.to_uppercase());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// The include and exclude patterns, after checking the other filter options
+fn input_filter(args: &InputArgs) -> Result<PathFilter> {
     // Validate that only one file type filter is used
-    let file_filters = [args.only_images, args.only_videos, args.only_pdfs].iter().filter(|&&f| f).count();
+    let file_filters = [args.only_images, args.only_videos, args.only_pdfs, args.only_audio, args.only_office].iter().filter(|&&f| f).count();
     if file_filters > 1 {
         anyhow::bail!("Only one file type filter can be used at a time");
     }
 
-    // Validate output directory if specified
-    if let Some(ref output_dir) = args.output_dir {
-        if !output_dir.exists() && !args.dry_run {
-            std::fs::create_dir_all(output_dir)
-                .with_context(|| format!("Failed to create output directory: {}", output_dir.display()))?;
-        }
-    }
+    PathFilter::new(&args.include, &args.exclude)
+}
 
-    // Collect all files to process
-    let files: Vec<FileInfo> = args.inputs
+/// Finds the files to process among `inputs`, walking the directories
+fn scan_inputs(inputs: &[PathBuf], args: &InputArgs, filter: &PathFilter) -> Vec<FileInfo> {
+    inputs
         .iter()
         .flat_map(|input| {
             if input.is_dir() {
@@ -132,213 +2301,557 @@ fn main() -> Result<()> {
                 } else {
                     WalkDir::new(input).max_depth(1)
                 };
-                
+                // Links are skipped unless followed, as walkdir reports them as neither files nor directories
+                let walker = walker.follow_links(args.follow_symlinks);
+
+                // Patterns are matched against the path below the scanned directory
+                let relative = |e: &walkdir::DirEntry| e.path().strip_prefix(input).unwrap_or(e.path()).to_path_buf();
+
                 walker
                     .into_iter()
+                    .filter_entry(|e| e.depth() == 0 || !e.file_type().is_dir() || !filter.excludes_dir(&relative(e)))
                     .filter_map(|e| e.ok())
                     .filter(|e| e.file_type().is_file())
+                    .filter(|e| filter.includes_file(&relative(e)))
                     .map(|e| {
                         let file_type = determine_file_type(e.path());
                         FileInfo {
                             path: e.path().to_path_buf(),
                             file_type,
+                            relative: relative(&e),
                         }
                     })
-                    .filter(|file| should_process_file_type(&file.file_type, &args))
+                    .filter(|file| should_process_file_type(&file.file_type, args))
                     .collect()
             } else {
                 let file_type = determine_file_type(input);
-                if should_process_file_type(&file_type, &args) {
+                if filter.includes_file(input) && should_process_file_type(&file_type, args) {
                     vec![FileInfo {
                         path: input.clone(),
                         file_type,
+                        relative: input.file_name().map(PathBuf::from).unwrap_or_else(|| input.clone()),
                     }]
                 } else {
                     vec![]
                 }
             }
         })
-        .collect();
-
-    if files.is_empty() {
-        anyhow::bail!("No valid files found to process");
-    }
-
-    if args.dry_run && !args.quiet {
-        println!("DRY RUN - No files will be modified");
-        println!("\nFiles that would be processed:");
-        for file in &files {
-            println!("  {} ({})", file.path.display(), file_type_to_string(&file.file_type));
-        }
-        println!("\nTotal: {} files", files.len());
-        return Ok(());
-    }
+        .collect()
+}
 
-    // Create progress bar unless in quiet mode
-    let pb = if !args.quiet {
-        let pb = ProgressBar::new(files.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%)")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
-        Some(pb)
+/// Reads the paths listed in `list`, or on standard input if it is `-`. Blank entries
+/// are skipped, and so is the trailing newline when entries are NUL-separated.
+fn read_file_list(list: &std::path::Path, null_separated: bool) -> Result<Vec<PathBuf>> {
+    let data = if list.as_os_str() == "-" {
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut data)
+            .context("Failed to read the file list from standard input")?;
+        data
     } else {
-        None
+        fs::read(list).with_context(|| format!("Failed to read file list: {}", list.display()))?
     };
 
-    let mut stats = ProcessingStats::default();
+    let separator = if null_separated { b'\0' } else { b'\n' };
+    Ok(data
+        .split(|&b| b == separator)
+        .map(|entry| if null_separated { entry } else { entry.strip_suffix(b"\r").unwrap_or(entry) })
+        .filter(|entry| !entry.is_empty() && entry != b"\n")
+        .map(path_from_bytes)
+        .collect())
+}
 
-    // Process files in parallel
-    let results: Vec<_> = files.par_iter()
-        .map(|file| {
-            // Create backup if requested
-            if args.backup && !args.dry_run {
-                let backup_path = format!("{}.bak", file.path.display());
-                if let Err(e) = fs::copy(&file.path, &backup_path) {
-                    warn!("Failed to create backup for {}: {}", file.path.display(), e);
+/// File names on Unix are arbitrary bytes, so only other systems need them to be text
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Prints metadata found before and after cleaning side by side. Items are paired by
+/// tag, so a kept orientation shows on both sides and removed GPS tags only on the left.
+fn print_diff(before: &[MetadataItem], after: &[MetadataItem]) {
+    const COLUMN_WIDTH: usize = 48;
+
+    let mut matched = vec![false; after.len()];
+    let mut rows: Vec<(String, String)> = before
+        .iter()
+        .map(|item| {
+            let kept = (0..after.len()).find(|&i| !matched[i] && after[i].tag == item.tag);
+            let right = match kept {
+                Some(i) => {
+                    matched[i] = true;
+                    after[i].to_string()
                 }
-            }
-            
-            let result = process_file(file, &args);
-            
-            if let Some(pb) = &pb {
-                pb.inc(1);
-            }
-            
-            (file, result)
+                None => "(removed)".to_string(),
+            };
+            (item.to_string(), right)
         })
         .collect();
-    
-    if let Some(pb) = pb {
-        pb.finish_with_message("Processing complete");
+    // Anything only found afterwards, such as properties of a re-encoded file
+    rows.extend(after.iter().zip(&matched).filter(|(_, &matched)| !matched).map(|(item, _)| ("-".to_string(), item.to_string())));
+
+    println!("  {:<width$}  After", "Before", width = COLUMN_WIDTH);
+    for (left, right) in rows {
+        println!("  {:<width$}  {}", truncate(&left, COLUMN_WIDTH), truncate(&right, COLUMN_WIDTH), width = COLUMN_WIDTH);
     }
-    
-    // Collect statistics
-    for (file, result) in &results {
-        match file.file_type {
-            FileType::Image => *stats.by_type.entry("Images".to_string()).or_insert(0) += 1,
-            FileType::Video => *stats.by_type.entry("Videos".to_string()).or_insert(0) += 1,
-            FileType::PDF => *stats.by_type.entry("PDFs".to_string()).or_insert(0) += 1,
-            FileType::Unknown => *stats.by_type.entry("Unknown".to_string()).or_insert(0) += 1,
-        }
-        
-        match result {
-            Ok(metadata) => {
-                stats.files_processed += 1;
-                stats.metadata_items_removed += metadata.len();
-            }
-            Err(_) => {
-                stats.files_failed += 1;
-            }
-        }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut truncated: String = text.chars().take(width - 1).collect();
+        truncated.push('…');
+        truncated
     }
-    
-    // Display results after the progress bar is done
-    if args.show_metadata && !args.quiet {
-        println!("\nRemoved metadata report:");
-        for (file, result) in &results {
-            match result {
-                Ok(metadata) => {
-                    if !metadata.is_empty() {
-                        println!("\n{}: ", file.path.display());
-                        for item in metadata {
-                            println!("  - {}", item);
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("\n{}: Failed - {}", file.path.display(), e);
-                }
-            }
-        }
+}
+
+/// Describes one file's outcome; `status` is used when processing succeeded
+/// The JSON entry for one file. Paths that aren't valid UTF-8 can't go into JSON as
+/// they are, so they're given with the invalid bytes replaced.
+fn file_json(file: &FileInfo, result: &Result<Report>, status: &str) -> Value {
+    match result {
+        Ok(report) => json!({
+            "path": file.path.to_string_lossy(),
+            "type": file.file_type.name(),
+            "status": status,
+            "items": report.metadata.iter().map(item_json).collect::<Vec<_>>(),
+            "properties": report.properties.iter().map(item_json).collect::<Vec<_>>(),
+            "bytes_saved": report.bytes_saved,
+            "error": null,
+        }),
+        Err(e) => json!({
+            "path": file.path.to_string_lossy(),
+            "type": file.file_type.name(),
+            "status": "failed",
+            "items": [],
+            "properties": [],
+            "bytes_saved": null,
+            "error": format!("{:#}", e),
+        }),
     }
-    
-    // Display statistics if requested
-    if args.stats && !args.quiet {
-        println!("\nProcessing Statistics:");
-        println!("  Files processed successfully: {}", stats.files_processed);
-        println!("  Files failed: {}", stats.files_failed);
-        println!("  Total metadata items removed: {}", stats.metadata_items_removed);
-        println!("\n  By File Type:");
-        for (file_type, count) in stats.by_type {
-            println!("    {}: {}", file_type, count);
+}
+
+fn item_json(item: &MetadataItem) -> Value {
+    json!({
+        "category": item.category.to_string(),
+        "tag": item.tag,
+        "value": item.value,
+        "sensitivity": item.sensitivity.to_string(),
+    })
+}
+
+fn print_json(format: OutputFormat, entries: Vec<Value>) -> Result<()> {
+    if format == OutputFormat::Ndjson {
+        for entry in entries {
+            println!("{}", serde_json::to_string(&entry)?);
         }
+    } else {
+        println!("{}", serde_json::to_string_pretty(&Value::Array(entries))?);
     }
-    
     Ok(())
 }
 
+fn ffmpeg_config(format: &FormatArgs) -> FfmpegConfig {
+    let mut config = match &format.ffmpeg_path {
+        Some(path) => FfmpegConfig::with_ffmpeg(path),
+        None => FfmpegConfig::discover(),
+    };
+    if let Some(path) = &format.ffprobe_path {
+        config.ffprobe = path.clone();
+    }
+    config.extra_args = format.ffmpeg_args.clone();
+    config.timeout = format.ffmpeg_timeout.map(Duration::from_secs);
+    config
+}
+
+fn video_options(format: &FormatArgs) -> VideoOptions {
+    let mut options = VideoOptions { keep_chapters: format.video_keep_chapters, ..VideoOptions::default() };
+    let mut keep = format.video_keep_streams.clone();
+    if format.video_keep_data {
+        keep.push(StreamKind::Data);
+    }
+    let mut drop = format.video_drop_streams.clone();
+    if format.video_drop_subs {
+        drop.push(StreamKind::Subtitle);
+    }
+    for kind in keep {
+        if !options.keeps(kind) {
+            options.streams.push(kind);
+        }
+    }
+    options.streams.retain(|kind| !drop.contains(kind));
+    options
+}
+
+fn build_stripper(format: &FormatArgs) -> MetaStripper {
+    let stripper = MetaStripper::new()
+        .keep_maker_notes(format.keep_maker_notes)
+        .recurse_archives(format.recurse_archives)
+        .orientation(format.orientation)
+        .strip_icc(format.strip_icc)
+        .ffmpeg(ffmpeg_config(format))
+        .video(video_options(format))
+        .pdf(PdfOptions {
+            remove_attachments: format.pdf_remove_attachments,
+            remove_javascript: format.pdf_remove_javascript,
+            flatten: format.pdf_flatten,
+            password: format.pdf_password.clone(),
+            ..PdfOptions::default()
+        })
+        .set(format.set.iter().cloned());
+
+    let stripper = match &format.temp_dir {
+        Some(dir) => stripper.temp_dir(dir),
+        None => stripper,
+    };
+
+    let stripper = if format.anonymize {
+        stripper.anonymize(format.anonymize_dates.unwrap_or_else(DateRange::recent))
+    } else {
+        stripper
+    };
+
+    let stripper = match format.min_severity {
+        Some(severity) => stripper.min_severity(severity),
+        None => stripper,
+    };
+
+    if format.remove.is_empty() {
+        stripper.keep(format.keep.iter().copied())
+    } else {
+        stripper.remove_only(format.remove.iter().copied())
+    }
+}
+
 fn file_type_to_string(file_type: &FileType) -> &'static str {
     match file_type {
         FileType::Image => "Image",
+        FileType::Raw => "RAW Image",
         FileType::Video => "Video",
-        FileType::PDF => "PDF",
+        FileType::Pdf => "PDF",
+        FileType::Audio => "Audio",
+        FileType::Office => "Office Document",
+        FileType::Archive => "Archive",
+        FileType::Location => "Location File",
         FileType::Unknown => "Unknown",
     }
 }
 
-fn should_process_file_type(file_type: &FileType, args: &Args) -> bool {
+fn should_process_file_type(file_type: &FileType, args: &InputArgs) -> bool {
     if args.only_images {
-        return *file_type == FileType::Image;
+        return matches!(file_type, FileType::Image | FileType::Raw);
     } else if args.only_videos {
         return *file_type == FileType::Video;
     } else if args.only_pdfs {
-        return *file_type == FileType::PDF;
+        return *file_type == FileType::Pdf;
+    } else if args.only_audio {
+        return *file_type == FileType::Audio;
+    } else if args.only_office {
+        return *file_type == FileType::Office;
     }
     // Process all supported types by default
     *file_type != FileType::Unknown
 }
 
-fn determine_file_type(path: &Path) -> FileType {
-    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        match ext.to_lowercase().as_str() {
-            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" => FileType::Image,
-            "mp4" | "mov" | "avi" | "mkv" => FileType::Video,
-            "pdf" => FileType::PDF,
-            _ => FileType::Unknown,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576"), Ok(1 << 20));
+        assert_eq!(parse_size("512b"), Ok(512));
+        assert_eq!(parse_size("1.5k"), Ok(1536));
+        assert_eq!(parse_size("10KiB"), Ok(10 << 10));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size(" 3 MB "), Ok(3 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        assert_eq!(parse_size("1t"), Ok(1 << 40));
+        for invalid in ["", "M", "abc", "-1M", "2X", "infG"] {
+            assert!(parse_size(invalid).is_err(), "{}", invalid);
         }
-    } else {
-        FileType::Unknown
     }
-}
 
-fn process_file(file: &FileInfo, args: &Args) -> Result<Vec<String>> {
-    let output_path = if args.overwrite {
-        file.path.clone()
-    } else {
-        let output_dir = args.output_dir.as_ref()
-            .map(|d| d.clone())
-            .unwrap_or_else(|| file.path.parent().unwrap().to_path_buf());
-        
-        let file_name = file.path.file_name().unwrap();
-        output_dir.join(file_name)
-    };
+    #[cfg(unix)]
+    #[test]
+    fn test_available_space() {
+        assert!(available_space(&std::env::temp_dir()).is_some());
+        assert_eq!(available_space(Path::new("/nonexistent/metastripper")), None);
+    }
 
-    // Skip actual processing in dry run mode
-    if args.dry_run {
-        return Ok(vec!["Dry run - no metadata removed".to_string()]);
+    fn file_info(path: &Path) -> FileInfo {
+        let relative = PathBuf::from(path.file_name().unwrap());
+        FileInfo { path: path.to_path_buf(), file_type: FileType::Image, relative }
+    }
+
+    #[test]
+    fn test_resolve_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        // Two inputs with the same name in different directories map to one output
+        let inputs = [dir.path().join("a/photo.jpg"), dir.path().join("b/photo.jpg"), dir.path().join("new.jpg")];
+        fs::write(out.join("photo.jpg"), b"existing").unwrap();
+        let output_path = |file: &FileInfo| out.join(file.path.file_name().unwrap());
+        let resolve = |policy, dry_run| {
+            let mut files: Vec<FileInfo> = inputs.iter().map(|path| file_info(path)).collect();
+            let (outputs, refused) = resolve_conflicts(&mut files, output_path, policy, dry_run, true)?;
+            anyhow::Ok((files.into_iter().map(|file| file.path).collect::<Vec<_>>(), outputs, refused))
+        };
+
+        let (files, outputs, _) = resolve(ConflictPolicy::Overwrite, false).unwrap();
+        assert_eq!(files, inputs);
+        assert_eq!(outputs, [out.join("photo.jpg"), out.join("photo.jpg"), out.join("new.jpg")]);
+
+        let (files, outputs, _) = resolve(ConflictPolicy::Skip, false).unwrap();
+        assert_eq!(files, [inputs[2].clone()]);
+        assert_eq!(outputs, [out.join("new.jpg")]);
+
+        // Numbers skip both existing files and ones claimed earlier in the batch
+        fs::write(out.join("photo-1.jpg"), b"existing").unwrap();
+        let (files, outputs, _) = resolve(ConflictPolicy::Rename, false).unwrap();
+        assert_eq!(files, inputs);
+        assert_eq!(outputs, [out.join("photo-2.jpg"), out.join("photo-3.jpg"), out.join("new.jpg")]);
+
+        let error = resolve(ConflictPolicy::Error, false).unwrap_err();
+        assert!(error.to_string().contains("photo.jpg already exists"), "{}", error);
+        let (files, _, refused) = resolve(ConflictPolicy::Error, true).unwrap();
+        assert_eq!(files, inputs);
+        assert_eq!(refused, HashSet::from([inputs[0].clone(), inputs[1].clone()]));
+
+        // A file cleaned in place is its own destination
+        fs::remove_file(out.join("photo.jpg")).unwrap();
+        let mut files = vec![file_info(&inputs[0]), file_info(&inputs[0])];
+        let in_place = |file: &FileInfo| file.path.clone();
+        let (outputs, _) = resolve_conflicts(&mut files, in_place, ConflictPolicy::Error, false, true).unwrap();
+        assert_eq!(outputs, [inputs[0].clone(), inputs[0].clone()]);
+        let mut files = vec![file_info(&inputs[0]), file_info(&inputs[1])];
+        let error = resolve_conflicts(&mut files, output_path, ConflictPolicy::Error, false, true).unwrap_err();
+        assert!(error.to_string().contains("photo.jpg already exists"), "{}", error);
     }
 
-    let result = match file.file_type {
-        FileType::Image => strip_image_metadata(&file.path, &output_path),
-        FileType::Video => video::strip_video_metadata(&file.path, &output_path),
-        FileType::PDF => strip_pdf_metadata(&file.path, &output_path),
-        FileType::Unknown => {
-            warn!("Unsupported file type: {}", file.path.display());
-            Ok(vec!["Unsupported file type - no metadata removed".to_string()])
+    #[test]
+    fn test_read_file_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("list.txt");
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        // Blank lines and the trailing newline are skipped, and so are CRLF line endings
+        fs::write(&list, "a.jpg\r\n\n\r\nb c.jpg\n").unwrap();
+        assert_eq!(read_file_list(&list, false).unwrap(), paths(&["a.jpg", "b c.jpg"]));
+
+        // With -0 a newline is part of the name, apart from the one ending the list
+        fs::write(&list, "a.jpg\0\0line\nbreak.jpg\0trailing\r\0\n").unwrap();
+        assert_eq!(read_file_list(&list, true).unwrap(), paths(&["a.jpg", "line\nbreak.jpg", "trailing\r"]));
+        fs::write(&list, "a.jpg\nb.jpg").unwrap();
+        assert_eq!(read_file_list(&list, true).unwrap(), paths(&["a.jpg\nb.jpg"]));
+        assert_eq!(read_file_list(&list, false).unwrap(), paths(&["a.jpg", "b.jpg"]));
+
+        fs::write(&list, "").unwrap();
+        assert!(read_file_list(&list, false).unwrap().is_empty());
+        assert!(read_file_list(&dir.path().join("missing.txt"), false).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_file_list_non_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("list.txt");
+        fs::write(&list, b"caf\xe9.jpg\0caf\xe8.jpg\0").unwrap();
+        let paths = read_file_list(&list, true).unwrap();
+        let names: Vec<&[u8]> = paths.iter().map(|path| path.as_os_str().as_bytes()).collect();
+        assert_eq!(names, [&b"caf\xe9.jpg"[..], &b"caf\xe8.jpg"[..]]);
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn test_handle_daemon_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("drawing.svg");
+        let copy = dir.path().join("copy.svg");
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><!-- made by Jane --><rect/></svg>"#;
+        fs::write(&input, svg).unwrap();
+        let stripper = MetaStripper::new();
+        let request = |request: Value| handle_daemon_request(&stripper, &request.to_string());
+
+        assert_eq!(request(json!({"id": 1, "command": "ping"})), json!({"id": 1, "status": "ok"}));
+        // Requests without an id get a null one
+        assert_eq!(request(json!({"command": "ping"}))["id"], Value::Null);
+
+        let inspected = request(json!({"id": "a", "command": "inspect", "path": input}));
+        assert_eq!(inspected["id"], "a");
+        assert_eq!(inspected["status"], "ok");
+        assert_eq!(inspected["items"][0]["value"], "made by Jane");
+        let dirty = request(json!({"id": 2, "command": "verify", "path": input}));
+        assert_eq!(dirty["status"], "dirty");
+
+        let stripped = request(json!({"id": 3, "command": "strip", "path": input, "output": copy}));
+        assert_eq!(stripped["status"], "ok");
+        assert_eq!(fs::read_to_string(&input).unwrap(), svg);
+        assert_eq!(request(json!({"id": 4, "command": "verify", "path": copy}))["status"], "clean");
+        assert_eq!(request(json!({"id": 5, "command": "strip", "path": input}))["status"], "ok");
+        assert_ne!(fs::read_to_string(&input).unwrap(), svg);
+
+        let missing = request(json!({"id": 6, "command": "inspect", "path": dir.path().join("missing.svg")}));
+        assert_eq!(missing["id"], 6);
+        assert_eq!(missing["status"], "failed");
+        assert!(missing["error"].as_str().is_some());
+
+        let unknown = r#"{"id": 7, "command": "delete", "path": "a.svg"}"#;
+        for invalid in ["not json", unknown, r#"{"id": 8, "command": "strip"}"#] {
+            let response = handle_daemon_request(&stripper, invalid);
+            assert_eq!(response["id"], Value::Null, "{}", invalid);
+            assert_eq!(response["status"], "failed", "{}", invalid);
+            assert!(response["error"].as_str().unwrap().starts_with("Invalid request: "), "{}", invalid);
         }
-    };
-    
-    if let Ok(ref metadata) = result {
-        if args.verbose && !args.quiet {
-            info!("Successfully processed: {}", file.path.display());
-            if !metadata.is_empty() {
-                info!("Removed {} metadata items", metadata.len());
-            }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clear_stale_socket() {
+        use std::os::unix::net::UnixListener;
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("metastripper.sock");
+        clear_stale_socket(&socket).unwrap();
+
+        // A daemon that's still listening keeps its socket
+        let listener = UnixListener::bind(&socket).unwrap();
+        let error = clear_stale_socket(&socket).unwrap_err();
+        assert!(error.to_string().contains("Another daemon is already listening"), "{}", error);
+        assert!(socket.exists());
+
+        // Once nothing answers, the socket is left over and goes
+        drop(listener);
+        clear_stale_socket(&socket).unwrap();
+        assert!(fs::symlink_metadata(&socket).is_err());
+
+        // Files and links that only point at a socket are never removed
+        let file = dir.path().join("file.sock");
+        fs::write(&file, b"data").unwrap();
+        assert!(clear_stale_socket(&file).unwrap_err().to_string().contains("isn't a socket"));
+        let link = dir.path().join("link.sock");
+        let _listener = UnixListener::bind(&socket).unwrap();
+        std::os::unix::fs::symlink(&socket, &link).unwrap();
+        assert!(clear_stale_socket(&link).unwrap_err().to_string().contains("isn't a socket"));
+        assert_eq!(fs::read(&file).unwrap(), b"data");
+        assert!(fs::symlink_metadata(&link).is_ok());
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let dir = tempfile::tempdir().unwrap();
+        let failed = dir.path().join("failed");
+        let failure = |name: &str, relative: PathBuf| {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, name).unwrap();
+            FileInfo { path, file_type: FileType::Image, relative }
+        };
+        let error = anyhow::anyhow!("Failed to decode image");
+
+        // Failed files keep their place below the scanned directory
+        let first = failure("scan/sub/a.jpg", PathBuf::from("sub/a.jpg"));
+        assert_eq!(quarantine(&failed, &[(&first, &error)]).unwrap(), 1);
+        assert!(!first.path.exists());
+        assert_eq!(fs::read_to_string(failed.join("sub/a.jpg")).unwrap(), "scan/sub/a.jpg");
+
+        // A name already taken gets a number, and absolute paths from a list land at the top
+        let second = failure("other/sub/a.jpg", PathBuf::from("sub/a.jpg"));
+        let listed = failure("listed/b.jpg", dir.path().join("listed/b.jpg"));
+        let missing = file_info(&dir.path().join("gone.jpg"));
+        let failures = [(&second, &error), (&listed, &error), (&missing, &error)];
+        assert_eq!(quarantine(&failed, &failures).unwrap(), 2);
+        assert_eq!(fs::read_to_string(failed.join("sub/a-1.jpg")).unwrap(), "other/sub/a.jpg");
+        assert_eq!(fs::read_to_string(failed.join("b.jpg")).unwrap(), "listed/b.jpg");
+        assert!(!failed.join("gone.jpg").exists());
+
+        // The log is appended to, with a line for each file moved
+        let log = fs::read_to_string(failed.join("failures.log")).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        assert!(log.lines().all(|line| line.ends_with(": Failed to decode image")), "{}", log);
+        assert!(log.contains(&format!("{} -> {}", second.path.display(), failed.join("sub/a-1.jpg").display())));
+    }
+
+    /// Runs where the temp directory and /dev/shm are different file systems
+    #[cfg(unix)]
+    #[test]
+    fn test_move_file_across_devices() {
+        let device = |path: &Path| hard_link_id(path).map(|((device, _), _)| device);
+        let shm = Path::new("/dev/shm");
+        if device(shm).is_none() || device(shm) == device(&std::env::temp_dir()) {
+            return;
         }
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir_in(shm).unwrap();
+        let source = from.path().join("photo.jpg");
+        fs::write(&source, b"pixels").unwrap();
+        move_file(&source, &to.path().join("photo.jpg")).unwrap();
+        assert!(!source.exists());
+        assert_eq!(fs::read(to.path().join("photo.jpg")).unwrap(), b"pixels");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_json_non_unicode_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let path = PathBuf::from(OsStr::from_bytes(b"photos/caf\xe9.svg"));
+        let file = FileInfo { path: path.clone(), file_type: FileType::Image, relative: path.clone() };
+        let report = Report {
+            path,
+            file_type: FileType::Image,
+            metadata: vec![MetadataItem::new("Author", "Jane")],
+            properties: Vec::new(),
+            bytes_saved: Some(12),
+        };
+        let entry = file_json(&file, &Ok(report), "ok");
+        assert_eq!(entry["path"], "photos/caf\u{fffd}.svg");
+        assert_eq!(entry["items"][0]["tag"], "Author");
+        let failed = file_json(&file, &Err(anyhow::anyhow!("broken")), "ok");
+        assert_eq!(failed["path"], "photos/caf\u{fffd}.svg");
+        assert_eq!(failed["status"], "failed");
+    }
+
+    /// Names that only differ where they aren't valid UTF-8 stay apart from cleaning
+    /// through the cache, the journal, the JSON output and the report
+    #[cfg(unix)]
+    #[test]
+    fn test_non_unicode_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join(OsStr::from_bytes(b"caf\xe9.svg"));
+        let second = dir.path().join(OsStr::from_bytes(b"caf\xe8.svg"));
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg"><title>Jane Doe</title><rect/></svg>"#;
+        fs::write(&first, svg).unwrap();
+        fs::write(&second, svg).unwrap();
+
+        let stripper = MetaStripper::new();
+        let result = stripper.strip_file_to(&first, &first);
+        assert!(result.is_ok());
+        let mut cache = CleanCache::load(&dir.path().join("cache.json"), &stripper.options_hash()).unwrap();
+        cache.record(&first, &first);
+        assert!(cache.is_clean(&first, &first));
+        assert!(!cache.is_clean(&second, &second));
+        let journal_path = dir.path().join("journal.jsonl");
+        Journal::create(&journal_path).unwrap().complete(&first).unwrap();
+        let (journal, _) = Journal::resume(&journal_path).unwrap();
+        assert!(journal.is_done(&first));
+        assert!(!journal.is_done(&second));
+
+        let file = FileInfo { path: first.clone(), file_type: FileType::Image, relative: first.clone() };
+        assert!(file_json(&file, &result, "ok")["path"].as_str().unwrap().ends_with("caf\u{fffd}.svg"));
+        let report_path = dir.path().join("report.json");
+        let entry = AuditEntry { file: &file, output_path: &first, found: &[], size_before: None, result: &result };
+        audit::write_report(&report_path, &[entry]).unwrap();
+        assert!(fs::read_to_string(&report_path).unwrap().contains("caf\u{fffd}.svg"));
     }
-    
-    result
 }
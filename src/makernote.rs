@@ -0,0 +1,185 @@
+// Maker notes are a block of vendor-specific tags inside EXIF, and often hold the
+// serial number of the camera body and the owner name set in the camera even when
+// the standard EXIF tags for them are empty. They are removed in full, but the
+// IFD-based layouts of the common vendors are read here so the report can say which
+// camera a photo would have been traced to.
+
+use std::ops::Range;
+
+use crate::metadata::MetadataItem;
+
+/// Names of the items that tie a photo to one physical camera or its owner. These are
+/// removed even when their category is kept, as `--keep device` is meant for the make
+/// and model, not for what identifies the individual camera.
+pub(crate) const FINGERPRINT_ITEMS: &[&str] = &[
+    "Maker Notes",
+    "Body Serial Number",
+    "Lens Serial Number",
+    "Camera Serial Number",
+    "Camera Owner Name",
+];
+
+/// Whether `item` is one of `FINGERPRINT_ITEMS`, or a value read from maker notes
+pub(crate) fn is_fingerprint(item: &MetadataItem) -> bool {
+    FINGERPRINT_ITEMS.contains(&item.tag.as_str()) || item.tag.ends_with(MAKER_NOTE_SUFFIX)
+}
+
+const MAKER_NOTE_SUFFIX: &str = " (Maker Notes)";
+
+/// Where a vendor's IFD starts and what its value offsets count from
+struct Layout {
+    ifd: usize,
+    base: usize,
+    big_endian: bool,
+    tags: &'static [(u16, &'static str)],
+}
+
+const CANON_TAGS: &[(u16, &str)] = &[
+    (0x0009, "Owner Name"),
+    (0x000C, "Body Serial Number"),
+    (0x0096, "Internal Serial Number"),
+];
+const NIKON_TAGS: &[(u16, &str)] = &[(0x001D, "Body Serial Number")];
+const FUJIFILM_TAGS: &[(u16, &str)] = &[(0x0010, "Internal Serial Number")];
+const PANASONIC_TAGS: &[(u16, &str)] = &[(0x0025, "Internal Serial Number"), (0x0052, "Lens Serial Number")];
+const PENTAX_TAGS: &[(u16, &str)] = &[(0x0229, "Body Serial Number")];
+
+/// The serial numbers and owner names in the maker notes at `note` in `tiff`, a TIFF
+/// block in the byte order given by `big_endian`, from a camera of make `make`, e.g.
+/// "Body Serial Number (Maker Notes): 123456". Unknown layouts give nothing.
+pub(crate) fn identifying_fields(tiff: &[u8], big_endian: bool, make: &str, note: Range<usize>) -> Vec<MetadataItem> {
+    let Some(bytes) = tiff.get(note.clone()) else { return Vec::new() };
+    let Some(layout) = layout(tiff, big_endian, make, note.start, bytes) else { return Vec::new() };
+
+    let mut metadata = Vec::new();
+    for (tag, value) in read_ifd(tiff, &layout) {
+        let Some((_, name)) = layout.tags.iter().find(|(known, _)| *known == tag) else { continue };
+        if !value.is_empty() && !metadata.iter().any(|item: &MetadataItem| item.value == value) {
+            metadata.push(MetadataItem::new(format!("{}{}", name, MAKER_NOTE_SUFFIX), value));
+        }
+    }
+    metadata
+}
+
+fn layout(tiff: &[u8], big_endian: bool, make: &str, start: usize, bytes: &[u8]) -> Option<Layout> {
+    let make = make.trim().to_ascii_lowercase();
+    if bytes.starts_with(b"Nikon\0\x02") {
+        // A TIFF header of its own follows the version, and offsets count from it
+        let base = start + 10;
+        let big_endian = match tiff.get(base..base + 2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let ifd = base + read_u32(tiff, base + 4, big_endian)? as usize;
+        Some(Layout { ifd, base, big_endian, tags: NIKON_TAGS })
+    } else if bytes.starts_with(b"FUJIFILM") {
+        // Always little-endian, with offsets from the start of the notes
+        let ifd = start + read_u32(bytes, 8, false)? as usize;
+        Some(Layout { ifd, base: start, big_endian: false, tags: FUJIFILM_TAGS })
+    } else if bytes.starts_with(b"Panasonic\0\0\0") {
+        Some(Layout { ifd: start + 12, base: 0, big_endian, tags: PANASONIC_TAGS })
+    } else if bytes.starts_with(b"AOC\0") {
+        let big_endian = bytes.get(4..6)? == b"MM";
+        Some(Layout { ifd: start + 6, base: start, big_endian, tags: PENTAX_TAGS })
+    } else if make.starts_with("canon") {
+        // No header: the IFD starts right away, with offsets from the EXIF block
+        Some(Layout { ifd: start, base: 0, big_endian, tags: CANON_TAGS })
+    } else {
+        None
+    }
+}
+
+/// The tags in the IFD with their values as text, skipping values that aren't text or
+/// integers or that fall outside `data`
+fn read_ifd(data: &[u8], layout: &Layout) -> Vec<(u16, String)> {
+    let big_endian = layout.big_endian;
+    let Some(count) = read_u16(data, layout.ifd, big_endian) else { return Vec::new() };
+    let mut fields = Vec::new();
+    for index in 0..count as usize {
+        let pos = layout.ifd + 2 + index * 12;
+        let (Some(tag), Some(field_type), Some(count)) =
+            (read_u16(data, pos, big_endian), read_u16(data, pos + 2, big_endian), read_u32(data, pos + 4, big_endian))
+        else {
+            break;
+        };
+        let unit = match field_type {
+            1 | 2 | 7 => 1,
+            3 => 2,
+            4 => 4,
+            _ => continue,
+        };
+        let size = unit * count as usize;
+        let value_pos = if size > 4 {
+            let Some(offset) = read_u32(data, pos + 8, big_endian) else { continue };
+            layout.base + offset as usize
+        } else {
+            pos + 8
+        };
+        let Some(value) = data.get(value_pos..value_pos.saturating_add(size)) else { continue };
+        let text = match field_type {
+            3 => read_u16(value, 0, big_endian).map(|n| n.to_string()),
+            4 => read_u32(value, 0, big_endian).map(|n| n.to_string()),
+            _ => Some(String::from_utf8_lossy(value.split(|&b| b == 0).next().unwrap_or_default()).trim().to_string()),
+        };
+        if let Some(text) = text.filter(|text| text != "0") {
+            fields.push((tag, text));
+        }
+    }
+    fields
+}
+
+fn read_u16(data: &[u8], pos: usize, big_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(pos..pos + 2)?.try_into().ok()?;
+    Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+}
+
+fn read_u32(data: &[u8], pos: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+    Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tag: u16, field_type: u16, count: u32, value: u32) -> Vec<u8> {
+        let mut bytes = tag.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&field_type.to_le_bytes());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(&value.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_identifying_fields() {
+        // Canon notes at 8, with the owner name stored after the IFD
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend(entry(0x0009, 2, 9, 50));
+        tiff.extend(entry(0x000C, 4, 1, 123456));
+        tiff.extend(entry(0x0001, 3, 1, 7));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.resize(50, 0);
+        tiff.extend_from_slice(b"Jane Doe\0");
+
+        let fields: Vec<String> = identifying_fields(&tiff, false, "Canon", 8..tiff.len())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(fields, ["Owner Name (Maker Notes): Jane Doe", "Body Serial Number (Maker Notes): 123456"]);
+        assert!(identifying_fields(&tiff, false, "Unknown", 8..tiff.len()).is_empty());
+        assert!(identifying_fields(&tiff, false, "Canon", 8..1000).is_empty());
+
+        // Nikon notes carry their own TIFF header
+        let mut note = b"Nikon\0\x02\x10\0\0II\x2a\x00\x08\x00\x00\x00".to_vec();
+        note.extend_from_slice(&1u16.to_le_bytes());
+        note.extend(entry(0x001D, 2, 8, 26));
+        note.extend_from_slice(&0u32.to_le_bytes());
+        note.extend_from_slice(b"3001234\0");
+        let items = identifying_fields(&note, true, "NIKON CORPORATION", 0..note.len());
+        assert_eq!(items[0].to_string(), "Body Serial Number (Maker Notes): 3001234");
+        assert!(is_fingerprint(&items[0]));
+        assert!(!is_fingerprint(&MetadataItem::new("Camera Model", "D850")));
+    }
+}
@@ -0,0 +1,133 @@
+// The `strip --hash-manifest` file: the SHA-256 hash of every input before cleaning and
+// of its cleaned output, with when each was taken, as CSV. Whoever receives the cleaned
+// files can check that they are the ones that were processed, and from what.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use metastripper::Report;
+
+const HEADER: &str =
+    "input,input_sha256,input_size,input_hashed_at,output,output_sha256,output_size,output_hashed_at,status,error";
+
+/// A file's hash and size at a moment in time
+pub struct Hashed {
+    sha256: String,
+    size: u64,
+    at: DateTime<Utc>,
+}
+
+impl Hashed {
+    /// Hashes the file at `path`, or `None` if it can't be read
+    pub fn of(path: &Path) -> Option<Self> {
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut fs::File::open(path).ok()?, &mut hasher).ok()?;
+        Some(Self { sha256: format!("{:x}", hasher.finalize()), size, at: Utc::now() })
+    }
+}
+
+/// One processed file, as it goes into the manifest
+pub struct ManifestEntry<'a> {
+    pub input: &'a Path,
+    pub output: &'a Path,
+    /// Taken before cleaning, as cleaning in place replaces the input
+    pub input_hash: Option<&'a Hashed>,
+    pub result: &'a Result<Report>,
+}
+
+/// Writes the manifest, hashing each cleaned output as it goes
+pub fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> Result<()> {
+    let mut csv = format!("{}\n", HEADER);
+    for entry in entries {
+        let output_hash = entry.result.as_ref().ok().and_then(|_| Hashed::of(entry.output));
+        let (status, error) = match entry.result {
+            Ok(_) => ("cleaned", String::new()),
+            Err(e) => ("failed", format!("{:#}", e)),
+        };
+        let mut row = vec![field(&entry.input.display().to_string())];
+        row.extend(hash_fields(entry.input_hash));
+        row.push(field(&entry.output.display().to_string()));
+        row.extend(hash_fields(output_hash.as_ref()));
+        row.push(status.to_string());
+        row.push(field(&error));
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    fs::write(path, csv).with_context(|| format!("Failed to write hash manifest: {}", path.display()))
+}
+
+fn hash_fields(hash: Option<&Hashed>) -> [String; 3] {
+    match hash {
+        Some(hash) => [
+            hash.sha256.clone(),
+            hash.size.to_string(),
+            hash.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        ],
+        None => Default::default(),
+    }
+}
+
+/// Quotes a CSV field if it holds a comma, quote or line break
+fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metastripper::FileType;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn test_write_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a,\"b\".jpg");
+        let output = dir.path().join("cleaned.jpg");
+        fs::write(&input, "abc").unwrap();
+        fs::write(&output, "").unwrap();
+        let input_hash = Hashed::of(&input).unwrap();
+        let report = Report {
+            path: input.clone(),
+            file_type: FileType::Image,
+            metadata: Vec::new(),
+            properties: Vec::new(),
+            bytes_saved: Some(3),
+        };
+        let cleaned = Ok(report);
+        let failed = Err(anyhow::anyhow!("Failed to parse JPEG, \"truncated\""));
+        let missing = dir.path().join("missing.jpg");
+        let entries = [
+            ManifestEntry { input: &input, output: &output, input_hash: Some(&input_hash), result: &cleaned },
+            ManifestEntry { input: &missing, output: &missing, input_hash: None, result: &failed },
+        ];
+        let path = dir.path().join("manifest.csv");
+        write_manifest(&path, &entries).unwrap();
+
+        let csv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], HEADER);
+        let quoted = format!("\"{}\"", input.display().to_string().replace('"', "\"\""));
+        let row = lines[1].strip_prefix(&format!("{},", quoted)).unwrap();
+        let fields: Vec<&str> = row.split(',').collect();
+        assert_eq!(fields[..2], [ABC_SHA256, "3"]);
+        assert!(DateTime::parse_from_rfc3339(fields[2]).is_ok());
+        assert_eq!(fields[3], output.display().to_string());
+        assert_eq!(fields[4..6], [EMPTY_SHA256, "0"]);
+        assert_eq!(fields[7..], ["cleaned", ""]);
+
+        // Nothing is hashed for a failed file, and its error is quoted
+        let expected = format!("{0},,,,{0},,,,failed,\"Failed to parse JPEG, \"\"truncated\"\"\"", missing.display());
+        assert_eq!(lines[2], expected);
+        assert_eq!(lines.len(), 3);
+    }
+}
@@ -0,0 +1,454 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate};
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::{Category, MetadataItem};
+use crate::video::{StreamKind, VideoOptions, COVER_ART};
+
+// Matroska and WebM are EBML documents: nested elements, each an ID and a size
+// followed by the data. Seek entries and cues point at byte offsets within the
+// segment, so nothing is ever moved. Removed elements are overwritten in place
+// by Void elements of the same size, and mandatory strings are zero-filled,
+// which readers treat as empty.
+
+const ID_SEGMENT: u32 = 0x1853_8067;
+const ID_SEEK_HEAD: u32 = 0x114D_9B74;
+const ID_SEEK: u32 = 0x4DBB;
+const ID_SEEK_ID: u32 = 0x53AB;
+const ID_INFO: u32 = 0x1549_A966;
+const ID_TRACKS: u32 = 0x1654_AE6B;
+const ID_TRACK_ENTRY: u32 = 0xAE;
+const ID_TRACK_TYPE: u32 = 0x83;
+const ID_TITLE: u32 = 0x7BA9;
+const ID_MUXING_APP: u32 = 0x4D80;
+const ID_WRITING_APP: u32 = 0x5741;
+const ID_DATE_UTC: u32 = 0x4461;
+const ID_TAGS: u32 = 0x1254_C367;
+const ID_TAG: u32 = 0x7373;
+const ID_SIMPLE_TAG: u32 = 0x67C8;
+const ID_TAG_NAME: u32 = 0x45A3;
+const ID_TAG_STRING: u32 = 0x4487;
+const ID_CHAPTERS: u32 = 0x1043_A770;
+const ID_CHAPTER_ATOM: u32 = 0xB6;
+const ID_CHAPTER_DISPLAY: u32 = 0x80;
+const ID_CHAP_STRING: u32 = 0x85;
+const ID_ATTACHMENTS: u32 = 0x1941_A469;
+const ID_ATTACHED_FILE: u32 = 0x61A7;
+const ID_FILE_NAME: u32 = 0x466E;
+const ID_FILE_MIME_TYPE: u32 = 0x4660;
+const ID_FILE_DATA: u32 = 0x465C;
+const ID_VOID: u8 = 0xEC;
+
+/// Cleans a Matroska file in place, or returns `None` without writing anything if it
+/// has tracks that `options` drop, which takes remuxing
+pub fn strip_matroska_metadata(
+    input_path: &Path,
+    output_path: &Path,
+    keep: &[Category],
+    options: &VideoOptions,
+) -> Result<Option<Vec<MetadataItem>>> {
+    let mut data = fs::read(input_path)
+        .with_context(|| format!("Failed to read video: {}", input_path.display()))?;
+
+    let kinds = track_kinds(&data).with_context(|| format!("Failed to parse Matroska file: {}", input_path.display()))?;
+    if options.drops_tracks(&kinds) {
+        return Ok(None);
+    }
+    let removed_metadata = strip_ebml_elements(&mut data, keep, options.keep_chapters)
+        .with_context(|| format!("Failed to parse Matroska file: {}", input_path.display()))?;
+
+    fs::write(output_path, data)
+        .with_context(|| format!("Failed to save video: {}", output_path.display()))?;
+
+    Ok(Some(removed_metadata))
+}
+
+/// Lists the metadata of a Matroska file without changing it, for reporting when
+/// ffprobe can't be run
+pub(crate) fn read_matroska_metadata(path: &Path) -> Result<Vec<MetadataItem>> {
+    let mut data = fs::read(path).with_context(|| format!("Failed to read video: {}", path.display()))?;
+    strip_ebml_elements(&mut data, &[], false)
+        .with_context(|| format!("Failed to parse Matroska file: {}", path.display()))
+}
+
+#[derive(Clone, Copy)]
+struct Element {
+    id: u32,
+    start: usize,
+    data_start: usize,
+    end: usize,
+}
+
+/// The segments of an EBML document, which hold everything else
+fn segments(data: &[u8]) -> Result<Vec<Element>> {
+    if !data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return Err(anyhow::anyhow!("Missing EBML header"));
+    }
+    Ok(children(data, 0, data.len())?.into_iter().filter(|e| e.id == ID_SEGMENT).collect())
+}
+
+/// The kinds of the file's tracks, plus attachments if it has any
+fn track_kinds(data: &[u8]) -> Result<Vec<StreamKind>> {
+    let mut kinds = Vec::new();
+    for segment in segments(data)? {
+        for element in children(data, segment.data_start, segment.end)? {
+            match element.id {
+                ID_TRACKS => {
+                    for entry in children(data, element.data_start, element.end)? {
+                        if entry.id != ID_TRACK_ENTRY {
+                            continue;
+                        }
+                        let track_type = children(data, entry.data_start, entry.end)?
+                            .into_iter()
+                            .find(|e| e.id == ID_TRACK_TYPE)
+                            .map(|e| data[e.data_start..e.end].iter().fold(0u64, |acc, &b| (acc << 8) | b as u64));
+                        kinds.push(match track_type {
+                            Some(1) => StreamKind::Video,
+                            Some(2) => StreamKind::Audio,
+                            Some(0x11) => StreamKind::Subtitle,
+                            // Buttons, control and metadata tracks, which ffmpeg reads as data
+                            _ => StreamKind::Data,
+                        });
+                    }
+                }
+                ID_ATTACHMENTS => kinds.push(StreamKind::Attachment),
+                _ => {}
+            }
+        }
+    }
+    Ok(kinds)
+}
+
+fn strip_ebml_elements(data: &mut [u8], keep: &[Category], keep_chapters: bool) -> Result<Vec<MetadataItem>> {
+    let mut metadata = Vec::new();
+    let mut removed = vec![ID_TAGS];
+    if !keep_chapters {
+        removed.push(ID_CHAPTERS);
+    }
+    for segment in segments(data)? {
+        for element in children(data, segment.data_start, segment.end)? {
+            match element.id {
+                ID_INFO => clean_info(data, element, keep, &mut metadata)?,
+                ID_TAGS => {
+                    // Tags mix every kind of metadata, so they're removed in full
+                    list_simple_tags(data, element, &mut metadata)?;
+                    write_void(data, element);
+                }
+                // Chapter titles are free text, written by hand or by the tool that cut the video
+                ID_CHAPTERS if !keep_chapters => {
+                    list_chapter_titles(data, element, &mut metadata)?;
+                    write_void(data, element);
+                }
+                ID_ATTACHMENTS if !keep.contains(&Category::Thumbnails) => {
+                    remove_cover_art(data, element, &mut metadata)?
+                }
+                ID_SEEK_HEAD => {
+                    // Drop the index entries for the removed elements as well
+                    for seek in children(data, element.data_start, element.end)? {
+                        if seek.id != ID_SEEK {
+                            continue;
+                        }
+                        let points_to_removed = children(data, seek.data_start, seek.end)?.iter().any(|e| {
+                            e.id == ID_SEEK_ID && removed.iter().any(|id| data[e.data_start..e.end] == id.to_be_bytes())
+                        });
+                        if points_to_removed {
+                            write_void(data, seek);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn clean_info(data: &mut [u8], info: Element, keep: &[Category], metadata: &mut Vec<MetadataItem>) -> Result<()> {
+    for element in children(data, info.data_start, info.end)? {
+        let value = &data[element.data_start..element.end];
+        let item = match element.id {
+            ID_TITLE => MetadataItem::new("Title", ebml_string(value)),
+            ID_MUXING_APP => MetadataItem::new("Muxing Application", ebml_string(value)),
+            ID_WRITING_APP => MetadataItem::new("Writing Application", ebml_string(value)),
+            ID_DATE_UTC => MetadataItem::new("Creation Date", ebml_date(value)),
+            _ => continue,
+        };
+        if keep.contains(&item.category) {
+            continue;
+        }
+        if !item.value.is_empty() {
+            metadata.push(item);
+        }
+
+        match element.id {
+            // Required by the spec, so emptied rather than removed
+            ID_MUXING_APP | ID_WRITING_APP => data[element.data_start..element.end].fill(0),
+            _ => write_void(data, element),
+        }
+    }
+    Ok(())
+}
+
+fn list_simple_tags(data: &[u8], parent: Element, metadata: &mut Vec<MetadataItem>) -> Result<()> {
+    for element in children(data, parent.data_start, parent.end)? {
+        match element.id {
+            ID_TAG => list_simple_tags(data, element, metadata)?,
+            ID_SIMPLE_TAG => {
+                let fields = children(data, element.data_start, element.end)?;
+                let field = |id| fields.iter().find(|e| e.id == id).map(|e| ebml_string(&data[e.data_start..e.end]));
+                if let Some(name) = field(ID_TAG_NAME) {
+                    let value = field(ID_TAG_STRING).unwrap_or_default();
+                    metadata.push(MetadataItem::with_category(
+                        Category::from_tag(&name),
+                        format!("Matroska Tag ({})", name),
+                        value,
+                    ));
+                }
+                // Simple tags can nest
+                list_simple_tags(data, element, metadata)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Removes attached images, which players show as cover art and which are often a
+/// frame or photo straight from the camera. Fonts for subtitles and other files stay.
+fn remove_cover_art(data: &mut [u8], attachments: Element, metadata: &mut Vec<MetadataItem>) -> Result<()> {
+    for file in children(data, attachments.data_start, attachments.end)? {
+        if file.id != ID_ATTACHED_FILE {
+            continue;
+        }
+        let fields = children(data, file.data_start, file.end)?;
+        let field = |id| fields.iter().find(|e| e.id == id).map(|e| ebml_string(&data[e.data_start..e.end]));
+        let mime_type = field(ID_FILE_MIME_TYPE).unwrap_or_default();
+        if !mime_type.starts_with("image/") {
+            continue;
+        }
+        let size = fields.iter().find(|e| e.id == ID_FILE_DATA).map_or(0, |e| e.end - e.data_start);
+        let value = format!("{} ({}, {} KB)", field(ID_FILE_NAME).unwrap_or_default(), mime_type, size.div_ceil(1024));
+        metadata.push(MetadataItem::with_category(Category::Thumbnails, COVER_ART, value));
+        write_void(data, file);
+    }
+    Ok(())
+}
+
+/// Lists the title of every chapter, in each of its languages. Editions hold chapters,
+/// which can hold chapters of their own.
+fn list_chapter_titles(data: &[u8], parent: Element, metadata: &mut Vec<MetadataItem>) -> Result<()> {
+    for element in children(data, parent.data_start, parent.end)? {
+        match element.id {
+            ID_CHAP_STRING => {
+                let title = ebml_string(&data[element.data_start..element.end]);
+                if !title.is_empty() {
+                    metadata.push(MetadataItem::with_category(Category::Content, "Chapter Title", title));
+                }
+            }
+            ID_CHAPTER_ATOM | ID_CHAPTER_DISPLAY => list_chapter_titles(data, element, metadata)?,
+            // Editions are the only other container directly under Chapters
+            _ if parent.id == ID_CHAPTERS => list_chapter_titles(data, element, metadata)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parses the elements between `start` and `end`. An element of unknown size (as in
+/// live recordings) runs to `end` and ends the list.
+fn children(data: &[u8], start: usize, end: usize) -> Result<Vec<Element>> {
+    let mut elements = Vec::new();
+    let mut pos = start;
+
+    while pos < end {
+        // IDs are compared with their length marker included
+        let (_, id_len) = read_vint(data, pos).context("Invalid element ID")?;
+        let id = data[pos..pos + id_len].iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+        let (size, size_len) = read_vint(data, pos + id_len).context("Invalid element size")?;
+        let data_start = pos + id_len + size_len;
+
+        // A size with every value bit set means "unknown"
+        let unknown = size == (1u64 << (7 * size_len)) - 1;
+        let element_end = if unknown { end } else { data_start.saturating_add(size as usize) };
+        if element_end > end {
+            return Err(anyhow::anyhow!("Element at offset {} runs past its parent", pos));
+        }
+
+        elements.push(Element { id, start: pos, data_start, end: element_end });
+        pos = element_end;
+    }
+
+    Ok(elements)
+}
+
+/// Reads a variable-length integer, returning its value without the length marker
+/// and its length in bytes
+fn read_vint(data: &[u8], pos: usize) -> Option<(u64, usize)> {
+    let first = *data.get(pos)?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+    let bytes = data.get(pos..pos + len)?;
+    let marker_mask = if len == 8 { 0 } else { 0xFFu8 >> len };
+    let value = bytes[1..].iter().fold((first & marker_mask) as u64, |acc, &b| (acc << 8) | b as u64);
+    Some((value, len))
+}
+
+/// Overwrites an element with a zero-filled Void element of the same total size
+fn write_void(data: &mut [u8], element: Element) {
+    let total = element.end - element.start;
+    data[element.start..element.end].fill(0);
+    data[element.start] = ID_VOID;
+    if total < 10 {
+        // One byte size: 0x80 marker plus the data length
+        data[element.start + 1] = 0x80 | (total - 2) as u8;
+    } else {
+        // Eight byte size: 0x01 marker, then the data length in the remaining 7 bytes
+        let size = ((total - 9) as u64).to_be_bytes();
+        data[element.start + 1] = 0x01;
+        data[element.start + 2..element.start + 9].copy_from_slice(&size[1..]);
+    }
+}
+
+fn ebml_string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).trim_end_matches('\0').trim().to_string()
+}
+
+/// Dates are nanoseconds since the start of the millennium
+fn ebml_date(value: &[u8]) -> String {
+    let nanoseconds = value.iter().fold(0i64, |acc, &b| (acc << 8) | b as i64);
+    let epoch = NaiveDate::from_ymd_opt(2001, 1, 1).and_then(|d| d.and_hms_opt(0, 0, 0));
+    epoch
+        .and_then(|epoch| epoch.checked_add_signed(Duration::nanoseconds(nanoseconds)))
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    fn element(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = id.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        // Eight byte sizes keep the test simple
+        bytes.push(0x01);
+        bytes.extend_from_slice(&(data.len() as u64).to_be_bytes()[1..]);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn build_test_mkv() -> Vec<u8> {
+        let simple_tag = [element(ID_TAG_NAME, b"ARTIST"), element(ID_TAG_STRING, b"Jane Doe")].concat();
+        let tags = element(ID_TAGS, &element(ID_TAG, &element(ID_SIMPLE_TAG, &simple_tag)));
+
+        let seek_tags = element(ID_SEEK, &[element(ID_SEEK_ID, &ID_TAGS.to_be_bytes()), element(0x53AC, &[0, 0, 0, 0])].concat());
+        let seek_info = element(ID_SEEK, &[element(ID_SEEK_ID, &ID_INFO.to_be_bytes()), element(0x53AC, &[0, 0, 0, 0])].concat());
+        let seek_head = element(ID_SEEK_HEAD, &[seek_info, seek_tags].concat());
+
+        let info = element(
+            ID_INFO,
+            &[
+                element(0x2AD7B1, &[0x0F, 0x42, 0x40]),
+                element(ID_TITLE, b"Birthday party"),
+                element(ID_MUXING_APP, b"libebml v1.4.2"),
+                element(ID_WRITING_APP, b"HandBrake 1.6.1"),
+                // 2023-05-17 14:30:00 UTC
+                element(ID_DATE_UTC, &706_026_600_000_000_000i64.to_be_bytes()),
+            ]
+            .concat(),
+        );
+        let track = [element(ID_TRACK_TYPE, &[1]), element(0x86, b"V_tracks")].concat();
+        let tracks = element(ID_TRACKS, &element(ID_TRACK_ENTRY, &track));
+        let display = element(ID_CHAPTER_DISPLAY, &element(ID_CHAP_STRING, b"Speech by the mayor"));
+        let chapters = element(ID_CHAPTERS, &element(0x45B9, &element(ID_CHAPTER_ATOM, &display)));
+        let attachment = |name: &[u8], mime_type: &[u8], contents: &[u8]| {
+            let fields = [element(ID_FILE_NAME, name), element(ID_FILE_MIME_TYPE, mime_type), element(ID_FILE_DATA, contents)];
+            element(ID_ATTACHED_FILE, &fields.concat())
+        };
+        let attachments = element(
+            ID_ATTACHMENTS,
+            &[attachment(b"cover.jpg", b"image/jpeg", b"\xFF\xD8 frame"), attachment(b"font.ttf", b"font/ttf", b"glyphs")].concat(),
+        );
+
+        let mut mkv = element(0x1A45DFA3, &element(0x4282, b"matroska"));
+        mkv.extend(element(ID_SEGMENT, &[seek_head, info, tracks, chapters, attachments, tags].concat()));
+        mkv
+    }
+
+    #[test]
+    fn test_strip_matroska_metadata() {
+        let input = Builder::new().suffix(".mkv").tempfile().unwrap();
+        let output = Builder::new().suffix(".mkv").tempfile().unwrap();
+        let original = build_test_mkv();
+        fs::write(input.path(), &original).unwrap();
+
+        let options = VideoOptions::default();
+        let removed = strip_matroska_metadata(input.path(), output.path(), &[], &options).unwrap().unwrap();
+        let text: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(text.contains(&"Title: Birthday party".to_string()));
+        assert!(text.contains(&"Writing Application: HandBrake 1.6.1".to_string()));
+        assert!(text.contains(&"Creation Date: 2023-05-17 14:30:00".to_string()));
+        assert!(text.contains(&"Chapter Title: Speech by the mayor".to_string()));
+        assert!(text.contains(&"Cover Art: cover.jpg (image/jpeg, 1 KB)".to_string()));
+        let artist = removed.iter().find(|item| item.tag == "Matroska Tag (ARTIST)").unwrap();
+        assert_eq!((artist.value.as_str(), artist.category), ("Jane Doe", Category::Author));
+
+        // Same size, so every offset in the file still holds
+        let cleaned = fs::read(output.path()).unwrap();
+        assert_eq!(cleaned.len(), original.len());
+        for secret in [&b"Jane Doe"[..], b"Birthday", b"HandBrake", b"libebml", b"mayor", b"frame"] {
+            assert!(!cleaned.windows(secret.len()).any(|w| w == secret));
+        }
+        assert!(cleaned.windows(6).any(|w| w == b"tracks"));
+        assert!(cleaned.windows(6).any(|w| w == b"glyphs"));
+
+        // The cleaned file still parses, with Void elements in place of the removed ones
+        let mut data = cleaned;
+        assert!(strip_ebml_elements(&mut data, &[], false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_track_kinds() {
+        let original = build_test_mkv();
+        assert_eq!(track_kinds(&original).unwrap(), [StreamKind::Video, StreamKind::Attachment]);
+
+        // A metadata track, such as a GPS log, has to be remuxed away
+        let telemetry = element(ID_TRACKS, &element(ID_TRACK_ENTRY, &element(ID_TRACK_TYPE, &[0x21])));
+        let mut mkv = element(0x1A45DFA3, &element(0x4282, b"matroska"));
+        mkv.extend(element(ID_SEGMENT, &telemetry));
+        assert_eq!(track_kinds(&mkv).unwrap(), [StreamKind::Data]);
+        let input = Builder::new().suffix(".mkv").tempfile().unwrap();
+        let output = Builder::new().suffix(".mkv").tempfile().unwrap();
+        fs::write(input.path(), &mkv).unwrap();
+        assert!(strip_matroska_metadata(input.path(), output.path(), &[], &VideoOptions::default()).unwrap().is_none());
+        let options = VideoOptions { streams: StreamKind::ALL.to_vec(), keep_chapters: false };
+        assert!(strip_matroska_metadata(input.path(), output.path(), &[], &options).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_strip_matroska_keeps_categories() {
+        let mut data = build_test_mkv();
+        let removed = strip_ebml_elements(&mut data, &[Category::Content, Category::Software], true).unwrap();
+        assert!(!removed.iter().any(|item| item.tag == "Title" || item.tag.ends_with("Application")));
+        assert!(data.windows(14).any(|w| w == b"Birthday party"));
+        assert!(data.windows(9).any(|w| w == b"HandBrake"));
+        assert!(!removed.iter().any(|item| item.tag == "Chapter Title"));
+        assert!(data.windows(5).any(|w| w == b"mayor"));
+        assert!(!data.windows(8).any(|w| w == b"Jane Doe"));
+    }
+
+    #[test]
+    fn test_write_void() {
+        for total in [2, 9, 10, 300] {
+            let mut data = vec![0xAA; total];
+            write_void(&mut data, Element { id: 0, start: 0, data_start: 0, end: total });
+            let elements = children(&data, 0, total).unwrap();
+            assert_eq!(elements.len(), 1);
+            assert_eq!((elements[0].id, elements[0].end), (ID_VOID as u32, total));
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Broad kind of information a metadata item reveals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    Gps,
+    Device,
+    Author,
+    Copyright,
+    Timestamps,
+    Software,
+    Thumbnails,
+    ColorProfile,
+    Orientation,
+    /// Titles, descriptions, keywords and comments
+    Content,
+    /// Dimensions, codecs and other properties needed to render the file
+    Technical,
+    /// Warnings and status lines rather than metadata found in the file
+    Notice,
+    Other,
+}
+
+/// How much a metadata item gives away, from harmless file properties up to
+/// locations, names and serial numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Sensitivity {
+    /// Codecs, dimensions and other properties that don't identify anyone
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+/// One piece of metadata found in (or removed from) a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataItem {
+    pub category: Category,
+    pub tag: String,
+    pub value: String,
+    pub sensitivity: Sensitivity,
+}
+
+// Substrings of lower-cased tag names, checked in order. Earlier rules win, so
+// "Last Saved By" is an author before it's a timestamp and "Creator Tool" is
+// software before it's an author.
+const TAG_RULES: &[(&str, Category)] = &[
+    ("thumbnail", Category::Thumbnails),
+    ("preview", Category::Thumbnails),
+    ("gps", Category::Gps),
+    ("latitude", Category::Gps),
+    ("longitude", Category::Gps),
+    ("altitude", Category::Gps),
+    ("location", Category::Gps),
+    ("icc", Category::ColorProfile),
+    ("color profile", Category::ColorProfile),
+    ("orientation", Category::Orientation),
+    ("copyright", Category::Copyright),
+    ("rights", Category::Copyright),
+    ("creator tool", Category::Software),
+    ("software", Category::Software),
+    ("producer", Category::Software),
+    ("application", Category::Software),
+    ("encoder", Category::Software),
+    ("generator", Category::Software),
+    ("template", Category::Software),
+    ("owner", Category::Author),
+    ("serial", Category::Device),
+    ("camera", Category::Device),
+    ("make", Category::Device),
+    ("model", Category::Device),
+    ("lens", Category::Device),
+    // Camera settings, not the time the photo was taken
+    ("exposure", Category::Other),
+    ("device", Category::Device),
+    ("host computer", Category::Device),
+    (" by", Category::Author),
+    ("author", Category::Author),
+    ("artist", Category::Author),
+    ("creator", Category::Author),
+    ("manager", Category::Author),
+    ("company", Category::Author),
+    ("publisher", Category::Author),
+    ("email", Category::Author),
+    ("date", Category::Timestamps),
+    ("time", Category::Timestamps),
+    ("created", Category::Timestamps),
+    ("modified", Category::Timestamps),
+    ("last saved", Category::Timestamps),
+    ("last printed", Category::Timestamps),
+    ("dimensions", Category::Technical),
+    ("color type", Category::Technical),
+    ("codec", Category::Technical),
+    ("resolution", Category::Technical),
+    ("frame rate", Category::Technical),
+    ("sample rate", Category::Technical),
+    ("channels", Category::Technical),
+    ("duration", Category::Technical),
+    ("format", Category::Technical),
+    ("handler", Category::Technical),
+    ("language", Category::Technical),
+    ("title", Category::Content),
+    ("subject", Category::Content),
+    ("description", Category::Content),
+    ("keywords", Category::Content),
+    ("comment", Category::Content),
+    ("category", Category::Content),
+    ("name", Category::Content),
+    ("label", Category::Content),
+];
+
+impl Category {
+    pub const ALL: &[Category] = &[
+        Category::Gps,
+        Category::Device,
+        Category::Author,
+        Category::Copyright,
+        Category::Timestamps,
+        Category::Software,
+        Category::Thumbnails,
+        Category::ColorProfile,
+        Category::Orientation,
+        Category::Content,
+        Category::Technical,
+        Category::Notice,
+        Category::Other,
+    ];
+
+    /// Guesses the category from a human-readable tag name such as "GPS Latitude"
+    pub fn from_tag(tag: &str) -> Self {
+        let tag = tag.to_lowercase();
+        TAG_RULES
+            .iter()
+            .find(|(pattern, _)| tag.contains(pattern))
+            .map(|(_, category)| *category)
+            .unwrap_or(Category::Other)
+    }
+
+    pub fn default_sensitivity(self) -> Sensitivity {
+        match self {
+            // Serial numbers and lens IDs tie a file to one physical camera
+            Category::Gps | Category::Author | Category::Device => Sensitivity::High,
+            Category::Timestamps | Category::Content
+            | Category::Thumbnails | Category::Other => Sensitivity::Medium,
+            Category::Copyright | Category::Software | Category::ColorProfile
+            | Category::Orientation => Sensitivity::Low,
+            Category::Technical | Category::Notice => Sensitivity::Info,
+        }
+    }
+}
+
+impl Sensitivity {
+    pub const ALL: &[Sensitivity] = &[Sensitivity::Info, Sensitivity::Low, Sensitivity::Medium, Sensitivity::High];
+
+    /// Points a single item of this tier takes off the privacy score
+    fn penalty(self) -> u32 {
+        match self {
+            Sensitivity::Info => 0,
+            Sensitivity::Low => 2,
+            Sensitivity::Medium => 10,
+            Sensitivity::High => 25,
+        }
+    }
+}
+
+/// Rates how much a set of metadata items gives away, from 100 (nothing
+/// identifying) down to 0. Each item costs points according to its sensitivity,
+/// so a single GPS position outweighs a handful of timestamps.
+pub fn privacy_score(items: &[MetadataItem]) -> u32 {
+    let penalty: u32 = items.iter().map(|item| item.sensitivity.penalty()).sum();
+    100u32.saturating_sub(penalty)
+}
+
+// Editing applications that leave their name in comments, such as "Created with GIMP"
+const EDITORS: &[&str] = &[
+    "photoshop",
+    "lightroom",
+    "camera raw",
+    "gimp",
+    "affinity photo",
+    "paint.net",
+    "pixelmator",
+    "capture one",
+    "snapseed",
+    "darktable",
+    "rawtherapee",
+    "imagemagick",
+];
+
+impl MetadataItem {
+    /// Creates an item, classifying it by its tag name. A comment naming an editing
+    /// application is a software trace rather than content.
+    pub fn new(tag: impl Into<String>, value: impl Into<String>) -> Self {
+        let (tag, value) = (tag.into(), value.into());
+        let mut category = Category::from_tag(&tag);
+        if category == Category::Content && tag.to_lowercase().contains("comment") {
+            let lowercase = value.to_lowercase();
+            if EDITORS.iter().any(|editor| lowercase.contains(editor)) {
+                category = Category::Software;
+            }
+        }
+        Self::with_category(category, tag, value)
+    }
+
+    pub fn with_category(category: Category, tag: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            category,
+            tag: tag.into(),
+            value: value.into(),
+            sensitivity: category.default_sensitivity(),
+        }
+    }
+
+    pub fn sensitivity(mut self, sensitivity: Sensitivity) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    /// The latitude and longitude of a GPS position, written either as decimal
+    /// degrees ("51.500000, -0.125000") or ISO 6709 as in video location tags
+    /// ("+51.5000-000.1250/")
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        if self.category != Category::Gps {
+            return None;
+        }
+        let value = self.value.trim();
+        let (lat, lon) = match value.split_once(',') {
+            Some(pair) => pair,
+            None => {
+                let value = value.trim_end_matches('/');
+                // The longitude starts at the second sign
+                let split = value.char_indices().skip(1).find(|(_, c)| matches!(c, '+' | '-'))?.0;
+                let rest = &value[split..];
+                let end = rest.char_indices().skip(1).find(|(_, c)| matches!(c, '+' | '-')).map_or(rest.len(), |(i, _)| i);
+                (&value[..split], &rest[..end])
+            }
+        };
+        let lat: f64 = lat.trim().parse().ok()?;
+        let lon: f64 = lon.trim().parse().ok()?;
+        ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+    }
+
+    /// An OpenStreetMap link to the position, to show what a file gives away
+    pub fn maps_url(&self) -> Option<String> {
+        let (lat, lon) = self.coordinates()?;
+        Some(format!("https://www.openstreetmap.org/?mlat={lat:.6}&mlon={lon:.6}#map=16/{lat:.6}/{lon:.6}"))
+    }
+
+    /// Whether the item describes the file rather than identifying anyone. These are
+    /// listed by inspect but don't count as leftover metadata when verifying.
+    pub fn is_descriptive(&self) -> bool {
+        matches!(self.category, Category::Technical | Category::Notice)
+    }
+}
+
+impl fmt::Display for MetadataItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.value.is_empty() {
+            write!(f, "{}", self.tag)
+        } else {
+            write!(f, "{}: {}", self.tag, self.value)
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Category::Gps => "gps",
+            Category::Device => "device",
+            Category::Author => "author",
+            Category::Copyright => "copyright",
+            Category::Timestamps => "timestamps",
+            Category::Software => "software",
+            Category::Thumbnails => "thumbnails",
+            Category::ColorProfile => "icc",
+            Category::Orientation => "orientation",
+            Category::Content => "content",
+            Category::Technical => "technical",
+            Category::Notice => "notice",
+            Category::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Category {
+    type Err = anyhow::Error;
+
+    /// Parses the names printed by `Display`, e.g. "gps" or "icc"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Category::ALL
+            .iter()
+            .find(|category| category.to_string() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<String> = Category::ALL.iter().map(ToString::to_string).collect();
+                anyhow::anyhow!("Unknown metadata category '{}' (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Sensitivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Sensitivity::Info => "info",
+            Sensitivity::Low => "low",
+            Sensitivity::Medium => "medium",
+            Sensitivity::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Sensitivity {
+    type Err = anyhow::Error;
+
+    /// Parses the names printed by `Display`, e.g. "info" or "high"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Sensitivity::ALL
+            .iter()
+            .find(|sensitivity| sensitivity.to_string() == name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Unknown severity '{}' (expected one of: info, low, medium, high)", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_from_tag() {
+        assert_eq!(Category::from_tag("GPS Latitude"), Category::Gps);
+        assert_eq!(Category::from_tag("Camera Model"), Category::Device);
+        assert_eq!(Category::from_tag("Body Serial Number"), Category::Device);
+        assert_eq!(Category::from_tag("Camera Owner Name"), Category::Author);
+        assert_eq!(Category::from_tag("Last Saved By"), Category::Author);
+        assert_eq!(Category::from_tag("Last Saved"), Category::Timestamps);
+        assert_eq!(Category::from_tag("XMP Creator Tool"), Category::Software);
+        assert_eq!(Category::from_tag("XMP Creator"), Category::Author);
+        assert_eq!(Category::from_tag("Image Dimensions"), Category::Technical);
+        assert_eq!(Category::from_tag("Something Else"), Category::Other);
+        assert_eq!(Category::from_tag("ExposureTime"), Category::Other);
+    }
+
+    #[test]
+    fn test_editor_comments_are_software() {
+        assert_eq!(MetadataItem::new("JPEG Comment", "Created with GIMP").category, Category::Software);
+        assert_eq!(MetadataItem::new("PNG Comment", "Holiday 2019").category, Category::Content);
+        assert_eq!(MetadataItem::new("Title", "Photoshop tutorial").category, Category::Content);
+    }
+
+    #[test]
+    fn test_category_from_str() {
+        assert_eq!("gps".parse::<Category>().unwrap(), Category::Gps);
+        assert_eq!(" ICC".parse::<Category>().unwrap(), Category::ColorProfile);
+        for category in Category::ALL {
+            assert_eq!(category.to_string().parse::<Category>().unwrap(), *category);
+        }
+        assert!("colour".parse::<Category>().is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let item = MetadataItem::new("Author", "Jane Doe");
+        assert_eq!(item.to_string(), "Author: Jane Doe");
+        assert_eq!(item.sensitivity, Sensitivity::High);
+        assert_eq!(MetadataItem::new("Dry run", "").to_string(), "Dry run");
+    }
+
+    #[test]
+    fn test_is_descriptive() {
+        assert!(MetadataItem::new("Image Dimensions", "10x10").is_descriptive());
+        assert!(!MetadataItem::new("Formatted By", "Jane").is_descriptive());
+        assert!(!MetadataItem::new("Author", "Jane").is_descriptive());
+    }
+
+    #[test]
+    fn test_sensitivity_tiers() {
+        assert_eq!(MetadataItem::new("GPS Latitude", "51.5").sensitivity, Sensitivity::High);
+        assert_eq!(MetadataItem::new("Body Serial Number", "123").sensitivity, Sensitivity::High);
+        assert_eq!(MetadataItem::new("Comment", "hello").sensitivity, Sensitivity::Medium);
+        assert_eq!(MetadataItem::new("Date Taken", "2024").sensitivity, Sensitivity::Medium);
+        assert_eq!(MetadataItem::new("Video Codec", "h264").sensitivity, Sensitivity::Info);
+        assert_eq!("HIGH".parse::<Sensitivity>().unwrap(), Sensitivity::High);
+        assert!("severe".parse::<Sensitivity>().is_err());
+    }
+
+    #[test]
+    fn test_privacy_score() {
+        assert_eq!(privacy_score(&[]), 100);
+        assert_eq!(privacy_score(&[MetadataItem::new("Image Dimensions", "10x10")]), 100);
+        let items = [MetadataItem::new("GPS Latitude", "51.5"), MetadataItem::new("Date Taken", "2024")];
+        assert_eq!(privacy_score(&items), 65);
+        assert_eq!(privacy_score(&vec![MetadataItem::new("GPS Latitude", "51.5"); 5]), 0);
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let decimal = MetadataItem::new("GPS Location", "51.500000, -0.125000");
+        assert_eq!(decimal.coordinates(), Some((51.5, -0.125)));
+        assert_eq!(
+            decimal.maps_url().unwrap(),
+            "https://www.openstreetmap.org/?mlat=51.500000&mlon=-0.125000#map=16/51.500000/-0.125000"
+        );
+        let iso6709 = MetadataItem::with_category(Category::Gps, "location", "+51.5000-000.1250+035.000/");
+        assert_eq!(iso6709.coordinates(), Some((51.5, -0.125)));
+        assert_eq!(MetadataItem::new("GPS Altitude", "35.5 m").coordinates(), None);
+        assert_eq!(MetadataItem::new("Comment", "1.0, 2.0").coordinates(), None);
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::heif::{read_boxes, BoxHeader};
+use crate::metadata::{Category, MetadataItem};
+
+// A reader for the tags of MP4 and QuickTime files, used to report what a video holds
+// when ffprobe can't be run. Only the movie box is read: the media data, which makes
+// up nearly all of the file, is skipped over.
+
+// iTunes-style items, in udta/meta/ilst, and the QuickTime text atoms directly in udta
+const ITEMS: &[(&[u8; 4], &str)] = &[
+    (b"\xA9nam", "Title"),
+    (b"\xA9ART", "Artist"),
+    (b"\xA9alb", "Album"),
+    (b"\xA9day", "Date"),
+    (b"\xA9cmt", "Comment"),
+    (b"\xA9wrt", "Composer"),
+    (b"\xA9xyz", "GPS Location"),
+    (b"\xA9mak", "Device Make"),
+    (b"\xA9mod", "Device Model"),
+    (b"\xA9swr", "Software"),
+    (b"\xA9too", "Encoder"),
+    (b"cprt", "Copyright"),
+    (b"desc", "Description"),
+];
+
+// Keys of QuickTime metadata (moov/meta), as written by phones
+const KEYS: &[(&str, &str)] = &[
+    ("com.apple.quicktime.make", "Device Make"),
+    ("com.apple.quicktime.model", "Device Model"),
+    ("com.apple.quicktime.software", "Software"),
+    ("com.apple.quicktime.location.ISO6709", "GPS Location"),
+    ("com.apple.quicktime.creationdate", "Creation Date"),
+    ("com.android.version", "Android Version"),
+];
+
+/// Lists the creation time and tags of an MP4 or QuickTime file, named as they are
+/// when read with ffprobe
+pub(crate) fn read_mp4_metadata(path: &Path) -> Result<Vec<MetadataItem>> {
+    let moov = read_movie_box(path).with_context(|| format!("Failed to read MP4 boxes: {}", path.display()))?;
+    let mut metadata = Vec::new();
+    for header in read_boxes(&moov, 0, moov.len())? {
+        match &header.box_type {
+            b"mvhd" => {
+                if let Some(time) = creation_time(&moov[header.start..header.end]) {
+                    metadata.push(MetadataItem::new("Creation Time", time));
+                }
+            }
+            b"udta" => read_user_data(&moov, &header, &mut metadata)?,
+            b"meta" => read_keyed_items(&moov, &header, &mut metadata)?,
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+/// The payload of the top-level moov box, found by seeking from box to box
+fn read_movie_box(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut pos = 0;
+    while pos + 8 <= file_len {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+        let (size, header_len) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (file_len - pos, 8),
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large)?;
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size as u64, 8),
+        };
+        // A 64-bit size can be anything, so it mustn't wrap around
+        let box_end = pos.checked_add(size).filter(|&box_end| size >= header_len && box_end <= file_len);
+        let Some(box_end) = box_end else {
+            anyhow::bail!("Invalid box size at offset {}", pos);
+        };
+        if &header[4..8] == b"moov" {
+            let mut moov = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(moov);
+        }
+        pos = box_end;
+    }
+    anyhow::bail!("No movie box found")
+}
+
+/// mvhd counts seconds from 1904, in 32 bits in version 0 and 64 bits in version 1.
+/// Zero means the time was never set.
+fn creation_time(mvhd: &[u8]) -> Option<String> {
+    let seconds = match mvhd.first()? {
+        0 => u32::from_be_bytes(mvhd.get(4..8)?.try_into().ok()?) as i64,
+        _ => u64::from_be_bytes(mvhd.get(4..12)?.try_into().ok()?) as i64,
+    };
+    let epoch = NaiveDate::from_ymd_opt(1904, 1, 1)?.and_hms_opt(0, 0, 0)?;
+    let time = epoch.checked_add_signed(Duration::seconds(seconds)).filter(|_| seconds > 0)?;
+    Some(time.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string())
+}
+
+fn read_user_data(moov: &[u8], udta: &BoxHeader, metadata: &mut Vec<MetadataItem>) -> Result<()> {
+    for header in read_boxes(moov, udta.start, udta.end)? {
+        let payload = &moov[header.start..header.end];
+        match &header.box_type {
+            // A full box: version and flags come before its children
+            b"meta" => {
+                let children = read_boxes(moov, header.start + 4, header.end)?;
+                for ilst in children.iter().filter(|b| &b.box_type == b"ilst") {
+                    for item in read_boxes(moov, ilst.start, ilst.end)? {
+                        let Some(value) = item_value(moov, &item)? else { continue };
+                        metadata.push(named_item(&item.box_type, value));
+                    }
+                }
+            }
+            // QuickTime text atoms: a length and a language code, then the text
+            box_type if box_type[0] == 0xA9 => {
+                let Some(length) = payload.get(..2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize) else {
+                    continue;
+                };
+                let text = payload.get(4..4 + length).map(String::from_utf8_lossy).unwrap_or_default();
+                if !text.trim().is_empty() {
+                    metadata.push(named_item(box_type, text.trim().to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// QuickTime metadata: a keys box listing names, and items numbered after them
+fn read_keyed_items(moov: &[u8], meta: &BoxHeader, metadata: &mut Vec<MetadataItem>) -> Result<()> {
+    let children = read_boxes(moov, meta.start, meta.end)?;
+    let mut keys = Vec::new();
+    if let Some(header) = children.iter().find(|b| &b.box_type == b"keys") {
+        // Version and flags, an entry count, then a size, namespace and name per key
+        let mut pos = header.start + 8;
+        while pos + 8 <= header.end {
+            let size = u32::from_be_bytes(moov[pos..pos + 4].try_into()?) as usize;
+            if size < 8 || pos + size > header.end {
+                break;
+            }
+            keys.push(String::from_utf8_lossy(&moov[pos + 8..pos + size]).to_string());
+            pos += size;
+        }
+    }
+
+    for ilst in children.iter().filter(|b| &b.box_type == b"ilst") {
+        for item in read_boxes(moov, ilst.start, ilst.end)? {
+            let index = u32::from_be_bytes(item.box_type) as usize;
+            let key = index.checked_sub(1).and_then(|i| keys.get(i));
+            let (Some(key), Some(value)) = (key, item_value(moov, &item)?) else { continue };
+            let item = match KEYS.iter().find(|(known, _)| known == key) {
+                Some((_, name)) => MetadataItem::new(*name, value),
+                None => MetadataItem::new(key.as_str(), value),
+            };
+            metadata.push(item);
+        }
+    }
+    Ok(())
+}
+
+/// The text in an item's data box, which starts with a type and a locale. Only UTF-8
+/// and UTF-16 values are read; numbers and pictures are skipped.
+fn item_value(data: &[u8], item: &BoxHeader) -> Result<Option<String>> {
+    let children = read_boxes(data, item.start, item.end)?;
+    let Some(header) = children.iter().find(|b| &b.box_type == b"data") else { return Ok(None) };
+    let Some(value) = data.get(header.start + 8..header.end) else { return Ok(None) };
+    let text = match data[header.start + 3] {
+        1 => String::from_utf8_lossy(value).to_string(),
+        2 => {
+            let units: Vec<u16> = value.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return Ok(None),
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    Ok((!text.is_empty()).then_some(text))
+}
+
+fn named_item(box_type: &[u8; 4], value: String) -> MetadataItem {
+    match ITEMS.iter().find(|(known, _)| *known == box_type) {
+        // ffmpeg's encoder tag describes the tool rather than the author
+        Some((_, "Encoder")) => MetadataItem::with_category(Category::Technical, "Encoder", value),
+        Some((_, name)) => MetadataItem::new(*name, value),
+        None => {
+            // Latin-1, for the copyright sign in front of most names
+            let name: String = box_type.iter().map(|&b| b as char).collect();
+            MetadataItem::new(name, value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn data_box(text: &str) -> Vec<u8> {
+        mp4_box(b"data", &[&[0, 0, 0, 1, 0, 0, 0, 0], text.as_bytes()].concat())
+    }
+
+    #[test]
+    fn test_read_mp4_metadata() {
+        // 2023-05-17 14:30:00 UTC
+        let mut mvhd = vec![0, 0, 0, 0];
+        mvhd.extend_from_slice(&3_767_178_600u32.to_be_bytes());
+        mvhd.extend_from_slice(&[0; 12]);
+
+        let items = [mp4_box(b"\xA9nam", &data_box("Birthday party")), mp4_box(b"\xA9too", &data_box("Lavf60.3.100"))];
+        let ilst = mp4_box(b"ilst", &items.concat());
+        // A QuickTime text atom: length, language, text
+        let location = mp4_box(b"\xA9xyz", b"\x00\x12\x15\xC7+52.5200+013.4050/");
+        let udta = mp4_box(b"udta", &[mp4_box(b"meta", &[&[0; 4], ilst.as_slice()].concat()), location].concat());
+
+        // Version, flags and entry count, then each key in the mdta namespace
+        let mut keys = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        keys.extend(mp4_box(b"mdta", b"com.apple.quicktime.model"));
+        let model = mp4_box(b"ilst", &mp4_box(&1u32.to_be_bytes(), &data_box("iPhone 14")));
+        let meta = mp4_box(b"meta", &[mp4_box(b"keys", &keys), model].concat());
+
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(mp4_box(b"mdat", &[0; 64]));
+        file.extend(mp4_box(b"moov", &[mp4_box(b"mvhd", &mvhd), udta, meta].concat()));
+        let input = Builder::new().suffix(".mp4").tempfile().unwrap();
+        std::fs::write(input.path(), &file).unwrap();
+
+        let metadata = read_mp4_metadata(input.path()).unwrap();
+        let text: Vec<String> = metadata.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            text,
+            [
+                "Creation Time: 2023-05-17T14:30:00.000000Z",
+                "Title: Birthday party",
+                "Encoder: Lavf60.3.100",
+                "GPS Location: +52.5200+013.4050/",
+                "Device Model: iPhone 14",
+            ]
+        );
+        assert_eq!(metadata[2].category, Category::Technical);
+        assert_eq!(metadata[3].category, Category::Gps);
+
+        std::fs::write(input.path(), mp4_box(b"ftyp", b"isom")).unwrap();
+        assert!(read_mp4_metadata(input.path()).is_err());
+
+        // A moov box whose 64-bit size runs far past the end of the file
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0isom");
+        file.extend_from_slice(b"\0\0\0\x01moov");
+        file.extend_from_slice(&(u64::MAX - 15).to_be_bytes());
+        file.extend_from_slice(&[0; 12]);
+        assert_eq!(file.len(), 48);
+        std::fs::write(input.path(), &file).unwrap();
+        assert!(read_mp4_metadata(input.path()).is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::metadata::MetadataItem;
+use crate::ole::strip_ole_metadata;
+use crate::xmp::find_element_value;
+
+// Office Open XML documents (.docx/.xlsx/.pptx) are zip packages. Document
+// properties live in docProps/core.xml, app.xml and custom.xml; these parts
+// are replaced with empty property sets so the package stays valid.
+// OpenDocument files (.odt/.ods/.odp) keep theirs in meta.xml, plus a
+// rendered preview of the first page under Thumbnails/.
+
+const EMPTY_CORE_PROPERTIES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"></cp:coreProperties>"#;
+
+const EMPTY_APP_PROPERTIES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties" xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes"></Properties>"#;
+
+const EMPTY_CUSTOM_PROPERTIES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/custom-properties" xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes"></Properties>"#;
+
+const ODF_META_FIELDS: &[(&str, &str)] = &[
+    ("dc:title", "Title"),
+    ("dc:subject", "Subject"),
+    ("dc:description", "Description"),
+    ("meta:keyword", "Keywords"),
+    ("meta:initial-creator", "Initial Creator"),
+    ("dc:creator", "Creator"),
+    ("meta:creation-date", "Created"),
+    ("dc:date", "Modified"),
+    ("meta:printed-by", "Printed By"),
+    ("meta:print-date", "Printed"),
+    ("meta:editing-cycles", "Editing Cycles"),
+    ("meta:editing-duration", "Editing Duration"),
+    ("meta:generator", "Generator"),
+    ("meta:template", "Template"),
+];
+
+const ODF_THUMBNAILS: &str = "Thumbnails/";
+
+const CORE_FIELDS: &[(&str, &str)] = &[
+    ("dc:title", "Title"),
+    ("dc:subject", "Subject"),
+    ("dc:creator", "Author"),
+    ("cp:keywords", "Keywords"),
+    ("dc:description", "Description"),
+    ("cp:lastModifiedBy", "Last Modified By"),
+    ("cp:revision", "Revision"),
+    ("cp:lastPrinted", "Last Printed"),
+    ("dcterms:created", "Created"),
+    ("dcterms:modified", "Modified"),
+    ("cp:category", "Category"),
+];
+
+const APP_FIELDS: &[(&str, &str)] = &[
+    ("Application", "Application"),
+    ("AppVersion", "Application Version"),
+    ("Company", "Company"),
+    ("Manager", "Manager"),
+    ("Template", "Template"),
+    ("TotalTime", "Total Editing Time (minutes)"),
+    ("HyperlinkBase", "Hyperlink Base"),
+];
+
+/// What to do with a single part when rewriting a zip package
+pub enum PartAction {
+    Keep,
+    Replace(Vec<u8>),
+    Remove,
+}
+
+pub fn strip_office_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<MetadataItem>> {
+    // Pre-2007 formats are compound files rather than zip packages
+    match crate::detect_format(input_path).as_deref() {
+        Some("doc" | "xls" | "ppt") => return strip_ole_metadata(input_path, output_path),
+        Some("odt" | "ods" | "odp") => return strip_odf_metadata(input_path, output_path),
+        _ => {}
+    }
+
+    let mut removed_metadata = Vec::new();
+
+    rewrite_package(input_path, output_path, |name, content| match name {
+        "docProps/core.xml" => {
+            let xml = String::from_utf8_lossy(content);
+            removed_metadata.extend(describe_fields(&xml, CORE_FIELDS));
+            PartAction::Replace(EMPTY_CORE_PROPERTIES.as_bytes().to_vec())
+        }
+        "docProps/app.xml" => {
+            let xml = String::from_utf8_lossy(content);
+            removed_metadata.extend(describe_fields(&xml, APP_FIELDS));
+            PartAction::Replace(EMPTY_APP_PROPERTIES.as_bytes().to_vec())
+        }
+        "docProps/custom.xml" => {
+            let count = String::from_utf8_lossy(content).matches("<property ").count();
+            removed_metadata.push(MetadataItem::new("Custom Properties", count.to_string()));
+            PartAction::Replace(EMPTY_CUSTOM_PROPERTIES.as_bytes().to_vec())
+        }
+        _ => PartAction::Keep,
+    })?;
+
+    Ok(removed_metadata)
+}
+
+fn strip_odf_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<MetadataItem>> {
+    let mut removed_metadata = Vec::new();
+
+    rewrite_package(input_path, output_path, |name, content| {
+        if name == "meta.xml" {
+            let xml = String::from_utf8_lossy(content);
+            removed_metadata.extend(describe_fields(&xml, ODF_META_FIELDS));
+            let user_defined = xml.matches("<meta:user-defined").count();
+            if user_defined > 0 {
+                removed_metadata.push(MetadataItem::new("User-Defined Fields", user_defined.to_string()));
+            }
+            PartAction::Replace(empty_odf_meta(&xml).into_bytes())
+        } else if name == "META-INF/manifest.xml" {
+            // The manifest must not list the thumbnails we drop
+            let xml = String::from_utf8_lossy(content);
+            PartAction::Replace(remove_manifest_entries(&xml, ODF_THUMBNAILS).into_bytes())
+        } else if name.starts_with(ODF_THUMBNAILS) {
+            if !content.is_empty() {
+                let preview = format!("{} ({} bytes)", name, content.len());
+                removed_metadata.push(MetadataItem::new("Thumbnail Preview", preview));
+            }
+            PartAction::Remove
+        } else {
+            PartAction::Keep
+        }
+    })?;
+
+    Ok(removed_metadata)
+}
+
+fn empty_odf_meta(original: &str) -> String {
+    // Keep the declared ODF version so validators still accept the package
+    let version = original
+        .split_once("office:version=\"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(version, _)| version)
+        .unwrap_or("1.2");
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-meta xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/" office:version="{}"><office:meta/></office:document-meta>"#,
+        version
+    )
+}
+
+fn remove_manifest_entries(manifest: &str, path_prefix: &str) -> String {
+    let full_path = format!("manifest:full-path=\"{}", path_prefix);
+    let mut output = String::with_capacity(manifest.len());
+    let mut rest = manifest;
+
+    while let Some(start) = rest.find("<manifest:file-entry") {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        if !rest[start..end].contains(&full_path) {
+            output.push_str(&rest[start..end]);
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+
+    output
+}
+
+fn describe_fields(xml: &str, fields: &[(&str, &str)]) -> Vec<MetadataItem> {
+    fields
+        .iter()
+        .filter_map(|(tag, display_name)| {
+            find_element_value(xml, tag)
+                .filter(|value| !value.is_empty())
+                .map(|value| MetadataItem::new(*display_name, value))
+        })
+        .collect()
+}
+
+/// Copies a zip package entry by entry, letting `handler` replace or drop parts.
+/// Entry order and compression methods are preserved; timestamps are not.
+pub fn rewrite_package<F>(input_path: &Path, output_path: &Path, mut handler: F) -> Result<()>
+where
+    F: FnMut(&str, &[u8]) -> PartAction,
+{
+    let input = File::open(input_path)
+        .with_context(|| format!("Failed to open document: {}", input_path.display()))?;
+    let mut archive = ZipArchive::new(BufReader::new(input))
+        .with_context(|| format!("Failed to read zip container: {}", input_path.display()))?;
+
+    // Read everything first so the output may safely replace the input
+    let mut entries = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)
+            .with_context(|| format!("Failed to read {} from {}", entry.name(), input_path.display()))?;
+        entries.push((entry.name().to_string(), entry.is_dir(), entry.compression(), content));
+    }
+
+    let output = File::create(output_path)
+        .with_context(|| format!("Failed to create document: {}", output_path.display()))?;
+    let mut writer = ZipWriter::new(BufWriter::new(output));
+
+    for (name, is_dir, compression, content) in entries {
+        let options = FileOptions::default().compression_method(compression);
+
+        let content = match handler(&name, &content) {
+            PartAction::Keep => content,
+            PartAction::Replace(replacement) => replacement,
+            PartAction::Remove => continue,
+        };
+
+        if is_dir {
+            writer.add_directory(name, options)?;
+            continue;
+        }
+
+        writer.start_file(name, options)?;
+        writer.write_all(&content)?;
+    }
+
+    writer.finish()
+        .with_context(|| format!("Failed to save document: {}", output_path.display()))?
+        .flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    fn write_test_docx(path: &Path) {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        let options = FileOptions::default();
+        writer.start_file("[Content_Types].xml", options).unwrap();
+        writer.write_all(b"<Types/>").unwrap();
+        writer.start_file("docProps/core.xml", options).unwrap();
+        writer.write_all(br#"<cp:coreProperties><dc:creator>Jane Doe</dc:creator><cp:lastModifiedBy>John Roe</cp:lastModifiedBy><cp:revision>7</cp:revision></cp:coreProperties>"#).unwrap();
+        writer.start_file("docProps/app.xml", options).unwrap();
+        writer.write_all(b"<Properties><Company>ACME Corp</Company><TotalTime>42</TotalTime></Properties>").unwrap();
+        writer.start_file("word/document.xml", options).unwrap();
+        writer.write_all(b"<w:document>Hello</w:document>").unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn read_part(path: &Path, name: &str) -> String {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_strip_office_metadata() {
+        let input = Builder::new().suffix(".docx").tempfile().unwrap();
+        let output = Builder::new().suffix(".docx").tempfile().unwrap();
+        write_test_docx(input.path());
+
+        let removed = strip_office_metadata(input.path(), output.path()).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Author: Jane Doe".to_string()));
+        assert!(removed.contains(&"Last Modified By: John Roe".to_string()));
+        assert!(removed.contains(&"Revision: 7".to_string()));
+        assert!(removed.contains(&"Company: ACME Corp".to_string()));
+        assert!(removed.contains(&"Total Editing Time (minutes): 42".to_string()));
+
+        assert_eq!(read_part(output.path(), "docProps/core.xml"), EMPTY_CORE_PROPERTIES);
+        assert_eq!(read_part(output.path(), "docProps/app.xml"), EMPTY_APP_PROPERTIES);
+        assert_eq!(read_part(output.path(), "word/document.xml"), "<w:document>Hello</w:document>");
+    }
+
+    #[test]
+    fn test_strip_odf_metadata() {
+        let input = Builder::new().suffix(".odt").tempfile().unwrap();
+        let output = Builder::new().suffix(".odt").tempfile().unwrap();
+
+        {
+            let mut writer = ZipWriter::new(File::create(input.path()).unwrap());
+            let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            writer.start_file("mimetype", stored).unwrap();
+            writer.write_all(b"application/vnd.oasis.opendocument.text").unwrap();
+            writer.start_file("meta.xml", FileOptions::default()).unwrap();
+            writer.write_all(br#"<office:document-meta office:version="1.3"><office:meta><meta:initial-creator>Jane Doe</meta:initial-creator><meta:editing-cycles>12</meta:editing-cycles><meta:generator>LibreOffice/7.5</meta:generator></office:meta></office:document-meta>"#).unwrap();
+            writer.add_directory("Thumbnails/", FileOptions::default()).unwrap();
+            writer.start_file("Thumbnails/thumbnail.png", FileOptions::default()).unwrap();
+            writer.write_all(b"fake png").unwrap();
+            writer.start_file("META-INF/manifest.xml", FileOptions::default()).unwrap();
+            writer.write_all(br#"<manifest:manifest><manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.text"/><manifest:file-entry manifest:full-path="Thumbnails/thumbnail.png" manifest:media-type="image/png"/></manifest:manifest>"#).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let removed = strip_odf_metadata(input.path(), output.path()).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Initial Creator: Jane Doe".to_string()));
+        assert!(removed.contains(&"Editing Cycles: 12".to_string()));
+        assert!(removed.contains(&"Generator: LibreOffice/7.5".to_string()));
+        assert!(removed.contains(&"Thumbnail Preview: Thumbnails/thumbnail.png (8 bytes)".to_string()));
+
+        let mut archive = ZipArchive::new(File::open(output.path()).unwrap()).unwrap();
+        assert!(!archive.file_names().any(|name| name.starts_with(ODF_THUMBNAILS)));
+        // The mimetype entry must stay first and uncompressed
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert_eq!(archive.by_index(0).unwrap().compression(), zip::CompressionMethod::Stored);
+
+        let meta = read_part(output.path(), "meta.xml");
+        assert!(meta.contains(r#"office:version="1.3""#));
+        assert!(!meta.contains("Jane Doe"));
+        let manifest = read_part(output.path(), "META-INF/manifest.xml");
+        assert!(!manifest.contains("Thumbnails/"));
+        assert!(manifest.contains(r#"manifest:full-path="/""#));
+    }
+
+    #[test]
+    fn test_strip_office_metadata_rejects_non_zip() {
+        let input = Builder::new().suffix(".docx").tempfile().unwrap();
+        let output = Builder::new().suffix(".docx").tempfile().unwrap();
+        std::fs::write(input.path(), b"not a zip").unwrap();
+
+        assert!(strip_office_metadata(input.path(), output.path()).is_err());
+    }
+}
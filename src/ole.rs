@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::metadata::MetadataItem;
+
+// Legacy Office files (.doc/.xls/.ppt) are OLE2 compound files. Author, company
+// and timestamps live in two property set streams next to the document itself.
+
+const SUMMARY_INFORMATION: &str = "\u{5}SummaryInformation";
+const DOCUMENT_SUMMARY_INFORMATION: &str = "\u{5}DocumentSummaryInformation";
+
+// Property set value types we know how to display
+const VT_I2: u32 = 0x02;
+const VT_I4: u32 = 0x03;
+const VT_LPSTR: u32 = 0x1E;
+const VT_LPWSTR: u32 = 0x1F;
+const VT_FILETIME: u32 = 0x40;
+
+// Seconds between the FILETIME epoch (1601) and the Unix epoch
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+pub fn strip_ole_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<MetadataItem>> {
+    // Make sure the input parses before touching the output
+    cfb::open(input_path)
+        .with_context(|| format!("Failed to open compound document: {}", input_path.display()))?;
+
+    if input_path != output_path {
+        fs::copy(input_path, output_path)
+            .with_context(|| format!("Failed to copy document to: {}", output_path.display()))?;
+    }
+
+    let mut comp = cfb::open_rw(output_path)
+        .with_context(|| format!("Failed to open compound document: {}", output_path.display()))?;
+
+    let mut removed_metadata = Vec::new();
+
+    for (stream_name, describe) in [
+        (SUMMARY_INFORMATION, describe_summary_information as fn(&[u8]) -> Vec<MetadataItem>),
+        (DOCUMENT_SUMMARY_INFORMATION, describe_document_summary_information),
+    ] {
+        let path = format!("/{}", stream_name);
+        if !comp.is_stream(&path) {
+            continue;
+        }
+
+        let mut stream = comp.open_stream(&path)?;
+        let mut content = Vec::new();
+        stream.read_to_end(&mut content)?;
+        removed_metadata.extend(describe(&content));
+
+        // Overwrite the sectors before freeing them so the old values can't be recovered
+        stream.seek(SeekFrom::Start(0))?;
+        stream.write_all(&vec![0u8; content.len()])?;
+        stream.flush()?;
+        drop(stream);
+
+        comp.remove_stream(&path)?;
+    }
+
+    comp.flush()
+        .with_context(|| format!("Failed to save document: {}", output_path.display()))?;
+
+    Ok(removed_metadata)
+}
+
+fn describe_summary_information(data: &[u8]) -> Vec<MetadataItem> {
+    let names = |id: u32| match id {
+        2 => Some("Title"),
+        3 => Some("Subject"),
+        4 => Some("Author"),
+        5 => Some("Keywords"),
+        6 => Some("Comments"),
+        7 => Some("Template"),
+        8 => Some("Last Saved By"),
+        9 => Some("Revision Number"),
+        10 => Some("Total Editing Time"),
+        11 => Some("Last Printed"),
+        12 => Some("Created"),
+        13 => Some("Last Saved"),
+        18 => Some("Application"),
+        _ => None,
+    };
+    describe_property_set(data, names)
+}
+
+fn describe_document_summary_information(data: &[u8]) -> Vec<MetadataItem> {
+    let names = |id: u32| match id {
+        2 => Some("Category"),
+        14 => Some("Manager"),
+        15 => Some("Company"),
+        _ => None,
+    };
+    let mut metadata = describe_property_set(data, names);
+
+    // A second section holds user-defined custom properties
+    if read_u32(data, 24) == Some(2) {
+        metadata.push(MetadataItem::new("Custom Properties", "present"));
+    }
+    metadata
+}
+
+fn describe_property_set(data: &[u8], names: impl Fn(u32) -> Option<&'static str>) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    // Header (28 bytes) followed by the FMTID and offset of the first section
+    let section = match read_u32(data, 28 + 16) {
+        Some(offset) => offset as usize,
+        None => return metadata,
+    };
+    let count = read_u32(data, section + 4).unwrap_or(0) as usize;
+
+    for index in 0..count {
+        let entry = section + 8 + index * 8;
+        let (id, offset) = match (read_u32(data, entry), read_u32(data, entry + 4)) {
+            (Some(id), Some(offset)) => (id, section + offset as usize),
+            _ => break,
+        };
+
+        let name = match names(id) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let value = match read_u32(data, offset) {
+            Some(VT_LPSTR) => read_u32(data, offset + 4).and_then(|len| {
+                data.get(offset + 8..offset + 8 + len as usize)
+                    .map(|bytes| bytes.iter().take_while(|&&b| b != 0).map(|&b| b as char).collect())
+            }),
+            Some(VT_LPWSTR) => read_u32(data, offset + 4).and_then(|len| {
+                data.get(offset + 8..offset + 8 + len as usize * 2).map(|bytes| {
+                    let units: Vec<u16> = bytes
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                        .take_while(|&u| u != 0)
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                })
+            }),
+            Some(VT_I2) => data.get(offset + 4..offset + 6)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]).to_string()),
+            Some(VT_I4) => data.get(offset + 4..offset + 8)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]).to_string()),
+            Some(VT_FILETIME) => data.get(offset + 4..offset + 12).map(|b| {
+                let ticks = u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+                format_filetime(ticks, id == 10)
+            }),
+            _ => None,
+        };
+
+        if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+            metadata.push(MetadataItem::new(name, value.trim()));
+        }
+    }
+
+    metadata
+}
+
+fn format_filetime(ticks: u64, is_duration: bool) -> String {
+    let seconds = (ticks / 10_000_000) as i64;
+    if is_duration {
+        return format!("{} minutes", seconds / 60);
+    }
+    match Utc.timestamp_opt(seconds - FILETIME_UNIX_OFFSET, 0).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => ticks.to_string(),
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    data.get(pos..pos + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    fn build_summary_information(author: &str) -> Vec<u8> {
+        let mut data = vec![0xFE, 0xFF, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&48u32.to_le_bytes());
+
+        // Section with a single VT_LPSTR Author property
+        let mut value = VT_LPSTR.to_le_bytes().to_vec();
+        value.extend_from_slice(&(author.len() as u32 + 1).to_le_bytes());
+        value.extend_from_slice(author.as_bytes());
+        value.push(0);
+        let mut section = ((16 + value.len()) as u32).to_le_bytes().to_vec();
+        section.extend_from_slice(&1u32.to_le_bytes());
+        section.extend_from_slice(&4u32.to_le_bytes());
+        section.extend_from_slice(&16u32.to_le_bytes());
+        section.extend(value);
+
+        data.extend(section);
+        data
+    }
+
+    #[test]
+    fn test_strip_ole_metadata() {
+        let input = Builder::new().suffix(".doc").tempfile().unwrap();
+        let output = Builder::new().suffix(".doc").tempfile().unwrap();
+
+        {
+            let mut comp = cfb::create(input.path()).unwrap();
+            comp.create_stream(format!("/{}", SUMMARY_INFORMATION)).unwrap()
+                .write_all(&build_summary_information("Jane Doe")).unwrap();
+            comp.create_stream("/WordDocument").unwrap().write_all(b"document body").unwrap();
+            comp.flush().unwrap();
+        }
+
+        let removed = strip_ole_metadata(input.path(), output.path()).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert_eq!(removed, vec!["Author: Jane Doe".to_string()]);
+
+        let mut comp = cfb::open(output.path()).unwrap();
+        assert!(!comp.exists(format!("/{}", SUMMARY_INFORMATION)));
+        let mut body = String::new();
+        comp.open_stream("/WordDocument").unwrap().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "document body");
+
+        // The freed sectors must not still contain the old value
+        let raw = fs::read(output.path()).unwrap();
+        assert!(!raw.windows(8).any(|w| w == b"Jane Doe"));
+    }
+}
@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+// Paths that only Windows has: verbatim `\\?\` paths, which canonicalizing gives and not
+// every program can open, and paths relative to the current directory a drive keeps of
+// its own. Both are recognized from the path's text, so they're tested on every platform.
+
+// Beyond this length a Windows path only works with the verbatim prefix
+const MAX_PATH: usize = 260;
+
+/// `path` without a verbatim prefix where it works without one, so `\\?\C:\photos`
+/// becomes `C:\photos` and `\\?\UNC\server\share` becomes `\\server\share`
+pub(crate) fn simplify(path: &Path) -> Cow<'_, Path> {
+    let Some(text) = path.to_str() else { return Cow::Borrowed(path) };
+    let simple = if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = text.strip_prefix(r"\\?\").filter(|rest| drive_path_kind(rest) == Some(true)) {
+        rest.to_string()
+    } else {
+        return Cow::Borrowed(path);
+    };
+    // Verbatim paths can also hold names Windows otherwise rejects, such as a trailing dot
+    let plain = simple.split('\\').all(|part| !part.ends_with(['.', ' ']) || part == "." || part == "..");
+    if simple.len() < MAX_PATH && plain {
+        Cow::Owned(PathBuf::from(simple))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// For a path starting with a drive letter, whether it's absolute (`C:\photos`) rather
+/// than relative to the drive's current directory (`C:photos`)
+fn drive_path_kind(text: &str) -> Option<bool> {
+    match text.as_bytes() {
+        [letter, b':', rest @ ..] if letter.is_ascii_alphabetic() => Some(matches!(rest.first(), Some(b'\\' | b'/'))),
+        _ => None,
+    }
+}
+
+/// Whether `text` is a Windows path that depends on the current drive or a drive's
+/// current directory: `C:photo.jpg`, or `\photos` without a drive
+fn is_drive_relative(text: &str) -> bool {
+    match drive_path_kind(text) {
+        Some(absolute) => !absolute,
+        None => text.starts_with(['\\', '/']) && !text.starts_with(r"\\") && !text.starts_with("//"),
+    }
+}
+
+/// Makes an input path given on Windows as `C:photo.jpg` or `\photos` absolute, so it
+/// still means the same file when joined or handed to ffmpeg. Other paths are returned
+/// as they are.
+pub fn resolve_input(path: &Path) -> PathBuf {
+    if cfg!(windows) && path.to_str().is_some_and(is_drive_relative) {
+        std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simplify() {
+        let simple = |path: &str| simplify(Path::new(path)).to_str().unwrap().to_string();
+        assert_eq!(simple(r"\\?\C:\Users\jane\photo.jpg"), r"C:\Users\jane\photo.jpg");
+        assert_eq!(simple(r"\\?\UNC\server\share\clip.mp4"), r"\\server\share\clip.mp4");
+        assert_eq!(simple(r"C:\photo.jpg"), r"C:\photo.jpg");
+        assert_eq!(simple("/home/jane/photo.jpg"), "/home/jane/photo.jpg");
+
+        // Kept where only the verbatim form works
+        assert_eq!(simple(r"\\?\C:\photos\trailing."), r"\\?\C:\photos\trailing.");
+        let long = format!(r"\\?\C:\{}\photo.jpg", "a".repeat(MAX_PATH));
+        assert_eq!(simple(&long), long);
+        assert_eq!(simple(r"\\?\Volume{1234}\photo.jpg"), r"\\?\Volume{1234}\photo.jpg");
+    }
+
+    #[test]
+    fn test_is_drive_relative() {
+        assert!(is_drive_relative("C:photo.jpg"));
+        assert!(is_drive_relative("d:"));
+        assert!(is_drive_relative(r"\photos\beach.jpg"));
+        assert!(!is_drive_relative(r"C:\photos"));
+        assert!(!is_drive_relative("C:/photos"));
+        assert!(!is_drive_relative(r"\\server\share"));
+        assert!(!is_drive_relative(r"photos\beach.jpg"));
+
+        if !cfg!(windows) {
+            assert_eq!(resolve_input(Path::new("C:photo.jpg")), Path::new("C:photo.jpg"));
+            assert_eq!(resolve_input(Path::new("/photos")), Path::new("/photos"));
+        }
+    }
+}
@@ -1,136 +1,1181 @@
 use anyhow::{Context, Result};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
 use std::path::Path;
-use std::io::Read;
-use std::fs::File;
-
-pub fn strip_pdf_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<String>> {
-    // For now, we'll use a simpler approach to PDF metadata extraction
-    // Using the full pdf library is complex due to its many dependencies
-    let mut removed_metadata = extract_pdf_metadata_simple(input_path)?;
-    
-    if removed_metadata.is_empty() {
-        // Fallback to generic metadata if extraction fails
-        removed_metadata.push("Author (if present)".to_string());
-        removed_metadata.push("Creator (if present)".to_string()); 
-        removed_metadata.push("Producer (if present)".to_string());
-        removed_metadata.push("CreationDate (if present)".to_string());
-        removed_metadata.push("ModDate (if present)".to_string());
-        removed_metadata.push("Title (if present)".to_string());
-        removed_metadata.push("Subject (if present)".to_string());
-        removed_metadata.push("Keywords (if present)".to_string());
-    }
-
-    // For now, just copy the file (implement real PDF metadata stripping in a future version)
-    std::fs::copy(input_path, output_path)
-        .with_context(|| format!("Failed to copy PDF file from {} to {}", 
-                                input_path.display(), output_path.display()))?;
+use std::time::SystemTime;
+
+use crate::assign::{self, Assignment, SetField};
+use crate::metadata::{Category, MetadataItem, Sensitivity};
+use crate::xmp::{conformance_fields, conformance_packet, describe_conformance, extract_xmp_fields};
+
+const EMBEDDED_FILE_TAG: &str = "Embedded File";
+const CONFORMANCE_WARNING: &str = "Warning: conformance";
+const JAVASCRIPT_TAG: &str = "JavaScript";
+const ORPHANS_TAG: &str = "Orphaned Objects";
+const REVISIONS_TAG: &str = "Earlier Revisions";
+/// The standard entries that tell an Info dictionary apart from other dictionaries
+const INFO_KEYS: &[&[u8]] =
+    &[b"Title", b"Author", b"Subject", b"Keywords", b"Creator", b"Producer", b"CreationDate", b"ModDate"];
+
+/// Content beyond metadata that PDFs can carry and that is only removed on request
+#[derive(Debug, Clone, Default)]
+pub struct PdfOptions {
+    /// Remove embedded files: the EmbeddedFiles name tree, file attachment
+    /// annotations and associated files
+    pub remove_attachments: bool,
+    /// Remove document-level scripts and JavaScript actions
+    pub remove_javascript: bool,
+    /// Drop objects that nothing references any more, such as pages and metadata
+    /// replaced by an incremental update, and renumber the rest
+    pub flatten: bool,
+    /// Opens encrypted PDFs, which are encrypted again with the same password once
+    /// cleaned. Files that only have an owner password open without one.
+    pub password: Option<String>,
+    /// Derive the new /ID PDF/A files need from the file's content instead of picking
+    /// it at random, so the same input always gives the same output
+    pub deterministic: bool,
+}
+
+impl PdfOptions {
+    /// Whether stripping with these options removes `item`, as listed by `inspect_pdf_metadata`
+    pub(crate) fn removes(&self, item: &MetadataItem) -> bool {
+        match item.tag.as_str() {
+            EMBEDDED_FILE_TAG => self.remove_attachments,
+            JAVASCRIPT_TAG => self.remove_javascript,
+            ORPHANS_TAG => self.flatten,
+            _ => true,
+        }
+    }
+}
+
+/// Strips the Info dictionary, apart from entries in the `keep` categories, all XMP and
+/// any Info dictionaries left behind by earlier revisions, plus attachments, scripts and
+/// orphaned objects if `options` asks for it. The file is always written as a single
+/// revision, so earlier incremental updates and the objects they replaced are dropped.
+///
+/// PDF/A and PDF/UA files get a minimal XMP packet back that only declares the standard
+/// and mirrors the kept Info entries, and PDF/A files a fresh random /ID, so that they
+/// stay valid. Anything that still breaks conformance is returned as a warning notice.
+/// The `set` values are written to the Info dictionary.
+pub fn strip_pdf_metadata(
+    input_path: &Path,
+    output_path: &Path,
+    keep: &[Category],
+    set: &[Assignment],
+    options: &PdfOptions,
+) -> Result<Vec<MetadataItem>> {
+    let (mut doc, mut removed_metadata) = load_revisions(input_path)?;
+    let encryption = decrypt(&mut doc, input_path, options.password.as_deref())?;
+    let conformance = catalog_conformance(&doc);
+
+    removed_metadata.extend(remove_orphaned_info(&mut doc));
+    if options.flatten || removed_metadata.iter().any(|item| item.tag == REVISIONS_TAG) {
+        removed_metadata.extend(remove_orphans(&mut doc));
+    }
+
+    // Record what the Info dictionary contains before removing it
+    let mut info_metadata = extract_info_metadata(&doc);
+    info_metadata.retain(|item| !keep.contains(&item.category));
+    removed_metadata.extend(info_metadata);
+
+    remove_info_dictionary(&mut doc, keep);
+    if !set.is_empty() {
+        removed_metadata.extend(set_info_entries(&mut doc, set));
+    }
+    // The encryption key is derived from the ID, so encrypted files have to keep it
+    if encryption.is_none() {
+        removed_metadata.extend(remove_document_id(&mut doc));
+        if conformance.iter().any(|(tag, _)| tag.starts_with("pdfaid:")) {
+            let id = if options.deterministic { content_document_id(input_path)? } else { random_document_id() };
+            doc.trailer.set("ID", id);
+        }
+    }
+
+    // XMP packets duplicate (and often extend) the Info dictionary
+    removed_metadata.extend(remove_xmp_metadata(&mut doc));
+    if !conformance.is_empty() {
+        removed_metadata.extend(restore_conformance(&mut doc, &conformance));
+    }
+
+    if options.remove_attachments {
+        removed_metadata.extend(list_attachments(&doc));
+        remove_attachments(&mut doc);
+    }
+    if options.remove_javascript {
+        removed_metadata.extend(list_javascript(&doc));
+        remove_javascript(&mut doc);
+    }
+    if options.remove_attachments || options.remove_javascript {
+        // The embedded files and script objects are no longer referenced
+        doc.prune_objects();
+    }
+    // Each object is encrypted with a key that depends on its number, and strings nested
+    // in dictionaries were never decrypted, so encrypted files keep their numbering
+    if options.flatten && encryption.is_none() {
+        doc.renumber_objects();
+    }
+    if let Some(encryption) = encryption {
+        encrypt(&mut doc, encryption);
+    }
+
+    // Save the document without the Info dictionary or XMP streams
+    doc.save(output_path)
+        .with_context(|| format!("Failed to save PDF: {}", output_path.display()))?;
 
     Ok(removed_metadata)
 }
 
-fn extract_pdf_metadata_simple(path: &Path) -> Result<Vec<String>> {
-    // We'll extract metadata by searching for common PDF metadata patterns
-    // This is not perfect but avoids complex dependencies
-    
-    // Read the PDF file to a buffer
-    let mut file = File::open(path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    
-    // Convert buffer to string, ignoring invalid UTF-8 sequences
-    let content = String::from_utf8_lossy(&buffer);
-    
-    let mut metadata = Vec::new();
-    
-    // Look for common metadata patterns
-    extract_metadata_field(&content, "/Title", &mut metadata, "Title");
-    extract_metadata_field(&content, "/Author", &mut metadata, "Author");
-    extract_metadata_field(&content, "/Subject", &mut metadata, "Subject");
-    extract_metadata_field(&content, "/Keywords", &mut metadata, "Keywords");
-    extract_metadata_field(&content, "/Creator", &mut metadata, "Creator");
-    extract_metadata_field(&content, "/Producer", &mut metadata, "Producer");
-    extract_metadata_field(&content, "/CreationDate", &mut metadata, "Creation Date");
-    extract_metadata_field(&content, "/ModDate", &mut metadata, "Modification Date");
-    
+/// Lists the Info dictionary and XMP metadata without modifying the file
+pub fn inspect_pdf_metadata(input_path: &Path, options: &PdfOptions) -> Result<Vec<MetadataItem>> {
+    let (mut doc, mut metadata) = load_revisions(input_path)?;
+    let encryption = decrypt(&mut doc, input_path, options.password.as_deref())?;
+
+    // Only the in-memory copy is modified; it is never saved
+    metadata.extend(remove_orphaned_info(&mut doc));
+    metadata.extend(remove_orphans(&mut doc));
+    metadata.extend(extract_info_metadata(&doc));
+    if encryption.is_none() {
+        // PDF/A requires an ID, so stripping replaces it rather than removing it
+        let conformance = catalog_conformance(&doc);
+        metadata.extend(remove_document_id(&mut doc).map(|id| {
+            if conformance.iter().any(|(tag, _)| tag.starts_with("pdfaid:")) {
+                MetadataItem::with_category(Category::Technical, id.tag, id.value)
+            } else {
+                id
+            }
+        }));
+    }
+    metadata.extend(remove_xmp_metadata(&mut doc));
+    metadata.extend(list_attachments(&doc));
+    metadata.extend(list_javascript(&doc));
+
     Ok(metadata)
 }
 
-fn extract_metadata_field(content: &str, field_name: &str, metadata: &mut Vec<String>, display_name: &str) {
-    // Simple pattern matching for PDF metadata fields
-    // This is not comprehensive but works for basic metadata extraction
-    
-    if let Some(pos) = content.find(field_name) {
-        // Find the start of the value after the field name
-        let start = pos + field_name.len();
-        if start < content.len() {
-            // Find the end of the value (usually enclosed in parentheses or marked by a /)
-            let slice = &content[start..];
-            let mut value = String::new();
-            
-            let mut chars = slice.chars().skip_while(|c| c.is_whitespace());
-            
-            match chars.next() {
-                Some('(') => {
-                    // Value is in parentheses
-                    let mut depth = 1;
-                    for c in chars {
-                        if c == '(' {
-                            depth += 1;
-                        } else if c == ')' {
-                            depth -= 1;
-                            if depth == 0 {
-                                break;
-                            }
-                        }
-                        value.push(c);
-                    }
-                },
-                Some('/') => {
-                    // Value ends at next delimiter
-                },
-                Some(c) => {
-                    // Other format, take until next /
-                    value.push(c);
-                    for c in chars {
-                        if c == '/' {
-                            break;
-                        }
-                        value.push(c);
-                    }
+/// Loads the latest revision of a PDF, listing the incremental updates saved on top
+/// of the original. Each update leaves the objects it replaced in the file, where
+/// "deleted" text, metadata and redacted content can still be recovered.
+fn load_revisions(input_path: &Path) -> Result<(Document, Vec<MetadataItem>)> {
+    let data = fs::read(input_path)
+        .with_context(|| format!("Failed to read PDF: {}", input_path.display()))?;
+    let doc = Document::load_mem(&data)
+        .with_context(|| format!("Failed to open PDF: {}", input_path.display()))?;
+
+    let mut metadata = Vec::new();
+    let updates = incremental_updates(&data);
+    if updates > 0 {
+        let value = format!("{} incremental update{}", updates, if updates == 1 { "" } else { "s" });
+        metadata.push(MetadataItem::with_category(Category::Content, REVISIONS_TAG, value));
+    }
+    Ok((doc, metadata))
+}
+
+fn incremental_updates(data: &[u8]) -> usize {
+    let revisions = data.windows(5).filter(|window| window == b"%%EOF").count();
+    // Linearized files end their first-page section with a marker of their own
+    let head = &data[..data.len().min(1024)];
+    let linearized = head.windows(11).any(|window| window == b"/Linearized");
+    revisions.saturating_sub(if linearized { 2 } else { 1 })
+}
+
+/// What's needed to encrypt a decrypted document again the way it was
+struct Encryption {
+    dict_id: ObjectId,
+    dict: Object,
+    key: Vec<u8>,
+    encrypt_metadata: bool,
+}
+
+/// Decrypts an encrypted document in memory, trying `password` and then the empty
+/// user password that files restricted only by an owner password have
+fn decrypt(doc: &mut Document, input_path: &Path, password: Option<&str>) -> Result<Option<Encryption>> {
+    let Ok(dict_id) = doc.trailer.get(b"Encrypt").and_then(Object::as_reference) else {
+        return Ok(None);
+    };
+    let dict = doc
+        .get_object(dict_id)
+        .cloned()
+        .with_context(|| format!("Encrypted PDF with a missing encryption dictionary: {}", input_path.display()))?;
+    let encrypt_metadata = dict
+        .as_dict()
+        .ok()
+        .and_then(|dict| dict.get(b"EncryptMetadata").and_then(Object::as_bool).ok())
+        .unwrap_or(true);
+
+    let mut last_error = None;
+    for candidate in password.into_iter().chain([""]) {
+        match lopdf::encryption::get_encryption_key(doc, candidate, true) {
+            Ok(key) => {
+                doc.decrypt(candidate)
+                    .with_context(|| format!("Failed to decrypt PDF: {}", input_path.display()))?;
+                return Ok(Some(Encryption { dict_id, dict, key, encrypt_metadata }));
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match (last_error, password) {
+        (Some(lopdf::encryption::DecryptionError::IncorrectPassword), None) => Err(anyhow::anyhow!(
+            "Encrypted PDF: {} needs a password to open (use --pdf-password)",
+            input_path.display()
+        )),
+        (Some(lopdf::encryption::DecryptionError::IncorrectPassword), Some(_)) => Err(anyhow::anyhow!(
+            "Encrypted PDF: the password doesn't open {}",
+            input_path.display()
+        )),
+        (e, _) => Err(anyhow::anyhow!(
+            "Encrypted PDF: can't decrypt {} ({})",
+            input_path.display(),
+            e.map(|e| e.to_string()).unwrap_or_default()
+        )),
+    }
+}
+
+/// Encrypts every string and stream that `decrypt` decrypted, with the original key,
+/// and restores the encryption dictionary. RC4 is symmetric, so decrypting plain
+/// text with the object's key encrypts it.
+fn encrypt(doc: &mut Document, encryption: Encryption) {
+    let encrypt_object = |id: ObjectId, object: &mut Object| {
+        if let Ok(encrypted) = lopdf::encryption::decrypt_object(&encryption.key, id, object) {
+            match object {
+                Object::Stream(stream) => stream.set_content(encrypted),
+                Object::String(content, _) => *content = encrypted,
+                _ => {}
+            }
+        }
+    };
+
+    for (&id, object) in doc.objects.iter_mut() {
+        if object.type_name().unwrap_or("") == "Metadata" && !encryption.encrypt_metadata {
+            continue;
+        }
+        encrypt_object(id, object);
+    }
+    // Kept Info entries were decrypted one by one
+    if let Ok(info_id) = doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        if let Ok(info) = doc.get_dictionary_mut(info_id) {
+            for (_, value) in info.iter_mut() {
+                encrypt_object(info_id, value);
+            }
+        }
+    }
+
+    doc.objects.insert(encryption.dict_id, encryption.dict);
+    doc.trailer.set("Encrypt", encryption.dict_id);
+}
+
+/// Drops objects that are still in the file but no longer reachable from the trailer
+fn remove_orphans(doc: &mut Document) -> Option<MetadataItem> {
+    let orphans = doc.prune_objects().len();
+    (orphans > 0).then(|| MetadataItem::with_category(Category::Content, ORPHANS_TAG, format!("{} unreferenced", orphans)))
+}
+
+/// Drops Info dictionaries that nothing references any more, such as the one an
+/// incremental update replaced, listing what they still say
+fn remove_orphaned_info(doc: &mut Document) -> Vec<MetadataItem> {
+    let referenced: HashSet<ObjectId> = doc.traverse_objects(|_| {}).into_iter().collect();
+    let orphans: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(id, _)| !referenced.contains(id))
+        .filter(|(_, object)| {
+            object.as_dict().is_ok_and(|dict| {
+                !dict.has(b"Type") && !dict.has(b"Parent") && INFO_KEYS.iter().any(|key| dict.has(key))
+            })
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    let mut metadata = Vec::new();
+    for id in orphans {
+        let Some(Object::Dictionary(info)) = doc.objects.remove(&id) else { continue };
+        for (key, value) in info.iter() {
+            if let Some(text) = info_text(doc, value) {
+                let tag = format!("{} (earlier revision)", display_name(key));
+                metadata.push(MetadataItem::with_category(info_category(key), tag, text));
+            }
+        }
+    }
+    metadata
+}
+
+fn extract_info_metadata(doc: &Document) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    let info = match info_dictionary(doc) {
+        Some(info) => info,
+        None => return metadata,
+    };
+
+    for (key, value) in info.iter() {
+        if let Some(text) = info_text(doc, value) {
+            metadata.push(MetadataItem::with_category(info_category(key), display_name(key), text));
+        }
+    }
+
+    metadata
+}
+
+fn info_text(doc: &Document, value: &Object) -> Option<String> {
+    // Values may be stored as indirect objects
+    let (_, value) = doc.dereference(value).ok()?;
+    let text = match value {
+        Object::String(bytes, _) => decode_pdf_string(bytes),
+        Object::Name(name) => String::from_utf8_lossy(name).to_string(),
+        Object::Boolean(b) => b.to_string(),
+        Object::Integer(i) => i.to_string(),
+        Object::Real(r) => r.to_string(),
+        _ => return None,
+    };
+
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn info_dictionary(doc: &Document) -> Option<&Dictionary> {
+    let info = doc.trailer.get(b"Info").ok()?;
+    let (_, info) = doc.dereference(info).ok()?;
+    info.as_dict().ok()
+}
+
+fn info_dictionary_mut(doc: &mut Document) -> Option<&mut Dictionary> {
+    match doc.trailer.get(b"Info").ok()? {
+        Object::Reference(id) => {
+            let id = *id;
+            doc.get_dictionary_mut(id).ok()
+        }
+        _ => doc.trailer.get_mut(b"Info").ok()?.as_dict_mut().ok(),
+    }
+}
+
+fn remove_info_dictionary(doc: &mut Document, keep: &[Category]) {
+    if let Some(info) = info_dictionary_mut(doc).filter(|_| !keep.is_empty()) {
+        let dropped: Vec<Vec<u8>> = info
+            .iter()
+            .map(|(key, _)| key.clone())
+            .filter(|key| !keep.contains(&info_category(key)))
+            .collect();
+        for key in dropped {
+            info.remove(&key);
+        }
+        if !info.is_empty() {
+            return;
+        }
+    }
+
+    if let Some(Object::Reference(id)) = doc.trailer.remove(b"Info") {
+        doc.objects.remove(&id);
+    }
+}
+
+/// The Info entries each field is written to
+fn info_keys(field: SetField) -> &'static [&'static str] {
+    match field {
+        SetField::Author => &["Author"],
+        SetField::Title => &["Title"],
+        SetField::Software => &["Creator", "Producer"],
+        SetField::Date => &["CreationDate", "ModDate"],
+        SetField::Copyright | SetField::Make | SetField::Model => &[],
+    }
+}
+
+/// Writes the `set` values into the Info dictionary, creating one if stripping removed it.
+/// Returns a warning for the fields PDFs have no Info entry for.
+fn set_info_entries(doc: &mut Document, set: &[Assignment]) -> Option<MetadataItem> {
+    let supported: Vec<SetField> = SetField::ALL.iter().copied().filter(|&field| !info_keys(field).is_empty()).collect();
+    if set.iter().any(|assignment| supported.contains(&assignment.field)) && info_dictionary_mut(doc).is_none() {
+        let info_id = doc.add_object(Dictionary::new());
+        doc.trailer.set("Info", info_id);
+    }
+    if let Some(info) = info_dictionary_mut(doc) {
+        for &field in &supported {
+            let date = |date: chrono::NaiveDateTime| date.format("D:%Y%m%d%H%M%SZ").to_string();
+            if let Some(value) = assign::value_of(set, field, date) {
+                for &key in info_keys(field) {
+                    info.set(key, encode_pdf_string(&value));
+                }
+            }
+        }
+    }
+    assign::unsupported(set, &supported, "PDF")
+}
+
+/// Removes the trailer's /ID, a pair of identifiers that stays the same through every
+/// edit of a document and so can link copies of it, even after other metadata is gone
+fn remove_document_id(doc: &mut Document) -> Option<MetadataItem> {
+    let id = doc.trailer.remove(b"ID")?;
+    let first = match doc.dereference(&id) {
+        Ok((_, Object::Array(ids))) => ids.first().and_then(|id| id.as_str().ok()).map(<[u8]>::to_vec),
+        _ => None,
+    };
+    let hex = first.unwrap_or_default().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    Some(MetadataItem::new("Document ID", hex))
+}
+
+/// The PDF/A and PDF/UA identification in the catalog's XMP packet, if it has one
+fn catalog_conformance(doc: &Document) -> Vec<(&'static str, String)> {
+    let stream = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Metadata"))
+        .and_then(|metadata| doc.dereference(metadata))
+        .and_then(|(_, metadata)| metadata.as_stream());
+    match stream {
+        Ok(stream) => {
+            let content = stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone());
+            conformance_fields(&String::from_utf8_lossy(&content))
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Gives the catalog a minimal XMP packet with the conformance fields. PDF/A requires
+/// every Info entry to be mirrored in XMP, so the kept ones are copied over; the ones
+/// XMP has no standard property for are returned as warnings, as is a PDF/UA file
+/// left without the title it requires.
+fn restore_conformance(doc: &mut Document, conformance: &[(&'static str, String)]) -> Vec<MetadataItem> {
+    let mut properties = Vec::new();
+    let mut unmirrored = Vec::new();
+    if let Some(info) = info_dictionary(doc) {
+        for (key, value) in info.iter() {
+            let Some(text) = info_text(doc, value) else { continue };
+            match xmp_property(key) {
+                Some("xmp:CreateDate" | "xmp:ModifyDate") => match xmp_date(&text) {
+                    Some(date) => properties.push((xmp_property(key).unwrap_or_default(), date)),
+                    None => unmirrored.push(display_name(key)),
                 },
-                None => {}
+                Some(property) => properties.push((property, text)),
+                None => unmirrored.push(display_name(key)),
+            }
+        }
+    }
+
+    let mut stream = Stream::new(
+        lopdf::dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        conformance_packet(conformance, &properties).into_bytes(),
+    );
+    // PDF/A-1 doesn't allow filters on metadata streams
+    stream.allows_compression = false;
+    let metadata_id = doc.add_object(stream);
+    if let Ok(root) = doc.trailer.get(b"Root").and_then(Object::as_reference) {
+        if let Ok(catalog) = doc.get_dictionary_mut(root) {
+            catalog.set("Metadata", metadata_id);
+        }
+    }
+
+    let standards = describe_conformance(conformance);
+    let mut warnings = Vec::new();
+    if conformance.iter().any(|(tag, _)| tag.starts_with("pdfaid:")) && !unmirrored.is_empty() {
+        let warning = format!("kept Info entries {} have no XMP equivalent, which breaks {}", unmirrored.join(", "), standards);
+        warnings.push(MetadataItem::with_category(Category::Notice, CONFORMANCE_WARNING, warning));
+    }
+    if conformance.iter().any(|(tag, _)| *tag == "pdfuaid:part") && !properties.iter().any(|(tag, _)| *tag == "dc:title") {
+        let warning = format!("the title was removed, which breaks {} (keep it with --keep content)", standards);
+        warnings.push(MetadataItem::with_category(Category::Notice, CONFORMANCE_WARNING, warning));
+    }
+    warnings
+}
+
+/// The XMP property PDF/A mirrors a standard Info entry in
+fn xmp_property(key: &[u8]) -> Option<&'static str> {
+    match key {
+        b"Title" => Some("dc:title"),
+        b"Author" => Some("dc:creator"),
+        b"Subject" => Some("dc:description"),
+        b"Keywords" => Some("pdf:Keywords"),
+        b"Creator" => Some("xmp:CreatorTool"),
+        b"Producer" => Some("pdf:Producer"),
+        b"CreationDate" => Some("xmp:CreateDate"),
+        b"ModDate" => Some("xmp:ModifyDate"),
+        b"Trapped" => Some("pdf:Trapped"),
+        _ => None,
+    }
+}
+
+/// Converts a PDF date such as "D:20240131120000+01'00'" to XMP's ISO 8601 form
+fn xmp_date(date: &str) -> Option<String> {
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let digits: String = date.chars().take_while(char::is_ascii_digit).collect();
+    if digits.len() < 4 || !digits.len().is_multiple_of(2) || digits.len() > 14 {
+        return None;
+    }
+    let part = |start: usize, default: &'static str| digits.get(start..start + 2).unwrap_or(default).to_string();
+    let mut iso = format!(
+        "{}-{}-{}T{}:{}:{}",
+        &digits[..4],
+        part(4, "01"),
+        part(6, "01"),
+        part(8, "00"),
+        part(10, "00"),
+        part(12, "00")
+    );
+
+    let zone: String = date[digits.len()..].chars().filter(|c| *c != '\'').collect();
+    match zone.chars().next() {
+        Some('Z') => iso.push('Z'),
+        Some(sign @ ('+' | '-')) => {
+            if let Some(hours) = zone.get(1..3) {
+                iso.push_str(&format!("{}{}:{}", sign, hours, zone.get(3..5).unwrap_or("00")));
+            }
+        }
+        _ => {}
+    }
+    Some(iso)
+}
+
+/// A new pair of random identifiers for documents that have to have an /ID
+fn random_document_id() -> Vec<Object> {
+    let mut bytes = Vec::with_capacity(16);
+    for half in 0..2u8 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(half);
+        if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+        bytes.extend_from_slice(&hasher.finish().to_be_bytes());
+    }
+    let id = Object::String(bytes, lopdf::StringFormat::Hexadecimal);
+    vec![id.clone(), id]
+}
+
+/// A pair of identifiers that only depend on the original file's content
+fn content_document_id(path: &Path) -> Result<Vec<Object>> {
+    let data = fs::read(path).with_context(|| format!("Failed to read PDF: {}", path.display()))?;
+    let id = Object::String(Sha256::digest(&data)[..16].to_vec(), lopdf::StringFormat::Hexadecimal);
+    Ok(vec![id.clone(), id])
+}
+
+fn info_category(key: &[u8]) -> Category {
+    // /Creator names the application the document was made in, not a person
+    if key == b"Creator" {
+        Category::Software
+    } else {
+        Category::from_tag(&display_name(key))
+    }
+}
+
+fn remove_xmp_metadata(doc: &mut Document) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    // XMP lives in streams of /Type /Metadata, referenced from the catalog and
+    // optionally from pages, images, fonts and other objects
+    let xmp_ids: BTreeSet<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| {
+            object
+                .as_stream()
+                .map(|stream| stream.dict.type_is(b"Metadata"))
+                .unwrap_or(false)
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in &xmp_ids {
+        if let Some(Object::Stream(stream)) = doc.objects.remove(id) {
+            let content = stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone());
+            metadata.extend(extract_xmp_fields(&String::from_utf8_lossy(&content)));
+        }
+    }
+
+    // Drop every /Metadata entry so no dangling references remain
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+
+        if let Ok(Object::Reference(id)) = dict.get(b"Metadata") {
+            if xmp_ids.contains(id) {
+                dict.remove(b"Metadata");
+            }
+        }
+    }
+
+    metadata
+}
+
+/// Every object in the file, including dictionaries nested inside other objects
+fn all_dictionaries(doc: &Document) -> Vec<&Dictionary> {
+    fn visit<'a>(object: &'a Object, found: &mut Vec<&'a Dictionary>) {
+        match object {
+            Object::Dictionary(dict) => {
+                found.push(dict);
+                dict.iter().for_each(|(_, value)| visit(value, found));
             }
-            
-            value = value.trim().to_string();
-            
-            if !value.is_empty() {
-                // Clean up common encodings in PDF strings
-                value = value.replace("\\n", "\n")
-                             .replace("\\r", "\r")
-                             .replace("\\t", "\t")
-                             .replace("\\\\", "\\");
-                
-                metadata.push(format!("{}: {}", display_name, value));
+            Object::Stream(stream) => {
+                found.push(&stream.dict);
+                stream.dict.iter().for_each(|(_, value)| visit(value, found));
             }
+            Object::Array(array) => array.iter().for_each(|value| visit(value, found)),
+            _ => {}
         }
     }
+
+    let mut found = Vec::new();
+    doc.objects.values().for_each(|object| visit(object, &mut found));
+    found
+}
+
+fn name_is(dict: &Dictionary, key: &[u8], name: &[u8]) -> bool {
+    dict.get(key).and_then(Object::as_name).map(|value| value == name).unwrap_or(false)
+}
+
+/// File specifications with embedded file streams, wherever they are referenced from
+fn list_attachments(doc: &Document) -> Vec<MetadataItem> {
+    all_dictionaries(doc)
+        .into_iter()
+        .filter(|dict| dict.has(b"EF"))
+        .map(|filespec| {
+            let name = [b"UF".as_slice(), b"F"]
+                .iter()
+                .find_map(|key| filespec.get(key).ok().and_then(|name| doc.dereference(name).ok()))
+                .and_then(|(_, name)| name.as_str().ok().map(decode_pdf_string))
+                .unwrap_or_else(|| "unnamed".to_string());
+            let size = filespec
+                .get(b"EF")
+                .and_then(|ef| doc.dereference(ef))
+                .and_then(|(_, ef)| ef.as_dict())
+                .and_then(|ef| ef.get(b"F"))
+                .and_then(|file| doc.dereference(file))
+                .and_then(|(_, file)| file.as_stream())
+                .map(|stream| stream.content.len());
+            let value = match size {
+                Ok(size) => format!("{} ({} bytes)", name, size),
+                Err(_) => name,
+            };
+            MetadataItem::with_category(Category::Content, EMBEDDED_FILE_TAG, value).sensitivity(Sensitivity::High)
+        })
+        .collect()
+}
+
+fn remove_attachments(doc: &mut Document) {
+    remove_name_tree(doc, b"EmbeddedFiles");
+
+    let annotations: BTreeSet<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| {
+            object.as_dict().map(|dict| name_is(dict, b"Subtype", b"FileAttachment")).unwrap_or(false)
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    doc.traverse_objects(|object| {
+        let dict = match object {
+            // Annotation arrays lose their file attachment annotations
+            Object::Array(array) => {
+                array.retain(|item| match item {
+                    Object::Reference(id) => !annotations.contains(id),
+                    Object::Dictionary(dict) => !name_is(dict, b"Subtype", b"FileAttachment"),
+                    _ => true,
+                });
+                return;
+            }
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => return,
+        };
+        // Files associated with the document, a page or an image (PDF/A-3, ZUGFeRD invoices)
+        dict.remove(b"AF");
+        // Any file specification still referenced, e.g. by a link, loses its content
+        dict.remove(b"EF");
+    });
+}
+
+/// A snippet of each script, from the document's JavaScript name tree and from
+/// actions attached to the document, pages, annotations and form fields
+fn list_javascript(doc: &Document) -> Vec<MetadataItem> {
+    all_dictionaries(doc)
+        .into_iter()
+        .filter(|dict| name_is(dict, b"S", b"JavaScript"))
+        .map(|action| {
+            let script = match action.get(b"JS").and_then(|js| doc.dereference(js)) {
+                Ok((_, Object::String(bytes, _))) => decode_pdf_string(bytes),
+                Ok((_, Object::Stream(stream))) => {
+                    let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                    String::from_utf8_lossy(&content).to_string()
+                }
+                _ => String::new(),
+            };
+            let script = script.split_whitespace().collect::<Vec<_>>().join(" ");
+            let snippet = match script.char_indices().nth(60) {
+                Some((end, _)) => format!("{}…", &script[..end]),
+                None => script,
+            };
+            MetadataItem::with_category(Category::Content, JAVASCRIPT_TAG, snippet)
+        })
+        .collect()
+}
+
+fn remove_javascript(doc: &mut Document) {
+    remove_name_tree(doc, b"JavaScript");
+
+    let actions: BTreeSet<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| object.as_dict().map(|dict| name_is(dict, b"S", b"JavaScript")).unwrap_or(false))
+        .map(|(id, _)| *id)
+        .collect();
+    let is_script = |value: &Object| match value {
+        Object::Reference(id) => actions.contains(id),
+        Object::Dictionary(dict) => name_is(dict, b"S", b"JavaScript"),
+        _ => false,
+    };
+
+    doc.traverse_objects(|object| {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => return,
+        };
+        // Actions run on opening the document or activating a link or widget
+        for key in [b"OpenAction".as_slice(), b"A"] {
+            if dict.get(key).map(is_script).unwrap_or(false) {
+                dict.remove(key);
+            }
+        }
+        // Additional actions run on events such as closing, printing or changing a
+        // field, and are kept for anything that isn't a script
+        if let Ok(Object::Dictionary(triggers)) = dict.get_mut(b"AA") {
+            let scripts: Vec<Vec<u8>> =
+                triggers.iter().filter(|(_, value)| is_script(value)).map(|(key, _)| key.clone()).collect();
+            for key in scripts {
+                triggers.remove(&key);
+            }
+        }
+    });
+}
+
+/// Removes an entry such as EmbeddedFiles from the catalog's Names dictionary
+fn remove_name_tree(doc: &mut Document, key: &[u8]) {
+    let names = match doc.catalog().and_then(|catalog| catalog.get(b"Names")) {
+        Ok(Object::Reference(id)) => Some(*id),
+        _ => None,
+    };
+    let names = match names {
+        Some(id) => doc.get_dictionary_mut(id).ok(),
+        None => doc
+            .catalog_mut()
+            .ok()
+            .and_then(|catalog| catalog.get_mut(b"Names").ok())
+            .and_then(|names| names.as_dict_mut().ok()),
+    };
+    if let Some(names) = names {
+        names.remove(key);
+    }
+}
+
+fn display_name(key: &[u8]) -> String {
+    match key {
+        b"Title" => "Title".to_string(),
+        b"Author" => "Author".to_string(),
+        b"Subject" => "Subject".to_string(),
+        b"Keywords" => "Keywords".to_string(),
+        b"Creator" => "Creator".to_string(),
+        b"Producer" => "Producer".to_string(),
+        b"CreationDate" => "Creation Date".to_string(),
+        b"ModDate" => "Modification Date".to_string(),
+        b"Trapped" => "Trapped".to_string(),
+        // Custom Info entries are reported under their own key
+        _ => String::from_utf8_lossy(key).to_string(),
+    }
+}
+
+/// A text string in PDFDocEncoding if it's plain ASCII, and UTF-16BE otherwise
+fn encode_pdf_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, lopdf::StringFormat::Literal)
+}
+
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    // Text strings are either UTF-16BE with a byte order mark or PDFDocEncoding,
+    // which matches Latin-1 for all printable characters
+    if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lopdf::dictionary;
     use tempfile::NamedTempFile;
 
+    fn create_test_pdf(path: &Path) {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = doc.add_object(dictionary! {
+            "Author" => Object::string_literal("Jane Doe"),
+            "Producer" => Object::string_literal("Test Producer"),
+        });
+        let xmp_id = doc.add_object(lopdf::Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            br#"<x:xmpmeta><rdf:Description xmp:CreatorTool="Test Writer"/></x:xmpmeta>"#.to_vec(),
+        ));
+        doc.get_dictionary_mut(catalog_id).unwrap().set("Metadata", xmp_id);
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+        let id = Object::String(vec![0xAB; 16], lopdf::StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![id.clone(), id]);
+        doc.save(path).unwrap();
+    }
+
     #[test]
     fn test_strip_pdf_metadata() {
         let input = NamedTempFile::new().unwrap();
         let output = NamedTempFile::new().unwrap();
-        
-        // Write some content to the input file
-        std::fs::write(&input, b"test pdf content").unwrap();
+
+        create_test_pdf(input.path());
 
         // Test stripping metadata
-        let result = strip_pdf_metadata(input.path(), output.path());
+        let result = strip_pdf_metadata(input.path(), output.path(), &[], &[], &PdfOptions::default());
         assert!(result.is_ok());
+
+        let removed = result.unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Author: Jane Doe".to_string()));
+        assert!(removed.contains(&"Producer: Test Producer".to_string()));
+        assert!(removed.contains(&"XMP Creator Tool: Test Writer".to_string()));
+        assert!(removed.contains(&format!("Document ID: {}", "ab".repeat(16))));
+
+        // The cleaned document must no longer reference an Info dictionary or XMP stream
+        let cleaned = Document::load(output.path()).unwrap();
+        assert!(cleaned.trailer.get(b"Info").is_err());
+        assert!(cleaned.trailer.get(b"ID").is_err());
+        assert!(!cleaned.catalog().unwrap().has(b"Metadata"));
+        assert!(!cleaned.objects.values().any(|object| {
+            object.as_stream().map(|s| s.dict.type_is(b"Metadata")).unwrap_or(false)
+        }));
+        assert_eq!(cleaned.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn test_strip_pdf_metadata_keeps_categories() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[Category::Software], &[], &PdfOptions::default()).unwrap();
+        assert!(removed.contains(&MetadataItem::new("Author", "Jane Doe")));
+        assert!(!removed.iter().any(|item| item.tag == "Producer"));
+        // XMP is removed as a whole
+        assert!(removed.iter().any(|item| item.tag == "XMP Creator Tool"));
+
+        let cleaned = Document::load(output.path()).unwrap();
+        let metadata = extract_info_metadata(&cleaned);
+        assert_eq!(metadata, vec![MetadataItem::new("Producer", "Test Producer")]);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_set_info_entries() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+
+        let set: Vec<Assignment> = ["author=Zoë", "date=2020-01-02T03:04:05", "make=Canon"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[], &set, &PdfOptions::default()).unwrap();
+        assert!(removed.contains(&MetadataItem::with_category(Category::Author, "Author", "Jane Doe")));
+        let warning = removed.iter().find(|item| item.category == Category::Notice).unwrap();
+        assert_eq!(warning.value, "make can't be set in PDF files");
+
+        let metadata = inspect_pdf_metadata(output.path(), &PdfOptions::default()).unwrap();
+        assert!(metadata.contains(&MetadataItem::with_category(Category::Author, "Author", "Zoë")));
+        assert!(metadata.contains(&MetadataItem::new("Creation Date", "D:20200102030405Z")));
+        assert!(!metadata.iter().any(|item| item.tag == "Producer"));
+    }
+
+    #[test]
+    fn test_inspect_pdf_metadata() {
+        let input = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+        let original = std::fs::read(input.path()).unwrap();
+
+        let metadata = inspect_pdf_metadata(input.path(), &PdfOptions::default()).unwrap();
+        assert!(metadata.contains(&MetadataItem::new("Author", "Jane Doe")));
+        assert!(metadata.contains(&MetadataItem::with_category(Category::Software, "XMP Creator Tool", "Test Writer")));
+
+        assert_eq!(std::fs::read(input.path()).unwrap(), original);
+    }
+
+    /// Adds an attached file and scripts run on opening the document and clicking a link
+    fn add_attachment_and_scripts(path: &Path) {
+        let mut doc = Document::load(path).unwrap();
+        let file_id = doc.add_object(lopdf::Stream::new(
+            dictionary! { "Type" => "EmbeddedFile" },
+            b"salary spreadsheet".to_vec(),
+        ));
+        let filespec = dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal("salaries.csv"),
+            "EF" => dictionary! { "F" => file_id },
+        };
+        let script_id = doc.add_object(dictionary! {
+            "S" => "JavaScript",
+            "JS" => Object::string_literal("app.alert('hello');"),
+        });
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let link = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "A" => dictionary! { "S" => "JavaScript", "JS" => Object::string_literal("this.submitForm('x');") },
+        };
+        let attachment_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "FileAttachment",
+            "FS" => filespec.clone(),
+        });
+        doc.get_dictionary_mut(page_id).unwrap().set("Annots", vec![attachment_id.into(), link.into()]);
+
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("OpenAction", script_id);
+        catalog.set("Names", dictionary! {
+            "EmbeddedFiles" => dictionary! { "Names" => vec![Object::string_literal("salaries.csv"), filespec.into()] },
+        });
+        doc.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_strip_pdf_attachments_and_javascript() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+        add_attachment_and_scripts(input.path());
+
+        let inspected = inspect_pdf_metadata(input.path(), &PdfOptions::default()).unwrap();
+        assert!(inspected.iter().any(|item| item.tag == EMBEDDED_FILE_TAG && item.value == "salaries.csv (18 bytes)"));
+        assert_eq!(inspected.iter().filter(|item| item.tag == JAVASCRIPT_TAG).count(), 2);
+
+        // Without the options, attachments and scripts stay and aren't reported
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[], &[], &PdfOptions::default()).unwrap();
+        assert!(!removed.iter().any(|item| item.tag == EMBEDDED_FILE_TAG || item.tag == JAVASCRIPT_TAG));
+
+        let options = PdfOptions { remove_attachments: true, remove_javascript: true, ..PdfOptions::default() };
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[], &[], &options).unwrap();
+        assert!(removed.iter().any(|item| item.tag == EMBEDDED_FILE_TAG));
+        assert!(removed.contains(&MetadataItem::with_category(Category::Content, JAVASCRIPT_TAG, "app.alert('hello');")));
+
+        let cleaned = Document::load(output.path()).unwrap();
+        assert!(list_attachments(&cleaned).is_empty());
+        assert!(list_javascript(&cleaned).is_empty());
+        assert!(!cleaned.catalog().unwrap().has(b"OpenAction"));
+        let page = cleaned.get_dictionary(*cleaned.get_pages().values().next().unwrap()).unwrap();
+        let annotations = page.get(b"Annots").unwrap().as_array().unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert!(!annotations[0].as_dict().unwrap().has(b"A"));
+        let data = std::fs::read(output.path()).unwrap();
+        assert!(!data.windows(6).any(|window| window == b"salary"));
+    }
+
+    #[test]
+    fn test_flatten_incremental_update() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+
+        // Append an update that replaces the Info dictionary, leaving the old one orphaned
+        let mut data = std::fs::read(input.path()).unwrap();
+        let doc = Document::load_mem(&data).unwrap();
+        let root = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        let startxref = data.len() - data.windows(9).rev().position(|w| w == b"startxref").unwrap() - 9;
+        let prev = String::from_utf8_lossy(&data[startxref + 9..]).split_whitespace().next().unwrap().to_string();
+        let new_id = doc.max_id + 1;
+        let offset = data.len();
+        data.extend_from_slice(format!("{} 0 obj\n<< /Title (Final) >>\nendobj\n", new_id).as_bytes());
+        let xref = data.len();
+        let trailer = format!("<< /Size {} /Root {} 0 R /Info {} 0 R /Prev {} >>", new_id + 1, root.0, new_id, prev);
+        data.extend_from_slice(
+            format!("xref\n{} 1\n{:010} 00000 n \ntrailer\n{}\nstartxref\n{}\n%%EOF\n", new_id, offset, trailer, xref)
+                .as_bytes(),
+        );
+        std::fs::write(input.path(), &data).unwrap();
+
+        let inspected = inspect_pdf_metadata(input.path(), &PdfOptions::default()).unwrap();
+        let revisions = MetadataItem::with_category(Category::Content, REVISIONS_TAG, "1 incremental update");
+        assert!(inspected.contains(&revisions));
+        assert!(inspected.iter().any(|item| item.tag == ORPHANS_TAG));
+        assert!(inspected.contains(&MetadataItem::new("Title", "Final")));
+        let earlier_author = MetadataItem::new("Author (earlier revision)", "Jane Doe");
+        assert!(inspected.contains(&earlier_author));
+
+        // The replaced Info dictionary goes even without flattening
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[], &[], &PdfOptions::default()).unwrap();
+        assert!(removed.contains(&earlier_author));
+        let cleaned = std::fs::read(output.path()).unwrap();
+        assert_eq!(incremental_updates(&cleaned), 0);
+        assert!(!cleaned.windows(8).any(|window| window == b"Jane Doe"));
+        assert!(inspect_pdf_metadata(output.path(), &PdfOptions::default()).unwrap().is_empty());
+
+        let options = PdfOptions { flatten: true, ..PdfOptions::default() };
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[], &[], &options).unwrap();
+        assert!(removed.iter().any(|item| item.tag == ORPHANS_TAG));
+
+        let cleaned = std::fs::read(output.path()).unwrap();
+        assert_eq!(incremental_updates(&cleaned), 0);
+        assert!(!cleaned.windows(8).any(|window| window == b"Jane Doe"));
+        let cleaned = Document::load_mem(&cleaned).unwrap();
+        assert_eq!(cleaned.get_pages().len(), 1);
+    }
+
+    /// Encrypts the test PDF with RC4 (revision 2) and the user password "secret". The
+    /// O and U entries and the key were worked out for the test PDF's ID.
+    fn encrypt_test_pdf(path: &Path) {
+        let hex = |text: &str| {
+            (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect::<Vec<u8>>()
+        };
+        let owner = hex("92fe0f4454ad4c9644693f33c07cb54f587dce1e2682fe9ecea6107a1ef630dd");
+        let user = hex("ba69b5564c816dd0986552a09a4f4a905673715f535cfdad8f8b5fb6903800f8");
+        let dict = dictionary! {
+            "Filter" => "Standard",
+            "V" => 1,
+            "R" => 2,
+            "O" => Object::String(owner, lopdf::StringFormat::Hexadecimal),
+            "U" => Object::String(user, lopdf::StringFormat::Hexadecimal),
+            "P" => -4,
+        };
+
+        let mut doc = Document::load(path).unwrap();
+        let dict_id = doc.new_object_id();
+        let key = hex("ebac619133");
+        encrypt(&mut doc, Encryption { dict_id, dict: Object::Dictionary(dict), key, encrypt_metadata: true });
+        doc.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_pdf() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+        encrypt_test_pdf(input.path());
+
+        let error = strip_pdf_metadata(input.path(), output.path(), &[], &[], &PdfOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("needs a password"));
+        let wrong = PdfOptions { password: Some("guess".to_string()), ..PdfOptions::default() };
+        let error = inspect_pdf_metadata(input.path(), &wrong).unwrap_err();
+        assert!(error.to_string().contains("password doesn't open"));
+
+        let options = PdfOptions { password: Some("secret".to_string()), ..PdfOptions::default() };
+        let inspected = inspect_pdf_metadata(input.path(), &options).unwrap();
+        assert!(inspected.contains(&MetadataItem::new("Author", "Jane Doe")));
+
+        let removed = strip_pdf_metadata(input.path(), output.path(), &[Category::Software], &[], &options).unwrap();
+        assert!(removed.contains(&MetadataItem::new("Author", "Jane Doe")));
+
+        // Still encrypted with the same password, keeping the ID the key depends on
+        let mut cleaned = Document::load(output.path()).unwrap();
+        assert!(cleaned.is_encrypted());
+        assert!(cleaned.trailer.get(b"ID").is_ok());
+        cleaned.decrypt("secret").unwrap();
+        assert_eq!(extract_info_metadata(&cleaned), vec![MetadataItem::new("Producer", "Test Producer")]);
+        assert_eq!(cleaned.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn test_pdfa_conformance_preserved() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+
+        let mut doc = Document::load(input.path()).unwrap();
+        let info_id = doc.trailer.get(b"Info").and_then(Object::as_reference).unwrap();
+        let info = doc.get_dictionary_mut(info_id).unwrap();
+        info.set("Title", Object::string_literal("Annual Report"));
+        info.set("CreationDate", Object::string_literal("D:20240131120000+01'00'"));
+        info.set("Reviewer", Object::string_literal("QA"));
+        let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference).unwrap();
+        let xmp_id = doc.get_dictionary(catalog_id).unwrap().get(b"Metadata").and_then(Object::as_reference).unwrap();
+        doc.get_object_mut(xmp_id).unwrap().as_stream_mut().unwrap().set_plain_content(
+            br#"<rdf:Description pdfaid:part="2" pdfaid:conformance="B" xmp:CreatorTool="Test Writer"/>"#.to_vec(),
+        );
+        doc.save(input.path()).unwrap();
+
+        let keep = [Category::Content, Category::Timestamps, Category::Other];
+        let removed = strip_pdf_metadata(input.path(), output.path(), &keep, &[], &PdfOptions::default()).unwrap();
+        let warning = removed.iter().find(|item| item.tag == CONFORMANCE_WARNING).unwrap();
+        assert_eq!(warning.value, "kept Info entries Reviewer have no XMP equivalent, which breaks PDF/A-2B");
+
+        // The identification and the kept entries are back in XMP, and the ID is new
+        let cleaned = Document::load(output.path()).unwrap();
+        let metadata = cleaned.catalog().unwrap().get(b"Metadata").and_then(Object::as_reference).unwrap();
+        let packet = String::from_utf8(cleaned.get_object(metadata).unwrap().as_stream().unwrap().content.clone()).unwrap();
+        assert!(packet.contains(r#"pdfaid:part="2""#));
+        assert!(packet.contains("Annual Report"));
+        assert!(packet.contains("<xmp:CreateDate>2024-01-31T12:00:00+01:00</xmp:CreateDate>"));
+        assert!(!packet.contains("Test Writer"));
+        let ids = cleaned.trailer.get(b"ID").and_then(Object::as_array).unwrap();
+        assert_ne!(ids[0].as_str().unwrap(), &[0xAB; 16]);
+
+        let metadata = inspect_pdf_metadata(output.path(), &PdfOptions::default()).unwrap();
+        assert!(metadata.contains(&MetadataItem::with_category(Category::Technical, "XMP Conformance", "PDF/A-2B")));
+        assert!(metadata.iter().filter(|item| item.tag == "Document ID").all(|item| item.category == Category::Technical));
+    }
+
+    #[test]
+    fn test_deterministic_document_id() {
+        let input = NamedTempFile::new().unwrap();
+        create_test_pdf(input.path());
+        let mut doc = Document::load(input.path()).unwrap();
+        let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference).unwrap();
+        let xmp_id = doc.get_dictionary(catalog_id).unwrap().get(b"Metadata").and_then(Object::as_reference).unwrap();
+        doc.get_object_mut(xmp_id).unwrap().as_stream_mut().unwrap().set_plain_content(
+            br#"<rdf:Description pdfaid:part="1" pdfaid:conformance="B"/>"#.to_vec(),
+        );
+        doc.save(input.path()).unwrap();
+
+        let options = PdfOptions { deterministic: true, ..PdfOptions::default() };
+        let outputs: Vec<Vec<u8>> = (0..2)
+            .map(|_| {
+                let output = NamedTempFile::new().unwrap();
+                strip_pdf_metadata(input.path(), output.path(), &[], &[], &options).unwrap();
+                fs::read(output.path()).unwrap()
+            })
+            .collect();
+        assert_eq!(outputs[0], outputs[1]);
+        assert!(Document::load_mem(&outputs[0]).unwrap().trailer.get(b"ID").is_ok());
+    }
+
+    #[test]
+    fn test_strip_pdf_metadata_invalid_file() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+
+        std::fs::write(&input, b"test pdf content").unwrap();
+
+        assert!(strip_pdf_metadata(input.path(), output.path(), &[], &[], &PdfOptions::default()).is_err());
+    }
+}
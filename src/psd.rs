@@ -0,0 +1,270 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::image::{extract_exif_fields, filter_exif};
+use crate::iptc::{
+    extract_iptc_fields, RESOURCE_IPTC, RESOURCE_THUMBNAIL, RESOURCE_THUMBNAIL_OLD, RESOURCE_VERSION_INFO,
+};
+use crate::jp2::{kept_or_dropped, Action};
+use crate::makernote::is_fingerprint;
+use crate::metadata::{Category, MetadataItem};
+use crate::xmp::extract_xmp_fields;
+
+// Photoshop documents (PSD, and PSB for large ones) keep their metadata in the image
+// resources section, the same 8BIM blocks JPEGs carry in APP13. Those are filtered and
+// everything else, the layers and the merged image, is copied byte for byte. Layer
+// names can say as much as a caption, but renaming them would change the document, so
+// they are kept and named in a warning instead.
+
+const PSD_SIGNATURE: &[u8] = b"8BPS";
+const HEADER_LEN: usize = 26;
+const LAYERS_WARNING: &str = "Warning: layers";
+
+const RESOURCE_CAPTION: u16 = 0x03F0;
+const RESOURCE_COPYRIGHT_FLAG: u16 = 0x040A;
+const RESOURCE_URL: u16 = 0x040B;
+const RESOURCE_ICC_PROFILE: u16 = 0x040F;
+const RESOURCE_URL_LIST: u16 = 0x041E;
+const RESOURCE_EXIF: u16 = 0x0422;
+const RESOURCE_EXIF_3: u16 = 0x0423;
+const RESOURCE_XMP: u16 = 0x0424;
+// An MD5 of the IPTC resource, meaningless once that is gone
+const RESOURCE_CAPTION_DIGEST: u16 = 0x0425;
+// Print settings, which name the printer
+const RESOURCE_PRINT_INFO: u16 = 0x043A;
+const RESOURCE_PRINT_STYLE: u16 = 0x043B;
+
+pub fn strip_psd_metadata(input_path: &Path, output_path: &Path, keep: &[Category]) -> Result<Vec<MetadataItem>> {
+    let data = fs::read(input_path).with_context(|| format!("Failed to read image: {}", input_path.display()))?;
+    let (cleaned, metadata) = strip_psd_resources(&data, keep)
+        .with_context(|| format!("Failed to parse Photoshop document: {}", input_path.display()))?;
+    fs::write(output_path, cleaned).with_context(|| format!("Failed to save image: {}", output_path.display()))?;
+    Ok(metadata)
+}
+
+fn strip_psd_resources(data: &[u8], keep: &[Category]) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    // Version 2 is the large document format, with wider lengths in the layer section
+    let psb = match data.get(4..6) {
+        Some([0, 1]) if data.starts_with(PSD_SIGNATURE) => false,
+        Some([0, 2]) if data.starts_with(PSD_SIGNATURE) => true,
+        _ => return Err(anyhow::anyhow!("Missing Photoshop header")),
+    };
+
+    // The color mode data (palettes and duotone settings) comes first
+    let color_end = HEADER_LEN + 4 + read_u32(data, HEADER_LEN).context("Truncated header")? as usize;
+    let length = read_u32(data, color_end).context("Truncated color mode data")? as usize;
+    let resources_start = color_end + 4;
+    let resources_end = resources_start + length;
+    let resources = data.get(resources_start..resources_end).context("Truncated image resources")?;
+
+    let mut kept = Vec::with_capacity(resources.len());
+    let mut metadata = Vec::new();
+    let mut rest = resources;
+    while rest.len() >= 12 && rest.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([rest[4], rest[5]]);
+        // Pascal string name, padded so length byte plus name is even
+        let size_pos = 6 + ((rest[6] as usize + 2) & !1);
+        let size = read_u32(rest, size_pos).context("Truncated image resource")? as usize;
+        let start = size_pos + 4;
+        let end = start + size + (size & 1);
+        let payload = rest.get(start..start + size).context("Image resource past end of section")?;
+        let block = rest.get(..end).unwrap_or(rest);
+
+        match resource(id, payload, keep) {
+            Action::Copy => kept.extend_from_slice(block),
+            Action::Drop(items) => metadata.extend(items),
+            Action::Replace(payload, items) => {
+                kept.extend_from_slice(&block[..size_pos]);
+                kept.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+                kept.extend_from_slice(&payload);
+                if payload.len() % 2 == 1 {
+                    kept.push(0);
+                }
+                metadata.extend(items);
+            }
+        }
+        rest = &rest[block.len()..];
+    }
+    kept.extend_from_slice(rest);
+
+    let names = layer_names(data, resources_end, psb);
+    if !names.is_empty() {
+        let warning = format!("layer names were kept: {}", names.join(", "));
+        metadata.push(MetadataItem::with_category(Category::Notice, LAYERS_WARNING, warning));
+    }
+
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&data[..color_end]);
+    output.extend_from_slice(&(kept.len() as u32).to_be_bytes());
+    output.extend_from_slice(&kept);
+    output.extend_from_slice(&data[resources_end..]);
+    Ok((output, metadata))
+}
+
+fn resource(id: u16, payload: &[u8], keep: &[Category]) -> Action {
+    let size = || format!("{} bytes", payload.len());
+    match id {
+        // Removed in full regardless of `keep`, as in JPEGs
+        RESOURCE_IPTC => Action::Drop(extract_iptc_fields(payload)),
+        RESOURCE_XMP => Action::Drop(extract_xmp_fields(&String::from_utf8_lossy(payload))),
+        RESOURCE_CAPTION_DIGEST => Action::Drop(Vec::new()),
+        RESOURCE_EXIF | RESOURCE_EXIF_3 => {
+            let mut items = extract_exif_fields(payload);
+            if items.is_empty() {
+                items.push(MetadataItem::new("EXIF Data", size()));
+            }
+            items.retain(|item| !keep.contains(&item.category) || is_fingerprint(item));
+            match filter_exif(payload, keep) {
+                Some(kept) => Action::Replace(kept, items),
+                None => Action::Drop(items),
+            }
+        }
+        RESOURCE_THUMBNAIL | RESOURCE_THUMBNAIL_OLD => {
+            let kb = payload.len().div_ceil(1024);
+            kept_or_dropped(MetadataItem::new(format!("Photoshop thumbnail ({} KB)", kb), ""), keep)
+        }
+        RESOURCE_ICC_PROFILE => kept_or_dropped(MetadataItem::new("ICC Profile", size()), keep),
+        RESOURCE_VERSION_INFO => {
+            kept_or_dropped(MetadataItem::with_category(Category::Software, "Photoshop Version Info", size()), keep)
+        }
+        RESOURCE_CAPTION => kept_or_dropped(MetadataItem::new("Caption", pascal_string(payload)), keep),
+        RESOURCE_URL => {
+            let url = String::from_utf8_lossy(payload).trim_end_matches('\0').to_string();
+            kept_or_dropped(MetadataItem::with_category(Category::Other, "URL", url), keep)
+        }
+        RESOURCE_URL_LIST => kept_or_dropped(MetadataItem::with_category(Category::Other, "URL List", size()), keep),
+        RESOURCE_COPYRIGHT_FLAG if payload.first() == Some(&1) => {
+            kept_or_dropped(MetadataItem::new("Copyright Flag", "copyrighted work"), keep)
+        }
+        RESOURCE_PRINT_INFO | RESOURCE_PRINT_STYLE => {
+            kept_or_dropped(MetadataItem::with_category(Category::Device, "Print Settings", size()), keep)
+        }
+        _ => Action::Copy,
+    }
+}
+
+/// The names given to layers, leaving out the ones Photoshop makes up itself such as
+/// "Background" and "Layer 2", and the markers that close layer groups
+fn layer_names(data: &[u8], section: usize, psb: bool) -> Vec<String> {
+    let width = if psb { 8 } else { 4 };
+    // The layer and mask section starts with its length, then the layer info's
+    let count_pos = section + 2 * width;
+    let Some(count) = data.get(count_pos..count_pos + 2) else { return Vec::new() };
+    // Negative when the first alpha channel holds the merged transparency
+    let count = i16::from_be_bytes([count[0], count[1]]).unsigned_abs() as usize;
+
+    let mut names = Vec::new();
+    let mut pos = count_pos + 2;
+    for _ in 0..count {
+        // Bounds, then an id and a data length for each channel
+        let Some(channels) = data.get(pos + 16..pos + 18) else { break };
+        let channels = u16::from_be_bytes([channels[0], channels[1]]) as usize;
+        // Blend mode signature and key, opacity, clipping, flags and filler
+        let extra_pos = pos + 18 + channels * (2 + width) + 12;
+        let Some(extra_len) = read_u32(data, extra_pos) else { break };
+        let extra = extra_pos + 4;
+        pos = extra + extra_len as usize;
+
+        // Mask and blending ranges, each with its length, come before the name
+        let Some(mask_len) = read_u32(data, extra) else { break };
+        let ranges = extra + 4 + mask_len as usize;
+        let Some(ranges_len) = read_u32(data, ranges) else { break };
+        let Some(name) = data.get(ranges + 4 + ranges_len as usize..pos) else { break };
+        let name = pascal_string(name);
+        let generated = name == "Background"
+            || name.starts_with("</")
+            || name.strip_prefix("Layer ").is_some_and(|n| n.bytes().all(|b| b.is_ascii_digit()));
+        if !name.is_empty() && !generated && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn pascal_string(data: &[u8]) -> String {
+    let length = data.first().copied().unwrap_or(0) as usize;
+    let text = data.get(1..1 + length).unwrap_or_default();
+    String::from_utf8_lossy(text).trim().to_string()
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"8BIM".to_vec();
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn layer(name: &str) -> Vec<u8> {
+        let mut record = vec![0; 16];
+        record.extend_from_slice(&1u16.to_be_bytes());
+        record.extend_from_slice(&[0, 0, 0, 0, 0, 2]);
+        record.extend_from_slice(b"8BIMnorm\xFF\0\0\0");
+        // No mask or blending ranges, then the name padded to a multiple of 4
+        let mut extra = vec![0; 8];
+        extra.push(name.len() as u8);
+        extra.extend_from_slice(name.as_bytes());
+        extra.resize(8 + (name.len() + 4) / 4 * 4, 0);
+        record.extend_from_slice(&(extra.len() as u32).to_be_bytes());
+        record.extend(extra);
+        record
+    }
+
+    fn build_test_psd(resources: &[u8]) -> Vec<u8> {
+        let mut data = b"8BPS\0\x01\0\0\0\0\0\0\0\x01\0\0\0\x01\0\0\0\x01\0\x08\0\x03".to_vec();
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&(resources.len() as u32).to_be_bytes());
+        data.extend_from_slice(resources);
+
+        let mut layers = 2i16.to_be_bytes().to_vec();
+        layers.extend(layer("Background"));
+        layers.extend(layer("Draft for Acme"));
+        let mut section = (layers.len() as u32).to_be_bytes().to_vec();
+        section.extend(layers);
+        data.extend_from_slice(&(section.len() as u32).to_be_bytes());
+        data.extend(section);
+        // The merged image: raw, one gray pixel
+        data.extend_from_slice(&[0, 0, 0x80]);
+        data
+    }
+
+    #[test]
+    fn test_strip_psd_resources() {
+        let mut resources = resource(0x03ED, &[0; 16]);
+        resources.extend(resource(RESOURCE_XMP, b"<x:xmpmeta><xmp:CreatorTool>Adobe Photoshop</xmp:CreatorTool>"));
+        resources.extend(resource(RESOURCE_THUMBNAIL, &[0; 3000]));
+        resources.extend(resource(RESOURCE_ICC_PROFILE, &[0; 7]));
+        let data = build_test_psd(&resources);
+
+        let (cleaned, metadata) = strip_psd_resources(&data, &[Category::ColorProfile]).unwrap();
+        let text: Vec<String> = metadata.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            text,
+            vec![
+                "XMP Creator Tool: Adobe Photoshop",
+                "Photoshop thumbnail (3 KB)",
+                "Warning: layers: layer names were kept: Draft for Acme",
+            ]
+        );
+
+        // The resolution and color profile resources stay, as do the layers and image
+        let mut kept = resource(0x03ED, &[0; 16]);
+        kept.extend(resource(RESOURCE_ICC_PROFILE, &[0; 7]));
+        let expected = [&data[..30], &(kept.len() as u32).to_be_bytes(), &kept, &data[34 + resources.len()..]].concat();
+        assert_eq!(cleaned, expected);
+        assert!(strip_psd_resources(b"8BPS\0\x03", &[]).is_err());
+    }
+}
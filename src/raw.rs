@@ -0,0 +1,447 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::makernote;
+use crate::metadata::{Category, MetadataItem};
+
+// CR2, NEF, ARW and DNG are all TIFF containers. Re-encoding isn't an option
+// for sensor data, so identifying tags are removed by rewriting the IFD entry
+// tables in place. An IFD only ever shrinks, so no other offset in the file
+// has to move, and the values of dropped tags are zeroed so they can't be
+// recovered from the slack space. Multi-page TIFF documents are cleaned the
+// same way, which keeps every page and its compression.
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_MAKER_NOTE: u16 = 0x927C;
+const TAG_DNG_PRIVATE_DATA: u16 = 0xC634;
+
+const TYPE_ASCII: u16 = 2;
+
+// Tags removed from the main image IFDs
+const IMAGE_TAGS: &[(u16, &str)] = &[
+    (0x013B, "Artist"),
+    (0x013C, "Host Computer"),
+    (0x02BC, "XMP Packet"),
+    (0x8298, "Copyright"),
+    (0x83BB, "IPTC Data"),
+    (0xC62F, "Camera Serial Number"),
+    (0xC68B, "Original Raw File Name"),
+];
+
+// Tags removed from each page of a TIFF document, unless their category is kept
+const DOCUMENT_TAGS: &[(u16, &str)] = &[
+    (0x010D, "Document Name"),
+    (0x010E, "Image Description"),
+    (0x010F, "Camera Make"),
+    (0x0110, "Camera Model"),
+    (0x0112, "Orientation"),
+    (0x011D, "Page Name"),
+    (0x0131, "Software"),
+    (0x0132, "Date/Time"),
+    (0x013B, "Artist"),
+    (0x013C, "Host Computer"),
+    (0x02BC, "XMP Packet"),
+    (0x8298, "Copyright"),
+    (0x83BB, "IPTC Data"),
+    (0x8649, "Photoshop Image Resources"),
+    (0x8773, "ICC Profile"),
+    (0xC62F, "Camera Serial Number"),
+];
+
+// Removed in full whatever is kept, as in other formats
+const ALWAYS_REMOVED: &[u16] = &[0x02BC, 0x83BB, 0x8649, 0xC62F];
+
+// Tags removed from the Exif IFD
+const EXIF_TAGS: &[(u16, &str)] = &[
+    (0x9286, "User Comment"),
+    (0xA420, "Image Unique ID"),
+    (0xA430, "Camera Owner Name"),
+    (0xA431, "Body Serial Number"),
+    (0xA435, "Lens Serial Number"),
+];
+
+pub fn strip_raw_metadata(input_path: &Path, output_path: &Path, keep_maker_notes: bool) -> Result<Vec<MetadataItem>> {
+    let mut data = fs::read(input_path)
+        .with_context(|| format!("Failed to read RAW image: {}", input_path.display()))?;
+
+    let removed_metadata = strip_tiff_tags(&mut data, keep_maker_notes)
+        .with_context(|| format!("Failed to parse RAW image: {}", input_path.display()))?;
+
+    fs::write(output_path, data)
+        .with_context(|| format!("Failed to save RAW image: {}", output_path.display()))?;
+
+    Ok(removed_metadata)
+}
+
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    // Raw 12-byte entry, copied back verbatim for kept tags
+    bytes: [u8; 12],
+}
+
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    big_endian: bool,
+    keep_maker_notes: bool,
+    // Categories whose tags stay, for TIFF documents. Their Exif IFD is removed whole.
+    keep: Option<&'a [Category]>,
+    // Maker note layouts depend on the make, which comes before the Exif IFD
+    make: String,
+    visited: HashSet<usize>,
+    metadata: Vec<MetadataItem>,
+}
+
+fn strip_tiff_tags(data: &mut [u8], keep_maker_notes: bool) -> Result<Vec<MetadataItem>> {
+    let big_endian = tiff_byte_order(data).context("Not a TIFF-based RAW file")?;
+    let mut tiff = Tiff::new(data, big_endian, keep_maker_notes, None);
+
+    // Walk the chain of top-level IFDs (full image, thumbnail, ...)
+    let mut offset = tiff.read_u32(4)? as usize;
+    while offset != 0 && tiff.visited.insert(offset) {
+        offset = tiff.clean_ifd(offset, IMAGE_TAGS)? as usize;
+    }
+
+    Ok(tiff.metadata)
+}
+
+/// Removes identifying tags from every page of a TIFF document in place, leaving the
+/// image data, its compression and the order of the pages as they were. Tags in the
+/// `keep` categories stay, except for XMP, IPTC and Photoshop resources and serial
+/// numbers. Returns what was removed from each page.
+pub fn strip_tiff_pages(data: &mut [u8], keep: &[Category]) -> Result<Vec<Vec<MetadataItem>>> {
+    let big_endian = tiff_byte_order(data).context("Not a TIFF file")?;
+    let mut tiff = Tiff::new(data, big_endian, false, Some(keep));
+
+    let mut pages = Vec::new();
+    let mut offset = tiff.read_u32(4)? as usize;
+    while offset != 0 && tiff.visited.insert(offset) {
+        offset = tiff.clean_ifd(offset, DOCUMENT_TAGS)? as usize;
+        pages.push(std::mem::take(&mut tiff.metadata));
+    }
+    Ok(pages)
+}
+
+/// The number of pages in a TIFF document, or 0 if it isn't one
+pub fn count_tiff_pages(data: &[u8]) -> usize {
+    let Some(big_endian) = tiff_byte_order(data) else { return 0 };
+    let read_u16 = |pos: usize| -> Option<usize> {
+        let b = data.get(pos..pos + 2)?;
+        Some(if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) } as usize)
+    };
+    let read_u32 = |pos: usize| -> Option<usize> {
+        let b: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) } as usize)
+    };
+
+    let mut visited = HashSet::new();
+    let mut offset = read_u32(4).unwrap_or(0);
+    while offset != 0 && visited.insert(offset) {
+        let Some(count) = read_u16(offset) else { break };
+        offset = read_u32(offset + 2 + count * 12).unwrap_or(0);
+    }
+    visited.len()
+}
+
+fn tiff_byte_order(data: &[u8]) -> Option<bool> {
+    match data.get(0..4) {
+        Some([b'I', b'I', 42, 0]) => Some(false),
+        Some([b'M', b'M', 0, 42]) => Some(true),
+        _ => None,
+    }
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8], big_endian: bool, keep_maker_notes: bool, keep: Option<&'a [Category]>) -> Self {
+        Self {
+            data,
+            big_endian,
+            keep_maker_notes,
+            keep,
+            make: String::new(),
+            visited: HashSet::new(),
+            metadata: Vec::new(),
+        }
+    }
+
+    /// Removes the listed tags from the IFD at `offset` and returns the offset of the next IFD
+    fn clean_ifd(&mut self, offset: usize, removed_tags: &[(u16, &str)]) -> Result<u32> {
+        let entries = self.read_entries(offset)?;
+        let next_ifd = self.read_u32(offset + 2 + entries.len() * 12)?;
+        let mut kept = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            if let Some((_, name)) = removed_tags.iter().find(|(tag, _)| *tag == entry.tag) {
+                let item = MetadataItem::new(*name, self.describe_value(&entry));
+                let keep = self.keep.unwrap_or_default();
+                if !keep.contains(&item.category) || ALWAYS_REMOVED.contains(&entry.tag) {
+                    self.metadata.push(item);
+                    self.wipe_value(&entry)?;
+                    continue;
+                }
+            }
+
+            match entry.tag {
+                TAG_GPS_IFD => {
+                    let gps_offset = self.value_u32(&entry) as usize;
+                    if self.visited.insert(gps_offset) {
+                        let count = self.wipe_ifd(gps_offset)?;
+                        self.metadata.push(MetadataItem::new("GPS Data", format!("{} tags", count)));
+                    }
+                    continue;
+                }
+                TAG_MAKER_NOTE | TAG_DNG_PRIVATE_DATA if !self.keep_maker_notes => {
+                    self.metadata.push(MetadataItem::new("Maker Notes", format!("{} bytes", entry.count)));
+                    if entry.tag == TAG_MAKER_NOTE && value_size(&entry) > 4 {
+                        let offset = self.value_u32(&entry) as usize;
+                        let note = offset..offset + value_size(&entry);
+                        let fields = makernote::identifying_fields(self.data, self.big_endian, &self.make, note);
+                        self.metadata.extend(fields);
+                    }
+                    self.wipe_value(&entry)?;
+                    continue;
+                }
+                TAG_EXIF_IFD if self.keep.is_some() => {
+                    let exif_offset = self.value_u32(&entry) as usize;
+                    if self.visited.insert(exif_offset) {
+                        let count = self.wipe_ifd(exif_offset)?;
+                        self.metadata.push(MetadataItem::new("EXIF Data", format!("{} tags", count)));
+                    }
+                    continue;
+                }
+                TAG_EXIF_IFD => {
+                    let exif_offset = self.value_u32(&entry) as usize;
+                    if self.visited.insert(exif_offset) {
+                        self.clean_ifd(exif_offset, EXIF_TAGS)?;
+                    }
+                }
+                TAG_MAKE => self.make = self.describe_value(&entry),
+                _ => {}
+            }
+
+            kept.push(entry);
+        }
+
+        self.write_ifd(offset, &kept, next_ifd)?;
+        Ok(next_ifd)
+    }
+
+    /// Zeroes an IFD and all of its values, returning the number of tags it held
+    fn wipe_ifd(&mut self, offset: usize) -> Result<usize> {
+        let entries = self.read_entries(offset)?;
+        for entry in &entries {
+            self.wipe_value(entry)?;
+        }
+        self.write_ifd(offset, &[], 0)?;
+        Ok(entries.len())
+    }
+
+    fn read_entries(&self, offset: usize) -> Result<Vec<IfdEntry>> {
+        let count = self.read_u16(offset)? as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for index in 0..count {
+            let pos = offset + 2 + index * 12;
+            let bytes: [u8; 12] = self.data
+                .get(pos..pos + 12)
+                .context("IFD entry past end of file")?
+                .try_into()?;
+            entries.push(IfdEntry {
+                tag: self.read_u16(pos)?,
+                field_type: self.read_u16(pos + 2)?,
+                count: self.read_u32(pos + 4)?,
+                bytes,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Writes a (possibly shorter) entry table over the original, zeroing the leftover space
+    fn write_ifd(&mut self, offset: usize, entries: &[IfdEntry], next_ifd: u32) -> Result<()> {
+        let original_count = self.read_u16(offset)? as usize;
+        let end = offset + 2 + original_count * 12 + 4;
+        self.data.get(offset..end).context("IFD past end of file")?;
+
+        self.write_u16(offset, entries.len() as u16);
+        let mut pos = offset + 2;
+        for entry in entries {
+            self.data[pos..pos + 12].copy_from_slice(&entry.bytes);
+            pos += 12;
+        }
+        self.write_u32(pos, next_ifd);
+        self.data[pos + 4..end].fill(0);
+
+        Ok(())
+    }
+
+    fn wipe_value(&mut self, entry: &IfdEntry) -> Result<()> {
+        let size = value_size(entry);
+        if size > 4 {
+            let offset = self.value_u32(entry) as usize;
+            self.data
+                .get_mut(offset..offset + size)
+                .context("Tag value past end of file")?
+                .fill(0);
+        }
+        Ok(())
+    }
+
+    fn describe_value(&self, entry: &IfdEntry) -> String {
+        let size = value_size(entry);
+        if entry.field_type != TYPE_ASCII {
+            return format!("{} bytes", size);
+        }
+
+        let bytes = if size > 4 {
+            let offset = self.value_u32(entry) as usize;
+            self.data.get(offset..offset + size).unwrap_or(&[])
+        } else {
+            &entry.bytes[8..8 + size]
+        };
+        let text: String = bytes.iter().take_while(|&&b| b != 0).map(|&b| b as char).collect();
+        text.trim().to_string()
+    }
+
+    fn value_u32(&self, entry: &IfdEntry) -> u32 {
+        let b = &entry.bytes[8..12];
+        if self.big_endian {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        }
+    }
+
+    fn read_u16(&self, pos: usize) -> Result<u16> {
+        let b = self.data.get(pos..pos + 2).context("Unexpected end of TIFF data")?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    }
+
+    fn read_u32(&self, pos: usize) -> Result<u32> {
+        let b = self.data.get(pos..pos + 4).context("Unexpected end of TIFF data")?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        })
+    }
+
+    fn write_u16(&mut self, pos: usize, value: u16) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.data[pos..pos + 2].copy_from_slice(&bytes);
+    }
+
+    fn write_u32(&mut self, pos: usize, value: u32) {
+        let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        self.data[pos..pos + 4].copy_from_slice(&bytes);
+    }
+}
+
+fn value_size(entry: &IfdEntry) -> usize {
+    let unit = match entry.field_type {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    };
+    unit * entry.count as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    // Little-endian TIFF with IFD0 (Make, Artist, Exif and GPS pointers), an Exif
+    // IFD (BodySerialNumber, MakerNote) and a GPS IFD (GPSLatitudeRef)
+    fn build_test_raw() -> Vec<u8> {
+        let mut data = b"II\x2a\x00".to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+
+        let entry = |tag: u16, field_type: u16, count: u32, value: u32| {
+            let mut bytes = tag.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&field_type.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes
+        };
+
+        // IFD0 at 8: 4 entries -> ends at 8 + 2 + 48 + 4 = 62
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend(entry(0x010F, TYPE_ASCII, 4, u32::from_le_bytes(*b"Cam\0")));
+        data.extend(entry(0x013B, TYPE_ASCII, 9, 200));
+        data.extend(entry(TAG_EXIF_IFD, 4, 1, 100));
+        data.extend(entry(TAG_GPS_IFD, 4, 1, 150));
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.resize(100, 0);
+
+        // Exif IFD at 100: 2 entries -> ends at 130
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend(entry(0xA431, TYPE_ASCII, 7, 210));
+        data.extend(entry(TAG_MAKER_NOTE, 7, 8, 220));
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.resize(150, 0);
+
+        // GPS IFD at 150
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend(entry(0x0001, TYPE_ASCII, 2, u32::from_le_bytes(*b"N\0\0\0")));
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.resize(200, 0);
+
+        data.extend_from_slice(b"Jane Doe\0\0");
+        data.extend_from_slice(b"123456\0\0\0\0");
+        data.extend_from_slice(b"MAKERNOT");
+        data
+    }
+
+    #[test]
+    fn test_strip_raw_metadata() {
+        let input = Builder::new().suffix(".dng").tempfile().unwrap();
+        let output = Builder::new().suffix(".dng").tempfile().unwrap();
+        fs::write(input.path(), build_test_raw()).unwrap();
+
+        let removed = strip_raw_metadata(input.path(), output.path(), false).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(removed.contains(&"Artist: Jane Doe".to_string()));
+        assert!(removed.contains(&"Body Serial Number: 123456".to_string()));
+        assert!(removed.contains(&"Maker Notes: 8 bytes".to_string()));
+        assert!(removed.contains(&"GPS Data: 1 tags".to_string()));
+
+        let data = fs::read(output.path()).unwrap();
+        assert!(!data.windows(8).any(|w| w == b"Jane Doe"));
+        assert!(!data.windows(6).any(|w| w == b"123456"));
+        assert!(!data.windows(8).any(|w| w == b"MAKERNOT"));
+
+        // Make and the Exif pointer survive, in order
+        assert_eq!(u16::from_le_bytes([data[8], data[9]]), 2);
+        assert_eq!(u16::from_le_bytes([data[10], data[11]]), 0x010F);
+        assert_eq!(u16::from_le_bytes([data[22], data[23]]), TAG_EXIF_IFD);
+        assert_eq!(u16::from_le_bytes([data[100], data[101]]), 0);
+    }
+
+    #[test]
+    fn test_strip_raw_keeps_maker_notes() {
+        let mut data = build_test_raw();
+        let removed = strip_tiff_tags(&mut data, true).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        assert!(!removed.iter().any(|m| m.starts_with("Maker Notes")));
+        assert!(data.windows(8).any(|w| w == b"MAKERNOT"));
+        assert_eq!(u16::from_le_bytes([data[100], data[101]]), 1);
+    }
+
+    #[test]
+    fn test_strip_raw_rejects_non_tiff() {
+        let mut data = b"not a raw file".to_vec();
+        assert!(strip_tiff_tags(&mut data, false).is_err());
+    }
+}
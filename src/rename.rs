@@ -0,0 +1,159 @@
+// Output file names built from a template such as "{stem}_clean.{ext}", so cleaned
+// copies can sit next to their originals instead of in a separate directory.
+
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::cache::sha256_file;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    /// The file name without its extension
+    Stem,
+    /// The extension without the dot
+    Ext,
+    /// The whole file name
+    Name,
+    /// The first 8 hex digits of the original's SHA-256 hash
+    Hash,
+    /// The file's position in the run, starting at 1
+    Counter,
+    /// Today's date as YYYY-MM-DD
+    Date,
+}
+
+const PLACEHOLDERS: &[(&str, Part)] = &[
+    ("stem", Part::Stem),
+    ("ext", Part::Ext),
+    ("name", Part::Name),
+    ("hash", Part::Hash),
+    ("n", Part::Counter),
+    ("date", Part::Date),
+];
+
+/// A file name template with `{stem}`, `{ext}`, `{name}`, `{hash}`, `{n}` and
+/// `{date}` placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+impl NameTemplate {
+    /// The name for `path`, the `counter`th file of the run. Parts taken from the path
+    /// are copied as they are, so names that aren't valid UTF-8 survive.
+    pub fn render(&self, path: &Path, counter: usize) -> Result<OsString> {
+        let mut segments: Vec<OsString> = Vec::new();
+        for part in &self.parts {
+            let segment = match part {
+                Part::Text(text) => OsString::from(text),
+                Part::Stem => path.file_stem().unwrap_or_default().to_os_string(),
+                Part::Ext => path.extension().unwrap_or_default().to_os_string(),
+                Part::Name => path.file_name().unwrap_or_default().to_os_string(),
+                Part::Hash => {
+                    let hash = sha256_file(path).with_context(|| format!("Failed to hash {}", path.display()))?;
+                    OsString::from(&hash[..8])
+                }
+                Part::Counter => OsString::from(counter.to_string()),
+                Part::Date => OsString::from(chrono::Local::now().format("%Y-%m-%d").to_string()),
+            };
+            segments.push(segment);
+        }
+
+        // Files without an extension would otherwise end in a dot
+        while let Some(last) = segments.pop() {
+            match last.to_str().map(|text| text.trim_end_matches('.')) {
+                Some("") => continue,
+                Some(text) => segments.push(OsString::from(text)),
+                None => segments.push(last),
+            }
+            break;
+        }
+        if segments.is_empty() {
+            anyhow::bail!("The name template gives {} an empty name", path.display());
+        }
+        Ok(segments.into_iter().collect())
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['/', '\\']) {
+            anyhow::bail!("The name template '{}' must be a file name, not a path", s);
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .with_context(|| format!("Unclosed '{{' in name template '{}'", s))?;
+            let placeholder = &rest[start + 1..start + end];
+            let part = PLACEHOLDERS
+                .iter()
+                .find(|(name, _)| *name == placeholder)
+                .map(|(_, part)| part.clone())
+                .with_context(|| {
+                    format!(
+                        "Unknown placeholder '{{{}}}' in name template (expected stem, ext, name, hash, n or date)",
+                        placeholder
+                    )
+                })?;
+            parts.push(part);
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_render() {
+        let template: NameTemplate = "{stem}_clean.{ext}".parse().unwrap();
+        assert_eq!(template.render(Path::new("photos/beach.jpg"), 1).unwrap(), "beach_clean.jpg");
+        assert_eq!(template.render(Path::new("README"), 1).unwrap(), "README_clean");
+
+        let template: NameTemplate = "{n}-{name}".parse().unwrap();
+        assert_eq!(template.render(Path::new("a.tar.gz"), 7).unwrap(), "7-a.tar.gz");
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("empty.png");
+        fs::write(&path, b"").unwrap();
+        let template: NameTemplate = "{hash}.{ext}".parse().unwrap();
+        assert_eq!(template.render(&path, 1).unwrap(), "e3b0c442.png");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::PathBuf;
+
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(b"caf\xe9.jpg"));
+        let template: NameTemplate = "{stem}_clean.{ext}".parse().unwrap();
+        assert_eq!(template.render(&path, 1).unwrap().as_bytes(), b"caf\xe9_clean.jpg");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("{stem".parse::<NameTemplate>().is_err());
+        assert!("{size}.{ext}".parse::<NameTemplate>().is_err());
+        assert!("clean/{name}".parse::<NameTemplate>().is_err());
+    }
+}
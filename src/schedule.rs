@@ -0,0 +1,112 @@
+use log::warn;
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Ways to keep a long batch, such as a nightly run over a whole library, from crowding
+// out everything else on a shared machine. Priorities are lowered on each worker thread,
+// and the ffmpeg processes a worker starts inherit them from it.
+
+/// How much disk time workers get, as with `ionice`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// The lowest level of the normal, best-effort class
+    Low,
+    /// Disk time only when no other program wants it
+    Idle,
+}
+
+impl std::str::FromStr for IoPriority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(IoPriority::Low),
+            "idle" => Ok(IoPriority::Idle),
+            _ => Err(anyhow::anyhow!("Unknown I/O priority '{}' (expected low or idle)", s)),
+        }
+    }
+}
+
+/// Lowers the CPU priority of the calling thread to niceness `nice`, unless it's already
+/// lower, and its disk priority to `io`. CPU priorities are only set on Unix and disk
+/// priorities only on Linux; elsewhere they're left as they are.
+pub(crate) fn lower_priority(nice: Option<i32>, io: Option<IoPriority>) {
+    #[cfg(unix)]
+    if let Some(nice) = nice {
+        // On Linux this applies to the calling thread only, elsewhere to the process
+        // SAFETY: both calls only read and set the scheduling priority
+        unsafe {
+            let current = libc::getpriority(libc::PRIO_PROCESS, 0);
+            if nice > current && libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                warn!("failed to set niceness {}: {}", nice, std::io::Error::last_os_error());
+            }
+        }
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(io) = io {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        let value = match io {
+            IoPriority::Low => (2 << IOPRIO_CLASS_SHIFT) | 7,
+            IoPriority::Idle => 3 << IOPRIO_CLASS_SHIFT,
+        };
+        // SAFETY: ioprio_set takes plain integers, and who 0 is the calling thread
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) } != 0 {
+            warn!("failed to set I/O priority {:?}: {}", io, std::io::Error::last_os_error());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = nice;
+    #[cfg(not(target_os = "linux"))]
+    let _ = io;
+}
+
+/// Paces a batch to an average number of bytes per second, counting each file's size
+/// when it starts. Time spent idle isn't saved up for a burst later.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    bytes_per_second: u64,
+    /// When the next file may start
+    next: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self { bytes_per_second: bytes_per_second.max(1), next: Mutex::new(None) }
+    }
+
+    /// Waits for the turn of a file of `bytes`, and books the time it takes at the rate
+    pub(crate) fn wait(&self, bytes: u64) {
+        let start = {
+            let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+            let start = next.map_or_else(Instant::now, |next| next.max(Instant::now()));
+            *next = Some(start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64));
+            start
+        };
+        let now = Instant::now();
+        if start > now {
+            thread::sleep(start - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(1000);
+        let started = Instant::now();
+        throttle.wait(100);
+        assert!(started.elapsed() < Duration::from_millis(50));
+        throttle.wait(0);
+        throttle.wait(0);
+        // The first file's 100 bytes take 100ms at 1000 bytes per second
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        assert_eq!("Idle".parse::<IoPriority>().unwrap(), IoPriority::Idle);
+        assert!("high".parse::<IoPriority>().is_err());
+    }
+}
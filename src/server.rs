@@ -0,0 +1,313 @@
+// A minimal HTTP/1.1 server for `metastripper serve`. Each connection carries one
+// request with the file as its raw body; multipart forms and chunked uploads aren't
+// supported, which curl's --data-binary and most HTTP clients handle fine.
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use metastripper::{FileType, MetaStripper, Report};
+
+use crate::{file_json, FileInfo};
+
+/// The longest request or header line read, so a client can't grow one without end
+const MAX_LINE: u64 = 8 * 1024;
+/// The most headers read per request
+const MAX_HEADERS: usize = 100;
+/// How long a read or write may wait on the client before the connection is dropped
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Request {
+    method: String,
+    path: String,
+    /// The `type` query parameter, a format hint for content that isn't recognized
+    format: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    /// The JSON report sent along with a cleaned file
+    report: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: &Value) -> Self {
+        Self { status, content_type: "application/json", report: None, body: value.to_string().into_bytes() }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &json!({ "status": "failed", "error": message.into() }))
+    }
+}
+
+/// Serves requests until the process is stopped. A fixed pool of workers, one per
+/// core, takes connections in turn, so at most that many uploads are held in memory;
+/// the rest wait in the listen backlog until a worker is free.
+pub fn serve(stripper: MetaStripper, address: &str, max_upload: u64) -> Result<()> {
+    let listener = TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+    let stripper = Arc::new(stripper);
+
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..std::thread::available_parallelism().map_or(4, usize::from) {
+        let stripper = Arc::clone(&stripper);
+        let receiver = Arc::clone(&receiver);
+        std::thread::spawn(move || loop {
+            let next = receiver.lock().ok().and_then(|receiver| receiver.recv().ok());
+            let Some(stream) = next else { break };
+            handle_connection(&stripper, stream, max_upload);
+        });
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        sender.send(stream).context("The server's workers have stopped")?;
+    }
+
+    Ok(())
+}
+
+fn handle_connection(stripper: &MetaStripper, stream: TcpStream, max_upload: u64) {
+    if stream.set_read_timeout(Some(TIMEOUT)).is_err() || stream.set_write_timeout(Some(TIMEOUT)).is_err() {
+        return;
+    }
+    let Ok(mut writer) = stream.try_clone() else { return };
+    let mut reader = BufReader::new(stream);
+    let response = match read_request(&mut reader, &mut writer, max_upload) {
+        Ok(request) => route(stripper, request),
+        Err(response) => response,
+    };
+    let _ = write_response(&mut writer, &response);
+}
+
+fn route(stripper: &MetaStripper, request: Request) -> Response {
+    if request.path != "/strip" && request.path != "/inspect" {
+        return Response::error(404, format!("No such endpoint: {}", request.path));
+    }
+    if request.method != "POST" {
+        return Response::error(405, "Use POST with the file as the request body");
+    }
+
+    let format = request.format.as_deref();
+    if request.path == "/inspect" {
+        let result = stripper.inspect_stream(&request.body[..], format);
+        return match &result {
+            Ok(_) => Response::json(200, &report_json(&result)),
+            Err(_) => Response::json(422, &report_json(&result)),
+        };
+    }
+
+    let mut cleaned = Vec::new();
+    let result = stripper.strip_stream(&request.body[..], &mut cleaned, format);
+    match &result {
+        Ok(_) => Response {
+            status: 200,
+            content_type: "application/octet-stream",
+            report: Some(ascii_json(&report_json(&result))),
+            body: cleaned,
+        },
+        Err(_) => Response::json(422, &report_json(&result)),
+    }
+}
+
+fn report_json(result: &Result<Report>) -> Value {
+    let file = FileInfo {
+        path: PathBuf::from("-"),
+        file_type: result.as_ref().map(|report| report.file_type).unwrap_or(FileType::Unknown),
+        relative: PathBuf::from("-"),
+    };
+    file_json(&file, result, "ok")
+}
+
+/// Serializes JSON with non-ASCII characters escaped, so it fits in a header
+fn ascii_json(value: &Value) -> String {
+    let mut escaped = String::new();
+    for c in value.to_string().chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            let mut units = [0u16; 2];
+            for unit in c.encode_utf16(&mut units) {
+                escaped.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+/// Reads the request line, headers and body, or returns the error response to send
+fn read_request(reader: &mut impl BufRead, writer: &mut impl Write, max_upload: u64) -> Result<Request, Response> {
+    let bad_request = |_| Response::error(400, "Malformed request");
+
+    let mut line = String::new();
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "Malformed request"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let format = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("type="))
+        .filter(|format| !format.is_empty())
+        .map(str::to_string);
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut content_length = None;
+    let mut expect_continue = false;
+    for headers in 0.. {
+        if headers == MAX_HEADERS {
+            return Err(Response::error(431, "Too many headers"));
+        }
+        line.clear();
+        read_line(reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else { continue };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = Some(value.parse::<u64>().map_err(|_| Response::error(400, "Invalid Content-Length"))?),
+            "transfer-encoding" if !value.eq_ignore_ascii_case("identity") => {
+                return Err(Response::error(411, "Chunked uploads aren't supported; send a Content-Length"));
+            }
+            "expect" => expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            _ => {}
+        }
+    }
+
+    let content_length = content_length.unwrap_or(0);
+    if content_length > max_upload {
+        return Err(Response::error(413, format!("Uploads are limited to {} bytes", max_upload)));
+    }
+    if expect_continue {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").map_err(bad_request)?;
+    }
+
+    let mut body = Vec::with_capacity(content_length as usize);
+    reader.take(content_length).read_to_end(&mut body).map_err(bad_request)?;
+    if (body.len() as u64) < content_length {
+        return Err(Response::error(400, "The request body is shorter than its Content-Length"));
+    }
+
+    Ok(Request { method, path, format, body })
+}
+
+/// Reads one line of the request head into `line`, refusing lines over `MAX_LINE` bytes
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<(), Response> {
+    let read = reader.take(MAX_LINE + 1).read_line(line).map_err(|_| Response::error(400, "Malformed request"))?;
+    if read as u64 > MAX_LINE {
+        return Err(Response::error(431, format!("Request lines are limited to {} bytes", MAX_LINE)));
+    }
+    Ok(())
+}
+
+fn write_response(writer: &mut impl Write, response: &Response) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    };
+    write!(writer, "HTTP/1.1 {} {}\r\n", response.status, reason)?;
+    write!(writer, "Content-Type: {}\r\n", response.content_type)?;
+    write!(writer, "Content-Length: {}\r\n", response.body.len())?;
+    if let Some(report) = &response.report {
+        write!(writer, "X-Metastripper-Report: {}\r\n", report)?;
+    }
+    write!(writer, "Connection: close\r\n\r\n")?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Reads `request` with an upload limit of `max_upload`, returning the request or
+    /// the status of the error response, and what was written back before the response
+    fn read(request: &[u8], max_upload: u64) -> (Result<Request, u16>, Vec<u8>) {
+        let mut written = Vec::new();
+        let result = read_request(&mut Cursor::new(request), &mut written, max_upload);
+        (result.map_err(|response| response.status), written)
+    }
+
+    fn status(request: &[u8], max_upload: u64) -> u16 {
+        match read(request, max_upload).0 {
+            Ok(_) => 200,
+            Err(status) => status,
+        }
+    }
+
+    #[test]
+    fn test_read_request() {
+        let (request, written) = read(b"POST /strip?type=jpg&x=1 HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody", 4);
+        let Ok(request) = request else { panic!("request refused") };
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/strip"));
+        assert_eq!(request.format.as_deref(), Some("jpg"));
+        assert_eq!(request.body, b"body");
+        assert!(written.is_empty());
+
+        // Header names are case-insensitive
+        let head = b"POST /inspect HTTP/1.1\r\nexpect: 100-continue\r\ncontent-length: 2\r\n\r\n";
+        let (request, written) = read(&[&head[..], b"ab"].concat(), 2);
+        assert_eq!(request.ok().map(|request| request.body), Some(b"ab".to_vec()));
+        assert_eq!(written, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+        // A request without a length has no body
+        let (request, _) = read(b"GET /inspect?type= HTTP/1.1\n\n", 0);
+        let Ok(request) = request else { panic!("request refused") };
+        assert_eq!(request.format, None);
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn test_read_request_refusals() {
+        assert_eq!(status(b"", 10), 400);
+        assert_eq!(status(b"POST\r\n\r\n", 10), 400);
+        assert_eq!(status(b"POST /strip HTTP/1.1\r\nContent-Length: ten\r\n\r\n", 10), 400);
+        assert_eq!(status(b"POST /strip HTTP/1.1\r\nContent-Length: 5\r\n\r\nabc", 10), 400);
+        assert_eq!(status(b"POST /strip HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n", 10), 411);
+        assert_eq!(status(b"POST /strip HTTP/1.1\r\nTransfer-Encoding: identity\r\n\r\n", 10), 200);
+
+        // The body limit is checked before anything is read or a 100 Continue is sent
+        let (result, written) = read(b"POST /strip HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 11\r\n\r\n", 10);
+        assert_eq!(result.err(), Some(413));
+        assert!(written.is_empty());
+        assert_eq!(status(b"POST /strip HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123456789", 10), 200);
+
+        // Lines up to the cap are read, longer ones refused without reading on
+        let target = "a".repeat(MAX_LINE as usize - "POST / HTTP/1.1\r\n".len());
+        assert_eq!(status(format!("POST /{} HTTP/1.1\r\n\r\n", target).as_bytes(), 0), 200);
+        assert_eq!(status(format!("POST /{}a HTTP/1.1\r\n\r\n", target).as_bytes(), 0), 431);
+        let header = format!("POST / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_LINE as usize));
+        assert_eq!(status(header.as_bytes(), 0), 431);
+
+        let mut headers = "POST / HTTP/1.1\r\n".to_string();
+        headers.push_str(&"X-Header: 1\r\n".repeat(MAX_HEADERS));
+        assert_eq!(status(format!("{}\r\n", headers).as_bytes(), 0), 431);
+        let fewer = headers.replacen("X-Header: 1\r\n", "", 1);
+        assert_eq!(status(format!("{}\r\n", fewer).as_bytes(), 0), 200);
+    }
+}
@@ -0,0 +1,41 @@
+// Overwrites the contents of originals before they are deleted or replaced, so the
+// metadata they held doesn't linger in free disk space. This only reaches the blocks
+// the file system hands back: SSD wear leveling, journals, copy-on-write file systems
+// and snapshots can keep older copies that no user-space tool can get at.
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Overwrites the whole file with zeros and flushes it to disk, keeping its length
+pub fn overwrite(mut file: &File) -> io::Result<()> {
+    let zeros = vec![0u8; CHUNK_SIZE];
+    let mut remaining = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    while remaining > 0 {
+        let chunk = remaining.min(CHUNK_SIZE as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 100).map(|i| (i % 251) as u8 + 1).collect();
+        fs::write(&path, &data).unwrap();
+
+        overwrite(&File::options().write(true).open(&path).unwrap()).unwrap();
+        let overwritten = fs::read(&path).unwrap();
+        assert_eq!(overwritten.len(), data.len());
+        assert!(overwritten.iter().all(|&b| b == 0));
+    }
+}
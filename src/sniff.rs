@@ -0,0 +1,152 @@
+// Recognizes file formats by their magic bytes, so misnamed and extensionless
+// files are still handled as what they really are.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// How much of the file is read for sniffing. XML formats are recognized by their
+// root element, which may follow a prolog and comments.
+const SNIFF_LEN: usize = 4096;
+
+// Formats sharing a container, which the content alone can't tell apart. Within
+// a family the file name's extension is more specific, so it's trusted.
+const FAMILIES: &[&[&str]] = &[
+    &["zip", "docx", "xlsx", "pptx", "odt", "ods", "odp"],
+    &["doc", "xls", "ppt"],
+    &["tiff", "tif", "cr2", "nef", "arw", "dng"],
+    &["mp4", "mov", "m4a", "heic", "heif", "avif"],
+    &["mkv", "webm"],
+    &["jpg", "jpeg"],
+];
+
+/// The canonical extension of the format in `path`, judged by its content
+pub fn sniff_path(path: &Path) -> Option<&'static str> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path).ok()?.take(SNIFF_LEN as u64).read_to_end(&mut head).ok()?;
+    sniff(&head)
+}
+
+/// The canonical extension of the format that `head` (the start of a file) is in
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+
+    let format = if at(0, b"\xFF\xD8\xFF") {
+        "jpg"
+    } else if at(0, b"\x89PNG\r\n\x1a\n") {
+        "png"
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        "gif"
+    } else if at(0, b"II*\0") || at(0, b"MM\0*") {
+        "tiff"
+    } else if at(0, b"\x00\x00\x00\x0CjP  \r\n\x87\n") {
+        "jp2"
+    } else if at(0, b"\x00\x00\x00\x0CJXL \r\n\x87\n") || at(0, b"\xFF\x0A") {
+        "jxl"
+    } else if at(0, b"8BPS\0\x01") {
+        "psd"
+    } else if at(0, b"8BPS\0\x02") {
+        "psb"
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        "webp"
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        "wav"
+    } else if at(0, b"RIFF") && at(8, b"AVI ") {
+        "avi"
+    } else if at(4, b"ftyp") {
+        match head.get(8..12)? {
+            b"heic" | b"heix" | b"mif1" | b"msf1" => "heic",
+            b"avif" | b"avis" => "avif",
+            b"qt  " => "mov",
+            b"M4A " => "m4a",
+            _ => "mp4",
+        }
+    } else if at(0, b"\x1A\x45\xDF\xA3") {
+        // The EBML header names the document type
+        if head.windows(4).any(|w| w == b"webm") { "webm" } else { "mkv" }
+    } else if at(0, b"%PDF-") {
+        "pdf"
+    } else if at(0, b"fLaC") {
+        "flac"
+    } else if at(0, b"OggS") {
+        "ogg"
+    } else if at(0, b"ID3") || (head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE0 == 0xE0) {
+        // An ID3 tag, or an MPEG audio frame sync
+        "mp3"
+    } else if at(0, b"PK\x03\x04") || at(0, b"PK\x05\x06") {
+        "zip"
+    } else if at(0, b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") {
+        "doc"
+    } else if (at(0, b"\0\0\x01\0") || at(0, b"\0\0\x02\0")) && is_icon_directory(head) {
+        if head[2] == 1 { "ico" } else { "cur" }
+    } else if at(0, b"BM") && head.len() >= 14 {
+        "bmp"
+    } else {
+        let text = String::from_utf8_lossy(head);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if !text.starts_with('<') {
+            return None;
+        }
+        if text.contains("<svg") {
+            "svg"
+        } else if text.contains("<gpx") {
+            "gpx"
+        } else if text.contains("<kml") {
+            "kml"
+        } else {
+            return None;
+        }
+    };
+    Some(format)
+}
+
+// Four bytes are a weak signature, so the first directory entry has to make sense too:
+// a zero reserved byte and an image that starts after the directory
+fn is_icon_directory(head: &[u8]) -> bool {
+    let count = u16::from_le_bytes([head.get(4).copied().unwrap_or(0), head.get(5).copied().unwrap_or(0)]) as u32;
+    let Some(entry) = head.get(6..22) else { return false };
+    let offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+    count > 0 && entry[3] == 0 && offset >= 6 + 16 * count
+}
+
+/// Whether an extension belongs to the same container family as a sniffed format
+pub fn same_family(extension: &str, sniffed: &str) -> bool {
+    extension == sniffed
+        || FAMILIES
+            .iter()
+            .any(|family| family.contains(&extension) && family.contains(&sniffed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\xFF\xD8\xFF\xE0\0\x10JFIF"), Some("jpg"));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some("png"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8L"), Some("webp"));
+        assert_eq!(sniff(b"\0\0\0\x18ftypheic\0\0\0\0"), Some("heic"));
+        assert_eq!(sniff(b"\0\0\0\x18ftypisom\0\0\0\0"), Some("mp4"));
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some("pdf"));
+        assert_eq!(sniff(b"\0\0\0\x0CjP  \r\n\x87\n\0\0\0\x14ftypjp2 "), Some("jp2"));
+        assert_eq!(sniff(b"\xFF\x0A\xFA\x1F"), Some("jxl"));
+        let icon = b"\0\0\x01\0\x01\0\x10\x10\0\0\x01\0\x20\0\x10\0\0\0\x16\0\0\0";
+        assert_eq!(sniff(icon), Some("ico"));
+        assert_eq!(sniff(b"8BPS\0\x02\0\0"), Some("psb"));
+        assert_eq!(sniff(b"\0\0\x01\0\0\0"), None);
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Some("svg"));
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?><gpx version=\"1.1\">"), Some("gpx"));
+        assert_eq!(sniff(b"hello world"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_same_family() {
+        assert!(same_family("docx", "zip"));
+        assert!(same_family("nef", "tiff"));
+        assert!(same_family("png", "png"));
+        assert!(!same_family("png", "jpg"));
+        assert!(!same_family("zip", "doc"));
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::metadata::MetadataItem;
+use crate::xmp::find_element_value;
+
+// Namespace prefixes used by drawing editors for their own bookkeeping. None of
+// them affect rendering, but they record file paths, window geometry and tool
+// versions.
+const EDITOR_PREFIXES: &[&str] = &["inkscape", "sodipodi", "sketch", "serif", "i", "x"];
+
+// Namespaces only needed by the metadata block we remove
+const METADATA_PREFIXES: &[&str] = &["rdf", "dc", "cc"];
+
+const METADATA_FIELDS: &[(&str, &str)] = &[
+    ("dc:title", "Title"),
+    ("dc:creator", "Creator"),
+    ("dc:publisher", "Publisher"),
+    ("dc:date", "Date"),
+    ("dc:rights", "Rights"),
+    ("dc:description", "Description"),
+];
+
+pub fn strip_svg_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<MetadataItem>> {
+    let data = fs::read(input_path)
+        .with_context(|| format!("Failed to read SVG: {}", input_path.display()))?;
+    let text = String::from_utf8_lossy(&data);
+
+    let (cleaned, removed_metadata) = clean_svg(&text)
+        .with_context(|| format!("Failed to parse SVG: {}", input_path.display()))?;
+
+    fs::write(output_path, cleaned)
+        .with_context(|| format!("Failed to save SVG: {}", output_path.display()))?;
+
+    Ok(removed_metadata)
+}
+
+fn clean_svg(text: &str) -> Result<(Vec<u8>, Vec<MetadataItem>)> {
+    let mut reader = Reader::from_str(text);
+    let mut writer = Writer::new(Vec::with_capacity(text.len()));
+    let mut metadata = Vec::new();
+    let mut removed_attributes: BTreeMap<String, usize> = BTreeMap::new();
+
+    // Depth inside an element we're dropping, and where its source text began
+    let mut skip_depth = 0usize;
+    let mut skip_start = 0usize;
+
+    loop {
+        let position = reader.buffer_position();
+        let event = reader.read_event()?;
+
+        if skip_depth > 0 {
+            match event {
+                Event::Start(_) => skip_depth += 1,
+                Event::End(_) => {
+                    skip_depth -= 1;
+                    if skip_depth == 0 {
+                        let block = &text[skip_start..reader.buffer_position()];
+                        metadata.extend(describe_metadata_block(block));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(ref e) if is_removed_element(e) => {
+                skip_depth = 1;
+                skip_start = position;
+            }
+            Event::Empty(ref e) if is_removed_element(e) => {}
+            Event::Start(e) => {
+                let cleaned = clean_attributes(&e, &mut metadata, &mut removed_attributes)?;
+                writer.write_event(Event::Start(cleaned))?;
+            }
+            Event::Empty(e) => {
+                let cleaned = clean_attributes(&e, &mut metadata, &mut removed_attributes)?;
+                writer.write_event(Event::Empty(cleaned))?;
+            }
+            Event::Comment(comment) => {
+                let comment = String::from_utf8_lossy(&comment).trim().to_string();
+                if !comment.is_empty() {
+                    metadata.push(MetadataItem::new("Comment", comment));
+                }
+            }
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+
+    for (prefix, count) in removed_attributes {
+        metadata.push(MetadataItem::new(format!("Editor Attributes ({})", prefix), count.to_string()));
+    }
+
+    Ok((writer.into_inner(), metadata))
+}
+
+fn is_removed_element(element: &BytesStart) -> bool {
+    let name = element.name();
+    let name = name.as_ref();
+    if name == b"metadata" || name == b"rdf:RDF" {
+        return true;
+    }
+    prefix_of(name).map(|p| EDITOR_PREFIXES.contains(&p)).unwrap_or(false)
+}
+
+fn clean_attributes(
+    element: &BytesStart,
+    metadata: &mut Vec<MetadataItem>,
+    removed_attributes: &mut BTreeMap<String, usize>,
+) -> Result<BytesStart<'static>> {
+    let mut cleaned = element.to_owned();
+    cleaned.clear_attributes();
+
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = attribute.key.as_ref();
+
+        // Namespace declarations for editor and metadata prefixes
+        if let Some(declared) = key.strip_prefix(b"xmlns:") {
+            let declared = String::from_utf8_lossy(declared);
+            if EDITOR_PREFIXES.contains(&declared.as_ref()) || METADATA_PREFIXES.contains(&declared.as_ref()) {
+                continue;
+            }
+        }
+
+        if let Some(prefix) = prefix_of(key).filter(|p| EDITOR_PREFIXES.contains(p)) {
+            let value = attribute.unescape_value().unwrap_or_default();
+            // These two regularly contain absolute paths with user names
+            match key {
+                b"inkscape:export-filename" => metadata.push(MetadataItem::new("Export Filename", value)),
+                b"sodipodi:docname" => metadata.push(MetadataItem::new("Document Name", value)),
+                _ => {}
+            }
+            *removed_attributes.entry(prefix.to_string()).or_insert(0) += 1;
+            continue;
+        }
+
+        cleaned.push_attribute(attribute);
+    }
+
+    Ok(cleaned)
+}
+
+fn prefix_of(name: &[u8]) -> Option<&str> {
+    let colon = name.iter().position(|&b| b == b':')?;
+    std::str::from_utf8(&name[..colon]).ok()
+}
+
+fn describe_metadata_block(block: &str) -> Vec<MetadataItem> {
+    let fields: Vec<MetadataItem> = METADATA_FIELDS
+        .iter()
+        .filter_map(|(tag, display_name)| {
+            find_element_value(block, tag)
+                .filter(|value| !value.is_empty())
+                .map(|value| MetadataItem::new(*display_name, value))
+        })
+        .collect();
+
+    if fields.is_empty() {
+        vec![MetadataItem::new("SVG Metadata Block", format!("{} bytes", block.len()))]
+    } else {
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_svg() {
+        let svg = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- Created with Inkscape (http://www.inkscape.org/) -->
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" xmlns:sodipodi="http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" width="10" height="10" sodipodi:docname="drawing.svg" inkscape:export-filename="/home/jane/drawing.png">
+  <sodipodi:namedview id="namedview1" inkscape:zoom="2"/>
+  <metadata><rdf:RDF><rdf:Description><dc:creator>Jane Doe</dc:creator></rdf:Description></rdf:RDF></metadata>
+  <rect x="0" y="0" width="10" height="10" inkscape:label="Layer 1" fill="red"/>
+</svg>"#;
+
+        let (cleaned, removed) = clean_svg(svg).unwrap();
+        let removed: Vec<String> = removed.iter().map(ToString::to_string).collect();
+        let cleaned = String::from_utf8(cleaned).unwrap();
+
+        assert!(removed.contains(&"Comment: Created with Inkscape (http://www.inkscape.org/)".to_string()));
+        assert!(removed.contains(&"Creator: Jane Doe".to_string()));
+        assert!(removed.contains(&"Export Filename: /home/jane/drawing.png".to_string()));
+        assert!(removed.contains(&"Document Name: drawing.svg".to_string()));
+        assert!(removed.contains(&"Editor Attributes (inkscape): 2".to_string()));
+
+        assert!(!cleaned.contains("inkscape"));
+        assert!(!cleaned.contains("sodipodi"));
+        assert!(!cleaned.contains("metadata"));
+        assert!(!cleaned.contains("<!--"));
+        assert!(cleaned.contains(r#"<rect x="0" y="0" width="10" height="10" fill="red"/>"#));
+        assert!(cleaned.contains(r#"xmlns="http://www.w3.org/2000/svg""#));
+    }
+
+    #[test]
+    fn test_clean_svg_rejects_malformed_xml() {
+        assert!(clean_svg("<svg><g></svg>").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::hash_map::RandomState;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+// Every file is written under a temporary name first and renamed once it's complete, so
+// an interruption never leaves a half-written file under the real name. Each name is
+// handed to the observer, if there is one, before the file is created, so a journal can
+// record exactly which files a run that gets interrupted leaves behind. Names carry a
+// random part, so they can't be guessed and taken ahead of time in a shared directory.
+
+// The longest file name most file systems allow, in bytes
+const MAX_NAME_LEN: usize = 255;
+
+type Observer = Box<dyn Fn(&Path) + Send + Sync>;
+
+static OBSERVER: RwLock<Option<Observer>> = RwLock::new(None);
+
+/// Has `observer` called with the path of every temporary file before it's created, or
+/// stops calling the current one
+pub(crate) fn observe(observer: Option<Observer>) {
+    *OBSERVER.write().unwrap_or_else(PoisonError::into_inner) = observer;
+}
+
+fn announce(path: &Path) {
+    if let Some(observer) = OBSERVER.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+        observer(path);
+    }
+}
+
+/// A unique name ending in `file_name`, so its extension still tells the format. A name
+/// that would get too long keeps just the extension, and one that's already temporary
+/// gets its own part replaced rather than a second one added.
+fn unique_name(prefix: &str, file_name: &OsStr) -> OsString {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let file_name = original_name(file_name);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    // RandomState is seeded by the OS, which is all the randomness a name needs
+    let token = RandomState::new().build_hasher().finish() as u32;
+    let mut name = OsString::from(format!("{}metastripper-{}-{}-{:08x}-", prefix, std::process::id(), id, token));
+    if name.len() + file_name.len() <= MAX_NAME_LEN {
+        name.push(file_name);
+    } else if let Some(extension) = Path::new(file_name).extension() {
+        name.push("long.");
+        name.push(extension);
+    }
+    name
+}
+
+/// `file_name` without the part `unique_name` put in front of it, if it has one
+fn original_name(file_name: &OsStr) -> &OsStr {
+    let bytes = file_name.as_encoded_bytes();
+    let Some(mut rest) = bytes.strip_prefix(b".").unwrap_or(bytes).strip_prefix(b"metastripper-") else {
+        return file_name;
+    };
+    // The process id, the counter and the random token, each followed by a dash
+    for _ in 0..3 {
+        match rest.iter().position(|&byte| byte == b'-') {
+            Some(dash) => rest = &rest[dash + 1..],
+            None => return file_name,
+        }
+    }
+    // SAFETY: `rest` is the end of `file_name`, split off right after an ASCII dash
+    unsafe { OsStr::from_encoded_bytes_unchecked(rest) }
+}
+
+/// A hidden sibling of `path`. Being on the same file system, it can be renamed over
+/// `path` atomically.
+pub(crate) fn sibling(path: &Path) -> PathBuf {
+    let sibling = path.with_file_name(unique_name(".", path.file_name().unwrap_or_default()));
+    announce(&sibling);
+    sibling
+}
+
+/// A new, empty file in `dir`, or the system's temporary directory without one, whose
+/// name ends in `file_name`. It's created exclusively and only its owner can read it, so
+/// nobody sharing the directory can read it or have a link waiting under its name.
+pub(crate) fn scratch(dir: Option<&Path>, file_name: impl AsRef<OsStr>) -> io::Result<PathBuf> {
+    let dir = dir.map_or_else(std::env::temp_dir, Path::to_path_buf);
+    let name = unique_name("", file_name.as_ref());
+    announce(&dir.join(&name));
+    let file = tempfile::Builder::new().prefix(&name).rand_bytes(0).tempfile_in(dir)?;
+    file.into_temp_path().keep().map_err(|e| e.error)
+}
+
+/// Moves a finished temporary file to `to`. A file on another file system is copied
+/// next to `to` first, so `to` still appears all at once.
+pub(crate) fn persist(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            let staged = sibling(to);
+            let result = fs::copy(from, &staged)
+                .and_then(|_| copy_owner(from, &staged))
+                .and_then(|_| fs::rename(&staged, to));
+            if result.is_err() {
+                let _ = fs::remove_file(&staged);
+            }
+            result?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Gives `to` the owner of `from`, which a copy doesn't keep, where that's allowed. The
+/// permissions are set again afterwards, since changing the owner can clear setuid bits.
+fn copy_owner(from: &Path, to: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = fs::metadata(from)?;
+        // Without root this fails, and the copy stays with the current user
+        let _ = std::os::unix::fs::chown(to, Some(metadata.uid()), Some(metadata.gid()));
+        fs::set_permissions(to, metadata.permissions())?;
+    }
+    #[cfg(not(unix))]
+    let _ = (from, to);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_temp_names() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("photo.jpg");
+        let (first, second) = (sibling(&output), sibling(&output));
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(dir.path()));
+        let name = first.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with(&format!(".metastripper-{}-", std::process::id())));
+        assert!(name.ends_with("-photo.jpg"));
+        // A temporary name's sibling replaces its temporary part
+        let nested = sibling(&first).file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(nested.matches("metastripper-").count(), 1);
+        assert!(nested.ends_with("-photo.jpg"));
+        assert_eq!(original_name(OsStr::new(".metastripper-1-2-photo.jpg")), ".metastripper-1-2-photo.jpg");
+
+        let scratch_path = scratch(Some(dir.path()), "stdin.png").unwrap();
+        assert_eq!(scratch_path.parent(), Some(dir.path()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&scratch_path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        let default_scratch = scratch(None, "stdin.png").unwrap();
+        assert!(default_scratch.starts_with(std::env::temp_dir()));
+        fs::remove_file(default_scratch).unwrap();
+
+        fs::write(&scratch_path, b"cleaned").unwrap();
+        persist(&scratch_path, &output).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"cleaned");
+        assert!(!scratch_path.exists());
+    }
+}
@@ -0,0 +1,215 @@
+// The terminal UI of `metastripper browse`: every file found, expandable into the
+// metadata it holds, with what gets removed marked and changeable before anything is
+// cleaned. Choices are made per category, as that's what cleaning works by, so
+// marking one GPS tag in a file marks all of that file's GPS tags.
+
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use metastripper::{Category, MetadataItem};
+
+const HELP: &str = "↑↓ move  → open  ← close  space mark  a all files  r clean  q quit";
+
+/// A file as found, before the browser opens
+pub struct Listing {
+    pub path: PathBuf,
+    /// The metadata it holds, or why it couldn't be read
+    pub items: Result<Vec<MetadataItem>, String>,
+}
+
+/// A file picked for cleaning and the categories to leave in it
+pub struct Selection {
+    pub path: PathBuf,
+    pub keep: Vec<Category>,
+}
+
+struct FileState {
+    listing: Listing,
+    selected: bool,
+    expanded: bool,
+    /// Categories to remove from this file, whether it holds any of them or not
+    removed: BTreeSet<Category>,
+}
+
+/// One line of the list: a file, or an item of an expanded file
+#[derive(Clone, Copy)]
+enum Row {
+    File(usize),
+    Item(usize, usize),
+}
+
+struct Browser {
+    files: Vec<FileState>,
+    list: ListState,
+}
+
+/// Shows `listings` until the user starts cleaning, giving the files picked, or quits,
+/// giving `None`. Items start out marked for removal where `removes` says so.
+pub fn browse(listings: Vec<Listing>, removes: impl Fn(Category) -> bool) -> Result<Option<Vec<Selection>>> {
+    let removed: BTreeSet<Category> = Category::ALL.iter().copied().filter(|&category| removes(category)).collect();
+    let files = listings
+        .into_iter()
+        .map(|listing| FileState {
+            selected: listing.items.is_ok(),
+            listing,
+            expanded: false,
+            removed: removed.clone(),
+        })
+        .collect();
+    let mut browser = Browser { files, list: ListState::default().with_selected(Some(0)) };
+
+    let mut terminal = ratatui::try_init().context("Failed to start the terminal UI")?;
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    let run = result.context("Terminal UI failed")?;
+
+    Ok(run.then(|| {
+        browser
+            .files
+            .into_iter()
+            .filter(|file| file.selected)
+            .map(|file| Selection {
+                path: file.listing.path,
+                keep: Category::ALL.iter().copied().filter(|category| !file.removed.contains(category)).collect(),
+            })
+            .collect()
+    }))
+}
+
+impl Browser {
+    /// Handles keys until the user cleans, giving `true`, or quits
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let rows = self.rows();
+            let current = self.list.selected().and_then(|index| rows.get(index).copied());
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                KeyCode::Char('r') => return Ok(true),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::PageDown => self.list.scroll_down_by(10),
+                KeyCode::PageUp => self.list.scroll_up_by(10),
+                KeyCode::Home => self.list.select_first(),
+                KeyCode::End => self.list.select_last(),
+                KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => {
+                    if let Some(Row::File(file)) = current {
+                        self.files[file].expanded = true;
+                    }
+                }
+                KeyCode::Left | KeyCode::Char('h') => {
+                    if let Some(Row::File(file) | Row::Item(file, _)) = current {
+                        self.files[file].expanded = false;
+                        let row = self.rows().iter().position(|row| matches!(row, Row::File(f) if *f == file));
+                        self.list.select(row);
+                    }
+                }
+                KeyCode::Char(' ') => match current {
+                    Some(Row::File(file)) if self.files[file].listing.items.is_ok() => {
+                        self.files[file].selected ^= true;
+                    }
+                    Some(Row::Item(file, item)) => {
+                        let file = &mut self.files[file];
+                        let category = file.items()[item].category;
+                        if !file.removed.remove(&category) {
+                            file.removed.insert(category);
+                        }
+                    }
+                    _ => {}
+                },
+                KeyCode::Char('a') => {
+                    let select = !self.files.iter().all(|file| file.selected || file.listing.items.is_err());
+                    for file in self.files.iter_mut().filter(|file| file.listing.items.is_ok()) {
+                        file.selected = select;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (index, file) in self.files.iter().enumerate() {
+            rows.push(Row::File(index));
+            if file.expanded {
+                rows.extend((0..file.items().len()).map(|item| Row::Item(index, item)));
+            }
+        }
+        rows
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, help_area] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let selected = self.files.iter().filter(|file| file.selected).count();
+        let title = format!(" MetaStripper: {} files, {} to clean ", self.files.len(), selected);
+
+        let lines: Vec<ListItem> = self.rows().into_iter().map(|row| ListItem::new(self.line(row))).collect();
+        let list = List::new(lines)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+        frame.render_widget(Paragraph::new(HELP).dim(), help_area);
+    }
+
+    fn line(&self, row: Row) -> Line<'static> {
+        match row {
+            Row::File(index) => {
+                let file = &self.files[index];
+                let arrow = if file.expanded { "▾" } else { "▸" };
+                let summary = match &file.listing.items {
+                    Ok(items) => {
+                        let removed = items.iter().filter(|item| file.removed.contains(&item.category)).count();
+                        Span::raw(format!("  {} items, {} to remove", items.len(), removed)).dim()
+                    }
+                    Err(error) => Span::raw(format!("  {}", error)).fg(Color::Red),
+                };
+                Line::from(vec![
+                    Span::raw(format!("{} {} ", arrow, checkbox(file.selected))),
+                    Span::raw(file.listing.path.display().to_string()).bold(),
+                    summary,
+                ])
+            }
+            Row::Item(index, item) => {
+                let file = &self.files[index];
+                let item = &file.items()[item];
+                let removed = file.removed.contains(&item.category);
+                let line = Line::from(vec![
+                    Span::raw(format!("    {} ", checkbox(removed))),
+                    Span::raw(format!("{}: {}", item.tag, item.value.replace(['\n', '\r'], " "))),
+                    Span::raw(format!("  ({})", item.category)).dim(),
+                ]);
+                if removed {
+                    line
+                } else {
+                    line.fg(Color::Green)
+                }
+            }
+        }
+    }
+}
+
+impl FileState {
+    fn items(&self) -> &[MetadataItem] {
+        self.listing.items.as_deref().unwrap_or_default()
+    }
+}
+
+fn checkbox(checked: bool) -> &'static str {
+    if checked {
+        "[x]"
+    } else {
+        "[ ]"
+    }
+}
@@ -1,43 +1,515 @@
 use anyhow::{Context, Result};
-use std::path::Path;
-use std::process::Command;
+use log::{debug, info};
+use std::borrow::Cow;
+use std::fmt;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-pub fn strip_video_metadata(input_path: &Path, output_path: &Path) -> Result<Vec<String>> {
-    // Check if ffmpeg is installed
-    if !is_ffmpeg_installed() {
-        return Err(anyhow::anyhow!("ffmpeg is not installed. Please install ffmpeg to process video files."));
+use crate::assign::{self, Assignment, SetField};
+use crate::matroska;
+use crate::metadata::{Category, MetadataItem};
+use crate::mp4;
+use crate::paths;
+use crate::temp;
+
+/// Which ffmpeg and ffprobe binaries to run, plus extra arguments for ffmpeg
+#[derive(Debug, Clone)]
+pub struct FfmpegConfig {
+    pub ffmpeg: PathBuf,
+    pub ffprobe: PathBuf,
+    /// Passed to ffmpeg ahead of the input, e.g. hardware acceleration flags
+    pub extra_args: Vec<String>,
+    /// Leave the ffmpeg version and the current time out of written files, so the
+    /// same input always gives the same bytes
+    pub bitexact: bool,
+    /// How long ffmpeg or ffprobe may run on one file before it's stopped, so a
+    /// corrupt file can't stall a whole batch
+    pub timeout: Option<Duration>,
+    /// When the file being processed has to be done by, set by `for_file`, so the
+    /// timeout covers every ffmpeg and ffprobe run on the file together
+    deadline: Option<Instant>,
+    /// Whether ffmpeg could be run, once checked, so batches and the daemon don't run
+    /// `ffmpeg -version` for every file. Shared with the copies made for each file.
+    installed: Arc<OnceLock<bool>>,
+}
+
+impl Default for FfmpegConfig {
+    fn default() -> Self {
+        Self {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            ffprobe: PathBuf::from("ffprobe"),
+            extra_args: Vec::new(),
+            bitexact: false,
+            timeout: None,
+            deadline: None,
+            installed: Arc::default(),
+        }
+    }
+}
+
+impl FfmpegConfig {
+    /// Uses the ffmpeg at `path` and the ffprobe next to it
+    pub fn with_ffmpeg(path: impl Into<PathBuf>) -> Self {
+        let ffmpeg = path.into();
+        // Keep any extension, so ffmpeg.exe pairs with ffprobe.exe
+        let ffprobe_name = match ffmpeg.extension().and_then(|e| e.to_str()) {
+            Some(extension) => format!("ffprobe.{}", extension),
+            None => "ffprobe".to_string(),
+        };
+        let ffprobe = ffmpeg.with_file_name(ffprobe_name);
+        Self { ffmpeg, ffprobe, ..Self::default() }
+    }
+
+    /// Like `default`, but if ffmpeg isn't on `PATH`, uses one from a common install
+    /// location such as `C:\ffmpeg\bin`, a package manager's directory, or Homebrew's,
+    /// which programs started from a GUI often don't have on their `PATH`
+    pub fn discover() -> Self {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        if find_program("ffmpeg", std::env::split_paths(&path)).is_some() {
+            return Self::default();
+        }
+        match find_program("ffmpeg", common_install_dirs()) {
+            Some(ffmpeg) => Self::with_ffmpeg(ffmpeg),
+            None => Self::default(),
+        }
+    }
+
+    pub fn is_installed(&self) -> bool {
+        Command::new(&self.ffmpeg)
+            .arg("-version")
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false)
+    }
+
+    /// An ffmpeg command with the extra arguments already added
+    pub(crate) fn ffmpeg_command(&self) -> Command {
+        let mut command = Command::new(&self.ffmpeg);
+        command.args(&self.extra_args);
+        command
+    }
+
+    /// Options for the file ffmpeg writes, which go after the input
+    pub(crate) fn output_args(&self) -> &'static [&'static str] {
+        if self.bitexact {
+            &["-fflags", "+bitexact"]
+        } else {
+            &[]
+        }
+    }
+
+    fn ffprobe_command(&self) -> Command {
+        Command::new(&self.ffprobe)
+    }
+
+    /// These settings for processing one file, with the timeout starting now and
+    /// shared by everything run on the file
+    pub(crate) fn for_file(&self) -> Cow<'_, Self> {
+        match self.timeout {
+            Some(timeout) => Cow::Owned(Self { deadline: Some(Instant::now() + timeout), ..self.clone() }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Runs `command` to completion like `Command::output`, killing it once the file's
+    /// deadline passes, or once it has run longer than the timeout outside of `for_file`.
+    /// Its output is read on other threads meanwhile, so a full pipe can't block it.
+    pub(crate) fn run(&self, command: &mut Command) -> io::Result<Output> {
+        debug!("running {:?}", command);
+        let Some(timeout) = self.timeout else { return command.output() };
+        let deadline = self.deadline.unwrap_or_else(|| Instant::now() + timeout);
+        let timed_out = || {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {} seconds and was stopped", timeout.as_secs_f64()),
+            )
+        };
+        if Instant::now() >= deadline {
+            return Err(timed_out());
+        }
+        let mut child = command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(timed_out());
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        };
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+
+    /// Fails with an install hint if ffmpeg can't be run
+    pub(crate) fn require(&self, purpose: &str) -> Result<()> {
+        if *self.installed.get_or_init(|| self.is_installed()) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "ffmpeg is not installed (tried '{}'). Please install ffmpeg to process {}.",
+                self.ffmpeg.display(),
+                purpose
+            ))
+        }
+    }
+}
+
+/// The first of `dirs` holding the program `name`, with `.exe` added on Windows
+fn find_program(name: &str, dirs: impl IntoIterator<Item = PathBuf>) -> Option<PathBuf> {
+    let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    dirs.into_iter().map(|dir| dir.join(&file_name)).find(|path| path.is_file())
+}
+
+/// Where installers and package managers put ffmpeg outside the usual `PATH`
+fn common_install_dirs() -> Vec<PathBuf> {
+    let env_dir = |var: &str, rest: &str| std::env::var_os(var).map(|dir| PathBuf::from(dir).join(rest));
+    if cfg!(windows) {
+        [
+            env_dir("ProgramFiles", r"ffmpeg\bin"),
+            Some(PathBuf::from(r"C:\ffmpeg\bin")),
+            env_dir("LOCALAPPDATA", r"Microsoft\WinGet\Links"),
+            env_dir("USERPROFILE", r"scoop\shims"),
+            env_dir("ProgramData", r"chocolatey\bin"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    } else {
+        ["/opt/homebrew/bin", "/usr/local/bin", "/opt/local/bin", "/snap/bin"].iter().map(PathBuf::from).collect()
+    }
+}
+
+// How often a running ffmpeg is checked on when there's a timeout
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut data);
+        }
+        data
+    })
+}
+
+/// Kinds of streams a video file can hold, for choosing which are carried over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    /// QuickTime timecode tracks, which editors need to line up footage
+    Timecode,
+    /// Other data streams, such as camera telemetry, which can hold a GPS track
+    Data,
+    /// Files attached to Matroska videos, such as fonts for subtitles
+    Attachment,
+}
+
+impl StreamKind {
+    pub const ALL: &[StreamKind] = &[
+        StreamKind::Video,
+        StreamKind::Audio,
+        StreamKind::Subtitle,
+        StreamKind::Timecode,
+        StreamKind::Data,
+        StreamKind::Attachment,
+    ];
+
+    /// The kind of a stream as ffprobe describes it
+    fn of(codec_type: &str, codec_tag: &str) -> Option<StreamKind> {
+        match codec_type {
+            "video" => Some(StreamKind::Video),
+            "audio" => Some(StreamKind::Audio),
+            "subtitle" => Some(StreamKind::Subtitle),
+            "data" if codec_tag == "tmcd" => Some(StreamKind::Timecode),
+            "data" => Some(StreamKind::Data),
+            "attachment" => Some(StreamKind::Attachment),
+            _ => None,
+        }
+    }
+
+    /// ffmpeg's stream specifier for every stream of this kind, for when the streams
+    /// can't be listed one by one
+    fn specifier(self) -> &'static str {
+        match self {
+            StreamKind::Video => "0:V?",
+            StreamKind::Audio => "0:a?",
+            StreamKind::Subtitle => "0:s?",
+            // Timecodes can't be told apart from other data without listing streams
+            StreamKind::Timecode | StreamKind::Data => "0:d?",
+            StreamKind::Attachment => "0:t?",
+        }
+    }
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StreamKind::Video => "video",
+            StreamKind::Audio => "audio",
+            StreamKind::Subtitle => "subtitle",
+            StreamKind::Timecode => "timecode",
+            StreamKind::Data => "data",
+            StreamKind::Attachment => "attachment",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for StreamKind {
+    type Err = anyhow::Error;
+
+    /// Parses the names printed by `Display`, e.g. "subtitle" or "data"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        StreamKind::ALL.iter().find(|kind| kind.to_string() == name).copied().ok_or_else(|| {
+            let names: Vec<String> = StreamKind::ALL.iter().map(ToString::to_string).collect();
+            anyhow::anyhow!("Unknown stream kind '{}' (expected one of: {})", s, names.join(", "))
+        })
+    }
+}
+
+/// Which streams and chapters survive cleaning. Video, audio, subtitle, timecode and
+/// attachment streams are kept, while chapters and other data streams are dropped
+/// unless asked for.
+#[derive(Debug, Clone)]
+pub struct VideoOptions {
+    /// Kinds of streams carried over into the cleaned file. Cover art goes with the
+    /// thumbnails category rather than with the video streams.
+    pub streams: Vec<StreamKind>,
+    /// Keep chapters and their titles
+    pub keep_chapters: bool,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        use StreamKind::*;
+        Self { streams: vec![Video, Audio, Subtitle, Timecode, Attachment], keep_chapters: false }
+    }
+}
+
+impl VideoOptions {
+    pub fn keeps(&self, kind: StreamKind) -> bool {
+        self.streams.contains(&kind)
+    }
+
+    /// Whether a file with tracks of `kinds` loses any of them, which Matroska files can
+    /// only do by remuxing. They're otherwise edited in place.
+    pub(crate) fn drops_tracks(&self, kinds: &[StreamKind]) -> bool {
+        kinds.iter().any(|&kind| !self.keeps(kind))
+    }
+
+    /// Whether stripping with these options removes `item`, as listed by `extract_video_metadata`
+    pub(crate) fn removes(&self, item: &MetadataItem) -> bool {
+        match item.tag.as_str() {
+            CHAPTER_TITLE => !self.keep_chapters,
+            SUBTITLE_TRACK => !self.keeps(StreamKind::Subtitle),
+            TIMECODE_TRACK => !self.keeps(StreamKind::Timecode),
+            DATA_TRACK => !self.keeps(StreamKind::Data),
+            _ => true,
+        }
     }
 
+    /// ffmpeg's stream mapping for these options, with notices listing the streams
+    /// carried over and dropped. Streams are mapped one by one where ffprobe could list
+    /// them, and by kind otherwise. Cover art, which ffmpeg treats as a video stream of
+    /// one frame, goes unless thumbnails are kept.
+    fn map_args(&self, streams: Option<&[Stream]>, keep: &[Category]) -> (Vec<String>, Vec<MetadataItem>) {
+        let keep_cover_art = keep.contains(&Category::Thumbnails);
+        let mut args = Vec::new();
+        let mut notices = Vec::new();
+        match streams {
+            Some(streams) => {
+                let (mut kept, mut dropped) = (Vec::new(), Vec::new());
+                for stream in streams {
+                    if self.maps(stream, keep) {
+                        args.extend(["-map".to_string(), format!("0:{}", stream.index)]);
+                        kept.push(stream.to_string());
+                    } else {
+                        dropped.push(stream.to_string());
+                    }
+                }
+                for (tag, list) in [(STREAMS_KEPT, kept), (STREAMS_DROPPED, dropped)] {
+                    if !list.is_empty() {
+                        notices.push(MetadataItem::with_category(Category::Notice, tag, list.join(", ")));
+                    }
+                }
+            }
+            None => {
+                let mut specifiers: Vec<&str> = Vec::new();
+                for &kind in StreamKind::ALL.iter().filter(|&&kind| self.keeps(kind)) {
+                    let specifier = match kind {
+                        StreamKind::Video if keep_cover_art => "0:v?",
+                        // Mapping timecodes maps every data stream, so they're only kept along with data
+                        StreamKind::Timecode if !self.keeps(StreamKind::Data) => continue,
+                        kind => kind.specifier(),
+                    };
+                    if !specifiers.contains(&specifier) {
+                        specifiers.push(specifier);
+                    }
+                }
+                for specifier in specifiers {
+                    args.extend(["-map".to_string(), specifier.to_string()]);
+                }
+            }
+        }
+        if !self.keep_chapters {
+            args.extend(["-map_chapters".to_string(), "-1".to_string()]);
+        }
+        (args, notices)
+    }
+
+    /// Whether `stream` is carried over into the cleaned file
+    fn maps(&self, stream: &Stream, keep: &[Category]) -> bool {
+        if stream.cover_art {
+            keep.contains(&Category::Thumbnails)
+        } else {
+            self.keeps(stream.kind)
+        }
+    }
+}
+
+const CHAPTER_TITLE: &str = "Chapter Title";
+pub(crate) const COVER_ART: &str = "Cover Art";
+const SUBTITLE_TRACK: &str = "Subtitle Track";
+const TIMECODE_TRACK: &str = "Timecode Track";
+const DATA_TRACK: &str = "Data Track";
+const STREAMS_KEPT: &str = "Streams Kept";
+const STREAMS_DROPPED: &str = "Streams Dropped";
+const KEPT_TAGS_WARNING: &str = "Warning: kept tags";
+
+/// A stream of the input file as ffprobe lists it
+#[derive(Debug, Clone, PartialEq)]
+struct Stream {
+    index: usize,
+    kind: StreamKind,
+    codec: String,
+    cover_art: bool,
+    tags: Vec<(String, String)>,
+}
+
+impl fmt::Display for Stream {
+    /// e.g. "#0 video (h264)" or "#2 video (mjpeg, cover art)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cover_art = if self.cover_art { ", cover art" } else { "" };
+        write!(f, "#{} {} ({}{})", self.index, self.kind, self.codec, cover_art)
+    }
+}
+
+/// Strips container and stream tags, apart from tags in the `keep` categories, and
+/// writes the `set` values as container tags. Which tracks are kept follows `options`.
+pub fn strip_video_metadata(
+    input_path: &Path,
+    output_path: &Path,
+    keep: &[Category],
+    set: &[Assignment],
+    options: &VideoOptions,
+    ffmpeg: &FfmpegConfig,
+) -> Result<Vec<MetadataItem>> {
+    ffmpeg.require("video files")?;
+
     // Extract the actual metadata before removing it
-    let removed_metadata = match extract_video_metadata(input_path) {
+    let mut removed_metadata = match extract_video_metadata(input_path, ffmpeg) {
         Ok(metadata) => metadata,
         Err(_) => {
             // Fallback to generic metadata if extraction fails
             vec![
-                "Creation time (if present)".to_string(),
-                "Encoder information (if present)".to_string(),
-                "Device information (if present)".to_string(),
-                "GPS data (if present)".to_string(),
-                "All metadata headers".to_string()
+                MetadataItem::new("Creation time (if present)", ""),
+                MetadataItem::new("Encoder information (if present)", ""),
+                MetadataItem::new("Device information (if present)", ""),
+                MetadataItem::new("GPS data (if present)", ""),
+                MetadataItem::new("All metadata headers", "")
             ]
         }
     };
 
-    // Create a temporary file path
-    let temp_path = output_path.with_extension("tmp.mp4");
+    removed_metadata.retain(|item| options.removes(item));
+    let streams = probe_streams(input_path, ffmpeg);
+    let (map_args, stream_notices) = options.map_args(streams.as_deref(), keep);
+
+    // Drop every tag, then write the kept ones back by name, so that no tag is carried
+    // over just because ffprobe didn't list it
+    let mut metadata_args = vec!["-map_metadata".to_string(), "-1".to_string()];
+    let mp4 = !matches!(extension(output_path).as_str(), "mkv" | "webm");
+    // Always write the colr atom, which says how to read the colors
+    let mut movflags = vec!["+write_colr"];
+    if !keep.is_empty() {
+        match (probe_format_tags(input_path, ffmpeg), streams.as_deref()) {
+            (Ok(format_tags), Some(streams)) => {
+                let mapped: Vec<&Stream> = streams.iter().filter(|stream| options.maps(stream, keep)).collect();
+                metadata_args.extend(kept_tag_args(&format_tags, &mapped, keep));
+                removed_metadata.retain(|item| !keep.contains(&item.category));
+                // Let the MP4 muxer write kept tags it has no predefined atom for
+                movflags.push("+use_metadata_tags");
+            }
+            // Tags ffprobe can't read can't be written back, so they're removed with the rest
+            _ if removed_metadata.iter().any(|item| keep.contains(&item.category)) => {
+                let warning = "tags in kept categories were removed too, as ffprobe couldn't read them";
+                removed_metadata.push(MetadataItem::with_category(Category::Notice, KEPT_TAGS_WARNING, warning));
+            }
+            _ => {}
+        }
+    }
+    if mp4 {
+        metadata_args.extend(["-movflags".to_string(), movflags.concat()]);
+    }
+
+    // Color signaling isn't metadata about anyone, and HDR video looks washed out
+    // without it, so it's restated for every video stream rather than left to chance
+    let signals = probe_color_signals(input_path, ffmpeg, keep.contains(&Category::Thumbnails));
+    metadata_args.extend(color_args(&signals, mp4));
+
+    // Container tags to write in place of the removed ones
+    let supported: Vec<SetField> = SetField::ALL.iter().copied().filter(|&field| ffmpeg_tag(field).is_some()).collect();
+    for &field in &supported {
+        let date = |date: chrono::NaiveDateTime| date.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string();
+        if let (Some(tag), Some(value)) = (ffmpeg_tag(field), assign::value_of(set, field, date)) {
+            metadata_args.extend(["-metadata".to_string(), format!("{}={}", tag, value)]);
+        }
+    }
+    removed_metadata.extend(assign::unsupported(set, &supported, "video"));
+    removed_metadata.extend(stream_notices);
+
+    // Keep the extension so ffmpeg writes the same container, as Matroska files with
+    // subtitles to drop come this way too
+    let temp_path = temp::sibling(&output_path.with_extension(extension(output_path)));
 
     // Construct ffmpeg command to strip metadata
-    let status = Command::new("ffmpeg")
+    let mut command = ffmpeg.ffmpeg_command();
+    command
+        .arg("-i")
+        .arg(file_arg(input_path))
+        .args(&map_args)
+        .args(&metadata_args)
+        .args(ffmpeg.output_args())
         .args([
-            "-i", input_path.to_str().unwrap(),
-            "-map_metadata", "-1",  // Remove all metadata
-            "-c:v", "copy",         // Copy video stream without re-encoding
-            "-c:a", "copy",         // Copy audio stream without re-encoding
+            "-c", "copy",           // Copy every stream without re-encoding
             "-y",                   // Overwrite output file if it exists
-            temp_path.to_str().unwrap(),
         ])
-        .output()
+        .arg(file_arg(&temp_path));
+    let status = ffmpeg.run(&mut command);
+    if !matches!(&status, Ok(output) if output.status.success()) {
+        // Don't leave a partly written file behind after a failure or a timeout
+        let _ = fs::remove_file(&temp_path);
+    }
+    let status = status
         .with_context(|| format!("Failed to execute ffmpeg command for: {}", input_path.display()))?;
 
     if !status.status.success() {
@@ -52,16 +524,75 @@ pub fn strip_video_metadata(input_path: &Path, output_path: &Path) -> Result<Vec
     Ok(removed_metadata)
 }
 
-fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
-    let output = Command::new("ffprobe")
+/// `path` as ffmpeg and ffprobe should take it: passed on as it is, so names that
+/// aren't UTF-8 work, without a `\\?\` prefix it doesn't need, and marked as a file so
+/// one starting with a dash or holding a colon isn't read as an option or a protocol
+pub(crate) fn file_arg(path: &Path) -> OsString {
+    let mut arg = OsString::from("file:");
+    arg.push(paths::simplify(path).as_os_str());
+    arg
+}
+
+/// The lower-cased extension of `path`, or "mp4" without one
+fn extension(path: &Path) -> String {
+    path.extension().and_then(|e| e.to_str()).map_or("mp4".to_string(), str::to_lowercase)
+}
+
+/// The container tag ffmpeg writes each field to
+fn ffmpeg_tag(field: SetField) -> Option<&'static str> {
+    match field {
+        SetField::Author => Some("artist"),
+        SetField::Title => Some("title"),
+        SetField::Copyright => Some("copyright"),
+        SetField::Software => Some("encoder"),
+        SetField::Date => Some("creation_time"),
+        SetField::Make | SetField::Model => None,
+    }
+}
+
+/// Lists the tags and stream properties of a video or audio file with ffprobe, or with
+/// the built-in MP4 and Matroska readers if ffprobe can't be run or read the file
+pub fn extract_video_metadata(input_path: &Path, ffmpeg: &FfmpegConfig) -> Result<Vec<MetadataItem>> {
+    let error = match probe_metadata(input_path, ffmpeg) {
+        Ok(metadata) => {
+            info!("read metadata path={:?} reader=ffprobe", input_path);
+            return Ok(metadata);
+        }
+        Err(error) => error,
+    };
+
+    let native = match crate::detect_format(input_path).as_deref() {
+        Some("mkv" | "webm") => matroska::read_matroska_metadata(input_path),
+        Some("mp4" | "mov" | "m4a") => mp4::read_mp4_metadata(input_path),
+        _ => return Err(error),
+    };
+    match native {
+        Ok(mut metadata) => {
+            let reason = format!("{:#}", error);
+            info!("read metadata path={:?} reader=built-in ffprobe_error={:?}", input_path, reason);
+            if metadata.is_empty() {
+                metadata.push(MetadataItem::with_category(Category::Notice, NO_METADATA, ""));
+            }
+            Ok(metadata)
+        }
+        Err(_) => Err(error),
+    }
+}
+
+const NO_METADATA: &str = "No readable metadata found in the video file";
+
+fn probe_metadata(input_path: &Path, ffmpeg: &FfmpegConfig) -> Result<Vec<MetadataItem>> {
+    let mut command = ffmpeg.ffprobe_command();
+    command
         .args([
             "-v", "quiet",
             "-print_format", "json",
             "-show_format",
             "-show_streams",
-            input_path.to_str().unwrap(),
+            "-show_chapters",
         ])
-        .output()
+        .arg(file_arg(input_path));
+    let output = ffmpeg.run(&mut command)
         .with_context(|| format!("Failed to execute ffprobe command for: {}", input_path.display()))?;
 
     if !output.status.success() {
@@ -82,7 +613,11 @@ fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
                 process_tag(tags, "album", "Album", &mut metadata);
                 process_tag(tags, "date", "Date", &mut metadata);
                 process_tag(tags, "creation_time", "Creation Time", &mut metadata);
-                process_tag(tags, "encoder", "Encoder", &mut metadata);
+                // ffmpeg writes its own encoder tag when remuxing, so it describes the tool
+                // rather than the author
+                if let Some(encoder) = tags.get("encoder").and_then(|v| v.as_str()).filter(|v| !v.is_empty()) {
+                    metadata.push(MetadataItem::with_category(Category::Technical, "Encoder", encoder));
+                }
                 process_tag(tags, "handler_name", "Handler", &mut metadata);
                 process_tag(tags, "make", "Device Make", &mut metadata);
                 process_tag(tags, "model", "Device Model", &mut metadata);
@@ -101,7 +636,7 @@ fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
                         }
                         
                         if let Some(val_str) = value.as_str() {
-                            metadata.push(format!("{}: {}", key, val_str));
+                            metadata.push(MetadataItem::new(key, val_str));
                         }
                     }
                 }
@@ -109,11 +644,11 @@ fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
             
             // Add basic format info
             if let Some(format_name) = format.get("format_name").and_then(|v| v.as_str()) {
-                metadata.push(format!("Format: {}", format_name));
+                metadata.push(MetadataItem::new("Format", format_name));
             }
             
             if let Some(duration) = format.get("duration").and_then(|v| v.as_str()) {
-                metadata.push(format!("Duration: {} seconds", duration));
+                metadata.push(MetadataItem::new("Duration", format!("{} seconds", duration)));
             }
         }
         
@@ -121,20 +656,23 @@ fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
         if let Some(streams) = json.get("streams").and_then(|s| s.as_array()) {
             for stream in streams {
                 if let Some(codec_type) = stream.get("codec_type").and_then(|v| v.as_str()) {
-                    if codec_type == "video" {
+                    // Attached pictures: cover art, or a still the camera or editor saved
+                    if codec_type == "video" && stream.pointer("/disposition/attached_pic") == Some(&1.into()) {
+                        metadata.push(describe_cover_art(stream));
+                    } else if codec_type == "video" {
                         if let Some(codec_name) = stream.get("codec_name").and_then(|v| v.as_str()) {
-                            metadata.push(format!("Video Codec: {}", codec_name));
+                            metadata.push(MetadataItem::new("Video Codec", codec_name));
                         }
                         
                         if let (Some(width), Some(height)) = (
                             stream.get("width").and_then(|v| v.as_u64()),
                             stream.get("height").and_then(|v| v.as_u64())
                         ) {
-                            metadata.push(format!("Resolution: {}x{}", width, height));
+                            metadata.push(MetadataItem::new("Resolution", format!("{}x{}", width, height)));
                         }
                         
                         if let Some(r_frame_rate) = stream.get("r_frame_rate").and_then(|v| v.as_str()) {
-                            metadata.push(format!("Frame Rate: {}", r_frame_rate));
+                            metadata.push(MetadataItem::new("Frame Rate", r_frame_rate));
                         }
                         
                         // Get video stream tags
@@ -146,15 +684,15 @@ fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
                         }
                     } else if codec_type == "audio" {
                         if let Some(codec_name) = stream.get("codec_name").and_then(|v| v.as_str()) {
-                            metadata.push(format!("Audio Codec: {}", codec_name));
+                            metadata.push(MetadataItem::new("Audio Codec", codec_name));
                         }
                         
                         if let Some(sample_rate) = stream.get("sample_rate").and_then(|v| v.as_str()) {
-                            metadata.push(format!("Audio Sample Rate: {} Hz", sample_rate));
+                            metadata.push(MetadataItem::new("Audio Sample Rate", format!("{} Hz", sample_rate)));
                         }
                         
                         if let Some(channels) = stream.get("channels").and_then(|v| v.as_u64()) {
-                            metadata.push(format!("Audio Channels: {}", channels));
+                            metadata.push(MetadataItem::new("Audio Channels", channels.to_string()));
                         }
                         
                         // Get audio stream tags
@@ -164,33 +702,270 @@ fn extract_video_metadata(input_path: &Path) -> Result<Vec<String>> {
                             process_tag(tags, "language", "Audio Language", &mut metadata);
                             process_tag(tags, "handler_name", "Audio Handler", &mut metadata);
                         }
+                    } else if codec_type == "subtitle" || codec_type == "data" {
+                        metadata.push(describe_track(stream));
                     }
                 }
             }
         }
+
+        for chapter in json.get("chapters").and_then(|c| c.as_array()).into_iter().flatten() {
+            if let Some(title) = chapter.pointer("/tags/title").and_then(|v| v.as_str()).filter(|v| !v.is_empty()) {
+                metadata.push(MetadataItem::with_category(Category::Content, CHAPTER_TITLE, title));
+            }
+        }
     }
     
     if metadata.is_empty() {
-        metadata.push("No readable metadata found in the video file".to_string());
+        metadata.push(MetadataItem::with_category(Category::Notice, NO_METADATA, ""));
     }
     
     Ok(metadata)
 }
 
-fn process_tag(tags: &serde_json::Value, key: &str, display_name: &str, metadata: &mut Vec<String>) {
-    if let Some(value) = tags.get(key).and_then(|v| v.as_str()) {
-        if !value.is_empty() {
-            metadata.push(format!("{}: {}", display_name, value));
+/// The duration of a video or audio file in seconds, where ffprobe can tell
+pub(crate) fn probe_duration(input_path: &Path, ffmpeg: &FfmpegConfig) -> Option<f64> {
+    let mut command = ffmpeg.ffprobe_command();
+    command
+        .args(["-v", "quiet", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(file_arg(input_path));
+    let output = ffmpeg.run(&mut command).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// A hash of the packets of each video and audio stream, cover art aside, read without
+/// decoding, so a remux that only dropped metadata gives the same list. Each is given
+/// as its type and hash, such as "v,SHA256=...", without its index, which dropping an
+/// earlier stream changes.
+pub(crate) fn stream_hashes(path: &Path, ffmpeg: &FfmpegConfig) -> Option<Vec<String>> {
+    ffmpeg.require("stream hashes").ok()?;
+    let mut command = ffmpeg.ffmpeg_command();
+    command
+        .args(["-v", "error", "-i"])
+        .arg(file_arg(path))
+        .args(["-map", "0:V?", "-map", "0:a?", "-c", "copy", "-f", "streamhash", "-hash", "sha256", "-"]);
+    let output = ffmpeg.run(&mut command).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // One line per stream, such as "0,v,SHA256=..."
+    let hashes: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.split_once(',').map_or(line, |(_, stream)| stream).to_string())
+        .collect();
+    (!hashes.is_empty()).then_some(hashes)
+}
+
+/// Every stream of the input, or `None` if ffprobe can't list them
+fn probe_streams(input_path: &Path, ffmpeg: &FfmpegConfig) -> Option<Vec<Stream>> {
+    let mut command = ffmpeg.ffprobe_command();
+    command
+        .args([
+            "-v", "quiet",
+            "-print_format", "json",
+            "-show_entries",
+            "stream=index,codec_type,codec_name,codec_tag_string:stream_tags:stream_disposition=attached_pic",
+        ])
+        .arg(file_arg(input_path));
+    let output = ffmpeg.run(&mut command).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_streams(&output.stdout)
+}
+
+fn parse_streams(json: &[u8]) -> Option<Vec<Stream>> {
+    let json: serde_json::Value = serde_json::from_slice(json).ok()?;
+    let streams = json.get("streams")?.as_array()?;
+    Some(
+        streams
+            .iter()
+            .filter_map(|stream| {
+                let field = |key: &str| stream.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+                Some(Stream {
+                    index: stream.get("index")?.as_u64()? as usize,
+                    kind: StreamKind::of(field("codec_type"), field("codec_tag_string"))?,
+                    codec: Some(field("codec_name")).filter(|codec| !codec.is_empty()).unwrap_or("unknown").to_string(),
+                    cover_art: stream.pointer("/disposition/attached_pic") == Some(&1.into()),
+                    tags: tag_pairs(stream.get("tags")),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// How the colors of a video stream are to be read: the primaries, transfer function,
+/// matrix and range as ffmpeg names them, e.g. "bt2020", "smpte2084", "bt2020nc", "tv"
+#[derive(Debug, Default, PartialEq)]
+struct ColorSignal {
+    primaries: Option<String>,
+    transfer: Option<String>,
+    space: Option<String>,
+    range: Option<String>,
+    /// Whether the stream carries HDR10 mastering display or content light level data
+    hdr10: bool,
+}
+
+const COLOR_FIELDS: &[&str] = &["color_primaries", "color_transfer", "color_space", "color_range"];
+
+/// The color signaling of each video stream that will be written, in output order.
+/// Cover art streams are left out unless `with_cover_art`.
+fn probe_color_signals(input_path: &Path, ffmpeg: &FfmpegConfig, with_cover_art: bool) -> Vec<ColorSignal> {
+    let mut command = ffmpeg.ffprobe_command();
+    command
+        .args([
+            "-v", "quiet",
+            "-print_format", "json",
+            "-select_streams", "v",
+            "-show_entries",
+        ])
+        .arg(format!("stream={}:stream_disposition=attached_pic:stream_side_data", COLOR_FIELDS.join(",")))
+        .arg(file_arg(input_path));
+    let output = ffmpeg.run(&mut command);
+    match output {
+        Ok(output) if output.status.success() => parse_color_signals(&output.stdout, with_cover_art),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_color_signals(json: &[u8], with_cover_art: bool) -> Vec<ColorSignal> {
+    let Ok(json) = serde_json::from_slice::<serde_json::Value>(json) else { return Vec::new() };
+    let streams = json.get("streams").and_then(|s| s.as_array()).map(Vec::as_slice).unwrap_or_default();
+    streams
+        .iter()
+        .filter(|stream| with_cover_art || stream.pointer("/disposition/attached_pic") != Some(&1.into()))
+        .map(|stream| {
+            let field = |key: &str| {
+                stream.get(key).and_then(|v| v.as_str()).filter(|v| *v != "unknown").map(str::to_string)
+            };
+            let side_data = stream.get("side_data_list").and_then(|s| s.as_array());
+            let hdr10 = side_data.into_iter().flatten().any(|data| {
+                matches!(
+                    data.get("side_data_type").and_then(|v| v.as_str()),
+                    Some("Mastering display metadata" | "Content light level metadata")
+                )
+            });
+            ColorSignal {
+                primaries: field("color_primaries"),
+                transfer: field("color_transfer"),
+                space: field("color_space"),
+                range: field("color_range"),
+                hdr10,
+            }
+        })
+        .collect()
+}
+
+/// ffmpeg output options restating `signals`, one set per video stream. Older ffmpeg
+/// versions only write HDR10 boxes into MP4 files when allowed unofficial extensions.
+fn color_args(signals: &[ColorSignal], mp4: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    for (index, signal) in signals.iter().enumerate() {
+        let options = [
+            ("-color_primaries", &signal.primaries),
+            ("-color_trc", &signal.transfer),
+            ("-colorspace", &signal.space),
+            ("-color_range", &signal.range),
+        ];
+        for (option, value) in options {
+            if let Some(value) = value {
+                args.extend([format!("{}:v:{}", option, index), value.clone()]);
+            }
+        }
+    }
+    if mp4 && signals.iter().any(|signal| signal.hdr10) {
+        args.extend(["-strict".to_string(), "unofficial".to_string()]);
+    }
+    args
+}
+
+/// Returns the tags of the container
+fn probe_format_tags(input_path: &Path, ffmpeg: &FfmpegConfig) -> Result<Vec<(String, String)>> {
+    let mut command = ffmpeg.ffprobe_command();
+    command
+        .args([
+            "-v", "quiet",
+            "-print_format", "json",
+            "-show_entries", "format_tags",
+        ])
+        .arg(file_arg(input_path));
+    let output = ffmpeg.run(&mut command)
+        .with_context(|| format!("Failed to execute ffprobe command for: {}", input_path.display()))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("ffprobe failed to list tags"));
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ffprobe output")?;
+    Ok(tag_pairs(json.pointer("/format/tags")))
+}
+
+/// The string tags of an ffprobe `tags` object, by name
+fn tag_pairs(tags: Option<&serde_json::Value>) -> Vec<(String, String)> {
+    tags.and_then(|tags| tags.as_object())
+        .map(|tags| tags.iter().filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string()))).collect())
+        .unwrap_or_default()
+}
+
+/// `-metadata` arguments writing back the tags in the `keep` categories, with the tags of
+/// each of the `mapped` streams going to that stream's place in the output
+fn kept_tag_args(format_tags: &[(String, String)], mapped: &[&Stream], keep: &[Category]) -> Vec<String> {
+    let kept = |(key, _): &&(String, String)| keep.contains(&Category::from_tag(key));
+    let mut args = Vec::new();
+    for (key, value) in format_tags.iter().filter(kept) {
+        args.extend(["-metadata".to_string(), format!("{}={}", key, value)]);
+    }
+    for (output_index, stream) in mapped.iter().enumerate() {
+        for (key, value) in stream.tags.iter().filter(kept) {
+            args.extend([format!("-metadata:s:{}", output_index), format!("{}={}", key, value)]);
         }
     }
+    args
+}
+
+/// An attached picture by its codec and size, e.g. "Cover Art: mjpeg 600x600 (cover.jpg)"
+fn describe_cover_art(stream: &serde_json::Value) -> MetadataItem {
+    let codec = stream.get("codec_name").and_then(|v| v.as_str()).unwrap_or("unknown");
+    let dimension = |key: &str| stream.get(key).and_then(|v| v.as_u64());
+    let mut value = match (dimension("width"), dimension("height")) {
+        (Some(width), Some(height)) => format!("{} {}x{}", codec, width, height),
+        _ => codec.to_string(),
+    };
+    if let Some(name) = stream.pointer("/tags/filename").and_then(|v| v.as_str()) {
+        value.push_str(&format!(" ({})", name));
+    }
+    MetadataItem::with_category(Category::Thumbnails, COVER_ART, value)
+}
+
+/// A subtitle or data stream, named by its title or handler and its language,
+/// e.g. "Subtitle Track: Director's notes (eng)"
+fn describe_track(stream: &serde_json::Value) -> MetadataItem {
+    let tag = |key: &str| stream.pointer(&format!("/tags/{}", key)).and_then(|v| v.as_str()).filter(|v| !v.is_empty());
+    let codec = stream.get("codec_name").and_then(|v| v.as_str()).unwrap_or("unknown");
+    let name = tag("title").or(tag("handler_name")).unwrap_or(codec);
+    let value = match tag("language") {
+        Some(language) => format!("{} ({})", name, language),
+        None => name.to_string(),
+    };
+    let field = |key: &str| stream.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    match StreamKind::of(field("codec_type"), field("codec_tag_string")) {
+        Some(StreamKind::Subtitle) => MetadataItem::with_category(Category::Content, SUBTITLE_TRACK, value),
+        // The time of day the recording started, in most cameras
+        Some(StreamKind::Timecode) => MetadataItem::with_category(Category::Timestamps, TIMECODE_TRACK, value),
+        _ => MetadataItem::with_category(Category::Other, DATA_TRACK, value),
+    }
 }
 
-fn is_ffmpeg_installed() -> bool {
-    Command::new("ffmpeg")
-        .arg("-version")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false)
+fn process_tag(tags: &serde_json::Value, key: &str, display_name: &str, metadata: &mut Vec<MetadataItem>) {
+    if let Some(value) = tags.get(key).and_then(|v| v.as_str()) {
+        if !value.is_empty() {
+            metadata.push(MetadataItem::new(display_name, value));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -201,7 +976,139 @@ mod tests {
     #[test]
     fn test_ffmpeg_installation() {
         // Don't fail the test if ffmpeg isn't installed
-        let _ = is_ffmpeg_installed();
+        let _ = FfmpegConfig::default().is_installed();
+        assert!(!FfmpegConfig::with_ffmpeg("/nonexistent/ffmpeg").is_installed());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_timeout() {
+        let config = FfmpegConfig { timeout: Some(Duration::from_millis(100)), ..FfmpegConfig::default() };
+        let started = Instant::now();
+        let error = config.run(Command::new("sleep").arg("5")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let output = config.run(Command::new("echo").arg("done")).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"done\n");
+
+        // Within one file, the timeout covers all of its runs together
+        let config = FfmpegConfig { timeout: Some(Duration::from_millis(300)), ..FfmpegConfig::default() };
+        let file = config.for_file();
+        assert!(file.run(Command::new("sleep").arg("0.2")).unwrap().status.success());
+        let error = file.run(Command::new("sleep").arg("0.2")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(config.run(Command::new("sleep").arg("0.2")).unwrap().status.success());
+    }
+
+    #[test]
+    fn test_file_arg() {
+        assert_eq!(file_arg(Path::new("-i.mp4")), "file:-i.mp4");
+        assert_eq!(file_arg(Path::new("clips/a:b.mkv")), "file:clips/a:b.mkv");
+    }
+
+    #[test]
+    fn test_find_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let ffmpeg = dir.path().join(format!("ffmpeg{}", std::env::consts::EXE_SUFFIX));
+        let missing = dir.path().join("missing");
+        assert_eq!(find_program("ffmpeg", [missing.clone(), dir.path().to_path_buf()]), None);
+        fs::write(&ffmpeg, b"").unwrap();
+        assert_eq!(find_program("ffmpeg", [missing, dir.path().to_path_buf()]), Some(ffmpeg));
+        assert!(!common_install_dirs().is_empty());
+    }
+
+    #[test]
+    fn test_ffprobe_next_to_ffmpeg() {
+        let config = FfmpegConfig::with_ffmpeg("/opt/ffmpeg/bin/ffmpeg");
+        assert_eq!(config.ffprobe, PathBuf::from("/opt/ffmpeg/bin/ffprobe"));
+        let config = FfmpegConfig::with_ffmpeg("C:/ffmpeg/ffmpeg.exe");
+        assert_eq!(config.ffprobe, PathBuf::from("C:/ffmpeg/ffprobe.exe"));
+        assert_eq!(FfmpegConfig::default().ffprobe, PathBuf::from("ffprobe"));
+    }
+
+    #[test]
+    fn test_video_options() {
+        let json = br#"{"streams": [
+            {"index": 0, "codec_type": "video", "codec_name": "hevc", "disposition": {"attached_pic": 0}},
+            {"index": 1, "codec_type": "audio", "codec_name": "aac", "disposition": {"attached_pic": 0},
+             "tags": {"creation_time": "2024-05-01T10:00:00Z", "handler_name": "Core Media Audio"}},
+            {"index": 2, "codec_type": "data", "codec_tag_string": "tmcd", "disposition": {"attached_pic": 0}},
+            {"index": 3, "codec_type": "data", "codec_tag_string": "gpmd", "disposition": {"attached_pic": 0}},
+            {"index": 4, "codec_type": "video", "codec_name": "mjpeg", "disposition": {"attached_pic": 1}}
+        ]}"#;
+        let streams = parse_streams(json).unwrap();
+        assert_eq!(streams[2].kind, StreamKind::Timecode);
+        assert_eq!(streams[3].to_string(), "#3 data (unknown)");
+
+        let (args, notices) = VideoOptions::default().map_args(Some(&streams), &[]);
+        assert_eq!(args, ["-map", "0:0", "-map", "0:1", "-map", "0:2", "-map_chapters", "-1"]);
+        let notices: Vec<String> = notices.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            notices,
+            [
+                "Streams Kept: #0 video (hevc), #1 audio (aac), #2 timecode (unknown)",
+                "Streams Dropped: #3 data (unknown), #4 video (mjpeg, cover art)",
+            ]
+        );
+
+        // Kept tags are written back to where their streams end up
+        let format_tags: Vec<(String, String)> =
+            [("title", "Trip"), ("location", "+48+2/")].map(|(key, value)| (key.to_string(), value.to_string())).into();
+        let mapped: Vec<&Stream> = streams[1..].iter().collect();
+        assert_eq!(
+            kept_tag_args(&format_tags, &mapped, &[Category::Content, Category::Timestamps]),
+            ["-metadata", "title=Trip", "-metadata:s:0", "creation_time=2024-05-01T10:00:00Z"]
+        );
+        assert!(kept_tag_args(&format_tags, &mapped, &[]).is_empty());
+
+        // Without a stream list, whole kinds are mapped
+        let options = VideoOptions { streams: vec![StreamKind::Video, StreamKind::Data], keep_chapters: true };
+        let (args, notices) = options.map_args(None, &[Category::Thumbnails]);
+        assert_eq!(args, ["-map", "0:v?", "-map", "0:d?"]);
+        assert!(notices.is_empty());
+        // Telemetry is dropped by default, and takes the timecodes with it
+        let (args, _) = VideoOptions::default().map_args(None, &[]);
+        assert_eq!(args, ["-map", "0:V?", "-map", "0:a?", "-map", "0:s?", "-map", "0:t?", "-map_chapters", "-1"]);
+        assert!(options.drops_tracks(&[StreamKind::Video, StreamKind::Audio]));
+        assert!(!options.drops_tracks(&[StreamKind::Video]));
+        assert!(VideoOptions::default().drops_tracks(&[StreamKind::Data]));
+        assert!(!VideoOptions::default().drops_tracks(&[StreamKind::Video, StreamKind::Attachment]));
+
+        let chapter = MetadataItem::with_category(Category::Content, CHAPTER_TITLE, "Intro");
+        assert!(VideoOptions::default().removes(&chapter));
+        assert!(!options.removes(&chapter));
+        let subtitles = MetadataItem::with_category(Category::Content, SUBTITLE_TRACK, "English");
+        assert!(!VideoOptions::default().removes(&subtitles));
+        assert!(options.removes(&subtitles));
+        assert_eq!("Timecode".parse::<StreamKind>().unwrap(), StreamKind::Timecode);
+        assert!("teletext".parse::<StreamKind>().is_err());
+    }
+
+    #[test]
+    fn test_color_args() {
+        let json = br#"{"streams": [
+            {"color_range": "tv", "color_space": "bt2020nc", "color_transfer": "smpte2084", "color_primaries": "bt2020",
+             "disposition": {"attached_pic": 0},
+             "side_data_list": [{"side_data_type": "Mastering display metadata"}]},
+            {"color_range": "pc", "color_space": "unknown", "disposition": {"attached_pic": 1}}
+        ]}"#;
+        let signals = parse_color_signals(json, false);
+        assert_eq!(signals.len(), 1);
+        assert!(signals[0].hdr10);
+        assert_eq!(
+            color_args(&signals, true),
+            [
+                "-color_primaries:v:0", "bt2020", "-color_trc:v:0", "smpte2084", "-colorspace:v:0", "bt2020nc",
+                "-color_range:v:0", "tv", "-strict", "unofficial",
+            ]
+        );
+
+        let signals = parse_color_signals(json, true);
+        assert_eq!(signals[1], ColorSignal { range: Some("pc".to_string()), ..ColorSignal::default() });
+        assert_eq!(color_args(&signals[1..], false), ["-color_range:v:0", "pc"]);
+        assert!(parse_color_signals(b"not json", false).is_empty());
     }
 
     #[test]
@@ -210,7 +1117,8 @@ mod tests {
         let output = NamedTempFile::new().unwrap();
 
         // Skip test if ffmpeg is not installed
-        if !is_ffmpeg_installed() {
+        let ffmpeg = FfmpegConfig::default();
+        if !ffmpeg.is_installed() {
             return;
         }
 
@@ -226,7 +1134,7 @@ mod tests {
             .unwrap();
 
         // Test stripping metadata
-        let result = strip_video_metadata(input.path(), output.path());
+        let result = strip_video_metadata(input.path(), output.path(), &[], &[], &VideoOptions::default(), &ffmpeg);
         assert!(result.is_ok());
     }
 } 
\ No newline at end of file
@@ -0,0 +1,284 @@
+// Change tracking for watch mode. Watched folders are scanned when something in them
+// changes, as inotify reports on Linux, or periodically elsewhere. A new or changed file
+// is only reported once it has looked the same in two scans in a row, so files still
+// being copied or saved aren't picked up half-written.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    stamp: Stamp,
+    handled: bool,
+}
+
+/// Remembers the files seen in a watched folder and which of them have been handled
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    entries: HashMap<PathBuf, Entry>,
+}
+
+impl ChangeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records files as already handled, e.g. the ones present before watching starts
+    pub fn baseline(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            if let Some(stamp) = stamp(path) {
+                self.entries.insert(path.clone(), Entry { stamp, handled: true });
+            }
+        }
+    }
+
+    /// Takes the files currently in the folder and returns the ones that are new or
+    /// changed since they were last handled, and unchanged since the previous scan
+    pub fn scan(&mut self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        let present: HashSet<&PathBuf> = paths.iter().collect();
+        self.entries.retain(|path, _| present.contains(path));
+
+        let mut ready = Vec::new();
+        for path in paths {
+            let Some(stamp) = stamp(path) else { continue };
+            match self.entries.get_mut(path) {
+                Some(entry) if entry.stamp == stamp => {
+                    if !entry.handled {
+                        ready.push(path.clone());
+                    }
+                }
+                Some(entry) => *entry = Entry { stamp, handled: false },
+                None => {
+                    self.entries.insert(path.clone(), Entry { stamp, handled: false });
+                }
+            }
+        }
+        ready
+    }
+
+    /// Whether any file seen is still waiting to settle, and needs another scan even if
+    /// nothing else changes
+    pub fn pending(&self) -> bool {
+        self.entries.values().any(|entry| !entry.handled)
+    }
+
+    /// Marks a file as handled as it is now, so cleaning it in place doesn't count as
+    /// a change. Files that were moved away are forgotten.
+    pub fn handled(&mut self, path: &Path) {
+        match stamp(path) {
+            Some(stamp) => {
+                self.entries.insert(path.to_path_buf(), Entry { stamp, handled: true });
+            }
+            None => {
+                self.entries.remove(path);
+            }
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp { len: metadata.len(), modified: metadata.modified().ok() })
+}
+
+/// Tells when something in the watched folders changed. Where that can't be watched,
+/// it says so every `interval` instead, so the folders are still scanned regularly.
+pub struct FolderEvents {
+    interval: Duration,
+    #[cfg(target_os = "linux")]
+    inotify: Option<inotify::Inotify>,
+}
+
+impl FolderEvents {
+    /// Watches `dirs`, and with `recursive` every folder below them too
+    pub fn new(dirs: &[PathBuf], recursive: bool, interval: Duration) -> Self {
+        #[cfg(target_os = "linux")]
+        let inotify = match inotify::Inotify::new(dirs, recursive) {
+            Ok(inotify) => Some(inotify),
+            Err(e) => {
+                log::warn!("can't watch for changes, scanning every {:?} instead: {}", interval, e);
+                None
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let _ = (dirs, recursive);
+        Self {
+            interval,
+            #[cfg(target_os = "linux")]
+            inotify,
+        }
+    }
+
+    /// Blocks until something changes
+    pub fn wait(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &mut self.inotify {
+            inotify.read(None);
+            return;
+        }
+        thread::sleep(self.interval);
+    }
+
+    /// Forgets the changes seen so far, for when the folders are about to be scanned anyway
+    pub fn clear(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &mut self.inotify {
+            inotify.read(Some(Duration::ZERO));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::HashMap;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    // Files written, moved in or out, or deleted, and folders created
+    const MASK: u32 = libc::IN_CLOSE_WRITE
+        | libc::IN_MODIFY
+        | libc::IN_CREATE
+        | libc::IN_MOVED_TO
+        | libc::IN_MOVED_FROM
+        | libc::IN_DELETE;
+
+    // The fixed part of an inotify_event: wd, mask, cookie and name length
+    const EVENT_HEADER: usize = 16;
+
+    pub(super) struct Inotify {
+        fd: OwnedFd,
+        /// The folder of each watch, to add watches for folders created in it
+        folders: HashMap<i32, PathBuf>,
+        recursive: bool,
+    }
+
+    impl Inotify {
+        pub(super) fn new(dirs: &[PathBuf], recursive: bool) -> io::Result<Self> {
+            // SAFETY: inotify_init1 takes only flags, and returns a new descriptor or -1
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the descriptor was just opened, and nothing else owns it
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let mut inotify = Self { fd, folders: HashMap::new(), recursive };
+            for dir in dirs {
+                inotify.add(dir)?;
+            }
+            Ok(inotify)
+        }
+
+        /// Watches `dir`, and with `recursive` the folders below it
+        fn add(&mut self, dir: &Path) -> io::Result<()> {
+            let depth = if self.recursive { usize::MAX } else { 0 };
+            for entry in walkdir::WalkDir::new(dir).max_depth(depth).into_iter().filter_map(Result::ok) {
+                if !entry.file_type().is_dir() {
+                    continue;
+                }
+                let path = CString::new(entry.path().as_os_str().as_bytes())?;
+                // SAFETY: the path is NUL-terminated and outlives the call
+                let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK) };
+                if wd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                self.folders.insert(wd, entry.into_path());
+            }
+            Ok(())
+        }
+
+        /// Waits up to `timeout`, or for as long as it takes without one, for changes,
+        /// then takes every change queued, watching any new folders
+        pub(super) fn read(&mut self, timeout: Option<Duration>) {
+            let timeout = timeout.map_or(-1, |timeout| timeout.as_millis().min(i32::MAX as u128) as i32);
+            let mut poll = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            // SAFETY: poll is given exactly one valid pollfd
+            if unsafe { libc::poll(&mut poll, 1, timeout) } <= 0 {
+                return;
+            }
+
+            let mut buffer = vec![0u8; 64 * 1024];
+            let mut created = Vec::new();
+            loop {
+                // SAFETY: the buffer is valid for its whole length, and the descriptor
+                // doesn't block once the queue is empty
+                let read = unsafe { libc::read(self.fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+                if read <= 0 {
+                    break;
+                }
+                let mut pos = 0;
+                while pos + EVENT_HEADER <= read as usize {
+                    let field = |at: usize| u32::from_ne_bytes(buffer[pos + at..pos + at + 4].try_into().unwrap());
+                    let (wd, mask, len) = (field(0) as i32, field(4), field(12) as usize);
+                    let name = &buffer[pos + EVENT_HEADER..(pos + EVENT_HEADER + len).min(read as usize)];
+                    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                    let new_folder = mask & libc::IN_ISDIR != 0 && mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0;
+                    if self.recursive && new_folder {
+                        if let Some(folder) = self.folders.get(&wd) {
+                            created.push(folder.join(std::ffi::OsStr::from_bytes(name)));
+                        }
+                    }
+                    pos += EVENT_HEADER + len;
+                }
+            }
+            for dir in created {
+                if let Err(e) = self.add(&dir) {
+                    log::warn!("can't watch {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_waits_for_files_to_settle() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.jpg");
+        let new = dir.path().join("new.jpg");
+        fs::write(&old, b"old").unwrap();
+
+        let paths = vec![old.clone(), new.clone()];
+        let mut tracker = ChangeTracker::new();
+        tracker.baseline(&paths);
+
+        assert!(!tracker.pending());
+        fs::write(&new, b"partial").unwrap();
+        assert!(tracker.scan(&paths).is_empty());
+        assert!(tracker.pending());
+
+        // Still being written
+        fs::write(&new, b"partial and more").unwrap();
+        assert!(tracker.scan(&paths).is_empty());
+
+        assert_eq!(tracker.scan(&paths), vec![new.clone()]);
+        fs::write(&new, b"cleaned").unwrap();
+        tracker.handled(&new);
+        assert!(tracker.scan(&paths).is_empty());
+        assert!(!tracker.pending());
+
+        // A handled file that changes again is picked up again
+        fs::write(&old, b"edited old").unwrap();
+        assert!(tracker.scan(&paths).is_empty());
+        assert_eq!(tracker.scan(&paths), vec![old.clone()]);
+
+        fs::remove_file(&old).unwrap();
+        tracker.handled(&old);
+        assert!(tracker.scan(&[new]).is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+// Extended file attributes. They live in the file system rather than in the file,
+// so they survive edits to the content: macOS records download origins, quarantine
+// details and Finder comments there, and Linux browsers store the download URL in
+// user.xdg.origin.url. NTFS keeps the same kind of thing in alternate data streams,
+// most notably the Zone.Identifier stream browsers attach to downloads.
+
+use std::path::Path;
+
+use crate::metadata::{Category, MetadataItem, Sensitivity};
+
+// Namespaces the system manages, such as SELinux labels and ACLs, which aren't metadata
+// about the content and usually can't be removed anyway
+#[cfg(unix)]
+const SYSTEM_PREFIXES: &[&str] = &["security.", "system.", "trusted."];
+
+// Attributes that give away where a file came from
+#[cfg_attr(not(unix), allow(dead_code))]
+const ORIGIN_ATTRIBUTES: &[&str] = &["com.apple.metadata:kMDItemWhereFroms", "user.xdg.origin.url", "user.xdg.referrer.url"];
+
+/// Lists the removable extended attributes of a file
+#[cfg(unix)]
+pub fn list_xattrs(path: &Path) -> Vec<MetadataItem> {
+    removable_names(path)
+        .into_iter()
+        .map(|name| {
+            let value = xattr::get(path, &name).ok().flatten().unwrap_or_default();
+            describe_xattr(&name, &value)
+        })
+        .collect()
+}
+
+/// Removes the removable extended attributes of a file
+#[cfg(unix)]
+pub fn strip_xattrs(path: &Path) {
+    for name in removable_names(path) {
+        // Some attributes are protected by the OS, e.g. com.apple.provenance
+        let _ = xattr::remove(path, &name);
+    }
+}
+
+// Alternate data streams written by Windows, browsers and file sync tools. Streams can't
+// be listed without the Win32 stream API, so the well-known ones are probed by name.
+#[cfg(windows)]
+const KNOWN_STREAMS: &[&str] = &[
+    "Zone.Identifier",
+    "\u{5}SummaryInformation",
+    "\u{5}DocumentSummaryInformation",
+    "{4c8cc155-6c1e-11d1-8e41-00c04fb9386d}",
+    "AFP_AfpInfo",
+    "AFP_Resource",
+    "com.dropbox.attributes",
+    "OECustomProperty",
+];
+
+/// Lists the alternate data streams of a file
+#[cfg(windows)]
+pub fn list_xattrs(path: &Path) -> Vec<MetadataItem> {
+    KNOWN_STREAMS
+        .iter()
+        .filter_map(|name| {
+            let value = std::fs::read(stream_path(path, name)).ok()?;
+            Some(describe_stream(name, &value))
+        })
+        .collect()
+}
+
+/// Removes the alternate data streams of a file, leaving its main content alone
+#[cfg(windows)]
+pub fn strip_xattrs(path: &Path) {
+    for name in KNOWN_STREAMS {
+        let _ = std::fs::remove_file(stream_path(path, name));
+    }
+}
+
+/// `file:stream`, the name Windows opens a file's alternate data stream by
+#[cfg(windows)]
+fn stream_path(path: &Path, name: &str) -> std::path::PathBuf {
+    let mut stream = path.as_os_str().to_os_string();
+    stream.push(":");
+    stream.push(name);
+    stream.into()
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn list_xattrs(_path: &Path) -> Vec<MetadataItem> {
+    Vec::new()
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn strip_xattrs(_path: &Path) {}
+
+#[cfg(unix)]
+fn removable_names(path: &Path) -> Vec<String> {
+    let Ok(names) = xattr::list(path) else { return Vec::new() };
+    names
+        .filter_map(|name| name.into_string().ok())
+        .filter(|name| !SYSTEM_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .collect()
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+fn describe_xattr(name: &str, value: &[u8]) -> MetadataItem {
+    let tag = format!("Extended attribute ({})", name);
+    let value = describe_value(value);
+    if ORIGIN_ATTRIBUTES.contains(&name) {
+        MetadataItem::with_category(Category::Other, tag, value).sensitivity(Sensitivity::High)
+    } else if name == "com.apple.metadata:kMDItemFinderComment" {
+        MetadataItem::with_category(Category::Content, tag, value)
+    } else {
+        MetadataItem::with_category(Category::Other, tag, value)
+    }
+}
+
+/// Zone.Identifier streams are small INI files with the security zone and, from
+/// most browsers, the page and URL the file was downloaded from
+#[cfg_attr(not(windows), allow(dead_code))]
+fn describe_stream(name: &str, value: &[u8]) -> MetadataItem {
+    let tag = format!("Alternate data stream ({})", name.trim_start_matches('\u{5}'));
+    if name != "Zone.Identifier" {
+        return MetadataItem::with_category(Category::Other, tag, describe_value(value));
+    }
+
+    let text = String::from_utf8_lossy(value);
+    let fields: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('['))
+        .collect();
+    let item = MetadataItem::with_category(Category::Other, tag, fields.join("; "));
+    if fields.iter().any(|field| field.starts_with("HostUrl=") || field.starts_with("ReferrerUrl=")) {
+        item.sensitivity(Sensitivity::High)
+    } else {
+        item
+    }
+}
+
+/// Shows text values as they are, and the readable strings in binary ones such as
+/// the property lists macOS stores
+#[cfg_attr(not(unix), allow(dead_code))]
+fn describe_value(value: &[u8]) -> String {
+    if let Ok(text) = std::str::from_utf8(value) {
+        let text = text.trim_end_matches('\0').trim();
+        if !text.is_empty() && !text.chars().any(char::is_control) {
+            return text.to_string();
+        }
+    }
+
+    let strings: Vec<String> = value
+        .split(|&b| !(0x20..0x7F).contains(&b))
+        .filter(|run| run.len() >= 8)
+        .map(|run| String::from_utf8_lossy(run).into_owned())
+        .filter(|run| !run.starts_with("bplist"))
+        .collect();
+    if strings.is_empty() {
+        format!("{} bytes", value.len())
+    } else {
+        strings.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_xattr() {
+        let quarantine = describe_xattr("com.apple.quarantine", b"0083;65a1b2c3;Safari;");
+        assert_eq!(quarantine.to_string(), "Extended attribute (com.apple.quarantine): 0083;65a1b2c3;Safari;");
+
+        let plist = b"bplist00\xa2\x01\x02_\x10\x1fhttps://example.com/photo.jpg\x00\x08\x0b";
+        let where_from = describe_xattr("com.apple.metadata:kMDItemWhereFroms", plist);
+        assert_eq!(where_from.value, "https://example.com/photo.jpg");
+        assert_eq!(where_from.sensitivity, Sensitivity::High);
+
+        assert_eq!(describe_xattr("com.apple.FinderInfo", &[0; 32]).value, "32 bytes");
+    }
+
+    #[test]
+    fn test_describe_stream() {
+        let zone = describe_stream(
+            "Zone.Identifier",
+            b"[ZoneTransfer]\r\nZoneId=3\r\nReferrerUrl=https://example.com/\r\nHostUrl=https://example.com/photo.jpg\r\n",
+        );
+        assert_eq!(
+            zone.to_string(),
+            "Alternate data stream (Zone.Identifier): ZoneId=3; ReferrerUrl=https://example.com/; HostUrl=https://example.com/photo.jpg"
+        );
+        assert_eq!(zone.sensitivity, Sensitivity::High);
+
+        let local = describe_stream("Zone.Identifier", b"[ZoneTransfer]\r\nZoneId=1\r\n");
+        assert_eq!(local.value, "ZoneId=1");
+        assert_eq!(local.sensitivity, Sensitivity::Medium);
+
+        assert_eq!(describe_stream("\u{5}SummaryInformation", &[0; 48]).tag, "Alternate data stream (SummaryInformation)");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_strip_xattrs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.jpg");
+        std::fs::write(&path, b"data").unwrap();
+
+        // Not every file system supports user attributes
+        if xattr::set(&path, "user.xdg.origin.url", b"https://example.com/photo.jpg").is_err() {
+            return;
+        }
+
+        let items = list_xattrs(&path);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].to_string(), "Extended attribute (user.xdg.origin.url): https://example.com/photo.jpg");
+
+        strip_xattrs(&path);
+        assert!(list_xattrs(&path).is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+// Lightweight XMP packet reader. XMP is RDF/XML, but the fields we report on
+// are simple enough that a full XML parser isn't worth the dependency.
+
+use crate::metadata::{Category, MetadataItem};
+
+const XMP_FIELDS: &[(&str, &str)] = &[
+    ("dc:title", "Title"),
+    ("dc:creator", "Creator"),
+    ("dc:description", "Description"),
+    ("dc:subject", "Subject"),
+    ("dc:rights", "Rights"),
+    ("xmp:CreatorTool", "Creator Tool"),
+    ("xmp:CreateDate", "Create Date"),
+    ("xmp:ModifyDate", "Modify Date"),
+    ("xmp:MetadataDate", "Metadata Date"),
+    ("pdf:Producer", "Producer"),
+    ("pdf:Keywords", "Keywords"),
+    ("xmpMM:DocumentID", "Document ID"),
+    ("xmpMM:InstanceID", "Instance ID"),
+    ("xmpMM:OriginalDocumentID", "Original Document ID"),
+];
+
+// Traces editing applications leave, reported as software whatever their names suggest
+const SOFTWARE_FIELDS: &[(&str, &str)] = &[
+    ("crs:Version", "Camera Raw Version"),
+    ("crs:ProcessVersion", "Camera Raw Process Version"),
+    ("GIMP:Version", "GIMP Version"),
+    ("GIMP:Platform", "GIMP Platform"),
+];
+
+/// PDF/A and PDF/UA identification, which archival validators look for
+const CONFORMANCE_FIELDS: &[&str] = &["pdfaid:part", "pdfaid:conformance", "pdfuaid:part"];
+
+/// Extracts the identifying fields from an XMP packet as "XMP <Field>" items
+pub fn extract_xmp_fields(packet: &str) -> Vec<MetadataItem> {
+    let mut metadata = Vec::new();
+
+    for (tag, display_name) in XMP_FIELDS {
+        if let Some(value) = find_element_value(packet, tag).or_else(|| find_attribute_value(packet, tag)) {
+            if !value.is_empty() {
+                metadata.push(MetadataItem::new(format!("XMP {}", display_name), value));
+            }
+        }
+    }
+
+    for (tag, display_name) in SOFTWARE_FIELDS {
+        if let Some(value) = find_element_value(packet, tag).or_else(|| find_attribute_value(packet, tag)) {
+            if !value.is_empty() {
+                metadata.push(MetadataItem::with_category(Category::Software, format!("XMP {}", display_name), value));
+            }
+        }
+    }
+
+    // Each save in Photoshop or Lightroom adds an event to the history, naming the
+    // application that made it
+    let mut agents: Vec<String> = Vec::new();
+    for agent in find_all_values(packet, "stEvt:softwareAgent") {
+        if !agent.is_empty() && !agents.contains(&agent) {
+            agents.push(agent);
+        }
+    }
+    if !agents.is_empty() {
+        metadata.push(MetadataItem::with_category(Category::Software, "XMP Edit History", agents.join(", ")));
+    }
+
+    let conformance = conformance_fields(packet);
+    if !conformance.is_empty() {
+        let value = describe_conformance(&conformance);
+        metadata.push(MetadataItem::with_category(Category::Technical, "XMP Conformance", value));
+    }
+
+    if metadata.is_empty() {
+        metadata.push(MetadataItem::new("XMP Metadata", format!("present ({} bytes)", packet.len())));
+    }
+
+    metadata
+}
+
+/// The PDF/A and PDF/UA identification fields in a packet, e.g. ("pdfaid:part", "2")
+pub fn conformance_fields(packet: &str) -> Vec<(&'static str, String)> {
+    CONFORMANCE_FIELDS
+        .iter()
+        .filter_map(|tag| {
+            let value = find_element_value(packet, tag).or_else(|| find_attribute_value(packet, tag))?;
+            (!value.is_empty()).then_some((*tag, value))
+        })
+        .collect()
+}
+
+/// Names the standards a document claims, e.g. "PDF/A-2B, PDF/UA-1"
+pub fn describe_conformance(fields: &[(&str, String)]) -> String {
+    let field = |tag: &str| fields.iter().find(|(t, _)| *t == tag).map(|(_, value)| value.as_str());
+    let mut standards = Vec::new();
+    if let Some(part) = field("pdfaid:part") {
+        standards.push(format!("PDF/A-{}{}", part, field("pdfaid:conformance").unwrap_or("").to_uppercase()));
+    }
+    if let Some(part) = field("pdfuaid:part") {
+        standards.push(format!("PDF/UA-{}", part));
+    }
+    standards.join(", ")
+}
+
+/// A minimal packet with the conformance fields and the given `properties`, such as
+/// ("dc:title", "Report"), which PDF/A requires to match the Info dictionary
+pub fn conformance_packet(fields: &[(&str, String)], properties: &[(&str, String)]) -> String {
+    let mut attributes = String::new();
+    for (tag, value) in fields {
+        attributes.push_str(&format!(" {}=\"{}\"", tag, encode_entities(value)));
+    }
+
+    let mut elements = String::new();
+    for (tag, value) in properties {
+        let value = encode_entities(value);
+        let element = match *tag {
+            "dc:title" | "dc:description" => format!("<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>", value),
+            "dc:creator" => format!("<rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq>", value),
+            _ => value,
+        };
+        elements.push_str(&format!("<{tag}>{element}</{tag}>"));
+    }
+
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\" xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\" ",
+            "xmlns:pdfuaid=\"http://www.aiim.org/pdfua/ns/id/\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" ",
+            "xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"{}>",
+            "{}</rdf:Description></rdf:RDF></x:xmpmeta><?xpacket end=\"w\"?>"
+        ),
+        attributes, elements
+    )
+}
+
+/// Returns the text content of the first `<tag>` element, joining nested values with commas
+pub fn find_element_value(packet: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+
+    let mut search_from = 0;
+    while let Some(pos) = packet[search_from..].find(&open) {
+        let start = search_from + pos + open.len();
+        search_from = start;
+
+        // Make sure we matched the whole tag name and not a prefix of a longer one
+        let rest = &packet[start..];
+        match rest.chars().next() {
+            Some('>') | Some(' ') | Some('\t') | Some('\r') | Some('\n') => {}
+            _ => continue,
+        }
+
+        let content_start = start + rest.find('>')? + 1;
+        let content_end = content_start + packet[content_start..].find(&close)?;
+
+        // Collect the text between nested tags (e.g. rdf:Seq/rdf:li lists)
+        let values: Vec<String> = packet[content_start..content_end]
+            .split('<')
+            .filter_map(|part| part.split_once('>').map(|(_, text)| text).or(Some(part)))
+            .map(|text| decode_entities(text.trim()))
+            .filter(|text| !text.is_empty())
+            .collect();
+
+        return Some(values.join(", "));
+    }
+
+    None
+}
+
+fn find_attribute_value(packet: &str, tag: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let pattern = format!("{}={}", tag, quote);
+        if let Some(pos) = packet.find(&pattern) {
+            let start = pos + pattern.len();
+            let end = start + packet[start..].find(quote)?;
+            return Some(decode_entities(packet[start..end].trim()));
+        }
+    }
+
+    None
+}
+
+/// The values of every `tag` attribute and of every `<tag>` element holding only text,
+/// in the order they appear
+fn find_all_values(packet: &str, tag: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut rest = packet;
+    while let Some(pos) = rest.find(tag) {
+        let after = &rest[pos + tag.len()..];
+        let preceded_by = rest[..pos].chars().next_back();
+        let value = match (preceded_by, after.chars().next()) {
+            (Some('<'), Some('>')) => after[1..].find('<').map(|end| &after[1..end + 1]),
+            (Some(c), Some('=')) if c.is_whitespace() => after[1..].chars().next().filter(|quote| matches!(quote, '"' | '\'')).and_then(|quote| {
+                let start = 1 + quote.len_utf8();
+                after[start..].find(quote).map(|end| &after[start..start + end])
+            }),
+            _ => None,
+        };
+        if let Some(value) = value {
+            values.push(decode_entities(value.trim()));
+        }
+        rest = after;
+    }
+    values
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn encode_entities(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_xmp_fields() {
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+            <rdf:Description xmp:CreatorTool="Writer &amp; Co" xmpMM:DocumentID="uuid:1234">
+                <dc:creator><rdf:Seq><rdf:li>Jane Doe</rdf:li><rdf:li>John Roe</rdf:li></rdf:Seq></dc:creator>
+                <dc:creatorExtra>ignored</dc:creatorExtra>
+            </rdf:Description>
+        </x:xmpmeta>"#;
+
+        let fields: Vec<String> = extract_xmp_fields(packet).iter().map(|f| f.to_string()).collect();
+        assert!(fields.contains(&"XMP Creator: Jane Doe, John Roe".to_string()));
+        assert!(fields.contains(&"XMP Creator Tool: Writer & Co".to_string()));
+        assert!(fields.contains(&"XMP Document ID: uuid:1234".to_string()));
+    }
+
+    #[test]
+    fn test_extract_software_traces() {
+        let packet = r#"<rdf:Description crs:Version="15.0" xmpMM:History="">
+            <xmpMM:History><rdf:Seq>
+                <rdf:li stEvt:action="created" stEvt:softwareAgent="Adobe Photoshop Lightroom Classic 12.0"/>
+                <rdf:li rdf:parseType="Resource"><stEvt:softwareAgent>Adobe Photoshop 24.1</stEvt:softwareAgent></rdf:li>
+                <rdf:li stEvt:softwareAgent='Adobe Photoshop 24.1'/>
+            </rdf:Seq></xmpMM:History>
+        </rdf:Description>"#;
+
+        let fields = extract_xmp_fields(packet);
+        let text: Vec<String> = fields.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            text,
+            vec![
+                "XMP Camera Raw Version: 15.0",
+                "XMP Edit History: Adobe Photoshop Lightroom Classic 12.0, Adobe Photoshop 24.1",
+            ]
+        );
+        assert!(fields.iter().all(|item| item.category == Category::Software));
+    }
+
+    #[test]
+    fn test_extract_xmp_fields_empty_packet() {
+        let fields = extract_xmp_fields("<x:xmpmeta/>");
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].to_string(), "XMP Metadata: present (12 bytes)");
+    }
+
+    #[test]
+    fn test_conformance_packet_round_trip() {
+        let packet = r#"<rdf:Description pdfaid:part="2" pdfaid:conformance="b" xmp:CreatorTool="Writer"/>"#;
+        let fields = conformance_fields(packet);
+        assert_eq!(fields, vec![("pdfaid:part", "2".to_string()), ("pdfaid:conformance", "b".to_string())]);
+        assert_eq!(describe_conformance(&fields), "PDF/A-2B");
+
+        let minimal = conformance_packet(&fields, &[("dc:title", "Q&A".to_string())]);
+        let items = extract_xmp_fields(&minimal);
+        assert_eq!(conformance_fields(&minimal), fields);
+        assert!(items.contains(&MetadataItem::new("XMP Title", "Q&A")));
+        assert!(items.contains(&MetadataItem::with_category(Category::Technical, "XMP Conformance", "PDF/A-2B")));
+        assert!(!items.iter().any(|item| item.tag == "XMP Creator Tool"));
+    }
+}